@@ -0,0 +1,798 @@
+//! ウォームアップ中のステップ幅と質量行列の適応
+
+use std::cell::Cell;
+use std::cmp::Ordering;
+
+use rand::SeedableRng;
+use serde::{Deserialize, Serialize};
+
+use crate::bounds::Space;
+use crate::chain::Leapfrog;
+use crate::integrator::{PhasePoint, VelocityVerlet};
+use crate::metric::MassMatrix;
+use crate::target::Model;
+use crate::{ChainRng, HmcError, Kinetic, Metric, Point, TargetDistribution, Vector};
+
+/// 対数ステップ幅のdual averaging（Hoffman & Gelman 2014, Algorithm 5）
+///
+/// 遷移ごとの採択確率の統計量と目標値の差を平均し、その平均が0になるよう log ε を動かす。
+/// ウォームアップ後は反復の重み付き平均 log ε̄ を使う。
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct DualAveraging {
+    /// 目標とする採択確率 δ
+    pub target_accept: f64,
+    /// log ε が引き寄せられる点 μ = log(10 ε₀)
+    pub mu: f64,
+    /// 更新回数 t
+    pub t: usize,
+    /// 目標との差の平均 H̄_t
+    pub h_bar: f64,
+    /// 現在の log ε_t
+    pub log_step: f64,
+    /// 重み付き平均 log ε̄_t
+    pub log_step_bar: f64,
+}
+
+/// 収縮の強さ γ
+const GAMMA: f64 = 0.05;
+/// 初期の反復の影響を弱める t₀
+const T0: f64 = 10.0;
+/// log ε̄ の平均の重みの減衰 κ
+const KAPPA: f64 = 0.75;
+
+impl DualAveraging {
+    /// 初期ステップ幅 `step_size` から始める
+    pub fn new(step_size: f64, target_accept: f64) -> Self {
+        Self {
+            target_accept,
+            mu: (10.0 * step_size).ln(),
+            t: 0,
+            h_bar: 0.0,
+            log_step: step_size.ln(),
+            log_step_bar: 0.0,
+        }
+    }
+
+    /// 1回の遷移の採択確率 `accept_prob` で更新し、次の遷移に使うステップ幅を返す
+    pub fn update(&mut self, accept_prob: f64) -> f64 {
+        self.t += 1;
+        let t = self.t as f64;
+        let eta = 1.0 / (t + T0);
+        self.h_bar = (1.0 - eta) * self.h_bar + eta * (self.target_accept - accept_prob);
+        self.log_step = self.mu - t.sqrt() / GAMMA * self.h_bar;
+        let weight = t.powf(-KAPPA);
+        self.log_step_bar = weight * self.log_step + (1.0 - weight) * self.log_step_bar;
+        self.log_step.exp()
+    }
+
+    /// ウォームアップ後に固定するステップ幅 ε̄（未更新なら現在の ε）
+    pub fn final_step_size(&self) -> f64 {
+        if self.t == 0 {
+            self.log_step.exp()
+        } else {
+            self.log_step_bar.exp()
+        }
+    }
+}
+
+/// 平均と共分散行列のWelfordのオンライン推定（質量行列の適応に使う）
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct WelfordVariance {
+    /// これまでに加えた点の数
+    pub n: usize,
+    pub mean: Point,
+    /// 平均からの偏差の2乗和
+    pub m2: Point,
+    /// 平均からの x, y の偏差の積の和（`Metric::AdaptDense` で推定する場合のみ。それ以外では0）
+    #[serde(default)]
+    pub m2_xy: f64,
+}
+
+/// 1座標分のWelfordの更新（`n` は `x` を含めた点の数）。更新前の平均からの偏差を返す
+fn welford_step(n: usize, mean: &mut f64, m2: &mut f64, x: f64) -> f64 {
+    let d = x - *mean;
+    *mean += d / n as f64;
+    *m2 += d * (x - *mean);
+    d
+}
+
+/// 偏差の2乗和 `m2` から求めた不偏分散を `regularization` 個分の擬似サンプルで1に引き寄せる
+///
+/// (n σ² + w) / (n + w)。`n` は2以上。
+fn shrink_variance(n: usize, m2: f64, regularization: f64) -> f64 {
+    let n = n as f64;
+    (n * m2 / (n - 1.0) + regularization) / (n + regularization)
+}
+
+/// 質量行列の逆の対角成分に使える分散か（正の有限値）
+fn is_valid_variance(v: f64) -> bool {
+    v.is_finite() && v > 0.0
+}
+
+impl WelfordVariance {
+    pub fn add(&mut self, q: &Point) {
+        self.n += 1;
+        let dx = welford_step(self.n, &mut self.mean.x, &mut self.m2.x, q.x);
+        welford_step(self.n, &mut self.mean.y, &mut self.m2.y, q.y);
+        self.m2_xy += dx * (q.y - self.mean.y);
+    }
+
+    /// 不偏分散を `regularization` 個分の擬似サンプルで1に引き寄せたもの
+    ///
+    /// (n σ² + w) / (n + w)。点が2つ未満か、結果が正の有限値にならなければ `None`。
+    pub fn regularized_variance(&self, regularization: f64) -> Option<Point> {
+        if self.n < 2 {
+            return None;
+        }
+        let var = Point {
+            x: shrink_variance(self.n, self.m2.x, regularization),
+            y: shrink_variance(self.n, self.m2.y, regularization),
+        };
+        (is_valid_variance(var.x) && is_valid_variance(var.y)).then_some(var)
+    }
+
+    /// 不偏共分散行列を `regularization` 個分の擬似サンプルで単位行列に引き寄せたもの
+    ///
+    /// (n Σ + w I) / (n + w)。点が2つ未満か、結果が正定値にならなければ `None`。
+    pub fn regularized_covariance(&self, regularization: f64) -> Option<[[f64; 2]; 2]> {
+        let var = self.regularized_variance(regularization)?;
+        let n = self.n as f64;
+        let cross = n * self.m2_xy / (n - 1.0) / (n + regularization);
+        let cov = [[var.x, cross], [cross, var.y]];
+        (cross.is_finite() && var.x * var.y - cross * cross > 0.0).then_some(cov)
+    }
+}
+
+/// 次元を問わない [`WelfordVariance`]（チェーンが質量行列の推定に使う）
+///
+/// 非対角の偏差の積は密な質量行列を推定する場合だけ持つ。
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct Welford {
+    n: usize,
+    mean: Vec<f64>,
+    m2: Vec<f64>,
+    /// i < j の組の偏差の積の和（行優先の上三角。対角だけを推定する場合は空）
+    cross: Vec<f64>,
+    /// 1点分の更新前の平均からの偏差（確保を使い回す）
+    deltas: Vec<f64>,
+}
+
+impl Welford {
+    /// `dim` 次元の点を集める（`dense` なら非対角の偏差の積も持つ）
+    pub(crate) fn new(dim: usize, dense: bool) -> Self {
+        Self {
+            n: 0,
+            mean: vec![0.0; dim],
+            m2: vec![0.0; dim],
+            cross: vec![
+                0.0;
+                if dense {
+                    dim * dim.saturating_sub(1) / 2
+                } else {
+                    0
+                }
+            ],
+            deltas: vec![0.0; dim],
+        }
+    }
+
+    pub(crate) fn add(&mut self, q: &[f64]) {
+        self.n += 1;
+        for (((mean, m2), d), &x) in self
+            .mean
+            .iter_mut()
+            .zip(&mut self.m2)
+            .zip(&mut self.deltas)
+            .zip(q)
+        {
+            *d = welford_step(self.n, mean, m2, x);
+        }
+        if self.cross.is_empty() {
+            return;
+        }
+        let mut k = 0;
+        for (i, delta) in self.deltas.iter().enumerate() {
+            for (x, mean) in q[i + 1..].iter().zip(&self.mean[i + 1..]) {
+                self.cross[k] += delta * (x - mean);
+                k += 1;
+            }
+        }
+    }
+
+    /// 2次元のチェックポイントから復元する
+    pub(crate) fn from_checkpoint(saved: WelfordVariance, dense: bool) -> Self {
+        let mut welford = Welford::new(2, dense);
+        welford.n = saved.n;
+        welford.mean = vec![saved.mean.x, saved.mean.y];
+        welford.m2 = vec![saved.m2.x, saved.m2.y];
+        if dense {
+            welford.cross[0] = saved.m2_xy;
+        }
+        welford
+    }
+
+    /// [`WelfordVariance::regularized_variance`] と同じ規則で座標ごとに求めた分散
+    pub(crate) fn regularized_variance(&self, regularization: f64) -> Option<Vec<f64>> {
+        if self.n < 2 {
+            return None;
+        }
+        let var: Vec<f64> = self
+            .m2
+            .iter()
+            .map(|&m2| shrink_variance(self.n, m2, regularization))
+            .collect();
+        var.iter().all(|&v| is_valid_variance(v)).then_some(var)
+    }
+
+    /// [`WelfordVariance::regularized_covariance`] と同じ規則で求めた共分散行列
+    ///
+    /// 正定値かどうかは確かめない（質量行列にするときのコレスキー分解で確かめる）。
+    pub(crate) fn regularized_covariance(&self, regularization: f64) -> Option<Vec<Vec<f64>>> {
+        let var = self.regularized_variance(regularization)?;
+        let n = self.n as f64;
+        let dim = var.len();
+        let cross: Vec<f64> = self
+            .cross
+            .iter()
+            .map(|c| n * c / (n - 1.0) / (n + regularization))
+            .collect();
+        if !cross.iter().all(|c| c.is_finite()) {
+            return None;
+        }
+        // 上三角の (i, j)（i < j）が行優先に並んだ `cross` での位置（対角だけを集めた場合は空）
+        let off_diagonal = |i: usize, j: usize| {
+            let k = i * dim - i * (i + 1) / 2 + j - i - 1;
+            cross.get(k).copied().unwrap_or(0.0)
+        };
+        let cov = (0..dim)
+            .map(|i| {
+                (0..dim)
+                    .map(|j| match i.cmp(&j) {
+                        Ordering::Equal => var[i],
+                        Ordering::Less => off_diagonal(i, j),
+                        Ordering::Greater => off_diagonal(j, i),
+                    })
+                    .collect()
+            })
+            .collect();
+        Some(cov)
+    }
+}
+
+/// 2次元のチェックポイントに保存する形
+impl From<&Welford> for WelfordVariance {
+    fn from(welford: &Welford) -> Self {
+        WelfordVariance {
+            n: welford.n,
+            mean: Point {
+                x: welford.mean[0],
+                y: welford.mean[1],
+            },
+            m2: Point {
+                x: welford.m2[0],
+                y: welford.m2[1],
+            },
+            m2_xy: welford.cross.first().copied().unwrap_or(0.0),
+        }
+    }
+}
+
+/// `warmup_schedule` を指定しない場合のウォームアップの区間（質量行列の推定に点を集める遅い区間は1つ）
+///
+/// 最初の15%は初期位置から典型集合に向かう途中として捨て、最後の25%は新しい質量行列で
+/// ステップ幅を適応し直すために残す。
+pub(crate) fn single_window(n_warmup: usize) -> Vec<WarmupWindow> {
+    let (start, end) = (n_warmup * 15 / 100, n_warmup * 75 / 100);
+    [
+        (WindowKind::Fast, 0, start),
+        (WindowKind::Slow, start, end),
+        (WindowKind::Fast, end, n_warmup),
+    ]
+    .into_iter()
+    .filter(|&(_, start, end)| start < end)
+    .map(|(kind, start, end)| WarmupWindow { kind, start, end })
+    .collect()
+}
+
+/// 遷移 `iteration`（1始まり）が質量行列を推定する遅い区間に含まれるか
+pub(crate) fn in_slow_window(windows: &[WarmupWindow], iteration: usize) -> bool {
+    windows
+        .iter()
+        .any(|w| w.kind == WindowKind::Slow && w.start < iteration && iteration <= w.end)
+}
+
+/// 遷移 `iteration` の後に始まる遅い区間が残っているか（あれば推定をやり直す）
+pub(crate) fn slow_window_ahead(windows: &[WarmupWindow], iteration: usize) -> bool {
+    windows
+        .iter()
+        .any(|w| w.kind == WindowKind::Slow && w.start >= iteration)
+}
+
+/// Stanと同じ段階的なウォームアップの区間の長さ
+///
+/// ウォームアップを、ステップ幅だけを適応する最初の速い区間、質量行列を推定する遅い区間の列、
+/// ステップ幅だけを適応し直す最後の速い区間に分ける。遅い区間は `base_window` から倍々に伸ばし、
+/// 次の区間が収まらなければ今の区間を最後の速い区間の手前まで延ばす。遅い区間が終わるたびに
+/// 質量行列を推定し直し（推定する設定の場合）、新しい質量行列でステップ幅の適応をやり直す。
+/// 3つの長さの和が `n_warmup` を超える場合は、`n_warmup` の15%・75%・10% に縮める。
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct WarmupSchedule {
+    /// 最初の速い区間の長さ
+    pub init_buffer: usize,
+    /// 最初の遅い区間の長さ（1以上）
+    pub base_window: usize,
+    /// 最後の速い区間の長さ
+    pub term_buffer: usize,
+}
+
+impl Default for WarmupSchedule {
+    fn default() -> Self {
+        Self {
+            init_buffer: 75,
+            base_window: 25,
+            term_buffer: 50,
+        }
+    }
+}
+
+/// ウォームアップの区間の種類
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum WindowKind {
+    /// ステップ幅だけを適応する
+    Fast,
+    /// ステップ幅を適応しながら質量行列を推定する点を集める
+    Slow,
+}
+
+/// ウォームアップの1区間（遷移の番号の範囲 [start, end)）
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct WarmupWindow {
+    pub kind: WindowKind,
+    pub start: usize,
+    pub end: usize,
+}
+
+impl WarmupSchedule {
+    /// 遅い区間の長さが1以上かを確かめる
+    pub fn validate(&self) -> Result<(), HmcError> {
+        if self.base_window == 0 {
+            return Err(HmcError::ZeroWarmupWindow);
+        }
+        Ok(())
+    }
+
+    /// `n_warmup` 回の遷移を前から隙間なく区切った区間の列（長さ0の区間は含めない）
+    pub fn windows(&self, n_warmup: usize) -> Vec<WarmupWindow> {
+        let (init, base, term) =
+            if self.init_buffer + self.base_window + self.term_buffer > n_warmup {
+                let init = n_warmup * 15 / 100;
+                let term = n_warmup / 10;
+                (init, n_warmup - init - term, term)
+            } else {
+                (self.init_buffer, self.base_window, self.term_buffer)
+            };
+        let window = |kind, start, end| WarmupWindow { kind, start, end };
+        let mut windows = Vec::new();
+        let slow_end = n_warmup - term;
+        if init > 0 {
+            windows.push(window(WindowKind::Fast, 0, init));
+        }
+        let (mut start, mut size) = (init, base);
+        while start < slow_end {
+            let mut end = start + size;
+            if end + 2 * size > slow_end {
+                end = slow_end;
+            }
+            windows.push(window(WindowKind::Slow, start, end));
+            start = end;
+            size *= 2;
+        }
+        if term > 0 {
+            windows.push(window(WindowKind::Fast, slow_end, n_warmup));
+        }
+        windows
+    }
+}
+
+/// ステップ幅を倍々に伸ばすか縮める回数の上限（2^100 倍で打ち切る）
+const MAX_STEP_SIZE_DOUBLINGS: usize = 100;
+
+/// 適応の初期値に使うステップ幅を探す（Hoffman & Gelman 2014, Algorithm 4）
+///
+/// `start` から1ステップだけ積分したときの採択確率が 1/2 をまたぐまで、`leapfrog.step_size` を
+/// 倍々に伸ばす（1/2 より大きい場合）か半分ずつ縮める（1/2 未満の場合）。エネルギーが有限でなければ
+/// 採択確率0とみなす。`MAX_STEP_SIZE_DOUBLINGS` 回で打ち切り、その時点の値を返す。
+pub(crate) fn reasonable_step_size<T: Model<V> + ?Sized, V: Vector>(
+    leapfrog: &mut Leapfrog<'_, T, V>,
+    start: &PhasePoint<V>,
+) -> f64 {
+    let current_h = leapfrog.target.potential_at(&start.q) + leapfrog.kinetic(&start.p);
+    if !current_h.is_finite() {
+        return leapfrog.step_size;
+    }
+    let log_accept = |leapfrog: &Leapfrog<'_, T, V>| {
+        let mut z = start.clone();
+        if leapfrog.step(&mut z, 1.0).is_err() {
+            return f64::NEG_INFINITY;
+        }
+        let diff = current_h - (leapfrog.target.potential_at(&z.q) + leapfrog.kinetic(&z.p));
+        if diff.is_nan() {
+            f64::NEG_INFINITY
+        } else {
+            diff
+        }
+    };
+    let half = 0.5_f64.ln();
+    let grow = log_accept(leapfrog) > half;
+    for _ in 0..MAX_STEP_SIZE_DOUBLINGS {
+        let next = if grow {
+            2.0 * leapfrog.step_size
+        } else {
+            0.5 * leapfrog.step_size
+        };
+        if !(next.is_finite() && next > 0.0) {
+            break;
+        }
+        leapfrog.step_size = next;
+        if (log_accept(leapfrog) > half) != grow {
+            break;
+        }
+    }
+    leapfrog.step_size
+}
+
+/// ターゲットのポテンシャルだけから、ステップ幅の適応の初期値の目安を求める
+///
+/// 単位質量行列のリープフロッグで、`q0` から ε = 1 を起点に `HmcConfig::find_step_size` と同じ探索を行う。
+/// 運動量は `seed` で初期化したRNGから引く。
+///
+/// ```
+/// use hamiltonian_sampler_rs::{find_reasonable_step_size, Point, StandardNormal2};
+///
+/// let step_size = find_reasonable_step_size(&StandardNormal2, &Point::default(), 1);
+/// assert!((0.1..10.0).contains(&step_size));
+/// ```
+pub fn find_reasonable_step_size<T: TargetDistribution + ?Sized>(
+    target: &T,
+    q0: &Point,
+    seed: u64,
+) -> f64 {
+    let mut rng = ChainRng::seed_from_u64(seed);
+    let mass = MassMatrix::Diagonal(Point { x: 1.0, y: 1.0 });
+    let mut leapfrog = Leapfrog {
+        target,
+        step_size: 1.0,
+        mass: &mass,
+        kinetic: Kinetic::Gaussian,
+        space: &Space::default(),
+        numdiff: None,
+        max_grad_norm: None,
+        integrator: &VelocityVerlet,
+        n_gradient_evals: Cell::new(0),
+        n_solver_failures: Cell::new(0),
+        n_gradient_clips: Cell::new(0),
+    };
+    let start = PhasePoint {
+        grad: leapfrog.gradient(q0),
+        q: q0.clone(),
+        p: mass.sample_momentum(q0, &mut rng),
+    };
+    reasonable_step_size(&mut leapfrog, &start)
+}
+
+/// ウォームアップの1区間を終えた時点の適応の状態
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct AdaptationWindow {
+    pub kind: WindowKind,
+    pub start: usize,
+    pub end: usize,
+    /// 区間の終わりのステップ幅（適応していればその区間の重み付き平均 ε̄）
+    pub step_size: f64,
+    /// 区間の終わりの質量行列（遅い区間では、その区間の点から推定したもの）
+    pub metric: Metric,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn converges_to_step_size_with_target_acceptance() {
+        // 採択確率が exp(-ε²) になるモデル。δ = 0.8 となる ε は sqrt(-ln 0.8)
+        let accept = |step: f64| (-step * step).exp();
+        let expected = (-0.8f64.ln()).sqrt();
+        for initial in [1e-3, 0.5, 20.0] {
+            let mut adapter = DualAveraging::new(initial, 0.8);
+            let mut step = initial;
+            for _ in 0..2000 {
+                step = adapter.update(accept(step));
+            }
+            let adapted = adapter.final_step_size();
+            assert!(
+                (adapted - expected).abs() < 0.02 * expected,
+                "initial {}: {} vs {}",
+                initial,
+                adapted,
+                expected
+            );
+        }
+        assert!((DualAveraging::new(0.3, 0.8).final_step_size() - 0.3).abs() < 1e-12);
+    }
+
+    #[test]
+    fn welford_variance_matches_two_pass_and_shrinks_toward_one() {
+        let points: Vec<Point> = (0..50)
+            .map(|i| {
+                let t = i as f64;
+                Point {
+                    x: (t * 0.7).sin() * 3.0 + 5.0,
+                    y: t * 0.1,
+                }
+            })
+            .collect();
+        let mut welford = WelfordVariance::default();
+        for p in &points {
+            welford.add(p);
+        }
+        let cov = crate::validate::sample_cov(&points);
+        let var = welford.regularized_variance(0.0).unwrap();
+        assert!((var.x - cov[0][0]).abs() < 1e-12 && (var.y - cov[1][1]).abs() < 1e-12);
+
+        // 擬似サンプルを増やすほど1に近づく
+        let shrunk = welford.regularized_variance(50.0).unwrap();
+        assert!((shrunk.x - (cov[0][0] + 1.0) / 2.0).abs() < 1e-12);
+        assert!(welford.regularized_variance(1e12).unwrap().x - 1.0 < 1e-9);
+
+        // 次元を問わない版も同じ値になり、チェックポイントの形に移しても変わらない
+        let mut general = Welford::new(2, true);
+        for p in &points {
+            general.add(&[p.x, p.y]);
+        }
+        for regularization in [0.0, 50.0] {
+            let var = welford.regularized_variance(regularization).unwrap();
+            assert_eq!(
+                general.regularized_variance(regularization),
+                Some(vec![var.x, var.y])
+            );
+            let cov = welford.regularized_covariance(regularization).unwrap();
+            assert_eq!(
+                general.regularized_covariance(regularization),
+                Some(cov.iter().map(|row| row.to_vec()).collect())
+            );
+        }
+        assert_eq!(WelfordVariance::from(&general), welford);
+        assert_eq!(
+            Welford::from_checkpoint(welford.clone(), true).regularized_covariance(1.0),
+            general.regularized_covariance(1.0)
+        );
+
+        // 全て同じ点で正則化なしなら分散0になり使えない
+        let mut stuck = WelfordVariance::default();
+        stuck.add(&Point { x: 1.0, y: 1.0 });
+        assert!(stuck.regularized_variance(5.0).is_none());
+        stuck.add(&Point { x: 1.0, y: 1.0 });
+        assert!(stuck.regularized_variance(0.0).is_none());
+        assert_eq!(
+            stuck.regularized_variance(5.0),
+            Some(Point {
+                x: 5.0 / 7.0,
+                y: 5.0 / 7.0
+            })
+        );
+    }
+
+    #[test]
+    fn warmup_schedule_partitions_n_warmup_exactly() {
+        let stan = WarmupSchedule::default().windows(1000);
+        let bounds: Vec<(WindowKind, usize, usize)> =
+            stan.iter().map(|w| (w.kind, w.start, w.end)).collect();
+        assert_eq!(
+            bounds,
+            [
+                (WindowKind::Fast, 0, 75),
+                (WindowKind::Slow, 75, 100),
+                (WindowKind::Slow, 100, 150),
+                (WindowKind::Slow, 150, 250),
+                (WindowKind::Slow, 250, 450),
+                (WindowKind::Slow, 450, 950),
+                (WindowKind::Fast, 950, 1000),
+            ]
+        );
+
+        let schedules = [
+            WarmupSchedule::default(),
+            WarmupSchedule {
+                init_buffer: 0,
+                base_window: 1,
+                term_buffer: 0,
+            },
+            WarmupSchedule {
+                init_buffer: 10,
+                base_window: 7,
+                term_buffer: 3,
+            },
+        ];
+        for schedule in schedules {
+            for n_warmup in (0..300).chain([999, 1000, 1001, 5000]) {
+                let windows = schedule.windows(n_warmup);
+                let mut next = 0;
+                for w in &windows {
+                    assert!(w.start == next && w.start < w.end, "{:?}", windows);
+                    next = w.end;
+                }
+                assert_eq!(next, n_warmup, "{:?}", windows);
+                if n_warmup > 0 {
+                    assert!(windows.iter().any(|w| w.kind == WindowKind::Slow));
+                }
+            }
+        }
+        // 短いウォームアップでは 15%・75%・10% に縮める
+        let short: Vec<(usize, usize)> = WarmupSchedule::default()
+            .windows(100)
+            .iter()
+            .map(|w| (w.start, w.end))
+            .collect();
+        assert_eq!(short, [(0, 15), (15, 90), (90, 100)]);
+        assert_eq!(
+            WarmupSchedule {
+                base_window: 0,
+                ..WarmupSchedule::default()
+            }
+            .validate(),
+            Err(HmcError::ZeroWarmupWindow)
+        );
+    }
+
+    #[test]
+    fn staged_warmup_recovers_anisotropic_scales_and_reports_each_window() {
+        use crate::{run_hmc, Algorithm, Chain, DistType, HmcConfig, MvNormal2};
+
+        let variances = [0.01, 100.0];
+        let target =
+            MvNormal2::new(Point::default(), [[variances[0], 0.0], [0.0, variances[1]]]).unwrap();
+        let config = HmcConfig {
+            n_samples: 1000,
+            n_warmup: 1000,
+            algorithm: Algorithm::Nuts { max_depth: 10 },
+            adapt_step_size: true,
+            adapt_mass_matrix: true,
+            // 1に引き寄せる正則化なしで、真の分散と比べる
+            mass_regularization: 0.0,
+            warmup_schedule: Some(WarmupSchedule::default()),
+            target: DistType::Gaussian(target),
+            seed: Some(75),
+            ..HmcConfig::default()
+        };
+        let result = run_hmc(&config).unwrap();
+        let Metric::Diagonal(inv_mass) = &result.metric else {
+            panic!("{:?}", result.metric);
+        };
+        for (estimate, truth) in inv_mass.iter().zip(variances) {
+            assert!((0.7..1.4).contains(&(estimate / truth)), "{:?}", inv_mass);
+        }
+
+        let windows = &result.adaptation_windows;
+        assert_eq!(windows.len(), 7);
+        assert_eq!(windows[0].metric, Metric::UnitE);
+        assert_eq!(windows[6].metric, result.metric);
+        assert_eq!(Some(windows[6].step_size), result.adapted_step_size);
+        // 質量行列が y のスケールに合うにつれて、ステップ幅は x の幅に縛られなくなる
+        assert!(
+            windows[6].step_size > 5.0 * windows[0].step_size,
+            "{:?}",
+            windows
+        );
+        let log_error = |metric: &Metric| match metric {
+            Metric::Diagonal(inv_mass) => inv_mass
+                .iter()
+                .zip(variances)
+                .map(|(v, truth)| (v / truth).ln().abs())
+                .fold(0.0, f64::max),
+            _ => panic!("{:?}", metric),
+        };
+        assert!(log_error(&windows[5].metric) < log_error(&windows[1].metric));
+
+        // ウォームアップの途中で保存して再開しても、同じ区間の記録になる
+        let mut chain = Chain::new(config.clone()).unwrap();
+        for _ in 0..300 {
+            chain.step();
+        }
+        assert_eq!(chain.adaptation_windows(), &windows[..4]);
+        let mut restored = Chain::restore(chain.save()).unwrap();
+        for _ in 300..1000 {
+            restored.step();
+        }
+        assert_eq!(restored.adaptation_windows(), windows.as_slice());
+    }
+
+    #[test]
+    fn reasonable_step_size_is_near_one_on_standard_normal() {
+        use crate::{run_hmc, Algorithm, Chain, DistType, HmcConfig, StandardNormal2};
+
+        for seed in 0..20 {
+            let step_size = find_reasonable_step_size(&StandardNormal2, &Point::default(), seed);
+            assert!((0.1..10.0).contains(&step_size), "{}: {}", seed, step_size);
+        }
+
+        for algorithm in [Algorithm::Hmc, Algorithm::Nuts { max_depth: 10 }] {
+            let config = |step_size, find_step_size| HmcConfig {
+                n_samples: 100,
+                n_warmup: 100,
+                step_size,
+                adapt_step_size: true,
+                find_step_size,
+                algorithm: algorithm.clone(),
+                target: DistType::Normal,
+                seed: Some(76),
+                ..HmcConfig::default()
+            };
+            for initial in [1e-8, 10.0] {
+                let mut chain = Chain::new(config(initial, true)).unwrap();
+                let first = chain.step().step_size.unwrap();
+                assert!((0.1..10.0).contains(&first), "{}: {}", initial, first);
+                let adapted = run_hmc(&config(initial, true))
+                    .unwrap()
+                    .adapted_step_size
+                    .unwrap();
+                assert!((0.5..2.0).contains(&adapted), "{}: {}", initial, adapted);
+            }
+        }
+        // 適応しなければ探索もしない
+        let mut fixed = Chain::new(HmcConfig {
+            step_size: 1e-8,
+            find_step_size: true,
+            ..HmcConfig::default()
+        })
+        .unwrap();
+        assert_eq!(fixed.step().step_size, Some(1e-8));
+    }
+
+    #[test]
+    fn step_size_search_stops_on_flat_and_non_finite_targets() {
+        use crate::TargetDistribution;
+
+        /// どこでも U = 0（採択確率は常に1で、倍々に伸ばし続ける）
+        struct Flat;
+        impl TargetDistribution for Flat {
+            fn potential(&self, _: &Point) -> f64 {
+                0.0
+            }
+            fn gradient(&self, _: &Point) -> Point {
+                Point::default()
+            }
+        }
+        let flat = find_reasonable_step_size(&Flat, &Point::default(), 1);
+        assert_eq!(flat, 2.0_f64.powi(MAX_STEP_SIZE_DOUBLINGS as i32));
+
+        /// 単位円の外ではポテンシャルも勾配も有限でない
+        struct Disk;
+        impl TargetDistribution for Disk {
+            fn potential(&self, q: &Point) -> f64 {
+                if q.x * q.x + q.y * q.y < 1.0 {
+                    0.5 * (q.x * q.x + q.y * q.y)
+                } else {
+                    f64::NAN
+                }
+            }
+            fn gradient(&self, q: &Point) -> Point {
+                if q.x * q.x + q.y * q.y < 1.0 {
+                    q.clone()
+                } else {
+                    Point {
+                        x: f64::NAN,
+                        y: f64::NAN,
+                    }
+                }
+            }
+        }
+        let disk = find_reasonable_step_size(&Disk, &Point::default(), 1);
+        assert!(disk > 0.0 && disk < 2.0, "{}", disk);
+        // 開始点でエネルギーが有限でなければ、そのまま ε = 1 を返す
+        assert_eq!(
+            find_reasonable_step_size(&Disk, &Point { x: 2.0, y: 0.0 }, 1),
+            1.0
+        );
+    }
+}
@@ -0,0 +1,224 @@
+//! HMCカーネルによる焼きなまし法（サンプリングではなくモード探索のため）
+
+use serde::{Deserialize, Serialize};
+
+use crate::{Chain, ChainRng, HmcConfig, HmcError, Point, TargetDistribution};
+
+/// 温度の下げ方
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Cooling {
+    /// T_k = T_0 (T_final / T_0)^(k / (N - 1))
+    #[default]
+    Geometric,
+    /// T_k = T_0 + (T_final - T_0) k / (N - 1)
+    Linear,
+}
+
+/// 焼きなましの温度スケジュール
+///
+/// JSONなどから読み込めるよう、全フィールドにデフォルト値を持つ。
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct AnnealSchedule {
+    /// 温度の下げ方
+    pub cooling: Cooling,
+    /// 最初の遷移の温度 T_0
+    pub initial_temperature: f64,
+    /// 最後の遷移の温度 T_final
+    pub final_temperature: f64,
+    /// 遷移の回数 N
+    pub n_iterations: usize,
+}
+
+impl Default for AnnealSchedule {
+    fn default() -> Self {
+        Self {
+            cooling: Cooling::Geometric,
+            initial_temperature: 10.0,
+            final_temperature: 1e-4,
+            n_iterations: 2000,
+        }
+    }
+}
+
+impl AnnealSchedule {
+    /// 温度が正の有限値で、遷移の回数が1以上かを確かめる
+    pub fn validate(&self) -> Result<(), HmcError> {
+        for temperature in [self.initial_temperature, self.final_temperature] {
+            if !(temperature.is_finite() && temperature > 0.0) {
+                return Err(HmcError::InvalidTemperature(temperature));
+            }
+        }
+        if self.n_iterations == 0 {
+            return Err(HmcError::ZeroAnnealIterations);
+        }
+        Ok(())
+    }
+
+    /// 各遷移の温度 T_0, ..., T_{N-1}（N = 1 なら T_0 だけ）
+    pub fn temperatures(&self) -> Vec<f64> {
+        let (t0, t1) = (self.initial_temperature, self.final_temperature);
+        let last = self.n_iterations.saturating_sub(1).max(1) as f64;
+        (0..self.n_iterations)
+            .map(|k| {
+                let s = k as f64 / last;
+                match self.cooling {
+                    Cooling::Geometric => t0 * (t1 / t0).powf(s),
+                    Cooling::Linear => t0 + (t1 - t0) * s,
+                }
+            })
+            .collect()
+    }
+}
+
+/// 焼きなましの結果
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct AnnealResult {
+    /// 開始位置と全遷移の後の位置のうち、ポテンシャルが最も低い点
+    pub best_position: Point,
+    /// `best_position` での（温度で割る前の）ポテンシャル
+    pub best_potential: f64,
+    /// 各遷移の温度
+    pub temperatures: Vec<f64>,
+    /// 各遷移の後の位置
+    pub trajectory: Vec<Point>,
+    /// 全遷移に対する採択率
+    pub acceptance_rate: f64,
+}
+
+/// `config.target` のポテンシャルの最小点を焼きなましで探す
+pub fn anneal(config: &HmcConfig, schedule: &AnnealSchedule) -> Result<AnnealResult, HmcError> {
+    anneal_with_target(config, &config.target, schedule)
+}
+
+/// ユーザー定義のターゲットのポテンシャルの最小点を焼きなましで探す
+///
+/// `config` のHMCカーネル（`step_size`・`num_steps`・`algorithm`・`metric`・開始位置・シードなど）で、
+/// 遷移 k ごとに U(q)/T_k をターゲットとして1回ずつ遷移する（`config.temperature` はスケジュールで置き換える）。
+/// U/T の曲率は温度に反比例するため、ステップ幅は √T_k 倍にする（温度1で `step_size` になる）。
+/// ウォームアップと適応は行わず、`n_samples`・`n_warmup`・`thin` は使わない。
+pub fn anneal_with_target<T: TargetDistribution>(
+    config: &HmcConfig,
+    target: T,
+    schedule: &AnnealSchedule,
+) -> Result<AnnealResult, HmcError> {
+    schedule.validate()?;
+    let temperatures = schedule.temperatures();
+    let config = HmcConfig {
+        n_warmup: 0,
+        temperature: temperatures[0],
+        ..config.clone()
+    };
+    let rng = match config.seed {
+        Some(seed) => rand::SeedableRng::seed_from_u64(seed),
+        None => rand::SeedableRng::from_entropy(),
+    };
+    let step_size = config.step_size;
+    let mut chain: Chain<ChainRng, T> = Chain::with_target(config, target, rng)?;
+
+    let mut best_position = chain.current_position().clone();
+    let mut best_potential = chain.target().potential(&best_position);
+    let mut trajectory = Vec::with_capacity(temperatures.len());
+    for &temperature in &temperatures {
+        chain.set_temperature(temperature);
+        chain.set_step_size(step_size * temperature.sqrt());
+        let position = chain.step().position;
+        let u = chain.target().potential(&position);
+        if u < best_potential {
+            best_potential = u;
+            best_position = position.clone();
+        }
+        trajectory.push(position);
+    }
+
+    Ok(AnnealResult {
+        best_position,
+        best_potential,
+        temperatures,
+        trajectory,
+        acceptance_rate: chain.acceptance_rate(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::init::find_mode;
+    use crate::{Banana, DistType};
+
+    #[test]
+    fn annealing_finds_the_narrow_banana_mode() {
+        let banana = Banana { a: 1.0, b: 100.0 };
+        let start = Point { x: -1.0, y: 1.0 };
+        let config = HmcConfig {
+            step_size: 0.02,
+            num_steps: 30,
+            initial_pos: start.clone(),
+            target: DistType::Banana(banana),
+            seed: Some(67),
+            ..HmcConfig::default()
+        };
+        let schedule = AnnealSchedule::default();
+        let result = anneal(&config, &schedule).unwrap();
+        assert!(result.best_potential < 1e-3, "{}", result.best_potential);
+        assert_eq!(
+            result.best_potential,
+            banana.potential(&result.best_position)
+        );
+        assert_eq!(result.trajectory.len(), schedule.n_iterations);
+        assert_eq!(result.temperatures.len(), schedule.n_iterations);
+
+        // 固定の学習率の勾配降下は、同じ反復回数では曲がった谷の底をなかなか進めず、
+        // 学習率を上げると谷を横切る方向に発散する
+        let descended = find_mode(&start, &banana, schedule.n_iterations, 1e-3, None).unwrap();
+        assert!(banana.potential(&descended) > 1e-2);
+        assert!(matches!(
+            find_mode(&start, &banana, schedule.n_iterations, 5e-3, None),
+            Err(HmcError::ModeSearchDiverged { .. })
+        ));
+    }
+
+    #[test]
+    fn schedules_run_from_initial_to_final_temperature() {
+        let schedule = |cooling| AnnealSchedule {
+            cooling,
+            initial_temperature: 8.0,
+            final_temperature: 1.0,
+            n_iterations: 4,
+        };
+        let geometric = schedule(Cooling::Geometric).temperatures();
+        let linear = schedule(Cooling::Linear).temperatures();
+        for (t, expected) in geometric.iter().zip([8.0, 4.0, 2.0, 1.0]) {
+            assert!((t - expected).abs() < 1e-12, "{:?}", geometric);
+        }
+        for (t, expected) in linear
+            .iter()
+            .zip([8.0, 5.0 + 2.0 / 3.0, 3.0 + 1.0 / 3.0, 1.0])
+        {
+            assert!((t - expected).abs() < 1e-12, "{:?}", linear);
+        }
+
+        let parsed: AnnealSchedule =
+            serde_json::from_str(r#"{"cooling": "linear", "n_iterations": 10}"#).unwrap();
+        assert_eq!(parsed.cooling, Cooling::Linear);
+        assert_eq!(
+            AnnealSchedule {
+                final_temperature: 0.0,
+                ..AnnealSchedule::default()
+            }
+            .validate(),
+            Err(HmcError::InvalidTemperature(0.0))
+        );
+        assert_eq!(
+            anneal(
+                &HmcConfig::default(),
+                &AnnealSchedule {
+                    n_iterations: 0,
+                    ..AnnealSchedule::default()
+                }
+            ),
+            Err(HmcError::ZeroAnnealIterations)
+        );
+    }
+}
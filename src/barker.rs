@@ -0,0 +1,189 @@
+//! Barker提案（Livingstone & Zanella 2022）
+
+use rand::Rng;
+use rand_distr::StandardNormal;
+
+use crate::chain::Leapfrog;
+use crate::nuts::Move;
+use crate::target::Model;
+use crate::vector::{self, Vector};
+
+/// log(1 + e^a) を桁あふれさせずに計算する
+fn softplus(a: f64) -> f64 {
+    if a > 0.0 {
+        a + (-a).exp().ln_1p()
+    } else {
+        a.exp().ln_1p()
+    }
+}
+
+/// 各座標で z ~ N(0, ε²) を引き、確率 1/(1 + exp(z ∂U(q))) で +z、残りで -z だけ動かした点を提案する
+///
+/// 勾配の下る向きに進みやすく歪めた提案で、移動量の大きさは勾配に依らず ε で決まる。w = q' - q として
+/// 採択確率は min(1, exp(U(q) - U(q') + Σ_i [log(1 + exp(w_i ∂_iU(q))) - log(1 + exp(-w_i ∂_iU(q')))]))。
+/// MALAと違い ε が大きすぎても勾配で遠くへ飛ばされないため、採択率の落ち方が緩やか。
+/// 反射境界の外への提案は棄却し、周期境界では折り畳む前の座標で提案密度を計算する（MALAと同じ近似）。
+pub(crate) fn transition<T, V, R>(
+    leapfrog: &Leapfrog<'_, T, V>,
+    q: &V,
+    current_u: f64,
+    step_size: f64,
+    rng: &mut R,
+) -> Move<V>
+where
+    T: Model<V> + ?Sized,
+    V: Vector,
+    R: Rng,
+{
+    let grad = leapfrog.gradient(q);
+    let mut coordinate = |g: f64| {
+        let z = step_size * rng.sample::<f64, _>(StandardNormal);
+        if rng.gen::<f64>() < 1.0 / (1.0 + (z * g).exp()) {
+            z
+        } else {
+            -z
+        }
+    };
+    let mut w = grad.clone();
+    for w in w.as_mut_slice() {
+        *w = coordinate(*w);
+    }
+    let proposal = vector::zip_with(q, &w, |q, w| q + w);
+    let inside = leapfrog.space.contains(&proposal);
+    let wrapped = leapfrog.space.wrap(&proposal);
+    let (new_u, new_grad) = if inside {
+        (
+            leapfrog.target.potential_at(&wrapped),
+            leapfrog.gradient(&wrapped),
+        )
+    } else {
+        (f64::INFINITY, vector::map(q, |_| 0.0))
+    };
+
+    let log_ratio = if new_u.is_finite() && vector::is_finite(&new_grad) {
+        let mut log_ratio = current_u - new_u;
+        for ((w, g), new_g) in w
+            .as_slice()
+            .iter()
+            .zip(grad.as_slice())
+            .zip(new_grad.as_slice())
+        {
+            log_ratio += softplus(w * g);
+            log_ratio -= softplus(-w * new_g);
+        }
+        log_ratio
+    } else {
+        f64::NEG_INFINITY
+    };
+    let accept_prob = if log_ratio.is_nan() {
+        0.0
+    } else {
+        log_ratio.exp().min(1.0)
+    };
+    let accepted = rng.gen::<f64>() < accept_prob;
+    let potential_energy = if accepted { new_u } else { current_u };
+
+    Move {
+        to: accepted.then_some(wrapped),
+        energy_error: -log_ratio,
+        divergent: false,
+        numerical_error: false,
+        out_of_support: !log_ratio.is_finite(),
+        accept_prob,
+        potential_energy,
+        energy: potential_energy,
+        n_leapfrog: 1,
+        tree_depth: None,
+        momentum: None,
+        grad: None,
+        trajectory: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::validate::z_score_of_mean;
+    use crate::{run_hmc, Algorithm, DistType, HmcConfig, HmcError, Point};
+
+    #[test]
+    fn barker_reproduces_standard_normal_moments() {
+        let config = HmcConfig {
+            n_samples: 20_000,
+            n_warmup: 500,
+            algorithm: Algorithm::Barker { step_size: 1.5 },
+            target: DistType::Normal,
+            seed: Some(84),
+            ..HmcConfig::default()
+        };
+        let result = run_hmc(&config).unwrap();
+        assert_eq!(result.n_leapfrog, 20_000);
+        assert!(result.acceptance_rate > 0.5, "{}", result.acceptance_rate);
+        let z = z_score_of_mean(
+            &result.samples,
+            &Point::default(),
+            &[[1.0, 0.0], [0.0, 1.0]],
+        );
+        assert!(z.x.abs() < 4.0 && z.y.abs() < 4.0, "{:?}", z);
+        // x², y² の平均（E = 1, Var = 2）
+        let squared: Vec<Point> = result
+            .samples
+            .iter()
+            .map(|p| Point {
+                x: p.x * p.x,
+                y: p.y * p.y,
+            })
+            .collect();
+        let z = z_score_of_mean(
+            &squared,
+            &Point { x: 1.0, y: 1.0 },
+            &[[2.0, 0.0], [0.0, 2.0]],
+        );
+        assert!(z.x.abs() < 4.0 && z.y.abs() < 4.0, "{:?}", z);
+    }
+
+    #[test]
+    fn barker_degrades_more_gracefully_than_mala_with_oversized_step() {
+        let acceptance = |algorithm| {
+            run_hmc(&HmcConfig {
+                n_samples: 5000,
+                algorithm,
+                target: DistType::Normal,
+                seed: Some(84),
+                ..HmcConfig::default()
+            })
+            .unwrap()
+            .acceptance_rate
+        };
+        let (mala, mala_large) = (
+            acceptance(Algorithm::Mala { step_size: 1.0 }),
+            acceptance(Algorithm::Mala { step_size: 10.0 }),
+        );
+        let (barker, barker_large) = (
+            acceptance(Algorithm::Barker { step_size: 1.0 }),
+            acceptance(Algorithm::Barker { step_size: 10.0 }),
+        );
+        // 適切なステップ幅ではどちらもよく採択する
+        assert!(mala > 0.5 && barker > 0.5, "{} {}", mala, barker);
+        // 10倍にするとMALAはほぼ動かなくなるが、Barkerはまだ動く
+        assert!(mala_large < 0.005, "{}", mala_large);
+        assert!(barker_large > 0.02, "{}", barker_large);
+        assert!(barker_large / barker > 10.0 * mala_large / mala);
+    }
+
+    #[test]
+    fn barker_rejects_invalid_step_size_and_loads_from_json() {
+        for bad in [0.0, -1.0, f64::NAN] {
+            let config = HmcConfig {
+                algorithm: Algorithm::Barker { step_size: bad },
+                ..HmcConfig::default()
+            };
+            assert!(matches!(
+                config.validate(),
+                Err(HmcError::InvalidStepSize(_))
+            ));
+        }
+        let parsed: HmcConfig =
+            serde_json::from_str(r#"{"algorithm": {"barker": {"step_size": 0.5}}}"#).unwrap();
+        assert_eq!(parsed.algorithm, Algorithm::Barker { step_size: 0.5 });
+    }
+}
@@ -0,0 +1,372 @@
+//! 状態空間の境界（矩形の境界での反射・周期境界）
+
+use serde::{Deserialize, Serialize};
+
+use crate::{HmcConfig, HmcError, Point, Transform, Vector};
+
+/// 矩形 `[xmin, xmax] × [ymin, ymax]`
+///
+/// `HmcConfig::bounds` に指定すると、リープフロッグの位置更新で境界を越えた座標を境界で
+/// 折り返し、その成分の運動量の符号を反転する。反射は体積を保存し時間反転可能なので
+/// 詳細釣り合いは崩れず、台の外に出る提案を棄却する場合より採択率が高く保てる。
+///
+/// ```
+/// use hamiltonian_sampler_rs::{BoundingBox, Point};
+///
+/// let bounds = BoundingBox { xmin: 0.0, xmax: 1.0, ymin: 0.0, ymax: 1.0 };
+/// let mut q = Point { x: 1.25, y: -2.5 };
+/// let mut p = Point { x: 1.0, y: -1.0 };
+/// bounds.reflect(&mut q, &mut p);
+/// assert_eq!(q, Point { x: 0.75, y: 0.5 });
+/// assert_eq!(p, Point { x: -1.0, y: 1.0 });
+/// ```
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct BoundingBox {
+    pub xmin: f64,
+    pub xmax: f64,
+    pub ymin: f64,
+    pub ymax: f64,
+}
+
+impl BoundingBox {
+    /// 範囲が有限で min < max か検証する
+    pub fn validate(&self) -> Result<(), HmcError> {
+        for (min, max) in [(self.xmin, self.xmax), (self.ymin, self.ymax)] {
+            if !(min.is_finite() && max.is_finite() && min < max) {
+                return Err(HmcError::InvalidBounds { min, max });
+            }
+        }
+        Ok(())
+    }
+
+    /// `q` が矩形の内側（境界を含む）にあるか
+    pub fn contains(&self, q: &Point) -> bool {
+        (self.xmin..=self.xmax).contains(&q.x) && (self.ymin..=self.ymax).contains(&q.y)
+    }
+
+    /// 範囲の外に出た座標を境界で折り返し、奇数回折り返した成分の運動量を反転する
+    ///
+    /// ステップが大きく何度も境界を越える場合も、その回数分まとめて折り返す。
+    pub fn reflect(&self, q: &mut Point, p: &mut Point) {
+        reflect_1d(&mut q.x, &mut p.x, self.xmin, self.xmax);
+        reflect_1d(&mut q.y, &mut p.y, self.ymin, self.ymax);
+    }
+}
+
+/// 状態空間の位相
+///
+/// JSONでは `"euclidean"` か `{"periodic": {"period_x": 6.283185307179586, "period_y": 6.283185307179586}}`。
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Topology {
+    /// 通常の平面
+    #[default]
+    Euclidean,
+    /// 座標ごとに周期を持つトーラス。位置は常に基本領域 [0, period) に折り畳まれる
+    Periodic { period_x: f64, period_y: f64 },
+}
+
+impl Topology {
+    /// 周期が正の有限値か検証する
+    pub fn validate(&self) -> Result<(), HmcError> {
+        if let Topology::Periodic { period_x, period_y } = *self {
+            for period in [period_x, period_y] {
+                if !(period.is_finite() && period > 0.0) {
+                    return Err(HmcError::InvalidPeriod(period));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// 周期境界なら `q` を基本領域 [0, period_x) × [0, period_y) に折り畳む
+    ///
+    /// ```
+    /// use hamiltonian_sampler_rs::{Point, Topology};
+    ///
+    /// let torus = Topology::Periodic { period_x: 1.0, period_y: 2.0 };
+    /// assert_eq!(torus.wrap(&Point { x: 2.25, y: -0.5 }), Point { x: 0.25, y: 1.5 });
+    /// ```
+    pub fn wrap(&self, q: &Point) -> Point {
+        match *self {
+            Topology::Euclidean => q.clone(),
+            Topology::Periodic { period_x, period_y } => Point {
+                x: wrap_1d(q.x, period_x),
+                y: wrap_1d(q.y, period_y),
+            },
+        }
+    }
+}
+
+/// チェーンが積分する空間（座標ごとの変数変換・反射境界・周期境界）
+///
+/// 2次元の `transform`・`bounds`・`topology` と、N次元の `transform_nd`・`bounds_nd`・`periods_nd` を
+/// 同じ形にしたもの。反射と折り畳みは非制約空間の座標に対して行う。
+#[derive(Clone, Debug, Default, PartialEq)]
+pub(crate) struct Space {
+    /// 座標ごとの変換（全て `Identity` なら空）
+    pub(crate) transforms: Vec<Transform>,
+    /// 座標ごとの反射境界 (min, max)
+    pub(crate) bounds: Option<Vec<(f64, f64)>>,
+    /// 座標ごとの周期（周期境界でなければ `None`）
+    pub(crate) periods: Option<Vec<f64>>,
+}
+
+impl Space {
+    /// 2次元の設定から作る
+    pub(crate) fn planar(config: &HmcConfig) -> Self {
+        let transform = config.transform;
+        Self {
+            transforms: if transform.is_identity() {
+                Vec::new()
+            } else {
+                vec![transform.x, transform.y]
+            },
+            bounds: config
+                .bounds
+                .map(|b| vec![(b.xmin, b.xmax), (b.ymin, b.ymax)]),
+            periods: match config.topology {
+                Topology::Euclidean => None,
+                Topology::Periodic { period_x, period_y } => Some(vec![period_x, period_y]),
+            },
+        }
+    }
+
+    /// N次元の設定から作る
+    pub(crate) fn nd(config: &HmcConfig) -> Self {
+        let identity = config
+            .transform_nd
+            .iter()
+            .all(|t| *t == Transform::Identity);
+        Self {
+            transforms: if identity {
+                Vec::new()
+            } else {
+                config.transform_nd.clone()
+            },
+            bounds: config.bounds_nd.clone(),
+            periods: config.periods_nd.clone(),
+        }
+    }
+
+    /// 周期境界を持つか
+    pub(crate) fn is_periodic(&self) -> bool {
+        self.periods.is_some()
+    }
+
+    /// 非制約空間の z から元の座標 θ へ
+    pub(crate) fn to_constrained<V: Vector>(&self, z: &V) -> V {
+        let mut theta = z.clone();
+        for (x, t) in theta.as_mut_slice().iter_mut().zip(&self.transforms) {
+            *x = t.to_constrained(*x);
+        }
+        theta
+    }
+
+    /// 元の座標 θ から非制約空間の z へ
+    pub(crate) fn to_unconstrained<V: Vector>(&self, theta: &V) -> V {
+        let mut z = theta.clone();
+        for (x, t) in z.as_mut_slice().iter_mut().zip(&self.transforms) {
+            *x = t.to_unconstrained(*x);
+        }
+        z
+    }
+
+    /// 座標ごとの log |dθ/dz| の和
+    pub(crate) fn log_abs_det_jacobian<V: Vector>(&self, z: &V) -> f64 {
+        self.transforms
+            .iter()
+            .zip(z.as_slice())
+            .map(|(t, z)| t.log_abs_det_jacobian(*z))
+            .sum()
+    }
+
+    /// `q` が反射境界の内側（境界を含む）にあるか
+    pub(crate) fn contains<V: Vector>(&self, q: &V) -> bool {
+        self.bounds.as_ref().is_none_or(|bounds| {
+            bounds
+                .iter()
+                .zip(q.as_slice())
+                .all(|((min, max), q)| (min..=max).contains(&q))
+        })
+    }
+
+    /// 周期境界なら `q` を基本領域に折り畳む
+    pub(crate) fn wrap<V: Vector>(&self, q: &V) -> V {
+        let mut q = q.clone();
+        if let Some(periods) = &self.periods {
+            for (q, &period) in q.as_mut_slice().iter_mut().zip(periods) {
+                *q = wrap_1d(*q, period);
+            }
+        }
+        q
+    }
+
+    /// 積分の位置更新の後に、境界で折り返してから周期境界で折り畳む
+    pub(crate) fn constrain<V: Vector>(&self, q: &mut V, p: &mut V) {
+        if let Some(bounds) = &self.bounds {
+            for ((q, p), &(min, max)) in q
+                .as_mut_slice()
+                .iter_mut()
+                .zip(p.as_mut_slice())
+                .zip(bounds)
+            {
+                reflect_1d(q, p, min, max);
+            }
+        }
+        if let Some(periods) = &self.periods {
+            for (q, &period) in q.as_mut_slice().iter_mut().zip(periods) {
+                *q = wrap_1d(*q, period);
+            }
+        }
+    }
+}
+
+fn wrap_1d(q: f64, period: f64) -> f64 {
+    let r = q.rem_euclid(period);
+    // 負のごく小さな値では丸めで r == period になりうる
+    if r < period {
+        r
+    } else {
+        0.0
+    }
+}
+
+fn reflect_1d(q: &mut f64, p: &mut f64, min: f64, max: f64) {
+    // 有限でない位置は折り返さずに残し、後段の発散判定に任せる
+    if (min..=max).contains(q) || !q.is_finite() {
+        return;
+    }
+    // 周期 2w の折り返し: [0, w) は偶数回、[w, 2w) は奇数回折り返した位置に対応する
+    let width = max - min;
+    let t = (*q - min).rem_euclid(2.0 * width);
+    if t < width {
+        *q = min + t;
+    } else {
+        *q = max - (t - width);
+        *p = -*p;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{run_hmc_with_target, validate, HmcConfig, Target};
+
+    const UNIT: BoundingBox = BoundingBox {
+        xmin: 0.0,
+        xmax: 1.0,
+        ymin: 0.0,
+        ymax: 1.0,
+    };
+
+    #[test]
+    fn reflects_multiple_crossings() {
+        for (q0, q1, flipped) in [
+            (0.5, 0.5, false),
+            (-0.25, 0.25, true),
+            (2.25, 0.25, false),
+            (-1.75, 0.25, false),
+            (3.5, 0.5, true),
+        ] {
+            let (mut q, mut p) = (Point { x: q0, y: 0.5 }, Point { x: 1.0, y: 1.0 });
+            UNIT.reflect(&mut q, &mut p);
+            assert!((q.x - q1).abs() < 1e-12, "{} -> {}", q0, q.x);
+            assert_eq!(p.x, if flipped { -1.0 } else { 1.0 }, "{}", q0);
+            assert_eq!((q.y, p.y), (0.5, 1.0));
+        }
+        assert!(UNIT.contains(&Point { x: 1.0, y: 0.0 }));
+        assert_eq!(
+            BoundingBox { xmax: 0.0, ..UNIT }.validate().unwrap_err(),
+            HmcError::InvalidBounds { min: 0.0, max: 0.0 }
+        );
+    }
+
+    #[test]
+    fn reflection_keeps_samples_inside_and_acceptance_high() {
+        // 単位正方形の上の一様分布（外では密度0）
+        let flat = || {
+            Target::from_fn_with_grad(
+                |p: &Point| if UNIT.contains(p) { 0.0 } else { f64::INFINITY },
+                |_: &Point| Point::default(),
+            )
+        };
+        let config = HmcConfig {
+            n_samples: 100_000,
+            step_size: 0.1,
+            num_steps: 10,
+            initial_pos: Point { x: 0.3, y: 0.6 },
+            seed: Some(17),
+            ..HmcConfig::default()
+        };
+        let rejecting = run_hmc_with_target(&config, flat()).unwrap();
+
+        let reflecting = HmcConfig {
+            bounds: Some(UNIT),
+            ..config
+        };
+        let result = run_hmc_with_target(&reflecting, flat()).unwrap();
+        assert!(result
+            .samples
+            .iter()
+            .all(|p| 0.0 < p.x && p.x < 1.0 && 0.0 < p.y && p.y < 1.0));
+        assert_eq!(result.n_out_of_support, 0);
+        assert!(result.acceptance_rate > 0.99, "{}", result.acceptance_rate);
+        assert!(
+            rejecting.acceptance_rate < 0.5,
+            "{}",
+            rejecting.acceptance_rate
+        );
+
+        // 一様分布: 平均 1/2・分散 1/12
+        let var = 1.0 / 12.0;
+        let z = validate::z_score_of_mean(
+            &result.samples,
+            &Point { x: 0.5, y: 0.5 },
+            &[[var, 0.0], [0.0, var]],
+        );
+        assert!(z.x.abs() < 4.0 && z.y.abs() < 4.0, "{:?}", z);
+        let cov = validate::sample_cov(&result.samples);
+        assert!((cov[0][0] - var).abs() < 0.005 && (cov[1][1] - var).abs() < 0.005);
+
+        let outside = HmcConfig {
+            initial_pos: Point { x: 1.5, y: 0.5 },
+            ..reflecting
+        };
+        assert_eq!(
+            run_hmc_with_target(&outside, flat()).unwrap_err(),
+            HmcError::OutsideBounds { x: 1.5, y: 0.5 }
+        );
+    }
+
+    #[test]
+    fn periodic_wrap_stays_in_fundamental_domain() {
+        let torus = Topology::Periodic {
+            period_x: 1.0,
+            period_y: 2.0,
+        };
+        for x in [-3.75, -1e-17, 0.0, 0.5, 1.0, 7.25] {
+            let q = torus.wrap(&Point { x, y: x });
+            assert!(
+                (0.0..1.0).contains(&q.x) && (0.0..2.0).contains(&q.y),
+                "{:?}",
+                q
+            );
+        }
+        assert_eq!(torus.wrap(&Point { x: -3.75, y: 0.5 }).x, 0.25);
+        assert_eq!(
+            Topology::Euclidean.wrap(&Point { x: -3.0, y: 9.0 }),
+            Point { x: -3.0, y: 9.0 }
+        );
+        assert_eq!(
+            Topology::Periodic {
+                period_x: 1.0,
+                period_y: 0.0
+            }
+            .validate()
+            .unwrap_err(),
+            HmcError::InvalidPeriod(0.0)
+        );
+    }
+}
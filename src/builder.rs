@@ -0,0 +1,243 @@
+use std::sync::atomic::AtomicBool;
+use std::sync::Arc;
+
+use crate::{
+    run_hmc_with_hooks, DistType, HmcConfig, HmcError, HmcResult, Integrator, Point, ProgressInfo,
+    RunHooks,
+};
+
+/// `HmcConfig` をメソッドチェーンで組み立てるビルダー
+///
+/// 未設定の項目は `HmcConfig::default()` の値になる。
+///
+/// ```
+/// use hamiltonian_sampler_rs::{Banana, DistType, HmcBuilder};
+///
+/// let result = HmcBuilder::new()
+///     .n_samples(500)
+///     .step_size(0.1)
+///     .leapfrog_steps(20)
+///     .target(DistType::Banana(Banana::default()))
+///     .build()
+///     .unwrap()
+///     .run()
+///     .unwrap();
+/// assert_eq!(result.samples.len(), 500);
+/// ```
+#[derive(Debug, Default)]
+pub struct HmcBuilder<'a> {
+    config: HmcConfig,
+    hooks: RunHooks<'a>,
+}
+
+impl<'a> HmcBuilder<'a> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn n_samples(mut self, n_samples: usize) -> Self {
+        self.config.n_samples = n_samples;
+        self
+    }
+
+    pub fn n_warmup(mut self, n_warmup: usize) -> Self {
+        self.config.n_warmup = n_warmup;
+        self
+    }
+
+    pub fn thin(mut self, thin: usize) -> Self {
+        self.config.thin = thin;
+        self
+    }
+
+    pub fn save_warmup(mut self, save_warmup: bool) -> Self {
+        self.config.save_warmup = save_warmup;
+        self
+    }
+
+    pub fn step_size(mut self, step_size: f64) -> Self {
+        self.config.step_size = step_size;
+        self
+    }
+
+    pub fn leapfrog_steps(mut self, num_steps: usize) -> Self {
+        self.config.num_steps = num_steps;
+        self
+    }
+
+    pub fn initial(mut self, initial_pos: Point) -> Self {
+        self.config.initial_pos = initial_pos;
+        self
+    }
+
+    pub fn target(mut self, target: DistType) -> Self {
+        self.config.target = target;
+        self
+    }
+
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.config.seed = Some(seed);
+        self
+    }
+
+    /// `report_every` 回の遷移ごとに `callback` を呼ぶ（0は1として扱う）
+    ///
+    /// ```
+    /// use hamiltonian_sampler_rs::HmcBuilder;
+    ///
+    /// let mut rates = Vec::new();
+    /// HmcBuilder::new()
+    ///     .n_samples(1000)
+    ///     .on_progress(100, |info| rates.push(info.acceptance_rate))
+    ///     .build()
+    ///     .unwrap()
+    ///     .run()
+    ///     .unwrap();
+    /// assert_eq!(rates.len(), 10);
+    /// ```
+    pub fn on_progress<F>(mut self, report_every: usize, callback: F) -> Self
+    where
+        F: FnMut(ProgressInfo) + 'a,
+    {
+        self.hooks.on_progress = Some((report_every.max(1), Box::new(callback)));
+        self
+    }
+
+    /// `flag` に `true` がセットされた時点でサンプリングを打ち切る
+    ///
+    /// 打ち切られた場合は、それまでのサンプルと `completed == false` の結果が返る。
+    pub fn cancel_flag(mut self, flag: Arc<AtomicBool>) -> Self {
+        self.hooks.cancel = Some(flag);
+        self
+    }
+
+    /// `config.integrator` の代わりに独自の積分器を使う（`Integrator` の例を参照）
+    pub fn integrator<I: Integrator + 'static>(mut self, integrator: I) -> Self {
+        self.hooks.integrator = Some(Arc::new(integrator));
+        self
+    }
+
+    /// 設定を検証して `Sampler` を生成する
+    pub fn build(self) -> Result<Sampler<'a>, HmcError> {
+        self.config.validate()?;
+        Ok(Sampler {
+            config: self.config,
+            hooks: self.hooks,
+        })
+    }
+}
+
+/// 検証済みの設定と実行時フックを保持するサンプラー
+#[derive(Debug)]
+pub struct Sampler<'a> {
+    config: HmcConfig,
+    hooks: RunHooks<'a>,
+}
+
+impl Sampler<'_> {
+    pub fn config(&self) -> &HmcConfig {
+        &self.config
+    }
+
+    pub fn run(&mut self) -> Result<HmcResult, HmcError> {
+        run_hmc_with_hooks(&self.config, &mut self.hooks)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Banana;
+
+    #[test]
+    fn default_builder_matches_default_config() {
+        let sampler = HmcBuilder::new().build().unwrap();
+        let default = HmcConfig::default();
+
+        assert_eq!(sampler.config().n_samples, default.n_samples);
+        assert_eq!(sampler.config().step_size, default.step_size);
+        assert_eq!(sampler.config().num_steps, default.num_steps);
+    }
+
+    #[test]
+    fn full_construction() {
+        let mut sampler = HmcBuilder::new()
+            .n_samples(300)
+            .step_size(0.05)
+            .leapfrog_steps(30)
+            .initial(Point { x: 1.0, y: -1.0 })
+            .target(DistType::Banana(Banana::default()))
+            .build()
+            .unwrap();
+
+        let config = sampler.config();
+        assert_eq!(config.n_samples, 300);
+        assert_eq!(config.step_size, 0.05);
+        assert_eq!(config.num_steps, 30);
+        assert_eq!(config.initial_pos.x, 1.0);
+        assert!(matches!(config.target, DistType::Banana(_)));
+        assert_eq!(sampler.run().unwrap().samples.len(), 300);
+    }
+
+    #[test]
+    fn progress_callback_reports_periodically() {
+        let mut reports = Vec::new();
+        let result = HmcBuilder::new()
+            .n_samples(500)
+            .n_warmup(100)
+            .seed(3)
+            .on_progress(50, |info| reports.push(info))
+            .build()
+            .unwrap()
+            .run()
+            .unwrap();
+
+        assert_eq!(reports.len(), 12);
+        assert_eq!(reports[0].iteration, 50);
+        assert_eq!(reports.last().unwrap().iteration, 600);
+        assert!(reports.iter().all(|r| r.total_iterations == 600));
+        assert_eq!(
+            &reports.last().unwrap().position,
+            result.samples.last().unwrap()
+        );
+    }
+
+    #[test]
+    fn cancellation_returns_partial_result() {
+        let flag = Arc::new(AtomicBool::new(false));
+        let setter = Arc::clone(&flag);
+        let result = HmcBuilder::new()
+            .n_samples(1_000_000)
+            .seed(8)
+            .cancel_flag(flag)
+            .on_progress(100, move |_| {
+                setter.store(true, std::sync::atomic::Ordering::Relaxed)
+            })
+            .build()
+            .unwrap()
+            .run()
+            .unwrap();
+
+        assert!(!result.completed);
+        assert_eq!(result.samples.len(), 100);
+        assert!((0.0..=1.0).contains(&result.acceptance_rate));
+        assert!(result.acceptance_rate > 0.0);
+    }
+
+    #[test]
+    fn rejects_invalid_settings() {
+        assert_eq!(
+            HmcBuilder::new().n_samples(0).build().unwrap_err(),
+            HmcError::ZeroSamples
+        );
+        assert_eq!(
+            HmcBuilder::new().step_size(-0.1).build().unwrap_err(),
+            HmcError::InvalidStepSize(-0.1)
+        );
+        assert!(HmcBuilder::new().step_size(0.0).build().is_err());
+        assert_eq!(
+            HmcBuilder::new().leapfrog_steps(0).build().unwrap_err(),
+            HmcError::ZeroLeapfrogSteps
+        );
+    }
+}
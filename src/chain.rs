@@ -0,0 +1,1940 @@
+use rand::prelude::*;
+use rand_chacha::ChaCha12Rng;
+use serde::{Deserialize, Serialize};
+use std::cell::Cell;
+use std::sync::Arc;
+
+use crate::adapt::{
+    self, in_slow_window, single_window, slow_window_ahead, AdaptationWindow, DualAveraging,
+    WarmupWindow, Welford, WelfordVariance, WindowKind,
+};
+use crate::barker;
+use crate::bounds::Space;
+use crate::ensemble;
+use crate::gradcheck::ensure_gradient;
+use crate::init::find_mode;
+use crate::integrator::{Hamiltonian, Integrator, PhasePoint, StepFailure};
+use crate::mala;
+use crate::metric::{DenseMass, MassMatrix};
+use crate::numdiff::NumDiff;
+use crate::nuts::{self, Move};
+use crate::random_walk;
+use crate::slice;
+use crate::target::{sampler_gradient, Model};
+use crate::transform::Unconstrained;
+use crate::vector;
+use crate::{
+    ratio, Algorithm, DistType, HmcConfig, HmcError, InitStrategy, Kinetic, Metric, Point,
+    TargetDistribution, Vector,
+};
+
+/// チェーンの既定RNG
+///
+/// `StdRng` と同じ ChaCha12 で、同じシードから同じ乱数列を生成する。内部状態をシリアライズできる。
+pub type ChainRng = ChaCha12Rng;
+
+/// 発散した遷移の後の扱い
+///
+/// JSONでは `"continue"`、`{"shrink_step_size": {"factor": 0.5, "max_retries": 3}}`（各値は省略可）、`"abort"`。
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum DivergencePolicy {
+    /// 提案を棄却して次の遷移に進む
+    #[default]
+    Continue,
+    /// 同じ位置・運動量から、ステップ幅に `factor` を掛けて遷移をやり直す（最大 `max_retries` 回）
+    ///
+    /// やり直すのはその遷移だけで、次の遷移は元のステップ幅に戻る。
+    /// 発散したかどうかでステップ幅を選ぶため詳細釣り合いは厳密には成り立たず、発散が稀な場合の応急処置として使う。
+    ShrinkStepSize {
+        #[serde(default = "default_shrink_factor")]
+        factor: f64,
+        #[serde(default = "default_max_retries")]
+        max_retries: usize,
+    },
+    /// サンプリング期間中に発散した時点で打ち切り、それまでの結果を `HmcError::Diverged` で返す
+    Abort,
+}
+
+fn default_shrink_factor() -> f64 {
+    0.5
+}
+
+fn default_max_retries() -> usize {
+    3
+}
+
+impl DivergencePolicy {
+    /// `ShrinkStepSize` の `factor` が (0, 1) の範囲にあるかを確かめる
+    pub fn validate(&self) -> Result<(), HmcError> {
+        match *self {
+            DivergencePolicy::ShrinkStepSize { factor, .. } if !(factor > 0.0 && factor < 1.0) => {
+                Err(HmcError::InvalidShrinkFactor(factor))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// 1回のHMC遷移の結果
+#[derive(Clone, Debug)]
+pub struct Transition<V = Point> {
+    /// 遷移後の位置（棄却時は遷移前と同じ）
+    pub position: V,
+    /// 提案が採択されたか（NUTSでは開始点以外の軌道上の点が選ばれたか）
+    pub accepted: bool,
+    /// エネルギー誤差 H_new - H_current（NUTSでは選ばれた点と開始点の差）
+    ///
+    /// ランダムウォークとMALAではMetropolis-Hastings比の対数の符号を反転したもの、スライスサンプリングでは U の差。
+    pub energy_error: f64,
+    /// エネルギー誤差が `divergence_threshold` を超えたか、H_new が有限でない（台の外に出た場合を除く）
+    pub divergent: bool,
+    /// 軌道上でNaNなどの数値の破綻が起きたため、軌道を打ち切って棄却した（`divergent` でもある）
+    ///
+    /// ポテンシャル・運動エネルギー・位置のNaNと U = -∞、U が +∞ でない点での勾配のNaNを数える。
+    /// U = +∞ の点は台の外として扱い、そこでの勾配は問わない。
+    pub numerical_error: bool,
+    /// 軌道が台の外（ポテンシャルが有限でない点）に出たため棄却された（NUTSでは軌道の延長を止めた）
+    pub out_of_support: bool,
+    /// Metropolis採択確率 min(1, exp(H_current - H_new))（エネルギー差が有限でなければ0）
+    ///
+    /// NUTSでは軌道上の全ての点についての平均。
+    pub accept_prob: f64,
+    /// 遷移後の位置のポテンシャル U(q)
+    pub potential_energy: f64,
+    /// 遷移後の状態のハミルトニアン H = U + K（採択時は提案、棄却時は遷移前の (q, p) のもの）
+    pub energy: f64,
+    /// この遷移で行った積分のステップ数
+    pub n_leapfrog: usize,
+    /// この遷移の積分で勾配を評価した回数（リープフロッグでは `n_leapfrog`、`Yoshida4` ではその3倍、
+    /// 陰的中点法では不動点反復の回数による）
+    pub n_gradient_evals: usize,
+    /// 陰的中点法の反復が収束せずに軌道を打ち切った回数（`IntegratorKind::ImplicitMidpoint` のみ）
+    pub n_solver_failures: usize,
+    /// `max_grad_norm` を超えた勾配を縮めた回数
+    pub n_gradient_clips: usize,
+    /// NUTSの木の深さ（軌道を倍にした回数。固定長のHMCでは `None`）
+    pub tree_depth: Option<usize>,
+    /// 発散したためステップ幅を縮めてやり直した回数（`DivergencePolicy::ShrinkStepSize` のみ）
+    ///
+    /// 他の統計量は最後の試行のもの（`n_leapfrog` は全試行の合計）。
+    pub n_step_size_shrinks: usize,
+    /// 実際に使ったステップ幅（固定長のHMC・NUTS・レプリカ交換法のみ。`step_size_jitter` で揺らした後の値で、
+    /// やり直した場合は最後の試行のもの）
+    pub step_size: Option<f64>,
+    /// 軌道の始点の運動量（固定長のHMC・NUTS・レプリカ交換法のみ）
+    ///
+    /// `momentum_persistence` を指定した場合は、前の遷移の運動量を部分的に引き直した後の値。
+    pub initial_momentum: Option<V>,
+    /// 各ウォーカーの更新結果（`Algorithm::Ensemble` のみ。それ以外では空）
+    ///
+    /// 上の各フィールドはウォーカー0のもの（`position` はウォーカー0の位置）。
+    pub walkers: Vec<WalkerMove<V>>,
+    /// 隣り合う温度のレプリカの組ごとに、状態を交換したか（`Algorithm::ParallelTempering` で交換を提案した遷移のみ。
+    /// それ以外では空）
+    ///
+    /// 上の各フィールドは温度1のレプリカのもので、`position` は交換後の位置。`n_leapfrog` は全レプリカの合計。
+    pub swaps: Vec<bool>,
+    /// この遷移の積分の軌道（`config.record_trajectory_every` で記録する遷移のみ）
+    pub trajectory: Option<Trajectory<V>>,
+}
+
+/// アンサンブルサンプラーの1遷移での1ウォーカーの更新結果
+#[derive(Clone, Debug)]
+pub struct WalkerMove<V = Point> {
+    /// 更新後の位置（棄却時は更新前と同じ）
+    pub position: V,
+    /// stretch move が採択されたか
+    pub accepted: bool,
+    /// 採択確率 min(1, z exp(U(x) - U(y)))
+    pub accept_prob: f64,
+    /// 更新後の位置のポテンシャル U(q)
+    pub potential_energy: f64,
+}
+
+/// 記録した1遷移分の積分の軌道（`HmcConfig::record_trajectory_every` 指定時のみ）
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Trajectory<V = Point> {
+    /// この遷移が生んだサンプルの `HmcResult::samples` での番号
+    ///
+    /// 軌道の始点はこの遷移の前の位置（`thin == 1` なら `samples[sample_index - 1]`）。
+    pub sample_index: usize,
+    /// 始点と各ステップの後の位置（L + 1 点）
+    ///
+    /// 勾配が有限でない点に達して打ち切った場合は、その前のステップまで。
+    pub positions: Vec<V>,
+    /// `positions` の各点でのハミルトニアン H = U + K
+    pub energies: Vec<f64>,
+    /// 終点の提案が採択されたか（採択なら `positions` の最後が、棄却なら最初がサンプルになる）
+    pub accepted: bool,
+}
+
+/// レプリカ交換法の温度1以外のレプリカの状態
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Replica<V = Point> {
+    /// 非制約空間での位置
+    pub position: V,
+    /// このレプリカの遷移に使うRNG（温度1のレプリカと交換の判定はチェーンのRNGを使う）
+    pub rng: ChainRng,
+}
+
+/// 1遷移ずつ進められるHMCチェーン
+///
+/// 現在位置・RNG・設定・ターゲット分布・採択数を保持する。`run_hmc` はこの `step` のループとして実装されている。
+/// 型引数 `V` は状態のベクトルで、公開しているのは2次元の [`Point`] のチェーン。`run_hmc_nd` などは
+/// 同じチェーンを `Vec<f64>` か [`PointN`](crate::PointN) の状態で回す。
+///
+/// ```
+/// use hamiltonian_sampler_rs::{Chain, HmcConfig};
+///
+/// let config = HmcConfig { seed: Some(42), ..HmcConfig::default() };
+/// let mut chain = Chain::new(config).unwrap();
+/// for _ in 0..100 {
+///     chain.step();
+/// }
+/// assert_eq!(chain.iteration(), 100);
+/// ```
+pub struct Chain<R: Rng = ChainRng, T = DistType, V = Point> {
+    config: HmcConfig,
+    target: T,
+    position: V,
+    /// `space` の変換で移した非制約空間での現在位置（変換なしなら `position` と同じ）
+    unconstrained: V,
+    /// `unconstrained` での勾配（前の遷移で求めたものを使い回す。`None` なら次の遷移で評価し直す）
+    grad: Option<V>,
+    rng: R,
+    iteration: usize,
+    n_accepted: usize,
+    init_mode: Option<V>,
+    /// 座標ごとの変換・反射境界・周期境界（2次元なら `config.transform` などから、N次元なら `_nd` の設定から作る）
+    space: Space,
+    /// 現在のステップ幅（適応しなければ `config.step_size`）
+    step_size: f64,
+    /// ウォームアップ中のステップ幅の適応状態（適応しない場合と適応を終えた後は `None`）
+    adaptation: Option<DualAveraging>,
+    /// 現在の質量行列（推定しなければ `config.metric` のもの）
+    mass: MassMatrix<V>,
+    /// ウォームアップで質量行列を推定し終えたか
+    mass_adapted: bool,
+    /// ウォームアップ中の質量行列の推定状態（推定しない場合と推定を終えた後は `None`）
+    mass_adaptation: Option<Welford>,
+    /// ウォームアップの区間の列
+    windows: Vec<WarmupWindow>,
+    /// 終えた区間ごとの適応の状態（`config.warmup_schedule` 指定時のみ）
+    adaptation_windows: Vec<AdaptationWindow>,
+    /// アンサンブルの各ウォーカーの非制約空間での位置（`Algorithm::Ensemble` 以外では空）
+    ///
+    /// ウォーカー0が `unconstrained` に対応する。
+    walkers: Vec<V>,
+    /// 各ウォーカーのポテンシャル（空なら次の遷移の前に計算し直す）
+    walker_potentials: Vec<f64>,
+    /// レプリカ交換法の温度1以外のレプリカ（温度の低い順。`Algorithm::ParallelTempering` 以外では空）
+    replicas: Vec<Replica<V>>,
+    /// 前の遷移から持ち越す運動量（`config.momentum_persistence` が正のときのみ。`None` なら次の遷移で引き直す）
+    momentum: Option<V>,
+    /// `set_integrator` で差し替えた積分器（`None` なら `config.integrator`）
+    custom_integrator: Option<Arc<dyn Integrator<V>>>,
+}
+
+/// チェーンの全状態のスナップショット
+///
+/// RNGの内部状態も含むため、復元後は中断しなかった場合と全く同じサンプル列が続く。
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ChainCheckpoint {
+    pub config: HmcConfig,
+    pub position: Point,
+    /// 非制約空間での位置（`config.transform` 指定時のみ。逆変換の丸め誤差なしに再開するため）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub unconstrained: Option<Point>,
+    pub rng: ChainRng,
+    pub iteration: usize,
+    pub n_accepted: usize,
+    /// 現在のステップ幅（`config.adapt_step_size` 指定時のみ）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub step_size: Option<f64>,
+    /// ステップ幅の適応状態（ウォームアップ中に保存した場合のみ）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub adaptation: Option<DualAveraging>,
+    /// 推定した質量行列（推定を終えた後に保存した場合のみ）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub metric: Option<Metric>,
+    /// 質量行列の推定状態（推定を終える前に保存した場合のみ）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mass_adaptation: Option<WelfordVariance>,
+    /// 終えたウォームアップの区間ごとの適応の状態（`config.warmup_schedule` 指定時のみ）
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub adaptation_windows: Vec<AdaptationWindow>,
+    /// アンサンブルの各ウォーカーの非制約空間での位置（`Algorithm::Ensemble` のみ）
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub walkers: Vec<Point>,
+    /// レプリカ交換法の温度1以外のレプリカ（`Algorithm::ParallelTempering` のみ）
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub replicas: Vec<Replica>,
+    /// 持ち越す運動量（`config.momentum_persistence` が正のときのみ）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub momentum: Option<Point>,
+}
+
+impl Chain<ChainRng> {
+    /// `config.seed` からRNGを初期化してチェーンを作る（シード未指定ならエントロピーから）
+    pub fn new(config: HmcConfig) -> Result<Self, HmcError> {
+        let rng = match config.seed {
+            Some(seed) => ChainRng::seed_from_u64(seed),
+            None => ChainRng::from_entropy(),
+        };
+        Self::with_rng(config, rng)
+    }
+
+    /// 現在の状態をチェックポイントとして保存する
+    pub fn save(&self) -> ChainCheckpoint {
+        ChainCheckpoint {
+            config: self.config.clone(),
+            position: self.position.clone(),
+            unconstrained: (!self.config.transform.is_identity())
+                .then(|| self.unconstrained.clone()),
+            rng: self.rng.clone(),
+            iteration: self.iteration,
+            n_accepted: self.n_accepted,
+            step_size: self.config.adapt_step_size.then_some(self.step_size),
+            adaptation: self.adaptation.clone(),
+            metric: self.mass_adapted.then(|| self.metric()),
+            mass_adaptation: self.mass_adaptation.as_ref().map(WelfordVariance::from),
+            adaptation_windows: self.adaptation_windows.clone(),
+            walkers: self.walkers.clone(),
+            replicas: self.replicas.clone(),
+            momentum: self.momentum.clone(),
+        }
+    }
+
+    /// チェックポイントからチェーンを復元する
+    pub fn restore(checkpoint: ChainCheckpoint) -> Result<Self, HmcError> {
+        // アンサンブルとレプリカ交換法では初期化にRNGを使うため、保存した状態で上書きする
+        let mut chain = Self::with_rng(checkpoint.config, checkpoint.rng.clone())?;
+        chain.rng = checkpoint.rng;
+        chain.unconstrained = checkpoint
+            .unconstrained
+            .unwrap_or_else(|| chain.space.to_unconstrained(&checkpoint.position));
+        chain.position = checkpoint.position;
+        chain.iteration = checkpoint.iteration;
+        chain.n_accepted = checkpoint.n_accepted;
+        if let Some(step_size) = checkpoint.step_size {
+            chain.step_size = step_size;
+        }
+        chain.adaptation = checkpoint.adaptation;
+        if let Some(metric) = &checkpoint.metric {
+            chain.mass = MassMatrix::new(metric, &chain.unconstrained)?;
+            chain.mass_adapted = true;
+        }
+        let dense = chain.config.metric == Metric::AdaptDense;
+        chain.mass_adaptation = checkpoint
+            .mass_adaptation
+            .map(|saved| Welford::from_checkpoint(saved, dense));
+        chain.adaptation_windows = checkpoint.adaptation_windows;
+        if !checkpoint.walkers.is_empty() {
+            chain.walkers = checkpoint.walkers;
+        }
+        if !checkpoint.replicas.is_empty() {
+            chain.replicas = checkpoint.replicas;
+        }
+        chain.momentum = checkpoint.momentum;
+        Ok(chain)
+    }
+}
+
+impl<R: Rng> Chain<R> {
+    /// 外部から与えたRNGでチェーンを作る（`config.seed` は無視される）
+    pub fn with_rng(config: HmcConfig, rng: R) -> Result<Self, HmcError> {
+        let target = config.target.clone();
+        Self::with_target(config, target, rng)
+    }
+}
+
+impl<R: Rng, T: TargetDistribution> Chain<R, T> {
+    /// ユーザー定義のターゲット分布でチェーンを作る（`config.target` と `config.seed` は無視される）
+    ///
+    /// `config.init` が `FindMode` なら、ここでモード探索を行ってその位置から始める。
+    pub fn with_target(config: HmcConfig, target: T, rng: R) -> Result<Self, HmcError> {
+        config.validate_for(&target)?;
+        if config.debug_check_gradient {
+            ensure_gradient(&target, config.start_position())?;
+        }
+        let start = config.start_position().clone();
+        let space = Space::planar(&config);
+        Self::build(config, target, rng, start, space).map_err(|err| match err {
+            HmcError::NonFiniteInitialStateNd {
+                position,
+                potential,
+            } => HmcError::NonFiniteInitialPotential {
+                x: position[0],
+                y: position[1],
+                potential,
+            },
+            err => err,
+        })
+    }
+
+    /// 1回の遷移を実行する（`config.algorithm` に従い固定長のHMC・NUTS・ランダムウォーク・MALA・スライス・
+    /// アンサンブル・レプリカ交換法）
+    pub fn step(&mut self) -> Transition {
+        self.transition()
+    }
+
+    /// `n` 回の遷移を進め、その間の位置を返す（採択数などの累計はチェーン側に残る）
+    ///
+    /// 続けて呼んだ結果をつなげると、同じシードで `n` の合計回だけ `step` した列と一致する。
+    /// 1回の呼び出しの仕事量が `n` で決まるので、WASMからは描画の合間に少しずつ呼べる。
+    pub fn sample_chunk(&mut self, n: usize) -> Vec<Point> {
+        let mut chunk = Vec::with_capacity(n);
+        self.sample_chunk_into(n, &mut chunk);
+        chunk
+    }
+
+    /// `sample_chunk` と同じ `n` 回の位置を `out` の末尾に足す（呼び出し側のバッファを使い回す）
+    pub fn sample_chunk_into(&mut self, n: usize, out: &mut Vec<Point>) {
+        out.reserve(n);
+        out.extend(self.by_ref().take(n));
+    }
+
+    /// `sample_chunk` と同じ `n` 回の位置を x, y の順に `out` の末尾に足す（点ごとの `Point` を経ない）
+    pub fn sample_chunk_flat_into(&mut self, n: usize, out: &mut Vec<f64>) {
+        out.reserve(2 * n);
+        for q in self.by_ref().take(n) {
+            out.extend([q.x, q.y]);
+        }
+    }
+}
+
+// `Model` はクレート内のトレイトで、このブロックのメソッドもクレート内からしか呼ばない
+#[allow(private_bounds)]
+impl<R: Rng, T: Model<V>, V: Vector> Chain<R, T, V> {
+    /// 検証済みの設定と開始位置 `start`（元の座標）からチェーンを作る
+    ///
+    /// アンサンブルのウォーカーをポテンシャルが有限の点に置けなければ `HmcError::NonFiniteInitialStateNd`。
+    pub(crate) fn build(
+        config: HmcConfig,
+        target: T,
+        mut rng: R,
+        start: V,
+        space: Space,
+    ) -> Result<Self, HmcError> {
+        let start_unconstrained = space.wrap(&space.to_unconstrained(&start));
+        let unconstrained = match (&config.resume_from, &config.init) {
+            (
+                None,
+                InitStrategy::FindMode {
+                    max_iters,
+                    learning_rate,
+                },
+            ) => space.wrap(&find_mode(
+                &start_unconstrained,
+                &Unconstrained {
+                    target: &target,
+                    space: &space,
+                    inv_temp: config.temperature.recip(),
+                },
+                *max_iters,
+                *learning_rate,
+                config.numdiff.as_ref(),
+            )?),
+            _ => start_unconstrained,
+        };
+        let init_mode = matches!(
+            (&config.resume_from, &config.init),
+            (None, InitStrategy::FindMode { .. })
+        )
+        .then(|| space.to_constrained(&unconstrained));
+        let adaptation = (config.adapt_step_size && config.n_warmup > 0)
+            .then(|| DualAveraging::new(config.step_size, config.target_accept));
+        // 区間は質量行列の推定と区間ごとの記録にしか使わない（ステップ幅だけの適応なら確保しない）
+        let windows = match config.warmup_schedule {
+            Some(schedule) => schedule.windows(config.n_warmup),
+            None if adapts_mass(&config) => single_window(config.n_warmup),
+            None => Vec::new(),
+        };
+        let dense = config.metric == Metric::AdaptDense;
+        let mass_adaptation = (adapts_mass(&config) && slow_window_ahead(&windows, 0))
+            .then(|| Welford::new(start.dim(), dense));
+        let mass = MassMatrix::new(&config.metric, &start)?;
+        let walkers = match config.algorithm {
+            Algorithm::Ensemble { n_walkers, .. } => ensemble::init_walkers(
+                &Unconstrained {
+                    target: &target,
+                    space: &space,
+                    inv_temp: 1.0,
+                },
+                &space,
+                &unconstrained,
+                n_walkers,
+                &mut rng,
+            )?,
+            _ => Vec::new(),
+        };
+        // 高温のレプリカも同じ位置から始め、それぞれチェーンのRNGから導いた独立なRNGを使う
+        let replicas = match &config.algorithm {
+            Algorithm::ParallelTempering { temperatures, .. } => (1..temperatures.len())
+                .map(|_| Replica {
+                    position: unconstrained.clone(),
+                    rng: ChainRng::seed_from_u64(rng.gen()),
+                })
+                .collect(),
+            _ => Vec::new(),
+        };
+        // 平面上で探索しなかった場合は、逆変換の丸め誤差を避けて指定された開始位置そのものを使う
+        let position = match &init_mode {
+            Some(mode) => mode.clone(),
+            None if !space.is_periodic() => start,
+            None => space.to_constrained(&unconstrained),
+        };
+        Ok(Self {
+            position,
+            unconstrained,
+            grad: None,
+            target,
+            rng,
+            iteration: 0,
+            n_accepted: 0,
+            init_mode,
+            space,
+            step_size: config.step_size,
+            adaptation,
+            mass,
+            mass_adapted: false,
+            mass_adaptation,
+            windows,
+            adaptation_windows: Vec::new(),
+            walkers,
+            walker_potentials: Vec::new(),
+            replicas,
+            momentum: None,
+            custom_integrator: None,
+            config,
+        })
+    }
+
+    /// `step` の本体（状態の型によらない）
+    pub(crate) fn transition(&mut self) -> Transition<V> {
+        // 変換を指定した場合は非制約空間でリープフロッグを行う（変換なしなら元のターゲットそのまま）。
+        // 温度で割るのはターゲットのポテンシャルだけで、ヤコビアンの項は割らない
+        let target = &Unconstrained {
+            target: &self.target,
+            space: &self.space,
+            inv_temp: self.config.temperature.recip(),
+        };
+        let mut leapfrog = Leapfrog {
+            target,
+            step_size: self.step_size,
+            mass: &self.mass,
+            kinetic: self.config.kinetic,
+            space: &self.space,
+            numdiff: self.config.numdiff.as_ref(),
+            max_grad_norm: self.config.max_grad_norm,
+            integrator: self
+                .custom_integrator
+                .as_deref()
+                .unwrap_or(&self.config.integrator),
+            n_gradient_evals: Cell::new(0),
+            n_solver_failures: Cell::new(0),
+            n_gradient_clips: Cell::new(0),
+        };
+        if self.iteration == 0 && self.config.find_step_size {
+            let uses_leapfrog = matches!(
+                self.config.algorithm,
+                Algorithm::Hmc | Algorithm::Nuts { .. } | Algorithm::ParallelTempering { .. }
+            );
+            if let (Some(adaptation), true) = (&mut self.adaptation, uses_leapfrog) {
+                let start = PhasePoint {
+                    grad: leapfrog.gradient(&self.unconstrained),
+                    q: self.unconstrained.clone(),
+                    p: self
+                        .config
+                        .kinetic
+                        .sample(&self.mass, &self.unconstrained, &mut self.rng),
+                };
+                self.step_size = adapt::reasonable_step_size(&mut leapfrog, &start);
+                *adaptation = DualAveraging::new(self.step_size, self.config.target_accept);
+                self.grad = Some(start.grad);
+            }
+        }
+        let trajectory_index = self.trajectory_index();
+        let rng = &mut self.rng;
+        let current_u = target.potential_at(&self.unconstrained);
+        let mut walker_updates = Vec::new();
+        let mut step_size = None;
+        let mut initial_momentum = None;
+        let (mut result, n_step_size_shrinks) = match &self.config.algorithm {
+            &Algorithm::RandomWalk { proposal_std } => (
+                random_walk::transition(
+                    &leapfrog,
+                    &self.unconstrained,
+                    current_u,
+                    proposal_std,
+                    rng,
+                ),
+                0,
+            ),
+            &Algorithm::Mala { step_size } => (
+                mala::transition(&leapfrog, &self.unconstrained, current_u, step_size, rng),
+                0,
+            ),
+            &Algorithm::Barker { step_size } => (
+                barker::transition(&leapfrog, &self.unconstrained, current_u, step_size, rng),
+                0,
+            ),
+            &Algorithm::Slice {
+                initial_width,
+                max_step_out,
+            } => (
+                slice::transition(
+                    &leapfrog,
+                    &self.unconstrained,
+                    current_u,
+                    initial_width,
+                    max_step_out,
+                    rng,
+                ),
+                0,
+            ),
+            &Algorithm::Ensemble { a, .. } => {
+                if self.walker_potentials.len() != self.walkers.len() {
+                    self.walker_potentials = ensemble::potentials(&leapfrog, &self.walkers);
+                }
+                let (result, updates) = ensemble::sweep(
+                    &leapfrog,
+                    &mut self.walkers,
+                    &mut self.walker_potentials,
+                    a,
+                    rng,
+                );
+                walker_updates = updates;
+                (result, 0)
+            }
+            algorithm => {
+                // ステップ幅を揺らす（幅0なら乱数を引かず、揺らさない場合と同じ乱数列になる）
+                let jitter = self.config.step_size_jitter;
+                if jitter > 0.0 {
+                    leapfrog.step_size *= 1.0 + jitter * (2.0 * rng.gen::<f64>() - 1.0);
+                }
+                // 固定長の軌道の長さを {1, ..., num_steps} から一様に引く（NUTSでは使わない）
+                let num_steps = if self.config.randomize_steps
+                    && !matches!(algorithm, Algorithm::Nuts { .. })
+                {
+                    rng.gen_range(1..=self.config.num_steps)
+                } else {
+                    self.config.num_steps
+                };
+                // 1. 運動量のサンプリング p ~ exp(-K(p))（持ち越した運動量があれば部分的に更新する）
+                let alpha = self.config.momentum_persistence;
+                let fresh = self
+                    .config
+                    .kinetic
+                    .sample(&self.mass, &self.unconstrained, rng);
+                let current_p = match &self.momentum {
+                    Some(p) => {
+                        let beta = (1.0 - alpha * alpha).sqrt();
+                        vector::zip_with(p, &fresh, |p, fresh| alpha * p + beta * fresh)
+                    }
+                    None => fresh,
+                };
+                // 開始点の勾配は前の遷移で求めていれば使い回す（採択なら軌道の終点、棄却なら前の開始点のもの）
+                let grad = self
+                    .grad
+                    .take()
+                    .unwrap_or_else(|| leapfrog.gradient(&self.unconstrained));
+                self.grad = Some(grad.clone());
+                let start = PhasePoint {
+                    grad,
+                    q: self.unconstrained.clone(),
+                    p: current_p,
+                };
+                initial_momentum = Some(start.p.clone());
+
+                let threshold = self.config.divergence_threshold;
+                let transition = |leapfrog: &Leapfrog<'_, _, V>, rng: &mut R| match algorithm {
+                    &Algorithm::Nuts { max_depth } => nuts::transition(
+                        leapfrog,
+                        start.clone(),
+                        current_u,
+                        max_depth,
+                        threshold,
+                        rng,
+                    ),
+                    _ => static_transition(
+                        leapfrog,
+                        start.clone(),
+                        current_u,
+                        num_steps,
+                        threshold,
+                        trajectory_index.is_some(),
+                        rng,
+                    ),
+                };
+                let mut result = transition(&leapfrog, rng);
+                let mut n_step_size_shrinks = 0;
+                if let DivergencePolicy::ShrinkStepSize {
+                    factor,
+                    max_retries,
+                } = self.config.on_divergence
+                {
+                    while result.divergent && n_step_size_shrinks < max_retries {
+                        n_step_size_shrinks += 1;
+                        leapfrog.step_size *= factor;
+                        let n_leapfrog = result.n_leapfrog;
+                        result = transition(&leapfrog, rng);
+                        result.n_leapfrog += n_leapfrog;
+                    }
+                }
+                step_size = Some(leapfrog.step_size);
+                if alpha > 0.0 {
+                    self.momentum = result.momentum.take();
+                }
+                (result, n_step_size_shrinks)
+            }
+        };
+        let mut n_gradient_evals = leapfrog.n_gradient_evals.get();
+        let mut n_solver_failures = leapfrog.n_solver_failures.get();
+        let mut n_gradient_clips = leapfrog.n_gradient_clips.get();
+
+        let accepted = result.to.is_some();
+        let trajectory = trajectory_index
+            .zip(result.trajectory.take())
+            .map(|(index, points)| {
+                let (positions, energies) = points
+                    .into_iter()
+                    .map(|(q, h)| (self.space.to_constrained(&q), h))
+                    .unzip();
+                Trajectory {
+                    sample_index: index,
+                    positions,
+                    energies,
+                    accepted,
+                }
+            });
+        if let Some(q) = result.to.take() {
+            self.position = self.space.to_constrained(&q);
+            self.unconstrained = q;
+            self.grad = result.grad.take();
+            self.n_accepted += 1;
+        }
+
+        let mut swaps = Vec::new();
+        if let Algorithm::ParallelTempering {
+            temperatures,
+            swap_every,
+        } = &self.config.algorithm
+        {
+            // 高温のレプリカを固定長のHMCで1回ずつ進める（各レプリカのRNGは独立）
+            for (replica, &temperature) in self.replicas.iter_mut().zip(&temperatures[1..]) {
+                let hot = &Unconstrained {
+                    target: &self.target,
+                    space: &self.space,
+                    inv_temp: (self.config.temperature * temperature).recip(),
+                };
+                let jitter = self.config.step_size_jitter;
+                let leapfrog = Leapfrog {
+                    target: hot,
+                    step_size: if jitter > 0.0 {
+                        self.step_size * (1.0 + jitter * (2.0 * replica.rng.gen::<f64>() - 1.0))
+                    } else {
+                        self.step_size
+                    },
+                    mass: &self.mass,
+                    kinetic: self.config.kinetic,
+                    space: &self.space,
+                    numdiff: self.config.numdiff.as_ref(),
+                    max_grad_norm: self.config.max_grad_norm,
+                    integrator: self
+                        .custom_integrator
+                        .as_deref()
+                        .unwrap_or(&self.config.integrator),
+                    n_gradient_evals: Cell::new(0),
+                    n_solver_failures: Cell::new(0),
+                    n_gradient_clips: Cell::new(0),
+                };
+                let num_steps = if self.config.randomize_steps {
+                    replica.rng.gen_range(1..=self.config.num_steps)
+                } else {
+                    self.config.num_steps
+                };
+                let start = PhasePoint {
+                    grad: leapfrog.gradient(&replica.position),
+                    q: replica.position.clone(),
+                    p: self
+                        .config
+                        .kinetic
+                        .sample(&self.mass, &replica.position, &mut replica.rng),
+                };
+                let moved = static_transition(
+                    &leapfrog,
+                    start,
+                    hot.potential_at(&replica.position),
+                    num_steps,
+                    self.config.divergence_threshold,
+                    false,
+                    &mut replica.rng,
+                );
+                result.n_leapfrog += moved.n_leapfrog;
+                n_gradient_evals += leapfrog.n_gradient_evals.get();
+                n_solver_failures += leapfrog.n_solver_failures.get();
+                n_gradient_clips += leapfrog.n_gradient_clips.get();
+                if let Some(q) = moved.to {
+                    replica.position = q;
+                }
+            }
+
+            if (self.iteration + 1).is_multiple_of(*swap_every) {
+                // 隣り合う組 (k, k+1) を低温側から順に、確率
+                // min(1, exp((β_k - β_{k+1})(U(x_k) - U(x_{k+1})))) で交換する。
+                // U は温度で割る前のターゲットのポテンシャル（ヤコビアンの項は両側で打ち消し合う）
+                let mut positions: Vec<V> = std::iter::once(self.unconstrained.clone())
+                    .chain(self.replicas.iter().map(|replica| replica.position.clone()))
+                    .collect();
+                let mut potentials: Vec<f64> = positions
+                    .iter()
+                    .map(|q| self.target.potential_at(&self.space.to_constrained(q)))
+                    .collect();
+                let inv_temp = |t: f64| (self.config.temperature * t).recip();
+                for k in 0..positions.len() - 1 {
+                    let log_ratio = (inv_temp(temperatures[k]) - inv_temp(temperatures[k + 1]))
+                        * (potentials[k] - potentials[k + 1]);
+                    let swapped = self.rng.gen::<f64>() < log_ratio.exp();
+                    if swapped {
+                        positions.swap(k, k + 1);
+                        potentials.swap(k, k + 1);
+                    }
+                    swaps.push(swapped);
+                }
+                let mut positions = positions.into_iter();
+                let cold = positions.next().unwrap();
+                for (replica, q) in self.replicas.iter_mut().zip(positions) {
+                    replica.position = q;
+                }
+                if cold.as_slice() != self.unconstrained.as_slice() {
+                    let new_u = target.potential_at(&cold);
+                    result.energy += new_u - result.potential_energy;
+                    result.potential_energy = new_u;
+                    self.position = self.space.to_constrained(&cold);
+                    self.unconstrained = cold;
+                    self.grad = None;
+                }
+            }
+        }
+        self.iteration += 1;
+        if let Some(adaptation) = &mut self.adaptation {
+            self.step_size = adaptation.update(result.accept_prob);
+            if self.iteration >= self.config.n_warmup {
+                // ウォームアップが終わったら平均値に固定し、以後は適応しない
+                self.step_size = adaptation.final_step_size();
+                self.adaptation = None;
+            }
+        }
+        let iteration = self.iteration;
+        let ended = self.windows.iter().find(|w| w.end == iteration).copied();
+        let window_step_size = self
+            .adaptation
+            .as_ref()
+            .map_or(self.step_size, DualAveraging::final_step_size);
+        if let Some(welford) = &mut self.mass_adaptation {
+            if in_slow_window(&self.windows, iteration) {
+                welford.add(self.unconstrained.as_slice());
+            }
+            if ended.is_some_and(|w| w.kind == WindowKind::Slow) {
+                let regularization = self.config.mass_regularization;
+                let dense = self.config.metric == Metric::AdaptDense;
+                let estimate = if dense {
+                    welford
+                        .regularized_covariance(regularization)
+                        .and_then(|cov| DenseMass::from_covariance(&cov).ok())
+                        .map(MassMatrix::Dense)
+                } else {
+                    welford.regularized_variance(regularization).map(|var| {
+                        MassMatrix::Diagonal(vector::from_slice(&self.unconstrained, &var))
+                    })
+                };
+                if let Some(mass) = estimate {
+                    self.mass = mass;
+                    self.mass_adapted = true;
+                    // 古い質量行列の下での運動量は持ち越さない
+                    self.momentum = None;
+                    // 新しい質量行列に合わせて、残りのウォームアップでステップ幅を適応し直す
+                    if let Some(adaptation) = &mut self.adaptation {
+                        *adaptation = DualAveraging::new(self.step_size, self.config.target_accept);
+                    }
+                }
+                // 後に遅い区間が残っていれば、その区間の点だけで推定し直す
+                self.mass_adaptation = slow_window_ahead(&self.windows, iteration)
+                    .then(|| Welford::new(self.unconstrained.dim(), dense));
+            }
+        }
+        if let (Some(_), Some(window)) = (self.config.warmup_schedule, ended) {
+            self.adaptation_windows.push(AdaptationWindow {
+                kind: window.kind,
+                start: window.start,
+                end: window.end,
+                step_size: window_step_size,
+                metric: self.metric(),
+            });
+        }
+
+        Transition {
+            position: self.position.clone(),
+            accepted,
+            energy_error: result.energy_error,
+            divergent: result.divergent,
+            numerical_error: result.numerical_error,
+            out_of_support: result.out_of_support,
+            accept_prob: result.accept_prob,
+            potential_energy: result.potential_energy,
+            energy: result.energy,
+            n_leapfrog: result.n_leapfrog,
+            n_gradient_evals,
+            n_solver_failures,
+            n_gradient_clips,
+            tree_depth: result.tree_depth,
+            n_step_size_shrinks,
+            step_size,
+            initial_momentum,
+            walkers: walker_updates
+                .into_iter()
+                .map(|update| WalkerMove {
+                    position: self.space.to_constrained(&update.position),
+                    accepted: update.accepted,
+                    accept_prob: update.accept_prob,
+                    potential_energy: update.potential_energy,
+                })
+                .collect(),
+            swaps,
+            trajectory,
+        }
+    }
+
+    /// この遷移の軌道を記録するなら、それが生むサンプルの番号
+    ///
+    /// サンプリング期間の遷移のうち、保存するサンプルを生み、その番号が `record_trajectory_every` の倍数のもの。
+    fn trajectory_index(&self) -> Option<usize> {
+        let every = self.config.record_trajectory_every?;
+        let k = self.iteration.checked_sub(self.config.n_warmup)? + 1;
+        if !k.is_multiple_of(self.config.thin) {
+            return None;
+        }
+        let index = k / self.config.thin - 1;
+        index.is_multiple_of(every).then_some(index)
+    }
+}
+
+impl<R: Rng, T, V: Vector> Chain<R, T, V> {
+    pub fn config(&self) -> &HmcConfig {
+        &self.config
+    }
+
+    pub fn target(&self) -> &T {
+        &self.target
+    }
+
+    /// 現在位置
+    ///
+    /// `Iterator::position` と衝突しないよう `current_position` という名前にしている。
+    pub fn current_position(&self) -> &V {
+        &self.position
+    }
+
+    /// `InitStrategy::FindMode` で見つけた開始位置
+    pub fn init_mode(&self) -> Option<&V> {
+        self.init_mode.as_ref()
+    }
+
+    /// これまでに実行した遷移の回数
+    pub fn iteration(&self) -> usize {
+        self.iteration
+    }
+
+    /// これまでに採択された遷移の回数
+    pub fn n_accepted(&self) -> usize {
+        self.n_accepted
+    }
+
+    /// 次の遷移に使うステップ幅
+    ///
+    /// `adapt_step_size` 指定時はウォームアップ中に遷移ごとに変わり、
+    /// `n_warmup` 回目の遷移の後に適応の平均値に固定される。
+    pub fn step_size(&self) -> f64 {
+        self.step_size
+    }
+
+    /// 以後の遷移で使う積分器を独自の実装に差し替える（`config.integrator` より優先）
+    ///
+    /// 積分器はチェックポイントに含まれないので、`restore` した後にもう一度設定する。
+    pub fn set_integrator(&mut self, integrator: Arc<dyn Integrator<V>>) {
+        self.custom_integrator = Some(integrator);
+    }
+
+    /// 以後の遷移のステップ幅を変える（焼きなまし用。適応中なら次の遷移で上書きされる）
+    pub(crate) fn set_step_size(&mut self, step_size: f64) {
+        self.step_size = step_size;
+    }
+
+    /// 以後の遷移でターゲットのポテンシャルを割る温度を変える（焼きなまし用）
+    pub(crate) fn set_temperature(&mut self, temperature: f64) {
+        self.config.temperature = temperature;
+        self.grad = None;
+    }
+
+    /// 次の遷移に使う質量行列
+    ///
+    /// 推定しなければ `config.metric` そのもの。`adapt_mass_matrix` か `Metric::AdaptDense` 指定時は、
+    /// 推定に使うウォームアップの区間が終わった時点で推定値（`Diagonal` か `Dense`）に替わる。
+    pub fn metric(&self) -> Metric {
+        if self.mass_adapted {
+            self.mass.to_metric()
+        } else {
+            self.config.metric.clone()
+        }
+    }
+
+    /// 終えたウォームアップの区間ごとのステップ幅と質量行列（`config.warmup_schedule` 指定時のみ）
+    pub fn adaptation_windows(&self) -> &[AdaptationWindow] {
+        &self.adaptation_windows
+    }
+
+    /// これまでの全遷移に対する採択率（未実行なら0）
+    pub fn acceptance_rate(&self) -> f64 {
+        ratio(self.n_accepted, self.iteration)
+    }
+
+    /// 現在のアンサンブルの各ウォーカーの位置（`Algorithm::Ensemble` 以外では空）
+    pub fn walkers(&self) -> Vec<V> {
+        self.walkers
+            .iter()
+            .map(|q| self.space.to_constrained(q))
+            .collect()
+    }
+
+    /// レプリカ交換法の温度1以外のレプリカの現在位置（温度の低い順。`Algorithm::ParallelTempering` 以外では空）
+    pub fn replica_positions(&self) -> Vec<V> {
+        self.replicas
+            .iter()
+            .map(|replica| self.space.to_constrained(&replica.position))
+            .collect()
+    }
+}
+
+/// ウォームアップ中に質量行列を推定する設定か
+fn adapts_mass(config: &HmcConfig) -> bool {
+    match config.metric {
+        Metric::UnitE | Metric::Diagonal(_) => config.adapt_mass_matrix,
+        Metric::Dense(_) => false,
+        Metric::AdaptDense => true,
+    }
+}
+
+/// ハミルトン力学系とその積分器（境界での反射と周期境界での折り畳みを含む）
+pub(crate) struct Leapfrog<'a, T: ?Sized, V = Point> {
+    pub(crate) target: &'a T,
+    pub(crate) step_size: f64,
+    pub(crate) mass: &'a MassMatrix<V>,
+    pub(crate) kinetic: Kinetic,
+    pub(crate) space: &'a Space,
+    pub(crate) numdiff: Option<&'a NumDiff>,
+    pub(crate) max_grad_norm: Option<f64>,
+    pub(crate) integrator: &'a dyn Integrator<V>,
+    /// 積分で勾配を評価した回数
+    pub(crate) n_gradient_evals: Cell<usize>,
+    /// 陰的中点法の反復が収束しなかったステップの数
+    pub(crate) n_solver_failures: Cell<usize>,
+    /// `max_grad_norm` を超えた勾配を縮めた回数
+    pub(crate) n_gradient_clips: Cell<usize>,
+}
+
+impl<T: Model<V> + ?Sized, V: Vector> Leapfrog<'_, T, V> {
+    /// 積分に使う勾配（ノルムが `max_grad_norm` を超えれば向きを保ったまま縮める）
+    ///
+    /// ノルムが無限大・NaNの勾配はそのまま返し、発散として扱わせる。
+    pub(crate) fn gradient(&self, q: &V) -> V {
+        let g = sampler_gradient(self.target, q, self.numdiff);
+        let Some(cap) = self.max_grad_norm else {
+            return g;
+        };
+        let norm = vector::norm(&g);
+        if norm.is_finite() && norm > cap {
+            self.n_gradient_clips.set(self.n_gradient_clips.get() + 1);
+            let scale = cap / norm;
+            vector::map(&g, |g| g * scale)
+        } else {
+            g
+        }
+    }
+
+    /// 運動エネルギー K(p)
+    pub(crate) fn kinetic(&self, p: &V) -> f64 {
+        self.kinetic.energy(self.mass, p)
+    }
+
+    /// 速度 dK/dp
+    pub(crate) fn velocity(&self, p: &V) -> V {
+        self.kinetic.velocity(self.mass, p)
+    }
+
+    /// `z` を `integrator` で1ステップ進める（`direction` が -1 なら時間を逆向きに）
+    ///
+    /// 反復が収束しなかったステップは `n_solver_failures` に数える。
+    pub(crate) fn step(&self, z: &mut PhasePoint<V>, direction: f64) -> Result<(), StepFailure> {
+        let result = self.integrator.step(self, z, direction * self.step_size);
+        if result == Err(StepFailure::NotConverged) {
+            self.n_solver_failures.set(self.n_solver_failures.get() + 1);
+        }
+        result
+    }
+}
+
+/// 積分の到達点 `z`（ポテンシャル `u`、運動エネルギー `k`）で数値が破綻しているか
+///
+/// 無限大は台の外や発散として別に扱うので、NaNと U = -∞ だけを数える。
+/// U = +∞ の点は台の外なので、そこで勾配がNaNになっても破綻とはみなさない。
+pub(crate) fn is_numerical_error<V: Vector>(u: f64, k: f64, z: &PhasePoint<V>) -> bool {
+    let nan_grad = z.grad.as_slice().iter().any(|g| g.is_nan());
+    u.is_nan()
+        || u == f64::NEG_INFINITY
+        || k.is_nan()
+        || z.q.as_slice().iter().any(|q| q.is_nan())
+        || (nan_grad && u != f64::INFINITY)
+}
+
+/// 積分器に渡す側の勾配は、評価のたびに `n_gradient_evals` に数える
+impl<T: Model<V> + ?Sized, V: Vector> Hamiltonian<V> for Leapfrog<'_, T, V> {
+    fn gradient(&self, q: &V) -> V {
+        self.n_gradient_evals.set(self.n_gradient_evals.get() + 1);
+        Leapfrog::gradient(self, q)
+    }
+
+    fn velocity(&self, p: &V) -> V {
+        self.kinetic.velocity(self.mass, p)
+    }
+
+    fn constrain(&self, q: &mut V, p: &mut V) {
+        self.space.constrain(q, p);
+    }
+}
+
+/// 固定長 `num_steps` のリープフロッグで提案し、Metropolis判定する
+fn static_transition<T: Model<V> + ?Sized, V: Vector, R: Rng>(
+    leapfrog: &Leapfrog<'_, T, V>,
+    start: PhasePoint<V>,
+    current_u: f64,
+    num_steps: usize,
+    divergence_threshold: f64,
+    record: bool,
+    rng: &mut R,
+) -> Move<V> {
+    // ハミルトニアンの計算 H = U + K
+    let current_k = leapfrog.kinetic(&start.p);
+    let current_h = current_u + current_k;
+    // 記録する場合は各ステップの後の H のためにポテンシャルを余分に評価する
+    let mut trajectory = record.then(|| vec![(start.q.clone(), current_h)]);
+
+    // 2. リープフロッグ積分
+    let start_p = start.p.clone();
+    let mut z = start;
+    let mut truncated = false;
+    let mut solver_failed = false;
+    let mut n_leapfrog = 0;
+    for _ in 0..num_steps {
+        n_leapfrog += 1;
+        if let Err(failure) = leapfrog.step(&mut z, 1.0) {
+            // 有限でない勾配で運動量を壊さないよう、その場で軌道を打ち切る
+            truncated = true;
+            solver_failed = failure == StepFailure::NotConverged;
+            break;
+        }
+        if let Some(trajectory) = &mut trajectory {
+            let h = leapfrog.target.potential_at(&z.q) + leapfrog.kinetic(&z.p);
+            trajectory.push((z.q.clone(), h));
+        }
+    }
+
+    // 3. Metropolis Accept/Reject
+    let new_u = if solver_failed {
+        f64::NAN
+    } else {
+        leapfrog.target.potential_at(&z.q)
+    };
+
+    // 軌道を打ち切ったか終点で U が有限でない場合、提案の密度は0（H_new = +∞）。
+    // NaNが現れたら数値の破綻として発散に数える。そうでなければ、
+    // 運動エネルギーの増加が閾値以内なら台の外（ポテンシャルか勾配が有限でない点）に出た、
+    // そうでなければ発散して数値が溢れたとみなす
+    // 陰的中点法の反復が収束せずに打ち切った場合は、発散とも台の外とも数えずに棄却する
+    let new_k = leapfrog.kinetic(&z.p);
+    let numerical_error = !solver_failed && is_numerical_error(new_u, new_k, &z);
+    let (new_h, out_of_support) = if numerical_error {
+        (f64::INFINITY, false)
+    } else if new_u.is_finite() && !truncated {
+        (new_u + new_k, false)
+    } else if solver_failed {
+        (f64::INFINITY, false)
+    } else {
+        let blew_up = !(vector::is_finite(&z.q) && new_k.is_finite())
+            || new_k - current_k > divergence_threshold;
+        (f64::INFINITY, !blew_up)
+    };
+
+    // 判定
+    // H_new が無限大（NaN含む）になった場合は、確率0として扱う
+    let diff = current_h - new_h;
+    let accept_prob = if diff.is_finite() {
+        diff.exp().min(1.0)
+    } else {
+        0.0
+    };
+
+    let accepted = rng.gen::<f64>() < accept_prob;
+    let (potential_energy, energy) = if accepted {
+        (new_u, new_h)
+    } else {
+        (current_u, current_h)
+    };
+
+    Move {
+        to: accepted.then_some(z.q),
+        energy_error: -diff,
+        divergent: !out_of_support
+            && !solver_failed
+            && (!new_h.is_finite() || -diff > divergence_threshold),
+        numerical_error,
+        out_of_support,
+        accept_prob,
+        potential_energy,
+        energy,
+        n_leapfrog,
+        tree_depth: None,
+        grad: accepted.then_some(z.grad),
+        trajectory,
+        momentum: Some(if accepted {
+            z.p
+        } else {
+            vector::map(&start_p, |p| -p)
+        }),
+    }
+}
+
+/// 1回の `next()` が1回のHMC遷移に対応する無限イテレータ
+///
+/// 棄却時は同じ位置が繰り返し返される。採択率などの統計はイテレーション後もチェーン側で参照できる。
+///
+/// ```
+/// use hamiltonian_sampler_rs::{Banana, Chain, DistType, HmcConfig, Point};
+///
+/// let config = HmcConfig {
+///     target: DistType::Banana(Banana::default()),
+///     seed: Some(1),
+///     ..HmcConfig::default()
+/// };
+/// let mut chain = Chain::new(config).unwrap();
+/// let samples: Vec<Point> = chain.by_ref().take(500).collect();
+///
+/// assert_eq!(samples.len(), 500);
+/// assert!(chain.acceptance_rate() > 0.0);
+/// ```
+impl<R: Rng, T: TargetDistribution> Iterator for Chain<R, T> {
+    type Item = Point;
+
+    fn next(&mut self) -> Option<Point> {
+        Some(self.step().position)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{run_hmc, run_hmc_with_target, validate, Target, Transform, Transforms};
+
+    #[test]
+    fn stepping_matches_batch_api() {
+        let config = HmcConfig {
+            n_samples: 250,
+            seed: Some(21),
+            ..HmcConfig::default()
+        };
+        let batch = run_hmc(&config).unwrap();
+
+        let mut chain = Chain::new(config).unwrap();
+        let mut stepped = Vec::new();
+        for _ in 0..100 {
+            stepped.push(chain.step().position);
+        }
+        // 途中で状態を確認してから続行できる
+        assert_eq!(chain.iteration(), 100);
+        assert_eq!(chain.current_position(), &stepped[99]);
+        for _ in 0..150 {
+            stepped.push(chain.step().position);
+        }
+
+        assert_eq!(stepped, batch.samples);
+        assert_eq!(chain.acceptance_rate(), batch.acceptance_rate);
+    }
+
+    #[test]
+    fn iterator_adapters() {
+        let config = HmcConfig {
+            n_samples: 300,
+            seed: Some(4),
+            ..HmcConfig::default()
+        };
+        let batch = run_hmc(&config).unwrap();
+
+        let mut chain = Chain::new(config).unwrap();
+        let positive: Vec<Point> = chain.by_ref().take(300).filter(|p| p.x > 0.0).collect();
+        let expected: Vec<Point> = batch.samples.into_iter().filter(|p| p.x > 0.0).collect();
+
+        assert_eq!(positive, expected);
+        assert_eq!(chain.iteration(), 300);
+        assert_eq!(chain.acceptance_rate(), batch.acceptance_rate);
+    }
+
+    #[test]
+    fn chunks_concatenate_to_the_batch_samples() {
+        let config = HmcConfig {
+            n_samples: 250,
+            n_warmup: 100,
+            adapt_step_size: true,
+            seed: Some(94),
+            ..HmcConfig::default()
+        };
+        let batch = run_hmc(&config).unwrap();
+
+        let mut chain = Chain::new(config.clone()).unwrap();
+        let warmup = chain.sample_chunk(100);
+        assert_eq!(warmup.len(), 100);
+        let mut chunked = Vec::new();
+        for n in [1, 0, 64, 100, 85] {
+            let chunk = chain.sample_chunk(n);
+            assert_eq!(chunk.len(), n);
+            chunked.extend(chunk);
+        }
+
+        assert_eq!(chunked, batch.samples);
+        assert_eq!(chain.iteration(), 350);
+        assert_eq!(chain.step_size(), batch.adapted_step_size.unwrap());
+
+        // バッファに足していく版も同じ列になる
+        let config = HmcConfig {
+            n_warmup: 0,
+            ..config
+        };
+        let single = run_hmc(&config).unwrap();
+        let mut chain = Chain::new(config.clone()).unwrap();
+        let mut buffer = Vec::new();
+        chain.sample_chunk_into(120, &mut buffer);
+        chain.sample_chunk_into(130, &mut buffer);
+        assert_eq!(buffer, single.samples);
+        assert_eq!(chain.acceptance_rate(), single.acceptance_rate);
+
+        // 平坦なバッファに足す版は、空にして使い回しても確保し直さない
+        let mut chain = Chain::new(config).unwrap();
+        let mut flat = Vec::new();
+        chain.sample_chunk_flat_into(125, &mut flat);
+        assert_eq!(flat, Point::flatten(&single.samples[..125]));
+        let capacity = flat.capacity();
+        flat.clear();
+        chain.sample_chunk_flat_into(125, &mut flat);
+        assert_eq!(flat, Point::flatten(&single.samples[125..]));
+        assert_eq!(flat.capacity(), capacity);
+    }
+
+    #[test]
+    fn checkpoint_round_trip_through_json() {
+        // f64 の JSON の読み戻しが1 ULPでもずれると続きのサンプルが変わるので、多くのシードと保存位置で確かめる
+        for seed in 0..50 {
+            for save_at in [1, 150, 299] {
+                checkpoint_round_trip_through_json_from(seed, save_at);
+            }
+        }
+    }
+
+    fn checkpoint_round_trip_through_json_from(seed: u64, save_at: usize) {
+        let plain = HmcConfig {
+            seed: Some(seed),
+            ..HmcConfig::default()
+        };
+        let transformed = HmcConfig {
+            initial_pos: Point { x: 1.0, y: 0.0 },
+            transform: Transforms {
+                x: Transform::Log,
+                y: Transform::Logit {
+                    lower: -4.0,
+                    upper: 4.0,
+                },
+            },
+            ..plain.clone()
+        };
+        // ウォームアップの途中で保存した場合はステップ幅と質量行列の適応状態も引き継ぐ
+        let adapting = HmcConfig {
+            n_warmup: 300,
+            adapt_step_size: true,
+            adapt_mass_matrix: true,
+            ..plain.clone()
+        };
+        let adapting_dense = HmcConfig {
+            metric: Metric::AdaptDense,
+            ..adapting.clone()
+        };
+        for config in [plain, transformed, adapting, adapting_dense] {
+            let mut uninterrupted = Chain::new(config.clone()).unwrap();
+            let expected: Vec<Point> = uninterrupted.by_ref().take(400).collect();
+
+            let (mut samples, json) = {
+                let mut chain = Chain::new(config).unwrap();
+                let samples: Vec<Point> = chain.by_ref().take(save_at).collect();
+                (samples, serde_json::to_string(&chain.save()).unwrap())
+            };
+
+            let checkpoint: ChainCheckpoint = serde_json::from_str(&json).unwrap();
+            let mut restored = Chain::restore(checkpoint).unwrap();
+            assert_eq!(restored.iteration(), save_at);
+            samples.extend(restored.by_ref().take(400 - save_at));
+
+            assert_eq!(samples, expected, "seed {} saved at {}", seed, save_at);
+            assert_eq!(restored.n_accepted(), uninterrupted.n_accepted());
+            assert_eq!(restored.step_size(), uninterrupted.step_size());
+            assert_eq!(restored.metric(), uninterrupted.metric());
+        }
+    }
+
+    #[test]
+    fn config_numdiff_applies_only_to_numeric_targets() {
+        use crate::numdiff::{DiffScheme, NumDiff};
+        use crate::Target;
+        use std::cell::Cell;
+
+        let evals = Cell::new(0);
+        let u = |p: &Point| {
+            evals.set(evals.get() + 1);
+            0.5 * (p.x * p.x + p.y * p.y)
+        };
+        let config = HmcConfig {
+            num_steps: 1,
+            seed: Some(1),
+            numdiff: Some(NumDiff {
+                scheme: DiffScheme::Forward,
+                ..NumDiff::default()
+            }),
+            ..HmcConfig::default()
+        };
+        let count_step = |target: &dyn TargetDistribution| {
+            let mut chain =
+                Chain::with_target(config.clone(), target, ChainRng::seed_from_u64(1)).unwrap();
+            evals.set(0);
+            chain.step();
+            evals.get()
+        };
+
+        // U(q) と U(q_new) の2回 + 勾配2回分（前進差分は1回あたり3評価）
+        assert_eq!(count_step(&Target::from_fn(u)), 2 + 2 * 3);
+        // 解析的な勾配があれば数値微分は使わない
+        let analytic = Target::from_fn_with_grad(u, |p: &Point| p.clone());
+        assert_eq!(count_step(&analytic), 2);
+    }
+
+    #[test]
+    fn gradient_clipping_keeps_standard_normal_moments() {
+        // 標準正規分布の勾配のノルムは |q| なので、0.3 で切ればほとんどの勾配が縮められる
+        for algorithm in [Algorithm::Hmc, Algorithm::Nuts { max_depth: 10 }] {
+            let config = HmcConfig {
+                n_samples: 4000,
+                n_warmup: 500,
+                step_size: 0.5,
+                num_steps: 8,
+                algorithm: algorithm.clone(),
+                target: DistType::Normal,
+                max_grad_norm: Some(0.3),
+                seed: Some(78),
+                ..HmcConfig::default()
+            };
+            let result = run_hmc(&config).unwrap();
+            assert!(
+                result.n_gradient_clips > result.n_gradient_evals / 2,
+                "{:?}: {} of {}",
+                algorithm,
+                result.n_gradient_clips,
+                result.n_gradient_evals
+            );
+            let z = validate::z_score_of_mean(
+                &result.samples,
+                &Point::default(),
+                &[[1.0, 0.0], [0.0, 1.0]],
+            );
+            let cov = validate::sample_cov(&result.samples);
+            assert!(
+                z.x.abs() < 4.0 && z.y.abs() < 4.0,
+                "{:?}: {:?}",
+                algorithm,
+                z
+            );
+            assert!(
+                (cov[0][0] - 1.0).abs() < 0.15
+                    && (cov[1][1] - 1.0).abs() < 0.15
+                    && cov[0][1].abs() < 0.1,
+                "{:?}: {:?}",
+                algorithm,
+                cov
+            );
+
+            // 上限を指定しなければ縮めない
+            let unclipped = run_hmc(&HmcConfig {
+                max_grad_norm: None,
+                ..config
+            })
+            .unwrap();
+            assert_eq!(unclipped.n_gradient_clips, 0);
+        }
+
+        for bad in [0.0, -1.0, f64::INFINITY, f64::NAN] {
+            let config = HmcConfig {
+                max_grad_norm: Some(bad),
+                ..HmcConfig::default()
+            };
+            assert!(matches!(
+                config.validate(),
+                Err(HmcError::InvalidMaxGradNorm(_))
+            ));
+        }
+        let implicit = HmcConfig {
+            max_grad_norm: Some(1.0),
+            integrator: crate::IntegratorKind::ImplicitMidpoint {
+                tol: 1e-10,
+                max_iters: 50,
+            },
+            ..HmcConfig::default()
+        };
+        assert_eq!(implicit.validate(), Err(HmcError::ClippedImplicitMidpoint));
+        let parsed: HmcConfig = serde_json::from_str(r#"{"max_grad_norm": 10.0}"#).unwrap();
+        assert_eq!(parsed.max_grad_norm, Some(10.0));
+    }
+
+    #[test]
+    fn rejected_transition_keeps_position() {
+        let config = HmcConfig {
+            step_size: 3.0,
+            seed: Some(0),
+            ..HmcConfig::default()
+        };
+        let mut chain = Chain::new(config).unwrap();
+        for _ in 0..50 {
+            let before = chain.current_position().clone();
+            let t = chain.step();
+            if !t.accepted {
+                assert_eq!(t.position, before);
+            }
+        }
+    }
+
+    /// x >= 0 に切断した標準正規分布
+    fn half_plane_normal(p: &Point) -> f64 {
+        if p.x < 0.0 {
+            f64::INFINITY
+        } else {
+            0.5 * (p.x * p.x + p.y * p.y)
+        }
+    }
+
+    fn assert_samples_half_normal<T: TargetDistribution>(config: &HmcConfig, target: T) {
+        let result = run_hmc_with_target(config, target).unwrap();
+        assert!(result.samples.iter().all(|p| p.x >= 0.0));
+        assert!(result.n_out_of_support > 0);
+        assert_eq!(result.n_divergent, 0);
+        assert!(result
+            .potential_energy
+            .iter()
+            .chain(&result.energy)
+            .chain(&result.accept_prob)
+            .all(|v| v.is_finite()));
+        // 半正規分布の平均は sqrt(2/π)
+        let mean_x = validate::sample_mean(&result.samples).x;
+        assert!(
+            (mean_x - (2.0 / std::f64::consts::PI).sqrt()).abs() < 0.05,
+            "{}",
+            mean_x
+        );
+    }
+
+    #[test]
+    fn out_of_support_proposals_are_rejected() {
+        let config = HmcConfig {
+            n_samples: 5000,
+            step_size: 0.2,
+            num_steps: 5,
+            initial_pos: Point { x: 1.0, y: 0.0 },
+            seed: Some(8),
+            save_energy: true,
+            save_accept_prob: true,
+            ..HmcConfig::default()
+        };
+        // 台の外で NaN を返す勾配、台の外まで延長した有限の勾配、境界をまたぐと inf になる数値微分
+        let nan_outside = Target::from_fn_with_grad(half_plane_normal, |p: &Point| {
+            if p.x < 0.0 {
+                Point {
+                    x: f64::NAN,
+                    y: f64::NAN,
+                }
+            } else {
+                p.clone()
+            }
+        });
+        assert_samples_half_normal(&config, &nan_outside);
+        assert_samples_half_normal(
+            &config,
+            Target::from_fn_with_grad(half_plane_normal, |p: &Point| p.clone()),
+        );
+        assert_samples_half_normal(&config, Target::from_fn(half_plane_normal));
+
+        // 台の外に出る提案は確率0で、発散とは数えない
+        let mut chain =
+            Chain::with_target(config.clone(), &nan_outside, ChainRng::seed_from_u64(1)).unwrap();
+        let t = (0..200)
+            .map(|_| chain.step())
+            .find(|t| t.out_of_support)
+            .unwrap();
+        assert!(!t.accepted && !t.divergent);
+        assert_eq!((t.accept_prob, t.energy_error), (0.0, f64::INFINITY));
+
+        let outside = HmcConfig {
+            initial_pos: Point { x: -1.0, y: 0.0 },
+            ..config
+        };
+        assert!(matches!(
+            run_hmc_with_target(&outside, &nan_outside),
+            Err(HmcError::NonFiniteInitialPotential { .. })
+        ));
+    }
+
+    #[test]
+    fn recorded_trajectories_end_at_the_proposal() {
+        for thin in [1, 3] {
+            let config = HmcConfig {
+                n_samples: 200,
+                n_warmup: 50,
+                thin,
+                step_size: 0.15,
+                num_steps: 10,
+                record_trajectory_every: Some(25),
+                save_energy: true,
+                save_accept_flags: true,
+                seed: Some(82),
+                ..HmcConfig::default()
+            };
+            let result = run_hmc(&config).unwrap();
+            let indices: Vec<usize> = result.trajectories.iter().map(|t| t.sample_index).collect();
+            assert_eq!(indices, (0..200).step_by(25).collect::<Vec<_>>());
+            for t in &result.trajectories {
+                let i = t.sample_index;
+                assert_eq!(t.positions.len(), 11);
+                assert_eq!(t.energies.len(), 11);
+                assert_eq!(t.accepted, result.accepted[i]);
+                // 採択なら終点、棄却なら始点が、その遷移の生んだサンプル
+                let j = if t.accepted { 10 } else { 0 };
+                assert_eq!(t.positions[j], result.samples[i]);
+                assert_eq!(t.energies[j], result.energy[i]);
+                if thin == 1 && i > 0 {
+                    assert_eq!(t.positions[0], result.samples[i - 1]);
+                }
+            }
+            assert!(result.trajectories.iter().any(|t| t.accepted));
+        }
+
+        // 記録しない設定・固定長のHMC以外では空
+        let plain = run_hmc(&HmcConfig {
+            n_samples: 50,
+            seed: Some(82),
+            ..HmcConfig::default()
+        })
+        .unwrap();
+        assert!(plain.trajectories.is_empty());
+        let nuts = run_hmc(&HmcConfig {
+            n_samples: 50,
+            algorithm: Algorithm::Nuts { max_depth: 10 },
+            record_trajectory_every: Some(1),
+            seed: Some(82),
+            ..HmcConfig::default()
+        })
+        .unwrap();
+        assert!(nuts.trajectories.is_empty());
+        let zero = HmcConfig {
+            record_trajectory_every: Some(0),
+            ..HmcConfig::default()
+        };
+        assert_eq!(zero.validate(), Err(HmcError::ZeroTrajectoryInterval));
+    }
+
+    #[test]
+    fn nan_region_is_rejected_as_numerical_error() {
+        // 標準正規分布のうち、中心 (1, 0)・半径0.5の円板の中だけポテンシャルがNaN（数値微分の勾配もNaN）
+        let in_hole = |p: &Point| (p.x - 1.0).hypot(p.y) < 0.5;
+        let holed = Target::from_fn(move |p: &Point| {
+            if in_hole(p) {
+                f64::NAN
+            } else {
+                0.5 * (p.x * p.x + p.y * p.y)
+            }
+        });
+        for algorithm in [Algorithm::Hmc, Algorithm::Nuts { max_depth: 10 }] {
+            let config = HmcConfig {
+                n_samples: 2000,
+                step_size: 0.3,
+                num_steps: 10,
+                algorithm: algorithm.clone(),
+                initial_pos: Point { x: -1.0, y: 0.0 },
+                save_energy: true,
+                save_accept_prob: true,
+                seed: Some(79),
+                ..HmcConfig::default()
+            };
+            let result = run_hmc_with_target(&config, &holed).unwrap();
+            assert!(result.n_numerical_errors > 0, "{:?}", algorithm);
+            assert!(result.n_numerical_errors <= result.n_divergent);
+            assert_eq!(result.n_out_of_support, 0);
+            assert!(result
+                .samples
+                .iter()
+                .all(|p| p.x.is_finite() && p.y.is_finite() && !in_hole(p)));
+            assert!(result
+                .potential_energy
+                .iter()
+                .chain(&result.energy)
+                .chain(&result.accept_prob)
+                .all(|v| v.is_finite()));
+
+            // 破綻した遷移は確率0で棄却する
+            let mut chain =
+                Chain::with_target(config.clone(), &holed, ChainRng::seed_from_u64(1)).unwrap();
+            let t = (0..500)
+                .map(|_| chain.step())
+                .find(|t| t.numerical_error)
+                .unwrap();
+            assert!(t.divergent && !t.out_of_support);
+            if algorithm == Algorithm::Hmc {
+                assert!(!t.accepted);
+                assert_eq!(t.accept_prob, 0.0);
+            }
+        }
+
+        // U = +∞ の点での勾配のNaNは台の外で、破綻とは数えない
+        let mut z = PhasePoint {
+            q: Point::default(),
+            p: Point::default(),
+            grad: Point {
+                x: f64::NAN,
+                y: 0.0,
+            },
+        };
+        assert!(!is_numerical_error(f64::INFINITY, 0.0, &z));
+        assert!(is_numerical_error(1.0, 0.0, &z));
+        z.grad = Point::default();
+        assert!(!is_numerical_error(1.0, 0.0, &z));
+        assert!(is_numerical_error(f64::NEG_INFINITY, 0.0, &z));
+        assert!(is_numerical_error(1.0, f64::NAN, &z));
+    }
+
+    #[test]
+    fn parallel_tempering_cold_chain_crosses_between_bimodal_modes() {
+        // 距離5だけ離れた2つの山の間の障壁は、温度1のHMCでは同じ遷移数の間にほとんど越えられない
+        let target = crate::Bimodal::default();
+        let config = |algorithm| HmcConfig {
+            n_samples: 5000,
+            n_warmup: 500,
+            step_size: 0.2,
+            num_steps: 10,
+            algorithm,
+            initial_pos: target.centers[0].clone(),
+            mode_centers: target.centers.to_vec(),
+            target: DistType::Bimodal(target.clone()),
+            seed: Some(66),
+            ..HmcConfig::default()
+        };
+        let plain = run_hmc(&config(Algorithm::Hmc)).unwrap();
+        let tempered = run_hmc(&config(Algorithm::ParallelTempering {
+            temperatures: vec![1.0, 2.0, 4.0, 8.0, 16.0],
+            swap_every: 1,
+        }))
+        .unwrap();
+        assert!(
+            plain.mode_switches.unwrap() < 10,
+            "{:?}",
+            plain.mode_switches
+        );
+        assert!(
+            tempered.mode_switches.unwrap() >= 500,
+            "{:?}",
+            tempered.mode_switches
+        );
+        for fraction in &tempered.mode_occupancy {
+            assert!(*fraction > 0.3, "{:?}", tempered.mode_occupancy);
+        }
+        assert_eq!(tempered.samples.len(), 5000);
+        assert_eq!(tempered.swap_acceptance_rates.len(), 4);
+        assert!(tempered.swap_acceptance_rates.iter().all(|&r| r > 0.1));
+        // 全レプリカのリープフロッグの合計
+        assert_eq!(tempered.n_leapfrog, 5 * plain.n_leapfrog);
+    }
+
+    #[test]
+    fn parallel_tempering_resumes_from_checkpoint_and_validates_ladder() {
+        let config = HmcConfig {
+            n_samples: 100,
+            algorithm: Algorithm::ParallelTempering {
+                temperatures: vec![1.0, 3.0, 9.0],
+                swap_every: 2,
+            },
+            target: DistType::Bimodal(crate::Bimodal::default()),
+            seed: Some(66),
+            ..HmcConfig::default()
+        };
+        let mut uninterrupted = Chain::new(config.clone()).unwrap();
+        let expected: Vec<Transition> = (0..60).map(|_| uninterrupted.step()).collect();
+        // 交換は2回の遷移ごとにだけ提案する
+        assert!(expected.iter().step_by(2).all(|t| t.swaps.is_empty()));
+        assert!(expected
+            .iter()
+            .skip(1)
+            .step_by(2)
+            .all(|t| t.swaps.len() == 2));
+
+        let mut chain = Chain::new(config.clone()).unwrap();
+        for _ in 0..25 {
+            chain.step();
+        }
+        let mut restored = Chain::restore(chain.save()).unwrap();
+        assert_eq!(restored.replica_positions(), chain.replica_positions());
+        for t in &expected[25..] {
+            let resumed = restored.step();
+            assert_eq!(resumed.position, t.position);
+            assert_eq!(resumed.swaps, t.swaps);
+        }
+        assert_eq!(
+            restored.replica_positions(),
+            uninterrupted.replica_positions()
+        );
+
+        let ladder = |temperatures: Vec<f64>| HmcConfig {
+            algorithm: Algorithm::ParallelTempering {
+                temperatures,
+                swap_every: 1,
+            },
+            ..HmcConfig::default()
+        };
+        for temperatures in [
+            vec![1.0],
+            vec![2.0, 4.0],
+            vec![1.0, 4.0, 2.0],
+            vec![1.0, f64::INFINITY],
+        ] {
+            assert_eq!(
+                ladder(temperatures.clone()).validate(),
+                Err(HmcError::InvalidTemperatureLadder(temperatures))
+            );
+        }
+        let parsed: HmcConfig =
+            serde_json::from_str(r#"{"algorithm": {"parallel_tempering": {"swap_every": 5}}}"#)
+                .unwrap();
+        assert_eq!(
+            parsed.algorithm,
+            Algorithm::ParallelTempering {
+                temperatures: vec![1.0, 2.0, 4.0, 8.0],
+                swap_every: 5
+            }
+        );
+        assert_eq!(
+            HmcConfig {
+                algorithm: Algorithm::ParallelTempering {
+                    temperatures: vec![1.0, 2.0],
+                    swap_every: 0
+                },
+                ..HmcConfig::default()
+            }
+            .validate(),
+            Err(HmcError::ZeroSwapInterval)
+        );
+    }
+
+    #[test]
+    fn persistent_momentum_keeps_standard_normal_moments() {
+        let identity = [[1.0, 0.0], [0.0, 1.0]];
+        for alpha in [0.0, 0.5, 0.9] {
+            let config = HmcConfig {
+                n_samples: 20_000,
+                n_warmup: 500,
+                step_size: 0.3,
+                num_steps: 3,
+                momentum_persistence: alpha,
+                target: DistType::Normal,
+                seed: Some(70),
+                ..HmcConfig::default()
+            };
+            let result = run_hmc(&config).unwrap();
+            let z = validate::z_score_of_mean(&result.samples, &Point::default(), &identity);
+            assert!(z.x.abs() < 4.0 && z.y.abs() < 4.0, "{}: {:?}", alpha, z);
+            let cov = validate::sample_cov(&result.samples);
+            for i in 0..2 {
+                for j in 0..2 {
+                    assert!(
+                        (cov[i][j] - identity[i][j]).abs() < 0.1,
+                        "{}: {:?}",
+                        alpha,
+                        cov
+                    );
+                }
+            }
+        }
+
+        // 持ち越す運動量もチェックポイントに含まれる
+        let config = HmcConfig {
+            n_samples: 100,
+            num_steps: 3,
+            momentum_persistence: 0.9,
+            seed: Some(70),
+            ..HmcConfig::default()
+        };
+        let mut uninterrupted = Chain::new(config.clone()).unwrap();
+        let expected: Vec<Point> = (0..40).map(|_| uninterrupted.step().position).collect();
+        let mut chain = Chain::new(config).unwrap();
+        for _ in 0..20 {
+            chain.step();
+        }
+        let checkpoint = chain.save();
+        assert!(checkpoint.momentum.is_some());
+        let mut restored = Chain::restore(checkpoint).unwrap();
+        let resumed: Vec<Point> = (0..20).map(|_| restored.step().position).collect();
+        assert_eq!(resumed, expected[20..]);
+
+        for alpha in [-0.1, 1.0, f64::NAN] {
+            assert!(matches!(
+                HmcConfig {
+                    momentum_persistence: alpha,
+                    ..HmcConfig::default()
+                }
+                .validate(),
+                Err(HmcError::InvalidMomentumPersistence(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn persistent_momentum_improves_ess_on_correlated_gaussian() {
+        // 1ステップの軌道では、運動量を毎回引き直すと強い相関の方向をランダムウォークでしか進めない
+        let cov = [[1.0, 0.95], [0.95, 1.0]];
+        let ess_x = |alpha| {
+            let config = HmcConfig {
+                n_samples: 5000,
+                n_warmup: 500,
+                step_size: 0.1,
+                num_steps: 1,
+                momentum_persistence: alpha,
+                target: DistType::Gaussian(crate::MvNormal2::new(Point::default(), cov).unwrap()),
+                seed: Some(70),
+                ..HmcConfig::default()
+            };
+            let result = run_hmc(&config).unwrap();
+            let xs: Vec<f64> = result.samples.iter().map(|q| q.x).collect();
+            crate::diagnostics::ess(&xs)
+        };
+        let (fresh, persistent) = (ess_x(0.0), ess_x(0.9));
+        assert!(persistent > 3.0 * fresh, "{} vs {}", persistent, fresh);
+    }
+}
@@ -0,0 +1,613 @@
+//! サンプル列の収束診断
+
+use serde::{Deserialize, Serialize};
+
+use crate::{ratio, HmcError, HmcResult, HmcResultNd, Point, Vector};
+
+/// `HmcConfig::compute_diagnostics` 指定時に `HmcResult::diagnostics` に載せる収束診断
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Diagnostics {
+    /// 座標ごとのESS（[`ess`]。サンプルが4点未満なら `NaN`）
+    pub ess: Point,
+    /// x 座標と y 座標それぞれの積分自己相関時間（[`iact`]）
+    pub iact: (Iact, Iact),
+    /// 座標ごとのGewekeのzスコア（[`geweke`]。先頭10%と末尾50%の比較）
+    pub geweke: Point,
+    /// エネルギーのBFMI（[`ebfmi`]。`save_energy` で `HmcResult::energy` を記録した場合のみ）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ebfmi: Option<f64>,
+}
+
+impl Diagnostics {
+    /// 結果のサンプル列から全ての診断を求める
+    pub(crate) fn new(result: &HmcResult) -> Self {
+        let (xs, ys) = coordinates(result);
+        Self {
+            ess: result.ess(),
+            iact: (iact(&xs), iact(&ys)),
+            geweke: Point {
+                x: geweke(&xs, GEWEKE_FIRST, GEWEKE_LAST),
+                y: geweke(&ys, GEWEKE_FIRST, GEWEKE_LAST),
+            },
+            ebfmi: (!result.energy.is_empty()).then(|| ebfmi(&result.energy)),
+        }
+    }
+}
+
+/// `HmcConfig::compute_diagnostics` 指定時に `HmcResultNd::diagnostics` に載せる収束診断（座標ごとの [`Diagnostics`]）
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct DiagnosticsNd {
+    /// 座標ごとのESS（[`ess`]。サンプルが4点未満なら `NaN`）
+    pub ess: Vec<f64>,
+    /// 座標ごとの積分自己相関時間（[`iact`]）
+    pub iact: Vec<Iact>,
+    /// 座標ごとのGewekeのzスコア（[`geweke`]。先頭10%と末尾50%の比較）
+    pub geweke: Vec<f64>,
+    /// エネルギーのBFMI（[`ebfmi`]。`save_energy` で `HmcResultNd::energy` を記録した場合のみ）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ebfmi: Option<f64>,
+}
+
+impl DiagnosticsNd {
+    /// 結果のサンプル列から全ての診断を求める
+    pub(crate) fn new(result: &HmcResultNd) -> Self {
+        let coordinates: Vec<Vec<f64>> = (0..result.dim).map(|j| result.coordinate(j)).collect();
+        Self {
+            ess: coordinates.iter().map(|xs| ess(xs)).collect(),
+            iact: coordinates.iter().map(|xs| iact(xs)).collect(),
+            geweke: coordinates
+                .iter()
+                .map(|xs| geweke(xs, GEWEKE_FIRST, GEWEKE_LAST))
+                .collect(),
+            ebfmi: (!result.energy.is_empty()).then(|| ebfmi(&result.energy)),
+        }
+    }
+}
+
+/// 積分自己相関時間の推定値（[`iact`]）
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Iact {
+    /// τ = 1 + 2 Σ_{k=1}^{M} ρ_k（独立なサンプルなら1）
+    pub tau: f64,
+    /// 和を打ち切ったラグ M
+    pub window: usize,
+    /// 系列が短すぎて推定があてにならない（n < 50τ か、窓の条件を満たす M が見つからなかった）
+    pub too_short: bool,
+}
+
+/// Sokalの自動窓で打ち切る積分自己相関時間 τ
+///
+/// ラグ M までの τ(M) = 1 + 2 Σ_{k=1}^{M} ρ_k について、M ≥ c·τ(M)（c = 5）を満たす最小の M で打ち切る。
+/// 自己相関は [`autocorrelation`] と同じ偏りのある推定。n ≥ 50τ でないか、M が n - 1 に達しても条件を
+/// 満たさなければ `too_short` を立てる（後者では τ(n-1) を返す）。2点未満の系列や分散0の系列では
+/// τ は `NaN`（`window` は0、`too_short` は `true`）。負の自己相関が強い系列では τ は1未満（負にもなる）。
+///
+/// ```
+/// use hamiltonian_sampler_rs::diagnostics::iact;
+///
+/// let alternating: Vec<f64> = (0..1000).map(|i| (i % 2) as f64).collect();
+/// let est = iact(&alternating);
+/// assert!(est.tau < 1.0 && !est.too_short);
+/// assert!(iact(&[1.0; 10]).tau.is_nan());
+/// ```
+pub fn iact(series: &[f64]) -> Iact {
+    const C: f64 = 5.0;
+    let n = series.len();
+    let rho = autocorrelation(series, n.saturating_sub(1));
+    if n < 2 || rho[0].is_nan() {
+        return Iact {
+            tau: f64::NAN,
+            window: 0,
+            too_short: true,
+        };
+    }
+    let mut tau = 1.0;
+    let mut window = 0;
+    let mut found = false;
+    for (k, r) in rho.iter().enumerate().skip(1) {
+        tau += 2.0 * r;
+        window = k;
+        if k as f64 >= C * tau {
+            found = true;
+            break;
+        }
+    }
+    Iact {
+        tau,
+        window,
+        too_short: !found || (n as f64) < 50.0 * tau,
+    }
+}
+
+/// 有効サンプルサイズ（ESS）
+///
+/// 自己共分散をFFTでまとめて計算し、Geyerの initial monotone sequence で打ち切る。
+/// 負の自己相関が強い（反周期的な）チェーンでも発散しないよう、Stanと同じく ESS ≤ n·log10(n) に抑える。
+/// 4点未満の系列や分散0の系列では `NaN` を返す（4〜9点程度の短い系列でも値は返すが、あてにならない）。
+///
+/// ```
+/// use hamiltonian_sampler_rs::diagnostics::ess;
+///
+/// let alternating: Vec<f64> = (0..100).map(|i| (i % 2) as f64).collect();
+/// assert!(ess(&alternating) > 100.0);
+/// assert!(ess(&[1.0, 2.0, 3.0]).is_nan());
+/// ```
+pub fn ess(samples: &[f64]) -> f64 {
+    let n = samples.len();
+    if n < 4 {
+        return f64::NAN;
+    }
+    let gamma = autocovariances(samples);
+    let gamma0 = gamma[0];
+    if !(gamma0.is_finite() && gamma0 > 0.0) {
+        return f64::NAN;
+    }
+
+    // ρ_{2m} + ρ_{2m+1} のペア和が正である間だけ足し込み、単調非増加になるよう抑える
+    let mut sum_pairs = 0.0;
+    let mut prev_pair = f64::INFINITY;
+    let mut lag = 0;
+    while lag + 1 < n {
+        let pair = (gamma[lag] + gamma[lag + 1]) / gamma0;
+        if pair <= 0.0 {
+            break;
+        }
+        let pair = pair.min(prev_pair);
+        sum_pairs += pair;
+        prev_pair = pair;
+        lag += 2;
+    }
+
+    let tau = (-1.0 + 2.0 * sum_pairs).max(1.0 / (n as f64).log10());
+    n as f64 / tau
+}
+
+/// ラグ 0..=`max_lag` の自己相関 ρ_k = γ_k / γ_0（ρ_0 = 1）
+///
+/// 系列の平均を引いてから、全ラグで分母を n にそろえた偏りのある自己共分散
+/// γ_k = Σ_{t<n-k} (x_t - x̄)(x_{t+k} - x̄) / n を使う（ESSと同じFFTによる計算）。ラグごとに n - k で割る
+/// 不偏版と違って大きなラグで0に縮み、正定値性が保たれる。`max_lag` は n - 1 で頭打ちにする。
+/// 空の系列では空の列、分散が0か有限でない系列では全て `NaN` を返す。
+///
+/// ```
+/// use hamiltonian_sampler_rs::diagnostics::autocorrelation;
+///
+/// let rho = autocorrelation(&[1.0, -1.0, 1.0, -1.0], 10);
+/// assert_eq!(rho.len(), 4);
+/// assert_eq!(rho[0], 1.0);
+/// assert!((rho[1] + 0.75).abs() < 1e-12);
+/// ```
+pub fn autocorrelation(series: &[f64], max_lag: usize) -> Vec<f64> {
+    if series.is_empty() {
+        return Vec::new();
+    }
+    let mut gamma = autocovariances(series);
+    gamma.truncate(max_lag.saturating_add(1));
+    let gamma0 = gamma[0];
+    if !(gamma0.is_finite() && gamma0 > 0.0) {
+        return vec![f64::NAN; gamma.len()];
+    }
+    gamma.iter().map(|g| g / gamma0).collect()
+}
+
+/// `HmcResult::geweke` と `Diagnostics::geweke` で比べる先頭と末尾の割合（Gewekeの元の提案どおり）
+pub(crate) const GEWEKE_FIRST: f64 = 0.1;
+pub(crate) const GEWEKE_LAST: f64 = 0.5;
+
+/// Gewekeの収束診断のzスコア
+///
+/// 系列の先頭 `first_frac` と末尾 `last_frac` の平均の差を、それぞれの平均の分散の和の平方根で割る。
+/// 平均の分散は区間ごとのゼロ周波数のスペクトル密度 S(0) = γ_0·τ を長さで割ったもので、τ は [`ess`] と
+/// 同じ initial monotone sequence で求める（S(0)/n = γ_0 / ESS）。定常なら標準正規分布に従い、
+/// |z| が2〜3を超えればチェーンの前半がまだ定常分布に達していない。
+///
+/// 割合が (0, 1) の範囲にないか合計が1を超える場合、どちらかの区間が4点未満か分散0の場合は `NaN`。
+///
+/// ```
+/// use hamiltonian_sampler_rs::diagnostics::geweke;
+///
+/// let drifting: Vec<f64> = (0..1000).map(|i| (i as f64 * 0.7).sin() + i as f64 * 0.01).collect();
+/// assert!(geweke(&drifting, 0.1, 0.5).abs() > 3.0);
+/// assert!(geweke(&drifting, 0.6, 0.5).is_nan());
+/// ```
+pub fn geweke(series: &[f64], first_frac: f64, last_frac: f64) -> f64 {
+    let valid = |frac: f64| frac > 0.0 && frac < 1.0;
+    if !(valid(first_frac) && valid(last_frac) && first_frac + last_frac <= 1.0) {
+        return f64::NAN;
+    }
+    let n = series.len();
+    let first = &series[..(first_frac * n as f64) as usize];
+    let last = &series[n - (last_frac * n as f64) as usize..];
+    // 平均の分散 γ_0·τ / n = γ_0 / ESS（区間が短すぎるか分散0なら ESS が NaN になり、z も NaN）
+    let mean_variance = |segment: &[f64]| {
+        if segment.len() < 4 {
+            return f64::NAN;
+        }
+        let m = mean(segment);
+        let gamma0 = segment.iter().map(|x| (x - m).powi(2)).sum::<f64>() / segment.len() as f64;
+        gamma0 / ess(segment)
+    };
+    (mean(first) - mean(last)) / (mean_variance(first) + mean_variance(last)).sqrt()
+}
+
+/// エネルギーのBFMI（Bayesian fraction of missing information、Betancourt 2016）
+///
+/// 遷移ごとのハミルトニアンの列 E_t について E-BFMI = Σ_t (E_t - E_{t-1})² / Σ_t (E_t - Ē)²。
+/// 運動量の引き直しで動くエネルギーの幅が、周辺のエネルギー分布の幅に比べてどれだけあるかを表す。
+/// 0.3を下回るなら運動量の引き直しだけではエネルギー分布を探索しきれず、サンプルが偏りうる
+/// （質量行列の見直しや変数変換を検討する）。間引いた列では連続する遷移の差にならないので過大に出る。
+///
+/// 2点未満の列や分散0の列では `NaN`。
+///
+/// ```
+/// use hamiltonian_sampler_rs::diagnostics::ebfmi;
+///
+/// assert_eq!(ebfmi(&[1.0, 2.0, 1.0, 2.0]), 3.0);
+/// assert!(ebfmi(&[1.0]).is_nan());
+/// ```
+pub fn ebfmi(energies: &[f64]) -> f64 {
+    if energies.len() < 2 {
+        return f64::NAN;
+    }
+    let m = mean(energies);
+    let variance = energies.iter().map(|e| (e - m).powi(2)).sum::<f64>();
+    if !(variance.is_finite() && variance > 0.0) {
+        return f64::NAN;
+    }
+    let squared_steps = energies
+        .windows(2)
+        .map(|w| (w[1] - w[0]).powi(2))
+        .sum::<f64>();
+    squared_steps / variance
+}
+
+/// 分割R̂（split-R̂、ポテンシャル尺度縮小係数）
+///
+/// 各チェーンを前半と後半に分け（奇数長なら中央の1点を捨てる）、分割した M 本の系列の平均の間の分散 B と
+/// 系列内の分散の平均 W から R̂ = √(((N-1)/N·W + B/N) / W) を求める（N は分割した系列の長さ）。
+/// 長さの違うチェーンは最も短いものに揃え、それぞれ先頭から使う。収束していれば1に近く、
+/// 1.1を超えるならチェーンどうしがまだ同じ分布を探索できていない。
+///
+/// チェーンが0本なら `HmcError::ZeroChains`、最も短いチェーンが4点未満（分割した系列が2点未満）なら
+/// `HmcError::ChainTooShort`、W が0か有限でない（定数のチェーンなど）なら `HmcError::DegenerateChains`。
+///
+/// ```
+/// use hamiltonian_sampler_rs::diagnostics::rhat;
+///
+/// let a = [0.1, -0.4, 0.3, 0.0, -0.2, 0.5, -0.1, 0.2];
+/// let b = [-0.3, 0.2, 0.0, 0.4, -0.5, 0.1, 0.3, -0.2];
+/// assert!(rhat(&[&a, &b]).unwrap() < 1.1);
+/// let shifted: Vec<f64> = b.iter().map(|x| x + 5.0).collect();
+/// assert!(rhat(&[&a, &shifted]).unwrap() > 1.1);
+/// assert!(rhat(&[&[1.0; 8], &[1.0; 8]]).is_err());
+/// ```
+pub fn rhat(chains: &[&[f64]]) -> Result<f64, HmcError> {
+    let len = chains
+        .iter()
+        .map(|chain| chain.len())
+        .min()
+        .ok_or(HmcError::ZeroChains)?;
+    if len < 4 {
+        return Err(HmcError::ChainTooShort { min: 4, got: len });
+    }
+    let half = len / 2;
+    let splits: Vec<&[f64]> = chains
+        .iter()
+        .flat_map(|chain| [&chain[..half], &chain[len - half..len]])
+        .collect();
+    let means: Vec<f64> = splits.iter().map(|s| mean(s)).collect();
+
+    let within = splits
+        .iter()
+        .zip(&means)
+        .map(|(s, m)| s.iter().map(|x| (x - m).powi(2)).sum::<f64>() / (half - 1) as f64)
+        .sum::<f64>()
+        / splits.len() as f64;
+    if !(within.is_finite() && within > 0.0) {
+        return Err(HmcError::DegenerateChains);
+    }
+    let grand_mean = mean(&means);
+    let n = half as f64;
+    let between =
+        n * means.iter().map(|m| (m - grand_mean).powi(2)).sum::<f64>() / (splits.len() - 1) as f64;
+
+    Ok((((n - 1.0) / n * within + between / n) / within).sqrt())
+}
+
+/// チェーンごとの結果から座標ごとの分割R̂を求める（[`rhat`]。エラーの条件も同じ）
+pub fn rhat_per_coordinate(results: &[HmcResult]) -> Result<Point, HmcError> {
+    let (xs, ys): (Vec<Vec<f64>>, Vec<Vec<f64>>) = results.iter().map(coordinates).unzip();
+    let xs: Vec<&[f64]> = xs.iter().map(Vec::as_slice).collect();
+    let ys: Vec<&[f64]> = ys.iter().map(Vec::as_slice).collect();
+    Ok(Point {
+        x: rhat(&xs)?,
+        y: rhat(&ys)?,
+    })
+}
+
+/// 結果のサンプルの x 座標の列と y 座標の列（`flat_samples` 指定時は `samples_flat` から取り出す）
+pub(crate) fn coordinates(result: &HmcResult) -> (Vec<f64>, Vec<f64>) {
+    if result.samples.is_empty() {
+        let flat = &result.samples_flat;
+        (
+            flat.iter().step_by(2).copied().collect(),
+            flat.iter().skip(1).step_by(2).copied().collect(),
+        )
+    } else {
+        result.samples.iter().map(|p| (p.x, p.y)).unzip()
+    }
+}
+
+fn mean(xs: &[f64]) -> f64 {
+    xs.iter().sum::<f64>() / xs.len() as f64
+}
+
+/// 各サンプルを最も近い `centers` に割り当てたときの、中心ごとの占有率（`centers` と同じ順、合計1）
+///
+/// 多峰性の分布で各山に入ったサンプルの割合を見るのに使う。サンプルが空なら全て0。
+///
+/// ```
+/// use hamiltonian_sampler_rs::diagnostics::{mode_occupancy, mode_switches};
+/// use hamiltonian_sampler_rs::Point;
+///
+/// let centers = [Point { x: -1.0, y: 0.0 }, Point { x: 1.0, y: 0.0 }];
+/// let samples: Vec<Point> = [-1.2, -0.8, 0.9, 1.1]
+///     .iter()
+///     .map(|&x| Point { x, y: 0.0 })
+///     .collect();
+/// assert_eq!(mode_occupancy(&samples, &centers), vec![0.5, 0.5]);
+/// assert_eq!(mode_switches(&samples, &centers), 1);
+/// ```
+pub fn mode_occupancy<V: Vector>(samples: &[V], centers: &[V]) -> Vec<f64> {
+    let mut counts = vec![0; centers.len()];
+    for i in samples.iter().filter_map(|p| nearest_center(p, centers)) {
+        counts[i] += 1;
+    }
+    counts.iter().map(|&c| ratio(c, samples.len())).collect()
+}
+
+/// 連続するサンプルの間で最も近い中心が変わった回数（山の間の行き来の回数）
+///
+/// 間引いたサンプル列では、間引かれた遷移の中での行き来は数えない。
+pub fn mode_switches<V: Vector>(samples: &[V], centers: &[V]) -> usize {
+    let modes: Vec<Option<usize>> = samples.iter().map(|p| nearest_center(p, centers)).collect();
+    modes.windows(2).filter(|w| w[0] != w[1]).count()
+}
+
+/// `p` に最も近い中心の添字（`centers` が空なら `None`。等距離なら先のもの）
+fn nearest_center<V: Vector>(p: &V, centers: &[V]) -> Option<usize> {
+    let d2 = |c: &V| -> f64 {
+        p.as_slice()
+            .iter()
+            .zip(c.as_slice())
+            .map(|(p, c)| (p - c).powi(2))
+            .sum()
+    };
+    centers
+        .iter()
+        .enumerate()
+        .min_by(|(_, a), (_, b)| d2(a).total_cmp(&d2(b)))
+        .map(|(i, _)| i)
+}
+
+/// 平均を引いた系列の全ラグの自己共分散 γ_0..γ_{n-1}（分母は n）
+///
+/// 2n 以上に0埋めしてFFTを取り、パワースペクトルを逆変換する（Wiener–Khinchin）。
+fn autocovariances(series: &[f64]) -> Vec<f64> {
+    let n = series.len();
+    let mean = series.iter().sum::<f64>() / n as f64;
+    let size = (2 * n).next_power_of_two();
+
+    let mut buf: Vec<(f64, f64)> = series.iter().map(|x| (x - mean, 0.0)).collect();
+    buf.resize(size, (0.0, 0.0));
+    fft(&mut buf, false);
+    for c in buf.iter_mut() {
+        *c = (c.0 * c.0 + c.1 * c.1, 0.0);
+    }
+    fft(&mut buf, true);
+
+    buf.iter()
+        .take(n)
+        .map(|c| c.0 / size as f64 / n as f64)
+        .collect()
+}
+
+/// 反復型の radix-2 FFT（長さは2の冪であること。`inverse` でも 1/N の正規化はしない）
+fn fft(buf: &mut [(f64, f64)], inverse: bool) {
+    let n = buf.len();
+    debug_assert!(n.is_power_of_two());
+
+    // ビット反転並べ替え
+    let mut j = 0;
+    for i in 1..n {
+        let mut bit = n >> 1;
+        while j & bit != 0 {
+            j ^= bit;
+            bit >>= 1;
+        }
+        j |= bit;
+        if i < j {
+            buf.swap(i, j);
+        }
+    }
+
+    let sign = if inverse { 1.0 } else { -1.0 };
+    let mut len = 2;
+    while len <= n {
+        let angle = sign * 2.0 * std::f64::consts::PI / len as f64;
+        let (w_im, w_re) = angle.sin_cos();
+        for start in (0..n).step_by(len) {
+            let (mut cur_re, mut cur_im) = (1.0, 0.0);
+            for k in 0..len / 2 {
+                let a = buf[start + k];
+                let b = buf[start + k + len / 2];
+                let t = (b.0 * cur_re - b.1 * cur_im, b.0 * cur_im + b.1 * cur_re);
+                buf[start + k] = (a.0 + t.0, a.1 + t.1);
+                buf[start + k + len / 2] = (a.0 - t.0, a.1 - t.1);
+                let next_re = cur_re * w_re - cur_im * w_im;
+                cur_im = cur_re * w_im + cur_im * w_re;
+                cur_re = next_re;
+            }
+        }
+        len <<= 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::prelude::*;
+    use rand_distr::StandardNormal;
+
+    #[test]
+    fn ess_of_iid_noise_is_close_to_n() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let x: Vec<f64> = (0..10_000).map(|_| rng.sample(StandardNormal)).collect();
+        let e = ess(&x);
+        assert!((e / 10_000.0 - 1.0).abs() < 0.1, "ess = {}", e);
+    }
+
+    /// 分散1の定常なAR(1)過程 x_t = ρ x_{t-1} + √(1-ρ²) z_t（自己相関は ρ^k）
+    fn ar1(rho: f64, n: usize, seed: u64) -> Vec<f64> {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let mut x = Vec::with_capacity(n);
+        let mut v = 0.0;
+        for _ in 0..n {
+            let z: f64 = rng.sample(StandardNormal);
+            v = rho * v + (1.0 - rho * rho).sqrt() * z;
+            x.push(v);
+        }
+        x
+    }
+
+    #[test]
+    fn ess_of_ar1() {
+        let rho: f64 = 0.9;
+        let n = 100_000;
+        let x = ar1(rho, n, 1);
+        let expected = n as f64 * (1.0 - rho) / (1.0 + rho);
+        let e = ess(&x);
+        assert!(
+            (e / expected - 1.0).abs() < 0.15,
+            "ess = {}, expected {}",
+            e,
+            expected
+        );
+    }
+
+    #[test]
+    fn autocorrelation_of_ar1_decays_geometrically() {
+        let rho: f64 = 0.8;
+        let acf = autocorrelation(&ar1(rho, 100_000, 2), 20);
+        assert_eq!(acf.len(), 21);
+        assert_eq!(acf[0], 1.0);
+        for (k, r) in acf.iter().enumerate() {
+            assert!((r - rho.powi(k as i32)).abs() < 0.02, "lag {}: {}", k, r);
+        }
+    }
+
+    #[test]
+    fn autocorrelation_edge_cases() {
+        assert!(autocorrelation(&[], 5).is_empty());
+        assert_eq!(autocorrelation(&[1.0, 2.0, 3.0], 0), vec![1.0]);
+        // ラグは n - 1 で頭打ち、分母は全ラグで n
+        let acf = autocorrelation(&[1.0, 2.0, 3.0], usize::MAX);
+        assert_eq!(acf.len(), 3);
+        assert!((acf[2] + 0.5).abs() < 1e-12);
+        let constant = autocorrelation(&[2.0; 10], 3);
+        assert_eq!(constant.len(), 4);
+        assert!(constant.iter().all(|r| r.is_nan()));
+    }
+
+    #[test]
+    fn iact_of_ar1() {
+        // τ = (1 + ρ) / (1 - ρ)
+        for (rho, seed) in [(0.5, 3), (0.9, 4)] {
+            let expected: f64 = (1.0 + rho) / (1.0 - rho);
+            let est = iact(&ar1(rho, 100_000, seed));
+            assert!(
+                (est.tau / expected - 1.0).abs() < 0.1,
+                "rho = {}: {:?}",
+                rho,
+                est
+            );
+            assert!(est.window as f64 >= 5.0 * est.tau);
+            assert!(!est.too_short);
+        }
+
+        // 50τ に満たない系列には印を付ける
+        let short = iact(&ar1(0.9, 500, 5));
+        assert!(short.too_short, "{:?}", short);
+        let white = iact(&ar1(0.0, 1000, 6));
+        assert!(
+            (white.tau - 1.0).abs() < 0.2 && !white.too_short,
+            "{:?}",
+            white
+        );
+        assert!(iact(&[]).tau.is_nan() && iact(&[1.0]).too_short);
+    }
+
+    #[test]
+    fn geweke_of_stationary_series_is_standard_normal() {
+        let z: Vec<f64> = (0..200)
+            .map(|seed| geweke(&ar1(0.5, 2000, 100 + seed), 0.1, 0.5))
+            .collect();
+        let within_2 = z.iter().filter(|z| z.abs() < 2.0).count();
+        assert!(within_2 > 180, "{} of 200 within 2", within_2);
+
+        assert!(geweke(&ar1(0.5, 30, 7), 0.1, 0.5).is_nan());
+        assert!(geweke(&ar1(0.5, 100, 7), 0.0, 0.5).is_nan());
+        assert!(geweke(&ar1(0.5, 100, 7), 0.1, 1.0).is_nan());
+        assert!(geweke(&[1.0; 100], 0.1, 0.5).is_nan());
+    }
+
+    #[test]
+    fn ebfmi_of_independent_energies_is_near_two() {
+        // 独立な列では差の2乗の期待値が分散の2倍になる
+        let e = ebfmi(&ar1(0.0, 100_000, 8));
+        assert!((e - 2.0).abs() < 0.05, "{}", e);
+        // 強く相関した列ではエネルギーがほとんど動かない
+        let e = ebfmi(&ar1(0.99, 100_000, 9));
+        assert!(e < 0.05, "{}", e);
+        assert!(ebfmi(&[]).is_nan() && ebfmi(&[3.0; 5]).is_nan());
+    }
+
+    #[test]
+    fn fft_autocovariance_matches_direct_sum() {
+        let x = [1.0, 3.0, -2.0, 0.5, 4.0, -1.0, 2.0];
+        let mean = x.iter().sum::<f64>() / x.len() as f64;
+        let gamma = autocovariances(&x);
+        for (lag, g) in gamma.iter().enumerate() {
+            let direct: f64 = (0..x.len() - lag)
+                .map(|t| (x[t] - mean) * (x[t + lag] - mean))
+                .sum::<f64>()
+                / x.len() as f64;
+            assert!((g - direct).abs() < 1e-12);
+        }
+    }
+
+    #[test]
+    fn mode_occupancy_and_switches_follow_nearest_center() {
+        let centers = [
+            Point { x: 0.0, y: 0.0 },
+            Point { x: 5.0, y: 0.0 },
+            Point { x: 0.0, y: 5.0 },
+        ];
+        let samples: Vec<Point> = [(0.1, 0.2), (4.0, 1.0), (4.5, 0.0), (0.0, 4.0), (-1.0, 0.0)]
+            .iter()
+            .map(|&(x, y)| Point { x, y })
+            .collect();
+        assert_eq!(mode_occupancy(&samples, &centers), vec![0.4, 0.4, 0.2]);
+        assert_eq!(mode_switches(&samples, &centers), 3);
+
+        assert_eq!(mode_occupancy(&[], &centers), vec![0.0; 3]);
+        assert_eq!(mode_switches(&samples[..1], &centers), 0);
+        assert!(mode_occupancy(&samples, &[]).is_empty());
+    }
+
+    #[test]
+    fn degenerate_inputs() {
+        assert!(ess(&[1.0, 2.0]).is_nan());
+        assert!(ess(&[3.0; 100]).is_nan());
+    }
+}
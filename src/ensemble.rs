@@ -0,0 +1,286 @@
+//! アフィン不変なアンサンブルサンプラー（emcee の stretch move, Goodman & Weare 2010）
+
+use rand::Rng;
+use rand_distr::{Distribution, StandardNormal};
+
+use crate::bounds::Space;
+use crate::chain::Leapfrog;
+use crate::nuts::Move;
+use crate::target::Model;
+use crate::vector::{self, Vector};
+use crate::{HmcError, Point};
+
+/// 開始位置の周りにウォーカーを散らす標準偏差
+const INIT_SPREAD: f64 = 0.1;
+/// 台の外に出たウォーカーを開始位置に向けて半分ずつ引き戻す回数の上限
+const MAX_INIT_SHRINK: usize = 30;
+
+/// 1回の掃引での1ウォーカーの更新結果（位置は非制約空間）
+pub(crate) struct WalkerUpdate<V = Point> {
+    pub(crate) position: V,
+    pub(crate) accepted: bool,
+    pub(crate) accept_prob: f64,
+    pub(crate) potential_energy: f64,
+}
+
+/// `start` の周りに `n_walkers` 個のウォーカーを置く（先頭のウォーカーは `start` そのもの）
+///
+/// ポテンシャルが有限でない点（反射境界の外を含む）に落ちたウォーカーは、有限になるまで `start` に向けて引き戻す。
+/// 引き戻しきれなければ `HmcError::NonFiniteInitialStateNd`（2次元のチェーンは `NonFiniteInitialPotential` に直す）。
+pub(crate) fn init_walkers<T, V, R>(
+    target: &T,
+    space: &Space,
+    start: &V,
+    n_walkers: usize,
+    rng: &mut R,
+) -> Result<Vec<V>, HmcError>
+where
+    T: Model<V> + ?Sized,
+    V: Vector,
+    R: Rng,
+{
+    let mut walkers = vec![start.clone()];
+    for _ in 1..n_walkers {
+        let mut direction = start.clone();
+        for d in direction.as_mut_slice() {
+            *d = StandardNormal.sample(rng);
+        }
+        let mut scale = INIT_SPREAD;
+        let mut walker = start.clone();
+        for _ in 0..MAX_INIT_SHRINK {
+            walker = vector::zip_with(start, &direction, |s, d| s + scale * d);
+            if potential(target, space, &walker).is_finite() {
+                break;
+            }
+            scale *= 0.5;
+        }
+        let u = potential(target, space, &walker);
+        if !u.is_finite() {
+            return Err(HmcError::NonFiniteInitialStateNd {
+                position: walker.as_slice().to_vec(),
+                potential: u,
+            });
+        }
+        walkers.push(walker);
+    }
+    Ok(walkers)
+}
+
+/// 反射境界の外を密度0とみなしたポテンシャル
+fn potential<T: Model<V> + ?Sized, V: Vector>(target: &T, space: &Space, q: &V) -> f64 {
+    if space.contains(q) {
+        target.potential_at(q)
+    } else {
+        f64::INFINITY
+    }
+}
+
+/// 各ウォーカーのポテンシャル（掃引の前に一度だけ計算し、以後は更新のたびに差し替える）
+pub(crate) fn potentials<T: Model<V> + ?Sized, V: Vector>(
+    leapfrog: &Leapfrog<'_, T, V>,
+    walkers: &[V],
+) -> Vec<f64> {
+    walkers
+        .iter()
+        .map(|q| potential(leapfrog.target, leapfrog.space, q))
+        .collect()
+}
+
+/// 全ウォーカーを先頭から順に1回ずつ stretch move で更新する
+///
+/// ウォーカー k について他のウォーカー j を一様に選び、g(z) ∝ 1/√z（z ∈ [1/a, a]）から引いた z で
+/// y = x_j + z (x_k - x_j) を提案し、min(1, z^(d-1) π(y) / π(x_k))（d は状態の次元）で判定する。
+/// 更新済みのウォーカーはすぐ後のウォーカーの相手になる（逐次版の stretch move）。
+/// 提案は線形変換と可換なので、ターゲットをアフィン変換しても同じ振る舞いになる。
+///
+/// 戻り値の `Move` はウォーカー0のもの（チェーンの「現在位置」はウォーカー0とする）。
+pub(crate) fn sweep<T, V, R>(
+    leapfrog: &Leapfrog<'_, T, V>,
+    walkers: &mut [V],
+    potentials: &mut [f64],
+    a: f64,
+    rng: &mut R,
+) -> (Move<V>, Vec<WalkerUpdate<V>>)
+where
+    T: Model<V> + ?Sized,
+    V: Vector,
+    R: Rng,
+{
+    let n_walkers = walkers.len();
+    let exponent = (walkers[0].dim() - 1) as f64;
+    let mut updates = Vec::with_capacity(n_walkers);
+    let mut first_log_ratio = 0.0;
+    for k in 0..n_walkers {
+        let mut j = rng.gen_range(0..n_walkers - 1);
+        if j >= k {
+            j += 1;
+        }
+        let u: f64 = rng.gen();
+        let z = ((a - 1.0) * u + 1.0).powi(2) / a;
+        let proposal = vector::zip_with(&walkers[j], &walkers[k], |xj, xk| xj + z * (xk - xj));
+        let new_u = potential(leapfrog.target, leapfrog.space, &proposal);
+        let log_ratio = exponent * z.ln() + potentials[k] - new_u;
+        let accept_prob = if log_ratio.is_nan() {
+            0.0
+        } else {
+            log_ratio.exp().min(1.0)
+        };
+        let accepted = rng.gen::<f64>() < accept_prob;
+        if accepted {
+            walkers[k] = proposal;
+            potentials[k] = new_u;
+        }
+        if k == 0 {
+            first_log_ratio = log_ratio;
+        }
+        updates.push(WalkerUpdate {
+            position: walkers[k].clone(),
+            accepted,
+            accept_prob,
+            potential_energy: potentials[k],
+        });
+    }
+
+    let first = &updates[0];
+    let result = Move {
+        to: first.accepted.then(|| first.position.clone()),
+        energy_error: -first_log_ratio,
+        divergent: false,
+        numerical_error: false,
+        out_of_support: !first_log_ratio.is_finite(),
+        accept_prob: first.accept_prob,
+        potential_energy: first.potential_energy,
+        energy: first.potential_energy,
+        n_leapfrog: 0,
+        tree_depth: None,
+        momentum: None,
+        grad: None,
+        trajectory: None,
+    };
+    (result, updates)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::validate::sample_cov;
+    use crate::{
+        run_hmc, Algorithm, Chain, DistType, HmcConfig, HmcError, MvNormal2, Point, Topology,
+    };
+
+    fn ensemble_config(target: DistType) -> HmcConfig {
+        HmcConfig {
+            n_samples: 2000,
+            n_warmup: 500,
+            algorithm: Algorithm::Ensemble {
+                n_walkers: 16,
+                a: 2.0,
+            },
+            target,
+            seed: Some(65),
+            ..HmcConfig::default()
+        }
+    }
+
+    #[test]
+    fn ensemble_recovers_highly_anisotropic_covariance() {
+        // 標準偏差が10と0.1で相関0.99。ステップ幅のある手法なら細い方向に合わせた調整が要る
+        let cov = [[100.0, 0.99], [0.99, 0.01]];
+        let anisotropic = run_hmc(&ensemble_config(DistType::Gaussian(
+            MvNormal2::new(Point::default(), cov).unwrap(),
+        )))
+        .unwrap();
+        let isotropic = run_hmc(&ensemble_config(DistType::Normal)).unwrap();
+        assert_eq!(anisotropic.samples.len(), 2000 * 16);
+        assert_eq!(anisotropic.walker_acceptance_rates.len(), 16);
+
+        let estimate = sample_cov(&anisotropic.samples);
+        for i in 0..2 {
+            for j in 0..2 {
+                let scale = (cov[i][i] * cov[j][j]).sqrt();
+                assert!(
+                    (estimate[i][j] - cov[i][j]).abs() < 0.1 * scale,
+                    "{:?} vs {:?}",
+                    estimate,
+                    cov
+                );
+            }
+        }
+        // アフィン不変なので、線形変換で移り合う2つのターゲットでは採択率もほぼ同じになる
+        assert!(
+            (anisotropic.acceptance_rate - isotropic.acceptance_rate).abs() < 0.05,
+            "{} vs {}",
+            anisotropic.acceptance_rate,
+            isotropic.acceptance_rate
+        );
+    }
+
+    #[test]
+    fn ensemble_samples_are_grouped_by_walker_and_resume_from_checkpoint() {
+        let config = HmcConfig {
+            n_samples: 30,
+            n_warmup: 0,
+            save_accept_flags: true,
+            ..ensemble_config(DistType::Normal)
+        };
+        let result = run_hmc(&config).unwrap();
+        let walker_samples = result.walker_samples();
+        assert_eq!(walker_samples.len(), 16);
+        assert!(walker_samples.iter().all(|samples| samples.len() == 30));
+        assert_eq!(walker_samples[3][2], result.samples[2 * 16 + 3]);
+        let n_accepted = result.accepted.iter().filter(|&&a| a).count();
+        assert_eq!(
+            result.acceptance_rate,
+            n_accepted as f64 / result.samples.len() as f64
+        );
+        let mean_rate = result.walker_acceptance_rates.iter().sum::<f64>() / 16.0;
+        assert!((mean_rate - result.acceptance_rate).abs() < 1e-12);
+        // チェーンの位置はウォーカー0
+        assert_eq!(result.final_position, walker_samples[0][29]);
+
+        let mut chain = Chain::new(config.clone()).unwrap();
+        for _ in 0..10 {
+            chain.step();
+        }
+        let mut restored = Chain::restore(chain.save()).unwrap();
+        assert_eq!(restored.walkers(), chain.walkers());
+        for _ in 0..20 {
+            let (a, b) = (chain.step(), restored.step());
+            let positions = |t: &crate::Transition| -> Vec<Point> {
+                t.walkers.iter().map(|w| w.position.clone()).collect()
+            };
+            assert_eq!(positions(&a), positions(&b));
+        }
+    }
+
+    #[test]
+    fn ensemble_rejects_invalid_settings_and_loads_from_json() {
+        let config = |n_walkers, a| HmcConfig {
+            algorithm: Algorithm::Ensemble { n_walkers, a },
+            ..HmcConfig::default()
+        };
+        assert_eq!(config(3, 2.0).validate(), Err(HmcError::TooFewWalkers(3)));
+        for a in [1.0, 0.5, f64::INFINITY] {
+            assert_eq!(
+                config(8, a).validate(),
+                Err(HmcError::InvalidStretchScale(a))
+            );
+        }
+        let periodic = HmcConfig {
+            topology: Topology::Periodic {
+                period_x: 1.0,
+                period_y: 1.0,
+            },
+            ..config(8, 2.0)
+        };
+        assert_eq!(periodic.validate(), Err(HmcError::PeriodicEnsemble));
+        let parsed: HmcConfig =
+            serde_json::from_str(r#"{"algorithm": {"ensemble": {"n_walkers": 6}}}"#).unwrap();
+        assert_eq!(
+            parsed.algorithm,
+            Algorithm::Ensemble {
+                n_walkers: 6,
+                a: 2.0
+            }
+        );
+    }
+}
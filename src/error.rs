@@ -0,0 +1,461 @@
+use std::fmt;
+
+use crate::{DistType, HmcResult, HmcResultNd};
+
+/// サンプラーのエラー型
+#[derive(Debug, Clone, PartialEq)]
+pub enum HmcError {
+    /// 未知の分布名
+    UnknownDistribution(String),
+    /// サンプル数が0
+    ZeroSamples,
+    /// 間引き間隔が0
+    ZeroThin,
+    /// `target_ess` 指定時にESS計算間隔が0
+    ZeroEssCheckInterval,
+    /// 軌道を記録する間隔が0
+    ZeroTrajectoryInterval,
+    /// リープフロッグのステップ数が0
+    ZeroLeapfrogSteps,
+    /// NUTSの木の最大深さが0
+    ZeroTreeDepth,
+    /// ランダムウォークの提案の標準偏差が正の有限値でない
+    InvalidProposalStd(f64),
+    /// スライスサンプリングの初期区間の幅が正の有限値でない
+    InvalidSliceWidth(f64),
+    /// アンサンブルのウォーカー数が状態の次元の2倍（4）未満
+    TooFewWalkers(usize),
+    /// stretch move の伸縮率の上限 `a` が1より大きい有限値でない
+    InvalidStretchScale(f64),
+    /// アンサンブルサンプラーに周期境界が指定された
+    PeriodicEnsemble,
+    /// レプリカ交換法の温度の列が1から始まる2個以上の狭義単調増加な有限値の列でない
+    InvalidTemperatureLadder(Vec<f64>),
+    /// レプリカ交換法の交換の間隔が0
+    ZeroSwapInterval,
+    /// ステップ幅が正の有限値でない
+    InvalidStepSize(f64),
+    /// ステップ幅の揺らぎの幅が [0, 1) の範囲にない
+    InvalidStepSizeJitter(f64),
+    /// 運動量の持続率が [0, 1) の範囲にない
+    InvalidMomentumPersistence(f64),
+    /// 陰的中点法の許容誤差が正の有限値でない
+    InvalidSolverTolerance(f64),
+    /// 陰的中点法の反復回数の上限が0
+    ZeroSolverIterations,
+    /// 運動エネルギーのパラメータが正の有限値でない
+    InvalidKineticParam { param: &'static str, value: f64 },
+    /// ガウス分布の運動量を前提とする設定と、ガウス分布以外の運動エネルギーが組み合わされた
+    UnsupportedKinetic(&'static str),
+    /// ステップ幅の適応の目標採択確率が (0, 1) の範囲にない
+    InvalidTargetAccept(f64),
+    /// 未知の質量行列の名前
+    UnknownMetric(String),
+    /// 質量行列の要素数（密なら行数）が状態の次元と一致しない
+    MetricDimension { expected: usize, got: usize },
+    /// 質量行列の逆の対角成分が正の有限値でない
+    InvalidInvMass(Vec<f64>),
+    /// 密な質量行列が対称正定値でない
+    InvalidMassMatrix(Vec<Vec<f64>>),
+    /// 質量行列の推定の正則化の強さが非負の有限値でない
+    InvalidMassRegularization(f64),
+    /// 段階的なウォームアップの最初の遅い区間の長さが0
+    ZeroWarmupWindow,
+    /// 発散判定の閾値が正でない
+    InvalidDivergenceThreshold(f64),
+    /// 勾配のノルムの上限が正の有限値でない
+    InvalidMaxGradNorm(f64),
+    /// 実行時間の上限 [秒] が非負の有限値でない
+    InvalidMaxDuration(f64),
+    /// 勾配の切り詰めと陰的中点法が組み合わされた（切り詰めた力はポテンシャルの勾配でなく、体積を保たない）
+    ClippedImplicitMidpoint,
+    /// `DivergencePolicy::ShrinkStepSize` の縮小率が (0, 1) の範囲にない
+    InvalidShrinkFactor(f64),
+    /// 温度が正の有限値でない
+    InvalidTemperature(f64),
+    /// 焼きなましの遷移の回数が0
+    ZeroAnnealIterations,
+    /// 初期位置にNaN/無限大が含まれる
+    NonFiniteInitialPoint { x: f64, y: f64 },
+    /// 初期位置でポテンシャルが有限でない（確率密度が0の点から始めようとしている）
+    NonFiniteInitialPotential { x: f64, y: f64, potential: f64 },
+    /// N次元のサンプラーの開始位置かそこでのポテンシャルが有限でない
+    NonFiniteInitialStateNd { position: Vec<f64>, potential: f64 },
+    /// N次元のサンプラーの開始位置の長さがターゲットの次元と一致しない
+    DimensionMismatch { expected: usize, got: usize },
+    /// 座標ごとの設定の長さが状態の次元と一致しない
+    SettingLength {
+        setting: &'static str,
+        expected: usize,
+        got: usize,
+    },
+    /// 別の次元の状態向けの設定が指定された（2次元の `transform` をN次元で、`transform_nd` を2次元で使うなど）
+    SettingDimension { setting: &'static str, dim: usize },
+    /// N次元のサンプラーの開始位置が座標変換の定義域の外にある
+    OutsideTransformDomainNd { position: Vec<f64> },
+    /// N次元のサンプラーの開始位置が反射境界の外にある
+    OutsideBoundsNd { position: Vec<f64> },
+    /// チェーン数が0
+    ZeroChains,
+    /// 初期位置の個数がチェーン数と一致しない
+    InitialPointCount { expected: usize, got: usize },
+    /// 初期位置の生成範囲が不正（有限でない、または min > max）
+    InvalidInitRange { min: f64, max: f64 },
+    /// 初期位置のばらつきの大きさが非負の有限値でない
+    InvalidInitScale(f64),
+    /// モード探索の学習率が正の有限値でない
+    InvalidLearningRate(f64),
+    /// モード探索の勾配降下が発散した
+    ModeSearchDiverged { learning_rate: f64 },
+    /// ターゲット分布のパラメータが不正
+    InvalidTargetParam { param: String, value: f64 },
+    /// データの説明変数と目的変数の長さが一致しない
+    DataLengthMismatch { x: usize, y: usize },
+    /// 共分散行列が対称正定値でない
+    InvalidCovariance([[f64; 2]; 2]),
+    /// `Transform::Logit` の区間が有限でないか lower >= upper
+    InvalidTransform { lower: f64, upper: f64 },
+    /// 開始位置が座標変換の定義域（`Log` なら正、`Logit` なら区間の内側）の外にある
+    OutsideTransformDomain { x: f64, y: f64 },
+    /// 反射境界の範囲が有限でないか min >= max
+    InvalidBounds { min: f64, max: f64 },
+    /// 開始位置が反射境界の矩形の外にある
+    OutsideBounds { x: f64, y: f64 },
+    /// 周期境界の周期が正の有限値でない
+    InvalidPeriod(f64),
+    /// 数値微分の刻み幅が正の有限値でない
+    InvalidNumDiffStep(f64),
+    /// `debug_check_gradient` でターゲットの勾配が数値微分と食い違った
+    GradientMismatch { x: f64, y: f64, max_rel_error: f64 },
+    /// N次元のサンプラーの `debug_check_gradient` でターゲットの勾配が数値微分と食い違った
+    GradientMismatchNd {
+        position: Vec<f64>,
+        max_rel_error: f64,
+    },
+    /// `DivergencePolicy::Abort` 指定時にサンプリング期間中の遷移が発散した
+    ///
+    /// `partial` はその遷移までの結果（`completed` は `false`）。
+    Diverged {
+        iteration: usize,
+        partial: Box<HmcResult>,
+    },
+    /// N次元のサンプラーで `DivergencePolicy::Abort` 指定時にサンプリング期間中の遷移が発散した
+    DivergedNd {
+        iteration: usize,
+        partial: Box<HmcResultNd>,
+    },
+    /// 設定・結果のシリアライズ/デシリアライズに失敗
+    Serialization(String),
+    /// サンプルの書き出しに失敗
+    Io(String),
+    /// サンプルをメモリに溜めないストリーミング実行では使えない設定が指定された
+    UnsupportedStreaming(&'static str),
+    /// 収束診断に使うチェーンが短すぎる
+    ChainTooShort { min: usize, got: usize },
+    /// R̂ の系列内の分散が0か有限でない（定数のチェーンなど）
+    DegenerateChains,
+}
+
+impl fmt::Display for HmcError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HmcError::UnknownDistribution(name) => {
+                write!(
+                    f,
+                    "unknown distribution type: '{}' (expected one of: {})",
+                    name,
+                    DistType::variants().join(", ")
+                )
+            }
+            HmcError::ZeroSamples => write!(f, "n_samples must be at least 1"),
+            HmcError::ZeroThin => write!(f, "thin must be at least 1"),
+            HmcError::ZeroEssCheckInterval => {
+                write!(
+                    f,
+                    "ess_check_every must be at least 1 when target_ess is set"
+                )
+            }
+            HmcError::ZeroTrajectoryInterval => {
+                write!(f, "record_trajectory_every must be at least 1")
+            }
+            HmcError::ZeroLeapfrogSteps => write!(f, "num_steps must be at least 1"),
+            HmcError::ZeroTreeDepth => write!(f, "NUTS max_depth must be at least 1"),
+            HmcError::InvalidProposalStd(v) => write!(
+                f,
+                "random walk proposal_std must be a positive finite number, got {}",
+                v
+            ),
+            HmcError::InvalidSliceWidth(v) => write!(
+                f,
+                "slice initial_width must be a positive finite number, got {}",
+                v
+            ),
+            HmcError::TooFewWalkers(n) => {
+                write!(
+                    f,
+                    "ensemble n_walkers must be at least twice the dimension (4 in 2D), got {}",
+                    n
+                )
+            }
+            HmcError::InvalidStretchScale(v) => write!(
+                f,
+                "ensemble stretch scale a must be a finite number greater than 1, got {}",
+                v
+            ),
+            HmcError::PeriodicEnsemble => {
+                write!(f, "the ensemble sampler cannot be used with a periodic topology")
+            }
+            HmcError::InvalidTemperatureLadder(temperatures) => write!(
+                f,
+                "parallel tempering temperatures must start at 1 and be strictly increasing \
+                 finite numbers with at least two entries, got {:?}",
+                temperatures
+            ),
+            HmcError::ZeroSwapInterval => {
+                write!(f, "parallel tempering swap_every must be at least 1")
+            }
+            HmcError::InvalidStepSize(v) => {
+                write!(f, "step_size must be a positive finite number, got {}", v)
+            }
+            HmcError::InvalidStepSizeJitter(v) => {
+                write!(f, "step_size_jitter must be in [0, 1), got {}", v)
+            }
+            HmcError::InvalidMomentumPersistence(v) => {
+                write!(f, "momentum_persistence must be in [0, 1), got {}", v)
+            }
+            HmcError::InvalidSolverTolerance(v) => write!(
+                f,
+                "implicit midpoint tol must be a positive finite number, got {}",
+                v
+            ),
+            HmcError::ZeroSolverIterations => {
+                write!(f, "implicit midpoint max_iters must be at least 1")
+            }
+            HmcError::InvalidKineticParam { param, value } => write!(
+                f,
+                "kinetic energy parameter {} must be a positive finite number, got {}",
+                param, value
+            ),
+            HmcError::UnsupportedKinetic(feature) => write!(
+                f,
+                "{} requires the gaussian kinetic energy",
+                feature
+            ),
+            HmcError::InvalidTargetAccept(v) => {
+                write!(f, "target_accept must be in (0, 1), got {}", v)
+            }
+            HmcError::UnknownMetric(name) => write!(
+                f,
+                "unknown metric: '{}' (expected unit_e, adapt_dense, a list or a nested list)",
+                name
+            ),
+            HmcError::MetricDimension { expected, got } => write!(
+                f,
+                "metric must have dimension {}, got {}",
+                expected, got
+            ),
+            HmcError::InvalidInvMass(v) => write!(
+                f,
+                "diagonal metric entries must be positive and finite, got {:?}",
+                v
+            ),
+            HmcError::InvalidMassMatrix(m) => write!(
+                f,
+                "mass matrix must be symmetric positive definite, got {:?}",
+                m
+            ),
+            HmcError::InvalidMassRegularization(v) => write!(
+                f,
+                "mass_regularization must be a non-negative finite number, got {}",
+                v
+            ),
+            HmcError::ZeroWarmupWindow => {
+                write!(f, "warmup_schedule base_window must be at least 1")
+            }
+            HmcError::InvalidDivergenceThreshold(v) => {
+                write!(f, "divergence_threshold must be positive, got {}", v)
+            }
+            HmcError::InvalidMaxGradNorm(v) => {
+                write!(f, "max_grad_norm must be a positive finite number, got {}", v)
+            }
+            HmcError::InvalidMaxDuration(v) => write!(
+                f,
+                "max_duration must be a non-negative finite number of seconds, got {}",
+                v
+            ),
+            HmcError::ClippedImplicitMidpoint => {
+                write!(f, "max_grad_norm cannot be used with the implicit_midpoint integrator")
+            }
+            HmcError::InvalidShrinkFactor(v) => write!(
+                f,
+                "shrink_step_size factor must be between 0 and 1, got {}",
+                v
+            ),
+            HmcError::InvalidTemperature(v) => {
+                write!(f, "temperature must be a positive finite number, got {}", v)
+            }
+            HmcError::ZeroAnnealIterations => {
+                write!(f, "annealing n_iterations must be at least 1")
+            }
+            HmcError::NonFiniteInitialPoint { x, y } => {
+                write!(f, "initial position must be finite, got ({}, {})", x, y)
+            }
+            HmcError::NonFiniteInitialPotential { x, y, potential } => write!(
+                f,
+                "potential at initial position ({}, {}) must be finite, got {}",
+                x, y, potential
+            ),
+            HmcError::NonFiniteInitialStateNd {
+                position,
+                potential,
+            } => write!(
+                f,
+                "initial position {:?} must be finite with a finite potential, got {}",
+                position, potential
+            ),
+            HmcError::DimensionMismatch { expected, got } => write!(
+                f,
+                "initial position must have {} coordinates, got {}",
+                expected, got
+            ),
+            HmcError::SettingLength {
+                setting,
+                expected,
+                got,
+            } => write!(
+                f,
+                "{} must have one entry per coordinate ({}), got {}",
+                setting, expected, got
+            ),
+            HmcError::SettingDimension { setting, dim } => write!(
+                f,
+                "{} does not apply to a {}-dimensional state",
+                setting, dim
+            ),
+            HmcError::OutsideTransformDomainNd { position } => write!(
+                f,
+                "initial position {:?} is outside the domain of the coordinate transforms",
+                position
+            ),
+            HmcError::OutsideBoundsNd { position } => {
+                write!(f, "initial position {:?} is outside the bounds", position)
+            }
+            HmcError::ZeroChains => write!(f, "n_chains must be at least 1"),
+            HmcError::InitialPointCount { expected, got } => {
+                write!(
+                    f,
+                    "expected {} initial points (one per chain), got {}",
+                    expected, got
+                )
+            }
+            HmcError::InvalidInitRange { min, max } => {
+                write!(
+                    f,
+                    "initialization range must be finite with min <= max, got [{}, {}]",
+                    min, max
+                )
+            }
+            HmcError::InvalidInitScale(v) => {
+                write!(
+                    f,
+                    "initialization scale must be a non-negative finite number, got {}",
+                    v
+                )
+            }
+            HmcError::InvalidLearningRate(v) => write!(
+                f,
+                "learning_rate must be a positive finite number, got {}",
+                v
+            ),
+            HmcError::ModeSearchDiverged { learning_rate } => write!(
+                f,
+                "mode search diverged; try a smaller learning_rate than {}",
+                learning_rate
+            ),
+            HmcError::InvalidTargetParam { param, value } => {
+                write!(f, "invalid target parameter {}: {}", param, value)
+            }
+            HmcError::DataLengthMismatch { x, y } => {
+                write!(f, "x and y must have the same length, got {} and {}", x, y)
+            }
+            HmcError::InvalidCovariance(cov) => write!(
+                f,
+                "covariance matrix must be symmetric positive definite, got {:?}",
+                cov
+            ),
+            HmcError::InvalidTransform { lower, upper } => write!(
+                f,
+                "logit transform bounds must be finite with lower < upper, got ({}, {})",
+                lower, upper
+            ),
+            HmcError::OutsideTransformDomain { x, y } => write!(
+                f,
+                "initial position ({}, {}) is outside the domain of the coordinate transforms",
+                x, y
+            ),
+            HmcError::InvalidBounds { min, max } => write!(
+                f,
+                "bounds must be finite with min < max, got ({}, {})",
+                min, max
+            ),
+            HmcError::OutsideBounds { x, y } => {
+                write!(f, "initial position ({}, {}) is outside the bounds", x, y)
+            }
+            HmcError::InvalidPeriod(v) => {
+                write!(f, "period must be a positive finite number, got {}", v)
+            }
+            HmcError::InvalidNumDiffStep(v) => write!(
+                f,
+                "numerical differentiation step must be a positive finite number, got {}",
+                v
+            ),
+            HmcError::GradientMismatch {
+                x,
+                y,
+                max_rel_error,
+            } => write!(
+                f,
+                "gradient at ({}, {}) disagrees with finite differences (max relative error {:e})",
+                x, y, max_rel_error
+            ),
+            HmcError::GradientMismatchNd {
+                position,
+                max_rel_error,
+            } => write!(
+                f,
+                "gradient at {:?} disagrees with finite differences (max relative error {:e})",
+                position, max_rel_error
+            ),
+            HmcError::Diverged { iteration, partial } => write!(
+                f,
+                "transition {} diverged and on_divergence is abort ({} samples were drawn before stopping)",
+                iteration,
+                partial.samples.len()
+            ),
+            HmcError::DivergedNd { iteration, partial } => write!(
+                f,
+                "transition {} diverged and on_divergence is abort ({} samples were drawn before stopping)",
+                iteration,
+                partial.n_samples()
+            ),
+            HmcError::Serialization(msg) => write!(f, "serialization error: {}", msg),
+            HmcError::Io(msg) => write!(f, "failed to write samples: {}", msg),
+            HmcError::UnsupportedStreaming(feature) => write!(
+                f,
+                "{} needs the samples in memory and is not supported when streaming",
+                feature
+            ),
+            HmcError::ChainTooShort { min, got } => write!(
+                f,
+                "each chain needs at least {} draws for this diagnostic, got {}",
+                min, got
+            ),
+            HmcError::DegenerateChains => write!(
+                f,
+                "the within-chain variance is zero or not finite (are the chains constant?)"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for HmcError {}
@@ -0,0 +1,179 @@
+//! ユーザー定義の勾配の検証
+
+use serde::{Deserialize, Serialize};
+
+use crate::numdiff::NumDiff;
+use crate::target::Model;
+use crate::{numerical_gradient, HmcError, Point, TargetDistribution, Vector};
+
+/// `HmcConfig::debug_check_gradient` で使う許容誤差
+pub(crate) const DEBUG_CHECK_TOLERANCE: f64 = 1e-4;
+
+/// 1点での勾配チェックの結果
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct GradCheckReport {
+    /// 評価した点
+    pub point: Point,
+    /// ターゲットの `gradient` の値
+    pub gradient: Point,
+    /// ポテンシャルの中心差分
+    pub numerical: Point,
+    /// 座標ごとの絶対誤差
+    pub abs_error: Point,
+    /// 座標ごとの相対誤差 |g - g_num| / max(1, |g_num|)
+    pub rel_error: Point,
+    /// 座標ごとの相対誤差の最大値
+    pub max_rel_error: f64,
+    /// `max_rel_error` が許容誤差以内か（NaNを含めば `false`）
+    pub passed: bool,
+}
+
+/// `target.gradient` をポテンシャルの中心差分と比較する
+///
+/// ```
+/// use hamiltonian_sampler_rs::{check_gradient, Point, Target};
+///
+/// // 符号を間違えた勾配
+/// let target = Target::from_fn_with_grad(
+///     |p: &Point| 0.5 * (p.x * p.x + p.y * p.y),
+///     |p: &Point| Point { x: -p.x, y: -p.y },
+/// );
+/// let reports = check_gradient(&target, &[Point { x: 1.0, y: 2.0 }], 1e-4);
+/// assert!(!reports[0].passed);
+/// ```
+pub fn check_gradient<T: TargetDistribution + ?Sized>(
+    target: &T,
+    points: &[Point],
+    tol: f64,
+) -> Vec<GradCheckReport> {
+    points
+        .iter()
+        .map(|q| {
+            let gradient = target.gradient(q);
+            let numerical = numerical_gradient(target, q);
+            let abs_error = Point {
+                x: (gradient.x - numerical.x).abs(),
+                y: (gradient.y - numerical.y).abs(),
+            };
+            let rel_error = Point {
+                x: abs_error.x / numerical.x.abs().max(1.0),
+                y: abs_error.y / numerical.y.abs().max(1.0),
+            };
+            let max_rel_error = rel_error.x.max(rel_error.y);
+            GradCheckReport {
+                point: q.clone(),
+                gradient,
+                numerical,
+                abs_error,
+                passed: rel_error.x <= tol && rel_error.y <= tol,
+                rel_error,
+                max_rel_error,
+            }
+        })
+        .collect()
+}
+
+/// 開始位置で勾配をチェックし、不一致ならエラーにする
+pub(crate) fn ensure_gradient<T: TargetDistribution + ?Sized>(
+    target: &T,
+    q: &Point,
+) -> Result<(), HmcError> {
+    let report = check_gradient(target, std::slice::from_ref(q), DEBUG_CHECK_TOLERANCE).remove(0);
+    if report.passed {
+        Ok(())
+    } else {
+        Err(HmcError::GradientMismatch {
+            x: q.x,
+            y: q.y,
+            max_rel_error: report.max_rel_error,
+        })
+    }
+}
+
+/// 次元を問わない状態の開始位置 `q` で `ensure_gradient` と同じチェックをする
+pub(crate) fn ensure_gradient_at<V: Vector, T: Model<V> + ?Sized>(
+    target: &T,
+    q: &V,
+) -> Result<(), HmcError> {
+    let gradient = target.gradient_at(q);
+    let numerical = NumDiff::default().gradient_of(|q| target.potential_at(q), q);
+    let rel_error: Vec<f64> = gradient
+        .as_slice()
+        .iter()
+        .zip(numerical.as_slice())
+        .map(|(g, n)| (g - n).abs() / n.abs().max(1.0))
+        .collect();
+    if rel_error.iter().all(|e| *e <= DEBUG_CHECK_TOLERANCE) {
+        Ok(())
+    } else {
+        Err(HmcError::GradientMismatchNd {
+            position: q.as_slice().to_vec(),
+            max_rel_error: rel_error.iter().fold(f64::NAN, |m, e| m.max(*e)),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{run_hmc_with_target, Banana, Bimodal, HmcConfig, Target};
+
+    fn gaussian(p: &Point) -> f64 {
+        0.5 * (p.x * p.x + 4.0 * p.y * p.y)
+    }
+
+    #[test]
+    fn builtin_gradients_pass() {
+        let points = [
+            Point { x: 0.0, y: 0.0 },
+            Point { x: 1.5, y: -2.0 },
+            Point { x: -3.0, y: 4.0 },
+        ];
+        for report in check_gradient(&Bimodal::default(), &points, 1e-6)
+            .into_iter()
+            .chain(check_gradient(&Banana::default(), &points, 1e-6))
+        {
+            assert!(report.passed, "{:?}", report);
+        }
+    }
+
+    #[test]
+    fn wrong_gradient_is_flagged() {
+        // y 成分の係数を書き忘れた勾配
+        let target = Target::from_fn_with_grad(gaussian, |p: &Point| Point { x: p.x, y: p.y });
+        let reports = check_gradient(
+            &target,
+            &[Point { x: 1.0, y: 0.0 }, Point { x: 1.0, y: 1.0 }],
+            1e-4,
+        );
+        // y = 0 では誤りが表に出ない
+        assert!(reports[0].passed);
+        assert!(!reports[1].passed);
+        assert!(reports[1].abs_error.x < 1e-6);
+        assert!((reports[1].abs_error.y - 3.0).abs() < 1e-6);
+        assert!((reports[1].max_rel_error - 0.75).abs() < 1e-6);
+    }
+
+    #[test]
+    fn debug_check_rejects_wrong_gradient_before_sampling() {
+        let config = HmcConfig {
+            initial_pos: Point { x: 1.0, y: 1.0 },
+            debug_check_gradient: true,
+            ..HmcConfig::default()
+        };
+        let wrong = Target::from_fn_with_grad(gaussian, |p: &Point| Point {
+            x: -p.x,
+            y: -4.0 * p.y,
+        });
+        assert!(matches!(
+            run_hmc_with_target(&config, &wrong),
+            Err(HmcError::GradientMismatch { x, y, .. }) if x == 1.0 && y == 1.0
+        ));
+
+        let right = Target::from_fn_with_grad(gaussian, |p: &Point| Point {
+            x: p.x,
+            y: 4.0 * p.y,
+        });
+        assert!(run_hmc_with_target(&config, &right).is_ok());
+    }
+}
@@ -0,0 +1,208 @@
+//! チェーンの初期位置の決定（モード探索・複数チェーン用の初期位置の生成）
+
+use rand::prelude::*;
+use rand_distr::StandardNormal;
+use serde::{Deserialize, Serialize};
+
+use crate::numdiff::NumDiff;
+use crate::target::{sampler_gradient, Model};
+use crate::vector::{self, Vector};
+use crate::{ChainRng, HmcError, Point};
+
+/// チェーンの開始位置の決め方
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum InitStrategy {
+    /// `initial_pos`（または `resume_from`）からそのまま始める
+    #[default]
+    Given,
+    /// `initial_pos` から勾配降下でポテンシャルを最小化し、見つかったモード付近から始める
+    FindMode {
+        /// 勾配降下の最大反復回数
+        max_iters: usize,
+        /// 勾配降下の学習率
+        learning_rate: f64,
+    },
+}
+
+/// 勾配降下でポテンシャルの極小点を探す
+///
+/// 勾配のノルムが十分小さくなるか `max_iters` 回に達したら止める。
+/// 学習率が大きすぎると数値微分の精度が尽きる遠方まで飛んで勾配が0に見えることがあるため、
+/// 開始点よりポテンシャルが高い点で終わった場合も発散とみなす。
+pub(crate) fn find_mode<V: Vector, T: Model<V> + ?Sized>(
+    start: &V,
+    target: &T,
+    max_iters: usize,
+    learning_rate: f64,
+    numdiff: Option<&NumDiff>,
+) -> Result<V, HmcError> {
+    let mut p = start.clone();
+    for _ in 0..max_iters {
+        let grad = sampler_gradient(target, &p, numdiff);
+        if vector::norm(&grad) < 1e-8 {
+            break;
+        }
+        p.axpy(-learning_rate, &grad);
+        if !vector::is_finite(&p) {
+            break;
+        }
+    }
+    let u = target.potential_at(&p);
+    if !(vector::is_finite(&p) && u.is_finite() && u <= target.potential_at(start)) {
+        return Err(HmcError::ModeSearchDiverged { learning_rate });
+    }
+    Ok(p)
+}
+
+/// 矩形 `[xmin, xmax] × [ymin, ymax]` 内に一様に散らばった `n` 個の初期位置を作る
+///
+/// 多峰な分布で全チェーンが同じモードに落ちるのを避けるため、ターゲットより広めの範囲を与える。
+pub fn init_uniform_box(
+    n: usize,
+    xmin: f64,
+    xmax: f64,
+    ymin: f64,
+    ymax: f64,
+    seed: Option<u64>,
+) -> Result<Vec<Point>, HmcError> {
+    for (min, max) in [(xmin, xmax), (ymin, ymax)] {
+        if !(min.is_finite() && max.is_finite() && min <= max) {
+            return Err(HmcError::InvalidInitRange { min, max });
+        }
+    }
+    let mut rng = init_rng(seed);
+    Ok((0..n)
+        .map(|_| Point {
+            x: xmin + (xmax - xmin) * rng.gen::<f64>(),
+            y: ymin + (ymax - ymin) * rng.gen::<f64>(),
+        })
+        .collect())
+}
+
+/// `center` の周りに標準偏差 `scale` の正規ノイズを加えた `n` 個の初期位置を作る
+pub fn init_jitter(
+    n: usize,
+    center: &Point,
+    scale: f64,
+    seed: Option<u64>,
+) -> Result<Vec<Point>, HmcError> {
+    if !(scale.is_finite() && scale >= 0.0) {
+        return Err(HmcError::InvalidInitScale(scale));
+    }
+    let mut rng = init_rng(seed);
+    Ok((0..n)
+        .map(|_| {
+            let dx: f64 = rng.sample(StandardNormal);
+            let dy: f64 = rng.sample(StandardNormal);
+            Point {
+                x: center.x + scale * dx,
+                y: center.y + scale * dy,
+            }
+        })
+        .collect())
+}
+
+fn init_rng(seed: Option<u64>) -> ChainRng {
+    match seed {
+        Some(seed) => ChainRng::seed_from_u64(seed),
+        None => ChainRng::from_entropy(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{run_hmc_chains, Banana, DistType, HmcConfig};
+
+    #[test]
+    fn box_points_stay_inside_the_box() {
+        let points = init_uniform_box(100, -1.0, 2.0, 3.0, 4.0, Some(1)).unwrap();
+        assert_eq!(points.len(), 100);
+        assert!(points
+            .iter()
+            .all(|p| (-1.0..=2.0).contains(&p.x) && (3.0..=4.0).contains(&p.y)));
+        assert_eq!(
+            points,
+            init_uniform_box(100, -1.0, 2.0, 3.0, 4.0, Some(1)).unwrap()
+        );
+    }
+
+    #[test]
+    fn jitter_centers_on_given_point() {
+        let center = Point { x: 3.0, y: -1.0 };
+        let points = init_jitter(2000, &center, 0.5, Some(2)).unwrap();
+        let mean_x = points.iter().map(|p| p.x).sum::<f64>() / 2000.0;
+        let mean_y = points.iter().map(|p| p.y).sum::<f64>() / 2000.0;
+        assert!((mean_x - 3.0).abs() < 0.05);
+        assert!((mean_y + 1.0).abs() < 0.05);
+        assert_eq!(init_jitter(3, &center, 0.0, None).unwrap(), vec![center; 3]);
+    }
+
+    #[test]
+    fn dispersed_starts_visit_both_modes() {
+        let config = HmcConfig {
+            n_samples: 300,
+            target: DistType::default(),
+            seed: Some(5),
+            ..HmcConfig::default()
+        };
+        let starts = init_uniform_box(8, -5.0, 5.0, -5.0, 5.0, Some(5)).unwrap();
+        let result = run_hmc_chains(&config, starts.len(), &starts).unwrap();
+
+        let near = |p: &Point, c: f64| (p.x - c).hypot(p.y - c) < 1.5;
+        let visits = |c: f64| {
+            result
+                .chains
+                .iter()
+                .any(|chain| chain.samples.iter().any(|p| near(p, c)))
+        };
+        assert!(visits(2.5));
+        assert!(visits(-2.5));
+    }
+
+    #[test]
+    fn find_mode_lands_near_banana_mode() {
+        let mode = find_mode(
+            &Point { x: -1.0, y: 2.0 },
+            &Banana::default(),
+            20_000,
+            0.005,
+            None,
+        )
+        .unwrap();
+        assert!((mode.x - 1.0).abs() < 0.01, "{:?}", mode);
+        assert!((mode.y - 1.0).abs() < 0.02, "{:?}", mode);
+    }
+
+    #[test]
+    fn find_mode_reports_divergence() {
+        assert_eq!(
+            find_mode(
+                &Point { x: 2.0, y: 0.0 },
+                &Banana::default(),
+                100,
+                1.0,
+                None
+            )
+            .unwrap_err(),
+            HmcError::ModeSearchDiverged { learning_rate: 1.0 }
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_ranges() {
+        assert_eq!(
+            init_uniform_box(4, 1.0, -1.0, 0.0, 1.0, None).unwrap_err(),
+            HmcError::InvalidInitRange {
+                min: 1.0,
+                max: -1.0
+            }
+        );
+        assert!(init_uniform_box(4, 0.0, 1.0, f64::NAN, 1.0, None).is_err());
+        assert_eq!(
+            init_jitter(4, &Point::default(), -1.0, None).unwrap_err(),
+            HmcError::InvalidInitScale(-1.0)
+        );
+    }
+}
@@ -0,0 +1,525 @@
+//! ハミルトン方程式のシンプレクティック積分器
+
+use serde::{Deserialize, Serialize};
+
+use crate::vector::{self, Vector};
+use crate::{HmcError, Point};
+
+/// 積分器の種類
+///
+/// JSONでは `"leapfrog"`、`"yoshida4"`、`{"implicit_midpoint": {"tol": 1e-10, "max_iters": 100}}`（各値は省略可）。
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum IntegratorKind {
+    /// 2次のリープフロッグ（velocity Verlet）。1ステップで勾配を1回評価する
+    #[default]
+    Leapfrog,
+    /// リープフロッグを3回合成した4次の積分器（Yoshida 1990）。1ステップで勾配を3回評価する
+    ///
+    /// エネルギー誤差は ε⁴ に比例するため、滑らかなターゲットではずっと大きなステップ幅を使える。
+    Yoshida4,
+    /// 2次の陰的中点法 z' = z + ε J∇H((z + z') / 2)
+    ///
+    /// シンプレクティックで、二次形式の保存量（ガウス分布のハミルトニアンなど）を厳密に保つため、
+    /// 曲率の大きいターゲットでもエネルギー誤差が溜まりにくい。方程式は緩和付きの不動点反復で解き、
+    /// 反復ごとに勾配を1回評価する。中点の更新量の最大値が `tol` 以下になれば収束とし、
+    /// `max_iters` 回で収束しなければその遷移を棄却する（`HmcResult::n_solver_failures` に数える）。
+    /// 反射境界は解いた後の位置に適用する。
+    ImplicitMidpoint {
+        #[serde(default = "default_tol")]
+        tol: f64,
+        #[serde(default = "default_max_iters")]
+        max_iters: usize,
+    },
+}
+
+fn default_tol() -> f64 {
+    1e-10
+}
+
+fn default_max_iters() -> usize {
+    100
+}
+
+impl IntegratorKind {
+    /// 陰的中点法の許容誤差が正の有限値で、反復回数の上限が1以上かを確かめる
+    pub fn validate(&self) -> Result<(), HmcError> {
+        match *self {
+            IntegratorKind::ImplicitMidpoint { tol, .. } if !(tol.is_finite() && tol > 0.0) => {
+                Err(HmcError::InvalidSolverTolerance(tol))
+            }
+            IntegratorKind::ImplicitMidpoint { max_iters: 0, .. } => {
+                Err(HmcError::ZeroSolverIterations)
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// 相空間の点（位置・運動量と、その位置でのポテンシャルの勾配）
+#[derive(Clone, Debug, PartialEq)]
+pub struct PhasePoint<V = Point> {
+    pub q: V,
+    pub p: V,
+    /// ∇U(q)（積分器は `q` を動かしたら更新する）
+    pub grad: V,
+}
+
+/// 積分器から見たハミルトン力学系 H(q, p) = U(q) + K(p)
+///
+/// 位置は非制約空間のもので、温度・変換のヤコビアン・数値微分はすでに反映されている。
+pub trait Hamiltonian<V = Point> {
+    /// ポテンシャルの勾配 ∇U(q)（呼ぶたびに `HmcResult::n_gradient_evals` に数える）
+    fn gradient(&self, q: &V) -> V;
+
+    /// 速度 dK/dp（ガウス分布の運動量なら M⁻¹p）
+    fn velocity(&self, p: &V) -> V;
+
+    /// 位置を動かした後に、反射境界での反射（運動量も反転する）と周期境界での折り畳みを行う
+    fn constrain(&self, q: &mut V, p: &mut V);
+}
+
+/// 積分の1ステップが失敗した理由
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StepFailure {
+    /// 勾配が有限でない点に達した（軌道を打ち切り、台の外に出たか発散したとみなす）
+    NonFiniteGradient,
+    /// 陰的な方程式の反復が収束しなかった（発散とも台の外とも数えずに棄却する）
+    NotConverged,
+}
+
+/// 軌道を作る1ステップ分の積分
+///
+/// 固定長のHMC・NUTS・レプリカ交換法は、この `step` を繰り返して軌道を作る。組み込みの積分器は
+/// `IntegratorKind` で選び、独自の実装は `HmcBuilder::integrator` か `Chain::set_integrator` で差し替える。
+/// Metropolis判定が正しくなるよう、実装は時間反転可能（`step_size` を負にすると元に戻る）で
+/// 体積を保つ必要がある。型引数 `V` は状態のベクトルで、組み込みの積分器はどの [`Vector`] にも使える。
+///
+/// ```
+/// use hamiltonian_sampler_rs::{
+///     Banana, DistType, Hamiltonian, HmcBuilder, Integrator, PhasePoint, StepFailure,
+///     VelocityVerlet,
+/// };
+///
+/// /// 2段のリープフロッグの合成（幅 ε/2 を2回）
+/// struct HalfSteps;
+///
+/// impl Integrator for HalfSteps {
+///     fn step(
+///         &self,
+///         system: &dyn Hamiltonian,
+///         z: &mut PhasePoint,
+///         step_size: f64,
+///     ) -> Result<(), StepFailure> {
+///         VelocityVerlet.step(system, z, 0.5 * step_size)?;
+///         VelocityVerlet.step(system, z, 0.5 * step_size)
+///     }
+/// }
+///
+/// let result = HmcBuilder::new()
+///     .n_samples(200)
+///     .target(DistType::Banana(Banana::default()))
+///     .integrator(HalfSteps)
+///     .seed(1)
+///     .build()
+///     .unwrap()
+///     .run()
+///     .unwrap();
+/// assert_eq!(result.n_gradient_evals, 2 * result.n_leapfrog);
+/// ```
+pub trait Integrator<V = Point>: Send + Sync {
+    /// `z` を時間 `step_size`（負なら逆向き）だけ進める
+    ///
+    /// 成功したら `z.grad` は新しい位置での勾配になっている。
+    fn step(
+        &self,
+        system: &dyn Hamiltonian<V>,
+        z: &mut PhasePoint<V>,
+        step_size: f64,
+    ) -> Result<(), StepFailure>;
+}
+
+/// 2次のリープフロッグ（velocity Verlet）
+#[derive(Clone, Copy, Debug, Default)]
+pub struct VelocityVerlet;
+
+impl<V: Vector> Integrator<V> for VelocityVerlet {
+    /// 移動先の勾配が有限でなければ、運動量を壊さないよう後半の半ステップを行わずに止める
+    fn step(
+        &self,
+        system: &dyn Hamiltonian<V>,
+        z: &mut PhasePoint<V>,
+        step_size: f64,
+    ) -> Result<(), StepFailure> {
+        // --- Velocity Verlet (Standard Leapfrog) ---
+        // p half step
+        z.p.axpy(-0.5 * step_size, &z.grad);
+
+        // q full step
+        let v = system.velocity(&z.p);
+        z.q.axpy(step_size, &v);
+        system.constrain(&mut z.q, &mut z.p);
+
+        // p half step
+        z.grad = system.gradient(&z.q); // Re-evaluate gradient at new q
+        if !vector::is_finite(&z.grad) {
+            return Err(StepFailure::NonFiniteGradient);
+        }
+        z.p.axpy(-0.5 * step_size, &z.grad);
+        Ok(())
+    }
+}
+
+/// 幅 w₁ε, w₀ε, w₁ε のリープフロッグの合成（w₁ = 1/(2 - 2^(1/3)), w₀ = 1 - 2w₁ < 0）
+///
+/// 対称な合成なので時間反転可能で、シンプレクティック性もそのまま保つ。
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Yoshida4;
+
+impl Yoshida4 {
+    const W1: f64 = 1.351_207_191_959_657_6;
+    const W0: f64 = 1.0 - 2.0 * Self::W1;
+}
+
+impl<V: Vector> Integrator<V> for Yoshida4 {
+    fn step(
+        &self,
+        system: &dyn Hamiltonian<V>,
+        z: &mut PhasePoint<V>,
+        step_size: f64,
+    ) -> Result<(), StepFailure> {
+        for w in [Self::W1, Self::W0, Self::W1] {
+            VelocityVerlet.step(system, z, w * step_size)?;
+        }
+        Ok(())
+    }
+}
+
+/// 陰的中点法（`IntegratorKind::ImplicitMidpoint` を参照）
+#[derive(Clone, Copy, Debug)]
+pub struct ImplicitMidpoint {
+    /// 中点の更新量の最大値がこれ以下になれば収束とする
+    pub tol: f64,
+    /// 不動点反復の回数の上限
+    pub max_iters: usize,
+}
+
+impl<V: Vector> Integrator<V> for ImplicitMidpoint {
+    /// 収束しなかった場合は `z` を変えずに `StepFailure::NotConverged` を返す
+    ///
+    /// p' = p - ε∇U(m), q' = q + ε M⁻¹(p + p')/2 を中点 m = (q + q')/2 だけの方程式
+    /// m = q + (ε/2) M⁻¹p - (ε²/4) M⁻¹∇U(m) に直して反復する。この反復のヤコビ行列
+    /// -(ε²/4) M⁻¹∇²U の固有値は（凸な領域では）負の実数なので、連続する残差の比から
+    /// 振動の大きさを見積もって緩和係数を縮めれば、リープフロッグが不安定になる大きさの ε でも収束する。
+    fn step(
+        &self,
+        system: &dyn Hamiltonian<V>,
+        z: &mut PhasePoint<V>,
+        step_size: f64,
+    ) -> Result<(), StepFailure> {
+        let (half, quarter_sq) = (0.5 * step_size, 0.25 * step_size * step_size);
+        let mut center = z.q.clone();
+        center.axpy(half, &system.velocity(&z.p));
+        let solve = |grad: &V| {
+            let mut m = center.clone();
+            m.axpy(-quarter_sq, &system.velocity(grad));
+            m
+        };
+        // 初期値は開始点の勾配を使った陽的な近似
+        let mut mid = solve(&z.grad);
+        let mut relaxation = 1.0;
+        let mut last_residual: Option<V> = None;
+        for _ in 0..self.max_iters {
+            let grad = system.gradient(&mid);
+            if !vector::is_finite(&grad) {
+                return Err(StepFailure::NonFiniteGradient);
+            }
+            let next = solve(&grad);
+            let residual = vector::zip_with(&next, &mid, |n, m| n - m);
+            let change = residual
+                .as_slice()
+                .iter()
+                .map(|r| r.abs())
+                .reduce(f64::max)
+                .unwrap_or(0.0);
+            if change <= self.tol {
+                let mut q = vector::zip_with(&mid, &z.q, |m, q| 2.0 * m - q);
+                let mut p = z.p.clone();
+                p.axpy(-step_size, &grad);
+                system.constrain(&mut q, &mut p);
+                z.q = q;
+                z.p = p;
+                // 終点の勾配は次のステップの初期値に使う
+                z.grad = system.gradient(&z.q);
+                if !vector::is_finite(&z.grad) {
+                    return Err(StepFailure::NonFiniteGradient);
+                }
+                return Ok(());
+            }
+            if let Some(last) = &last_residual {
+                let ratio = residual.dot(last) / last.dot(last);
+                if ratio < 1.0 {
+                    relaxation = (relaxation / (1.0 - ratio)).min(1.0);
+                }
+            }
+            mid.axpy(relaxation, &residual);
+            last_residual = Some(residual);
+        }
+        Err(StepFailure::NotConverged)
+    }
+}
+
+impl<V: Vector> Integrator<V> for IntegratorKind {
+    fn step(
+        &self,
+        system: &dyn Hamiltonian<V>,
+        z: &mut PhasePoint<V>,
+        step_size: f64,
+    ) -> Result<(), StepFailure> {
+        match *self {
+            IntegratorKind::Leapfrog => VelocityVerlet.step(system, z, step_size),
+            IntegratorKind::Yoshida4 => Yoshida4.step(system, z, step_size),
+            IntegratorKind::ImplicitMidpoint { tol, max_iters } => {
+                ImplicitMidpoint { tol, max_iters }.step(system, z, step_size)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    use crate::{run_hmc, run_hmc_with_hooks, Algorithm, Banana, DistType, HmcConfig, RunHooks};
+
+    /// 軌道の長さ εL = 1 を保ったまま ε を変えたときの、エネルギー誤差の絶対値の平均
+    fn mean_abs_energy_error(integrator: IntegratorKind, step_size: f64) -> (f64, usize) {
+        let config = HmcConfig {
+            n_samples: 4000,
+            n_warmup: 0,
+            step_size,
+            num_steps: (1.0 / step_size).round() as usize,
+            integrator,
+            save_energy: true,
+            target: DistType::Normal,
+            seed: Some(71),
+            ..HmcConfig::default()
+        };
+        let result = run_hmc(&config).unwrap();
+        let mean = result.energy_errors.iter().map(|e| e.abs()).sum::<f64>()
+            / result.energy_errors.len() as f64;
+        (mean, result.n_gradient_evals)
+    }
+
+    #[test]
+    fn energy_error_scales_with_integrator_order() {
+        // ε を半分にすると誤差はリープフロッグで約 2² 倍、Yoshida で約 2⁴ 倍小さくなる
+        let mut errors = Vec::new();
+        for (integrator, order) in [
+            (IntegratorKind::Leapfrog, 2.0),
+            (IntegratorKind::Yoshida4, 4.0),
+        ] {
+            let (coarse, coarse_evals) = mean_abs_energy_error(integrator, 0.2);
+            let (fine, fine_evals) = mean_abs_energy_error(integrator, 0.1);
+            let exponent = (coarse / fine).log2();
+            assert!(
+                (exponent - order).abs() < 0.3,
+                "{:?}: {}",
+                integrator,
+                exponent
+            );
+            assert_eq!(fine_evals, 2 * coarse_evals);
+            errors.push((coarse, coarse_evals));
+        }
+        let ((leapfrog, leapfrog_evals), (yoshida, yoshida_evals)) = (errors[0], errors[1]);
+        assert_eq!(yoshida_evals, 3 * leapfrog_evals);
+        assert!(yoshida < leapfrog / 10.0, "{} vs {}", yoshida, leapfrog);
+    }
+
+    #[test]
+    fn implicit_midpoint_conserves_energy_where_leapfrog_diverges() {
+        // b = 100 では谷の底の曲率が大きく、ε = 0.08 でリープフロッグの安定条件を超える
+        let config = |integrator| HmcConfig {
+            n_samples: 100,
+            n_warmup: 0,
+            step_size: 0.08,
+            num_steps: 200,
+            integrator,
+            save_energy: true,
+            initial_pos: Point { x: 1.0, y: 1.0 },
+            target: DistType::Banana(Banana { a: 1.0, b: 100.0 }),
+            seed: Some(72),
+            ..HmcConfig::default()
+        };
+        let leapfrog = run_hmc(&config(IntegratorKind::Leapfrog)).unwrap();
+        assert!(leapfrog.n_divergent >= 95, "{}", leapfrog.n_divergent);
+
+        let parsed: HmcConfig =
+            serde_json::from_str(r#"{"integrator": {"implicit_midpoint": {}}}"#).unwrap();
+        let implicit = run_hmc(&config(parsed.integrator)).unwrap();
+        assert_eq!(implicit.n_divergent, 0);
+        assert_eq!(implicit.n_solver_failures, 0);
+        let max_error = implicit
+            .energy_errors
+            .iter()
+            .fold(0.0_f64, |m, e| m.max(e.abs()));
+        assert!(max_error < 5.0, "{}", max_error);
+        assert!(
+            implicit.acceptance_rate > 0.7,
+            "{}",
+            implicit.acceptance_rate
+        );
+        // 不動点反復のぶん、1ステップあたりの勾配の評価は多い
+        assert!(implicit.n_gradient_evals > 2 * implicit.n_leapfrog);
+    }
+
+    #[test]
+    fn implicit_midpoint_counts_unconverged_solves_as_rejections() {
+        let config = HmcConfig {
+            n_samples: 50,
+            n_warmup: 0,
+            step_size: 0.08,
+            num_steps: 20,
+            integrator: IntegratorKind::ImplicitMidpoint {
+                tol: 1e-10,
+                max_iters: 2,
+            },
+            initial_pos: Point { x: 1.0, y: 1.0 },
+            target: DistType::Banana(Banana { a: 1.0, b: 100.0 }),
+            seed: Some(72),
+            ..HmcConfig::default()
+        };
+        let result = run_hmc(&config).unwrap();
+        assert_eq!(result.n_solver_failures, 50);
+        assert_eq!(result.acceptance_rate, 0.0);
+        assert_eq!((result.n_divergent, result.n_out_of_support), (0, 0));
+
+        for (integrator, error) in [
+            (
+                IntegratorKind::ImplicitMidpoint {
+                    tol: 0.0,
+                    max_iters: 10,
+                },
+                HmcError::InvalidSolverTolerance(0.0),
+            ),
+            (
+                IntegratorKind::ImplicitMidpoint {
+                    tol: 1e-8,
+                    max_iters: 0,
+                },
+                HmcError::ZeroSolverIterations,
+            ),
+        ] {
+            assert_eq!(
+                HmcConfig {
+                    integrator,
+                    ..HmcConfig::default()
+                }
+                .validate(),
+                Err(error)
+            );
+        }
+    }
+
+    /// 既定のリープフロッグの出力を、積分器を差し替えられるようにする前の値と比べる
+    #[test]
+    fn default_leapfrog_output_is_unchanged() {
+        let config = |algorithm| HmcConfig {
+            n_samples: 300,
+            n_warmup: 100,
+            adapt_step_size: true,
+            algorithm,
+            target: DistType::Banana(Banana::default()),
+            seed: Some(73),
+            ..HmcConfig::default()
+        };
+        let golden = [
+            (
+                Algorithm::Hmc,
+                (0.9970548365530869, 0.9176908788223581),
+                (1.2716558036577903, 1.220280145869437),
+                0.06582001058284692,
+                6000,
+                0.9433333333333334,
+            ),
+            (
+                Algorithm::Nuts { max_depth: 10 },
+                (1.6370103201249713, 2.6677087304731018),
+                (1.3616743215378968, 2.007295866569712),
+                0.12954184965651988,
+                3424,
+                0.9566666666666667,
+            ),
+        ];
+        for (algorithm, last, middle, step_size, n_gradient_evals, acceptance_rate) in golden {
+            let config = config(algorithm);
+            let mut results = vec![run_hmc(&config).unwrap()];
+            // `VelocityVerlet` をトレイトオブジェクトとして差し込んでも同じ出力になる
+            let mut hooks = RunHooks {
+                integrator: Some(Arc::new(VelocityVerlet)),
+                ..RunHooks::default()
+            };
+            results.push(run_hmc_with_hooks(&config, &mut hooks).unwrap());
+            for result in results {
+                let point = |(x, y)| Point { x, y };
+                assert_eq!(result.samples[299], point(last));
+                assert_eq!(result.samples[150], point(middle));
+                assert_eq!(result.adapted_step_size, Some(step_size));
+                assert_eq!(result.n_gradient_evals, n_gradient_evals);
+                assert_eq!(result.acceptance_rate, acceptance_rate);
+            }
+        }
+    }
+
+    /// 固定長のHMCの1遷移では各ステップの移動先で1回ずつ勾配を評価し、開始点の勾配は前の遷移のものを使い回す
+    #[test]
+    fn static_transition_evaluates_gradient_once_per_step() {
+        use crate::{Chain, ChainRng, Target, TargetDistribution};
+        use rand::SeedableRng;
+        use std::cell::Cell;
+
+        let banana = Banana::default();
+        let calls = Cell::new(0);
+        let counting = Target::from_fn_with_grad(
+            |q: &Point| banana.potential(q),
+            |q: &Point| {
+                calls.set(calls.get() + 1);
+                banana.gradient(q)
+            },
+        );
+        let config = HmcConfig {
+            step_size: 0.1,
+            num_steps: 10,
+            target: DistType::Banana(Banana::default()),
+            seed: Some(81),
+            ..HmcConfig::default()
+        };
+        let mut chain =
+            Chain::with_target(config.clone(), &counting, ChainRng::seed_from_u64(81)).unwrap();
+        // 毎回チェックポイントから復元して勾配を使い回さないチェーンと、同じ軌跡になる
+        let mut fresh = Chain::new(config).unwrap();
+        let mut n_accepted = 0;
+        for i in 0..200 {
+            calls.set(0);
+            let t = chain.step();
+            fresh = Chain::restore(fresh.save()).unwrap();
+            assert_eq!(fresh.step().position, t.position);
+            assert_eq!(t.n_leapfrog, 10);
+            // 開始点の勾配を評価するのは最初の遷移だけ
+            assert_eq!(calls.get(), if i == 0 { 11 } else { 10 });
+            // 積分器が評価した分（開始点の勾配は含まない）
+            assert_eq!(t.n_gradient_evals, 10);
+            n_accepted += t.accepted as usize;
+        }
+        // 採択後（軌道の終点）と棄却後（前の開始点）の両方の使い回しを通る
+        assert!(n_accepted > 20 && n_accepted < 180, "{}", n_accepted);
+    }
+
+    #[test]
+    fn yoshida_weights_sum_to_one_and_cancel_third_order() {
+        let (w1, w0) = (Yoshida4::W1, Yoshida4::W0);
+        assert!((2.0 * w1 + w0 - 1.0).abs() < 1e-15);
+        assert!((2.0 * w1.powi(3) + w0.powi(3)).abs() < 1e-14);
+    }
+}
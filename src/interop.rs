@@ -0,0 +1,240 @@
+//! `nalgebra`・`ndarray` の型との相互変換（それぞれ同名のフィーチャーで有効になる）
+
+#[cfg(feature = "nalgebra")]
+use nalgebra::{DMatrix, Matrix2, SVector, Vector2};
+#[cfg(feature = "ndarray")]
+use ndarray::{Array1, Array2, ArrayView1};
+
+use crate::{HmcError, HmcResult, HmcResultNd, Point};
+#[cfg(feature = "nalgebra")]
+use crate::{Metric, PointN};
+
+impl HmcResult {
+    /// サンプルを行優先に並べた列（`flat_samples` 指定時は `samples_flat` をそのまま使う）
+    fn rows(&self) -> Vec<f64> {
+        if self.samples.is_empty() {
+            self.samples_flat.clone()
+        } else {
+            Point::flatten(&self.samples)
+        }
+    }
+
+    /// サンプルを1行1点の n×2 行列にする
+    #[cfg(feature = "ndarray")]
+    pub fn to_ndarray(&self) -> Array2<f64> {
+        let rows = self.rows();
+        Array2::from_shape_vec((rows.len() / 2, 2), rows).expect("two coordinates per sample")
+    }
+
+    /// サンプルを1行1点の n×2 行列にする
+    #[cfg(feature = "nalgebra")]
+    pub fn to_dmatrix(&self) -> DMatrix<f64> {
+        let rows = self.rows();
+        DMatrix::from_row_slice(rows.len() / 2, 2, &rows)
+    }
+}
+
+impl HmcResultNd {
+    /// サンプルを1行1点の n×dim 行列にする
+    #[cfg(feature = "ndarray")]
+    pub fn to_ndarray(&self) -> Array2<f64> {
+        Array2::from_shape_vec((self.n_samples(), self.dim), self.samples.clone())
+            .expect("samples hold n_samples * dim values")
+    }
+
+    /// サンプルを1行1点の n×dim 行列にする
+    #[cfg(feature = "nalgebra")]
+    pub fn to_dmatrix(&self) -> DMatrix<f64> {
+        DMatrix::from_row_slice(self.n_samples(), self.dim, &self.samples)
+    }
+}
+
+#[cfg(feature = "nalgebra")]
+impl From<Vector2<f64>> for Point {
+    fn from(v: Vector2<f64>) -> Self {
+        Point { x: v.x, y: v.y }
+    }
+}
+
+#[cfg(feature = "nalgebra")]
+impl From<Point> for Vector2<f64> {
+    fn from(p: Point) -> Self {
+        Vector2::new(p.x, p.y)
+    }
+}
+
+#[cfg(feature = "nalgebra")]
+impl<const D: usize> From<SVector<f64, D>> for PointN<D> {
+    fn from(v: SVector<f64, D>) -> Self {
+        PointN(v.into())
+    }
+}
+
+#[cfg(feature = "nalgebra")]
+impl<const D: usize> From<PointN<D>> for SVector<f64, D> {
+    fn from(p: PointN<D>) -> Self {
+        SVector::from(p.0)
+    }
+}
+
+/// 密な質量行列 M を `Metric::Dense` にする（次元は行列の大きさ）
+///
+/// 正方行列でなければ `HmcError::DimensionMismatch`、対称正定値でなければ `HmcError::InvalidMassMatrix`。
+#[cfg(feature = "nalgebra")]
+impl TryFrom<DMatrix<f64>> for Metric {
+    type Error = HmcError;
+
+    fn try_from(mass: DMatrix<f64>) -> Result<Self, HmcError> {
+        let (rows, cols) = mass.shape();
+        if rows != cols {
+            return Err(HmcError::DimensionMismatch {
+                expected: rows,
+                got: cols,
+            });
+        }
+        let metric = Metric::Dense(
+            mass.row_iter()
+                .map(|row| row.iter().copied().collect())
+                .collect(),
+        );
+        metric.validate_dim(rows)?;
+        Ok(metric)
+    }
+}
+
+/// 密な質量行列 M を `Metric::Dense` にする（対称正定値でなければ `HmcError::InvalidMassMatrix`）
+#[cfg(feature = "nalgebra")]
+impl TryFrom<Matrix2<f64>> for Metric {
+    type Error = HmcError;
+
+    fn try_from(mass: Matrix2<f64>) -> Result<Self, HmcError> {
+        Metric::try_from(DMatrix::from_row_slice(
+            2,
+            2,
+            &[mass.m11, mass.m12, mass.m21, mass.m22],
+        ))
+    }
+}
+
+#[cfg(feature = "ndarray")]
+impl From<Point> for Array1<f64> {
+    fn from(p: Point) -> Self {
+        Array1::from(vec![p.x, p.y])
+    }
+}
+
+/// 長さ2の配列を点にする（長さが違えば `HmcError::DimensionMismatch`）
+#[cfg(feature = "ndarray")]
+impl TryFrom<ArrayView1<'_, f64>> for Point {
+    type Error = HmcError;
+
+    fn try_from(v: ArrayView1<'_, f64>) -> Result<Self, HmcError> {
+        if v.len() != 2 {
+            return Err(HmcError::DimensionMismatch {
+                expected: 2,
+                got: v.len(),
+            });
+        }
+        Ok(Point { x: v[0], y: v[1] })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{run_hmc, run_hmc_nd, HmcConfig, StandardNormalNd};
+
+    fn config(flat_samples: bool) -> HmcConfig {
+        HmcConfig {
+            n_samples: 50,
+            seed: Some(91),
+            flat_samples,
+            ..HmcConfig::default()
+        }
+    }
+
+    #[cfg(feature = "nalgebra")]
+    #[test]
+    fn nalgebra_conversions_round_trip() {
+        let p = Point { x: 1.5, y: -2.0 };
+        let v: Vector2<f64> = p.clone().into();
+        assert_eq!(v, Vector2::new(1.5, -2.0));
+        assert_eq!(Point::from(v), p);
+        let q = PointN([1.0, 2.0, 3.0]);
+        assert_eq!(PointN::from(SVector::<f64, 3>::from(q)), q);
+
+        let result = run_hmc(&config(false)).unwrap();
+        let m = result.to_dmatrix();
+        assert_eq!(m.shape(), (50, 2));
+        for (i, p) in result.samples.iter().enumerate() {
+            assert_eq!(Point::from(Vector2::new(m[(i, 0)], m[(i, 1)])), *p);
+        }
+        assert_eq!(run_hmc(&config(true)).unwrap().to_dmatrix(), m);
+
+        let nd = run_hmc_nd(&config(false), &StandardNormalNd { dim: 3 }, &[0.0; 3]).unwrap();
+        let m = nd.to_dmatrix();
+        assert_eq!(m.shape(), (50, 3));
+        assert_eq!(m.row(7).iter().copied().collect::<Vec<_>>(), nd.sample(7));
+    }
+
+    #[cfg(feature = "nalgebra")]
+    #[test]
+    fn dense_mass_matrix_from_nalgebra() {
+        let mass = DMatrix::from_row_slice(2, 2, &[2.0, 0.5, 0.5, 1.0]);
+        assert_eq!(
+            Metric::try_from(mass).unwrap(),
+            Metric::Dense(vec![vec![2.0, 0.5], vec![0.5, 1.0]])
+        );
+        assert_eq!(
+            Metric::try_from(DMatrix::<f64>::identity(3, 3)).unwrap(),
+            Metric::Dense(vec![
+                vec![1.0, 0.0, 0.0],
+                vec![0.0, 1.0, 0.0],
+                vec![0.0, 0.0, 1.0]
+            ])
+        );
+        assert_eq!(
+            Metric::try_from(DMatrix::<f64>::zeros(2, 4)),
+            Err(HmcError::DimensionMismatch {
+                expected: 2,
+                got: 4
+            })
+        );
+        assert!(matches!(
+            Metric::try_from(Matrix2::new(1.0, 2.0, 2.0, 1.0)),
+            Err(HmcError::InvalidMassMatrix(_))
+        ));
+    }
+
+    #[cfg(feature = "ndarray")]
+    #[test]
+    fn ndarray_conversions_round_trip() {
+        let p = Point { x: 1.5, y: -2.0 };
+        let a = Array1::from(p.clone());
+        assert_eq!(Point::try_from(a.view()).unwrap(), p);
+        assert_eq!(
+            Point::try_from(Array1::from(vec![1.0, 2.0, 3.0]).view()),
+            Err(HmcError::DimensionMismatch {
+                expected: 2,
+                got: 3
+            })
+        );
+
+        let result = run_hmc(&config(false)).unwrap();
+        let samples = result.to_ndarray();
+        assert_eq!(samples.dim(), (50, 2));
+        let restored: Vec<Point> = samples
+            .rows()
+            .into_iter()
+            .map(|row| Point::try_from(row).unwrap())
+            .collect();
+        assert_eq!(restored, result.samples);
+        assert_eq!(run_hmc(&config(true)).unwrap().to_ndarray(), samples);
+
+        let nd = run_hmc_nd(&config(false), &StandardNormalNd { dim: 3 }, &[0.0; 3]).unwrap();
+        let samples = nd.to_ndarray();
+        assert_eq!(samples.dim(), (50, 3));
+        assert_eq!(samples.row(7).to_vec(), nd.sample(7));
+        assert_eq!(samples.column(2).to_vec(), nd.coordinate(2));
+    }
+}
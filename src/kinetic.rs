@@ -0,0 +1,407 @@
+//! 運動エネルギーの形（運動量の分布）
+
+use rand::Rng;
+use rand_distr::{Distribution, Exp1, Gamma, StandardNormal};
+use serde::{Deserialize, Serialize};
+
+use crate::metric::MassMatrix;
+use crate::vector::{self, Vector};
+use crate::HmcError;
+
+/// 運動エネルギー K(p) の形
+///
+/// どの形も質量行列 M = LLᵀ で白色化した運動量 w = L⁻¹p の関数として定義し、運動量は exp(-K) から引く。
+/// 位置の更新に使う速度は dK/dp = L⁻ᵀ dK/dw。
+/// JSONでは `"gaussian"`、`{"relativistic": {"c": 1.0, "m": 1.0}}`、`{"laplace": {"scale": 1.0}}`（各値は省略可）。
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Kinetic {
+    /// K = |w|²/2（p ~ N(0, M)、速度 M⁻¹p）
+    #[default]
+    Gaussian,
+    /// 相対論的な運動エネルギー K = mc² √(|w|²/(mc)² + 1)（Lu et al. 2017）
+    ///
+    /// 速さ |dK/dw| = |w| / (m √(|w|²/(mc)² + 1)) は c を超えないため、勾配の大きい領域で
+    /// 運動量が大きくなっても1ステップで動く距離は εc（白色化した座標で）に抑えられる。
+    /// c → ∞ で質量 m のガウス分布の運動量に近づく。
+    Relativistic {
+        /// 速さの上限
+        #[serde(default = "default_one")]
+        c: f64,
+        /// 静止質量
+        #[serde(default = "default_one")]
+        m: f64,
+    },
+    /// ラプラス分布の運動量 K = Σ|w_i| / scale
+    ///
+    /// 速度は各座標で ±1/scale の一定の大きさになり、運動量の大きさによらない。
+    /// K は w = 0 で微分できないが、リープフロッグの位置の更新は運動量だけに依存するずれなので、
+    /// 体積を保ち時間反転可能なままでMetropolis判定は正しい。
+    Laplace {
+        /// 運動量の尺度（w の各成分の平均絶対値）
+        #[serde(default = "default_one")]
+        scale: f64,
+    },
+}
+
+fn default_one() -> f64 {
+    1.0
+}
+
+impl Kinetic {
+    /// パラメータが正の有限値かを確かめる
+    pub fn validate(&self) -> Result<(), HmcError> {
+        let params: &[(&'static str, f64)] = match self {
+            Kinetic::Gaussian => &[],
+            Kinetic::Relativistic { c, m } => &[("c", *c), ("m", *m)],
+            Kinetic::Laplace { scale } => &[("scale", *scale)],
+        };
+        for &(param, value) in params {
+            if !(value.is_finite() && value > 0.0) {
+                return Err(HmcError::InvalidKineticParam { param, value });
+            }
+        }
+        Ok(())
+    }
+
+    /// 運動量 p ~ exp(-K(p)) を `like` と同じ形で引く
+    ///
+    /// `Gaussian` は `MassMatrix::sample_momentum` と同じ乱数列になる。
+    pub(crate) fn sample<V: Vector, R: Rng + ?Sized>(
+        &self,
+        mass: &MassMatrix<V>,
+        like: &V,
+        rng: &mut R,
+    ) -> V {
+        match *self {
+            Kinetic::Gaussian => mass.sample_momentum(like, rng),
+            Kinetic::Relativistic { c, m } => {
+                // 極座標で、s = √(r²/(mc)² + 1) の密度は [1, ∞) で (s² - 1)^((d-2)/2) s e^(-mc² s) に比例する
+                let beta = m * c * c;
+                let dim = like.dim();
+                let mut w = like.clone();
+                if dim == 2 {
+                    // t = s - 1 の密度 (1 + t) e^(-βt)（β = mc²）は、重み β/(1 + β) の指数分布と
+                    // 重み 1/(1 + β) の形状2のガンマ分布の混合
+                    let mut t: f64 = Exp1.sample(rng);
+                    if rng.gen::<f64>() * (1.0 + beta) < 1.0 {
+                        let extra: f64 = Exp1.sample(rng);
+                        t += extra;
+                    }
+                    t /= beta;
+                    let r = m * c * (t * (t + 2.0)).sqrt();
+                    let angle = std::f64::consts::TAU * rng.gen::<f64>();
+                    w.as_mut_slice()
+                        .copy_from_slice(&[r * angle.cos(), r * angle.sin()]);
+                } else {
+                    let t = sample_relativistic_excess(dim, beta, rng);
+                    let r = m * c * (t * (t + 2.0)).sqrt();
+                    // 方向は球面上の一様分布（正規乱数のベクトルを正規化する）
+                    for w in w.as_mut_slice() {
+                        *w = StandardNormal.sample(rng);
+                    }
+                    let norm = vector::norm(&w);
+                    for w in w.as_mut_slice() {
+                        *w *= r / norm;
+                    }
+                }
+                mass.color(&w)
+            }
+            Kinetic::Laplace { scale } => {
+                let mut w = like.clone();
+                for w in w.as_mut_slice() {
+                    let magnitude: f64 = Exp1.sample(rng);
+                    *w = if rng.gen::<bool>() {
+                        scale * magnitude
+                    } else {
+                        -scale * magnitude
+                    };
+                }
+                mass.color(&w)
+            }
+        }
+    }
+
+    /// 運動エネルギー K(p)
+    pub(crate) fn energy<V: Vector>(&self, mass: &MassMatrix<V>, p: &V) -> f64 {
+        match *self {
+            Kinetic::Gaussian => mass.kinetic(p),
+            Kinetic::Relativistic { c, m } => {
+                let w = mass.whiten(p);
+                m * c * c * lorentz_factor(&w, c, m)
+            }
+            Kinetic::Laplace { scale } => {
+                let w = mass.whiten(p);
+                w.as_slice().iter().map(|w| w.abs()).sum::<f64>() / scale
+            }
+        }
+    }
+
+    /// 速度 dK/dp
+    pub(crate) fn velocity<V: Vector>(&self, mass: &MassMatrix<V>, p: &V) -> V {
+        match *self {
+            Kinetic::Gaussian => mass.velocity(p),
+            Kinetic::Relativistic { c, m } => {
+                let w = mass.whiten(p);
+                let k = 1.0 / (m * lorentz_factor(&w, c, m));
+                mass.unwhiten(&vector::map(&w, |w| k * w))
+            }
+            Kinetic::Laplace { scale } => {
+                let w = mass.whiten(p);
+                // 符号関数は w = 0 で0とする（測度0なので判定には影響しない）
+                let sign = |v: f64| if v == 0.0 { 0.0 } else { v.signum() };
+                mass.unwhiten(&vector::map(&w, |w| sign(w) / scale))
+            }
+        }
+    }
+}
+
+/// √(|w|²/(mc)² + 1)
+fn lorentz_factor<V: Vector>(w: &V, c: f64, m: f64) -> f64 {
+    let mc = m * c;
+    (w.as_slice().iter().map(|w| (w / mc).powi(2)).sum::<f64>() + 1.0).sqrt()
+}
+
+/// `dim` 次元の相対論的な運動量で t = s - 1 を引く（密度は t^a (t + 2)^a (1 + t) e^(-βt)、a = (d - 2)/2）
+///
+/// h(t) = a ln(t + 2) + ln(1 + t) は凹なので、点 t₀ での接線で上から抑えれば
+/// t^a e^(h(t₀) + h'(t₀)(t - t₀) - βt) は形状 a + 1、率 β - h'(t₀) のガンマ分布に比例する包絡線になる。
+/// t₀ はこのガンマ分布の平均に一致するように選び、棄却の確率を次元によらず小さく保つ。
+fn sample_relativistic_excess<R: Rng + ?Sized>(dim: usize, beta: f64, rng: &mut R) -> f64 {
+    let a = 0.5 * (dim as f64 - 2.0);
+    let h = |t: f64| a * (t + 2.0).ln() + t.ln_1p();
+    let dh = |t: f64| a / (t + 2.0) + 1.0 / (1.0 + t);
+    // t₀ (β - h'(t₀)) = a + 1 を二分法で解く（左辺は0で0、無限大で無限大）
+    let excess = |t: f64| t * (beta - dh(t)) - (a + 1.0);
+    let (mut lo, mut hi) = (0.0, (a + 1.0) / beta + 1.0);
+    while excess(hi) <= 0.0 {
+        hi *= 2.0;
+    }
+    for _ in 0..60 {
+        let mid = 0.5 * (lo + hi);
+        if excess(mid) <= 0.0 {
+            lo = mid;
+        } else {
+            hi = mid;
+        }
+    }
+    let t0 = hi;
+    let envelope = Gamma::new(a + 1.0, 1.0 / (beta - dh(t0))).unwrap();
+    loop {
+        let t: f64 = envelope.sample(rng);
+        let log_ratio = h(t) - h(t0) - dh(t0) * (t - t0);
+        if rng.gen::<f64>().ln() < log_ratio {
+            return t;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{run_hmc, Algorithm, DistType, HmcConfig, IntegratorKind, Metric, Point, StudentT};
+    use rand::SeedableRng;
+
+    const KINETICS: [Kinetic; 3] = [
+        Kinetic::Gaussian,
+        Kinetic::Relativistic { c: 1.5, m: 0.8 },
+        Kinetic::Laplace { scale: 0.7 },
+    ];
+
+    #[test]
+    fn velocity_is_the_gradient_of_energy_and_momenta_satisfy_equipartition() {
+        let origin = Point::default();
+        let masses = [
+            MassMatrix::new(&Metric::Diagonal(vec![0.5, 4.0]), &origin).unwrap(),
+            MassMatrix::new(
+                &Metric::Dense(vec![vec![4.0, 1.2], vec![1.2, 0.9]]),
+                &origin,
+            )
+            .unwrap(),
+        ];
+        let mut rng = rand_chacha::ChaCha12Rng::seed_from_u64(74);
+        for mass in &masses {
+            for kinetic in KINETICS {
+                let p = Point { x: 0.7, y: -1.3 };
+                let v = kinetic.velocity(mass, &p);
+                let h = 1e-6;
+                let dx = (kinetic.energy(mass, &Point { x: p.x + h, ..p })
+                    - kinetic.energy(mass, &Point { x: p.x - h, ..p }))
+                    / (2.0 * h);
+                let dy = (kinetic.energy(mass, &Point { y: p.y + h, ..p })
+                    - kinetic.energy(mass, &Point { y: p.y - h, ..p }))
+                    / (2.0 * h);
+                assert!((v.x - dx).abs() < 1e-6 && (v.y - dy).abs() < 1e-6);
+
+                // exp(-K) に従う p では、各座標で E[p_i ∂K/∂p_i] = 1
+                let n = 200_000;
+                let mut sum = [0.0; 2];
+                for _ in 0..n {
+                    let p = kinetic.sample(mass, &origin, &mut rng);
+                    let v = kinetic.velocity(mass, &p);
+                    sum[0] += p.x * v.x;
+                    sum[1] += p.y * v.y;
+                }
+                for s in sum {
+                    let mean = s / n as f64;
+                    assert!((mean - 1.0).abs() < 0.02, "{:?}: {}", kinetic, mean);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn momenta_satisfy_equipartition_in_any_dimension() {
+        let mut rng = rand_chacha::ChaCha12Rng::seed_from_u64(85);
+        for dim in [1, 3, 10] {
+            let origin = vec![0.0; dim];
+            let inv_mass: Vec<f64> = (0..dim).map(|i| 0.5 + i as f64).collect();
+            let mass = MassMatrix::new(&Metric::Diagonal(inv_mass), &origin).unwrap();
+            for kinetic in KINETICS {
+                let n = 100_000;
+                let mut sum = vec![0.0; dim];
+                for _ in 0..n {
+                    let p = kinetic.sample(&mass, &origin, &mut rng);
+                    let v = kinetic.velocity(&mass, &p);
+                    for (s, (p, v)) in sum.iter_mut().zip(p.iter().zip(&v)) {
+                        *s += p * v;
+                    }
+                }
+                for s in sum {
+                    let mean = s / n as f64;
+                    assert!((mean - 1.0).abs() < 0.03, "{} {:?}: {}", dim, kinetic, mean);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn every_kinetic_samples_the_standard_normal() {
+        for (kinetic, algorithm) in KINETICS
+            .into_iter()
+            .flat_map(|k| [(k, Algorithm::Hmc), (k, Algorithm::Nuts { max_depth: 10 })])
+        {
+            let config = HmcConfig {
+                n_samples: 20_000,
+                n_warmup: 1000,
+                step_size: 0.3,
+                num_steps: 7,
+                kinetic,
+                algorithm: algorithm.clone(),
+                target: DistType::Normal,
+                seed: Some(74),
+                ..HmcConfig::default()
+            };
+            let result = run_hmc(&config).unwrap();
+            let n = result.samples.len() as f64;
+            for coord in [|p: &Point| p.x, |p: &Point| p.y] {
+                let mean = result.samples.iter().map(coord).sum::<f64>() / n;
+                let var = result
+                    .samples
+                    .iter()
+                    .map(|p| (coord(p) - mean).powi(2))
+                    .sum::<f64>()
+                    / n;
+                assert!(
+                    mean.abs() < 0.1,
+                    "{:?} {:?}: mean {}",
+                    kinetic,
+                    algorithm,
+                    mean
+                );
+                assert!(
+                    (var - 1.0).abs() < 0.1,
+                    "{:?} {:?}: var {}",
+                    kinetic,
+                    algorithm,
+                    var
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn bounded_speed_avoids_divergences_on_narrow_student_t() {
+        // 中心付近の曲率が大きく、ガウス分布の運動量では ε = 1 のリープフロッグが中心を大きく飛び越えて発散する。
+        // 速さの上限 c（ラプラス分布では 1/scale）を尺度に合わせれば、1ステップで動く距離は ε c に収まる
+        let config = |kinetic| HmcConfig {
+            n_samples: 1000,
+            n_warmup: 0,
+            step_size: 1.0,
+            num_steps: 10,
+            kinetic,
+            target: DistType::StudentT(StudentT {
+                scale: 0.01,
+                ..StudentT::default()
+            }),
+            seed: Some(74),
+            ..HmcConfig::default()
+        };
+        let gaussian = run_hmc(&config(Kinetic::Gaussian)).unwrap();
+        assert!(gaussian.n_divergent > 20, "{}", gaussian.n_divergent);
+        for kinetic in [
+            Kinetic::Relativistic { c: 0.01, m: 1.0 },
+            Kinetic::Laplace { scale: 100.0 },
+        ] {
+            let result = run_hmc(&config(kinetic)).unwrap();
+            assert_eq!(result.n_divergent, 0, "{:?}", kinetic);
+            assert!(
+                result.acceptance_rate > 0.5,
+                "{:?}: {}",
+                kinetic,
+                result.acceptance_rate
+            );
+        }
+    }
+
+    #[test]
+    fn kinetic_rejects_invalid_settings_and_loads_from_json() {
+        let parsed: HmcConfig =
+            serde_json::from_str(r#"{"kinetic": {"relativistic": {"c": 2}}}"#).unwrap();
+        assert_eq!(parsed.kinetic, Kinetic::Relativistic { c: 2.0, m: 1.0 });
+        let parsed: HmcConfig = serde_json::from_str(r#"{"kinetic": {"laplace": {}}}"#).unwrap();
+        assert_eq!(parsed.kinetic, Kinetic::Laplace { scale: 1.0 });
+
+        let config = |kinetic| HmcConfig {
+            kinetic,
+            ..HmcConfig::default()
+        };
+        assert_eq!(
+            config(Kinetic::Relativistic { c: 1.0, m: 0.0 }).validate(),
+            Err(HmcError::InvalidKineticParam {
+                param: "m",
+                value: 0.0
+            })
+        );
+        assert_eq!(
+            config(Kinetic::Laplace {
+                scale: f64::INFINITY
+            })
+            .validate(),
+            Err(HmcError::InvalidKineticParam {
+                param: "scale",
+                value: f64::INFINITY
+            })
+        );
+        let laplace = Kinetic::Laplace { scale: 1.0 };
+        assert_eq!(
+            HmcConfig {
+                momentum_persistence: 0.5,
+                ..config(laplace)
+            }
+            .validate(),
+            Err(HmcError::UnsupportedKinetic("momentum_persistence"))
+        );
+        assert!(matches!(
+            HmcConfig {
+                integrator: IntegratorKind::ImplicitMidpoint {
+                    tol: 1e-10,
+                    max_iters: 100
+                },
+                ..config(laplace)
+            }
+            .validate(),
+            Err(HmcError::UnsupportedKinetic(_))
+        ));
+    }
+}
@@ -0,0 +1,223 @@
+//! 混合分布の成分を並べてまとめて評価する経路
+
+use crate::Point;
+
+/// 成分数がこれ未満なら1成分ずつ評価する方が速い
+pub(crate) const MIN_COMPONENTS: usize = 4;
+
+/// 成分数をこの倍数に切り上げ、レーンごとの部分和を取る
+const LANES: usize = 4;
+
+/// 一度にスタック上のバッファへ展開する成分の数（`LANES` の倍数）
+const CHUNK: usize = 64;
+
+/// 成分が少ないときのバッファの大きさ（バッファの初期化の手間を成分数に見合わせる）
+const SMALL_CHUNK: usize = 16;
+
+/// これより小さい引数では `exp` を0とみなす（2^n が正規化数で表せる範囲に収める）
+const EXP_MIN: f64 = -708.0;
+
+/// 1/k!（k = 0..=7）
+const INV_FACTORIALS: [f64; 8] = {
+    let mut c = [1.0; 8];
+    let mut k = 1;
+    while k < 8 {
+        c[k] = c[k - 1] / k as f64;
+        k += 1;
+    }
+    c
+};
+
+/// `xs` の各要素を e^x で置き換える（x ≤ 0 を想定。相対誤差は 1e-14 程度）
+///
+/// `f64::exp` は要素ごとのライブラリ呼び出しになりSIMD命令にまとめられないため、x = n log 2 + r
+/// （|r| ≤ log 2 / 2）と分け、e^(r/8) を7次のテイラー多項式で求めて3回2乗し、2^n は指数部の
+/// ビット操作で作る。n の丸めも 1.5·2^52 を足して引く加減算で済ませ、分岐のない命令列にする。
+#[inline(always)]
+fn exp_in_place(xs: &mut [f64]) {
+    const MAGIC: f64 = 6755399441055744.0; // 1.5·2^52
+    const LN2_HI: f64 = 6.931_471_803_691_238e-1;
+    const LN2_LO: f64 = 1.908_214_929_270_587_7e-10;
+    for v in xs.iter_mut() {
+        let x = v.max(EXP_MIN);
+        let shifted = x * std::f64::consts::LOG2_E + MAGIC;
+        let n = shifted - MAGIC;
+        let r = ((x - n * LN2_HI) - n * LN2_LO) * 0.125;
+        let mut p = INV_FACTORIALS[7];
+        for c in INV_FACTORIALS[..7].iter().rev() {
+            p = p * r + c;
+        }
+        p *= p;
+        p *= p;
+        p *= p;
+        let scale = f64::from_bits(shifted.to_bits().wrapping_add(1023) << 52);
+        *v = if *v < EXP_MIN { 0.0 } else { p * scale };
+    }
+}
+
+/// `LANES` 本の部分和を取ってから足し合わせる（逐次の和と違い依存の鎖が短い）
+#[inline(always)]
+fn lane_sum(xs: &[f64]) -> f64 {
+    let mut lanes = [0.0; LANES];
+    for chunk in xs.chunks_exact(LANES) {
+        for (s, x) in lanes.iter_mut().zip(chunk) {
+            *s += x;
+        }
+    }
+    lanes.into_iter().sum()
+}
+
+/// `GaussianMixture` のポテンシャルと勾配を、成分をまとめて計算する
+///
+/// 係数を成分ごとではなく種類ごとの配列に並べ、`CHUNK` 成分ずつ log(w_k p_k) と ∇U_k を
+/// スタック上のバッファに展開してから exp と重み付きの和を取る。どのループも成分について同じ命令列に
+/// なるためコンパイラがSIMD命令にまとめる。x86_64 でAVX2が使える場合はその命令で組み直した版を呼ぶ。
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct MixtureLanes {
+    mean_x: Vec<f64>,
+    mean_y: Vec<f64>,
+    /// 精度行列（対称なので3成分）
+    p00: Vec<f64>,
+    p01: Vec<f64>,
+    p11: Vec<f64>,
+    /// log w_k - log det Σ_k / 2 - log 2π（埋め草は -∞）
+    offset: Vec<f64>,
+}
+
+impl MixtureLanes {
+    /// 成分ごとの (平均, 精度行列, log w_k - log det Σ_k / 2 - log 2π) から組み立てる
+    pub(crate) fn new<'a>(
+        components: impl IntoIterator<Item = (&'a Point, [[f64; 2]; 2], f64)>,
+    ) -> Self {
+        let mut lanes = Self {
+            mean_x: Vec::new(),
+            mean_y: Vec::new(),
+            p00: Vec::new(),
+            p01: Vec::new(),
+            p11: Vec::new(),
+            offset: Vec::new(),
+        };
+        for (mean, precision, offset) in components {
+            lanes.push(mean.x, mean.y, precision, offset);
+        }
+        // 寄与が0の成分で `LANES` の倍数に揃える
+        while !lanes.offset.len().is_multiple_of(LANES) {
+            lanes.push(0.0, 0.0, [[0.0; 2]; 2], f64::NEG_INFINITY);
+        }
+        lanes
+    }
+
+    fn push(&mut self, x: f64, y: f64, precision: [[f64; 2]; 2], offset: f64) {
+        self.mean_x.push(x);
+        self.mean_y.push(y);
+        self.p00.push(precision[0][0]);
+        self.p01.push(precision[0][1]);
+        self.p11.push(precision[1][1]);
+        self.offset.push(offset);
+    }
+
+    /// (max_k log(w_k p_k), Σ_k e_k, Σ_k e_k ∇U_k)、ただし e_k = exp(log(w_k p_k) - max)
+    fn accumulate(&self, q: &Point) -> (f64, f64, Point) {
+        #[cfg(target_arch = "x86_64")]
+        if std::arch::is_x86_feature_detected!("avx2") {
+            // SAFETY: 実行中のCPUがAVX2に対応していることを直前に確かめている
+            return unsafe { self.accumulate_avx2(q) };
+        }
+        self.accumulate_portable(q)
+    }
+
+    #[cfg(target_arch = "x86_64")]
+    #[target_feature(enable = "avx2")]
+    unsafe fn accumulate_avx2(&self, q: &Point) -> (f64, f64, Point) {
+        self.accumulate_portable(q)
+    }
+
+    #[inline(always)]
+    fn accumulate_portable(&self, q: &Point) -> (f64, f64, Point) {
+        if self.offset.len() <= SMALL_CHUNK {
+            self.accumulate_chunks::<SMALL_CHUNK>(q)
+        } else {
+            self.accumulate_chunks::<CHUNK>(q)
+        }
+    }
+
+    /// `N` 成分ごとに最大値を取り、それまでの和をその最大値の基準に直しながら積み上げる
+    #[inline(always)]
+    fn accumulate_chunks<const N: usize>(&self, q: &Point) -> (f64, f64, Point) {
+        let mut log_joint = [0.0; N];
+        let mut weighted_x = [0.0; N];
+        let mut weighted_y = [0.0; N];
+        let (mut max, mut sum, mut sum_x, mut sum_y) = (f64::NEG_INFINITY, 0.0, 0.0, 0.0);
+        for start in (0..self.offset.len()).step_by(N) {
+            let end = (start + N).min(self.offset.len());
+            let n = end - start;
+            let (log_joint, gx, gy) = (
+                &mut log_joint[..n],
+                &mut weighted_x[..n],
+                &mut weighted_y[..n],
+            );
+            let coefficients = self.mean_x[start..end]
+                .iter()
+                .zip(&self.mean_y[start..end])
+                .zip(&self.p00[start..end])
+                .zip(&self.p01[start..end])
+                .zip(&self.p11[start..end])
+                .zip(&self.offset[start..end]);
+            for ((((((mx, my), p00), p01), p11), offset), ((l, gx), gy)) in
+                coefficients.zip(log_joint.iter_mut().zip(gx.iter_mut()).zip(gy.iter_mut()))
+            {
+                let dx = q.x - mx;
+                let dy = q.y - my;
+                *gx = p00 * dx + p01 * dy;
+                *gy = p01 * dx + p11 * dy;
+                *l = offset - 0.5 * (dx * *gx + dy * *gy);
+            }
+            let chunk_max = log_joint.iter().fold(f64::NEG_INFINITY, |m, &l| m.max(l));
+            if chunk_max == f64::NEG_INFINITY {
+                // どの成分も寄与しない（q が有限でない場合もここに来る）
+                continue;
+            }
+            if chunk_max > max {
+                let rescale = (max - chunk_max).exp();
+                sum *= rescale;
+                sum_x *= rescale;
+                sum_y *= rescale;
+                max = chunk_max;
+            }
+            for l in log_joint.iter_mut() {
+                *l -= max;
+            }
+            exp_in_place(log_joint);
+            for ((e, gx), gy) in log_joint.iter().zip(gx.iter_mut()).zip(gy.iter_mut()) {
+                *gx *= e;
+                *gy *= e;
+            }
+            sum += lane_sum(log_joint);
+            sum_x += lane_sum(gx);
+            sum_y += lane_sum(gy);
+        }
+        if max == f64::NEG_INFINITY {
+            let nan = Point {
+                x: f64::NAN,
+                y: f64::NAN,
+            };
+            return (f64::NAN, f64::NAN, nan);
+        }
+        (max, sum, Point { x: sum_x, y: sum_y })
+    }
+
+    /// U = -log Σ_k w_k p_k(q)
+    pub(crate) fn potential(&self, q: &Point) -> f64 {
+        let (max, sum, _) = self.accumulate(q);
+        -(max + sum.ln())
+    }
+
+    /// ∇U = Σ_k r_k ∇U_k
+    pub(crate) fn gradient(&self, q: &Point) -> Point {
+        let (_, sum, g) = self.accumulate(q);
+        Point {
+            x: g.x / sum,
+            y: g.y / sum,
+        }
+    }
+}
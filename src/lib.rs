@@ -1,141 +1,1607 @@
 use rand::prelude::*;
-use rand_distr::{Distribution, StandardNormal};
 use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+mod adapt;
+mod anneal;
+mod barker;
+mod bounds;
+mod builder;
+mod chain;
+pub mod diagnostics;
+mod ensemble;
+mod error;
+mod gradcheck;
+mod init;
+mod integrator;
+#[cfg(any(feature = "nalgebra", feature = "ndarray"))]
+mod interop;
+mod kinetic;
+mod lanes;
+mod mala;
+mod metric;
+mod multichain;
+mod ndim;
+pub mod numdiff;
+mod nuts;
+mod random_walk;
+#[cfg(feature = "wasm")]
+mod single;
+mod sink;
+mod slice;
+mod target;
+mod transform;
+pub mod validate;
+mod vector;
+
+pub use adapt::{
+    find_reasonable_step_size, AdaptationWindow, DualAveraging, WarmupSchedule, WarmupWindow,
+    WelfordVariance, WindowKind,
+};
+pub use anneal::{anneal, anneal_with_target, AnnealResult, AnnealSchedule, Cooling};
+pub use bounds::{BoundingBox, Topology};
+pub use builder::{HmcBuilder, Sampler};
+pub use chain::{
+    Chain, ChainCheckpoint, ChainRng, DivergencePolicy, Replica, Trajectory, Transition, WalkerMove,
+};
+pub use error::HmcError;
+pub use gradcheck::{check_gradient, GradCheckReport};
+pub use init::{init_jitter, init_uniform_box, InitStrategy};
+pub use integrator::{
+    Hamiltonian, ImplicitMidpoint, Integrator, IntegratorKind, PhasePoint, StepFailure,
+    VelocityVerlet, Yoshida4,
+};
+pub use kinetic::Kinetic;
+pub use metric::Metric;
+pub use multichain::{
+    derive_chain_seed, run_hmc_chains, run_hmc_chains_with_target, MultiChainResult,
+};
+pub use ndim::{
+    run_hmc_1d, run_hmc_fixed, run_hmc_nd, DoubleWell1, HmcResultNd, Planar, StandardNormalNd,
+    TargetDistributionNd, Univariate,
+};
+pub use nuts::Algorithm;
+pub use sink::{BinarySink, CsvSink, SampleSink};
+pub use target::{
+    evaluate_potential_grid, numerical_gradient, Banana, Bimodal, Cauchy2, Funnel, GaussianMixture,
+    GridPotential, LinearRegression2, LogisticRegression2, MixtureComponent, MvNormal2, Ring,
+    RosenbrockNd, StandardNormal2, StudentT, Target, TargetDistribution, Tempered, VonMises2,
+};
+pub use transform::{Transform, Transforms};
+pub use vector::{PointN, Vector};
+
+use target::Model;
 
 // -----------------------------------------------------------------------------
 // Core Logic: Hamiltonian Mechanics
 // -----------------------------------------------------------------------------
 
-#[derive(Serialize, Deserialize, Clone, Debug)]
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[repr(C)]
 pub struct Point {
     pub x: f64,
     pub y: f64,
 }
 
-#[derive(Serialize, Deserialize, Clone, Debug)]
-pub struct HmcResult {
-    pub samples: Vec<Point>,
+impl Point {
+    /// 点の列を x, y を交互に並べた1本の列（行優先で `dim == 2`）にする
+    ///
+    /// ```
+    /// use hamiltonian_sampler_rs::Point;
+    ///
+    /// let points = vec![Point { x: 1.0, y: 2.0 }, Point { x: 3.0, y: 4.0 }];
+    /// let flat = Point::flatten(&points);
+    /// assert_eq!(flat, vec![1.0, 2.0, 3.0, 4.0]);
+    /// assert_eq!(Point::unflatten(&flat), points);
+    /// ```
+    pub fn flatten(points: &[Point]) -> Vec<f64> {
+        points.iter().flat_map(|p| [p.x, p.y]).collect()
+    }
+
+    /// `flatten` の逆（長さが奇数なら最後の値は使わない）
+    pub fn unflatten(values: &[f64]) -> Vec<Point> {
+        values
+            .chunks_exact(2)
+            .map(|xy| Point { x: xy[0], y: xy[1] })
+            .collect()
+    }
+}
+
+/// サンプリングの結果
+///
+/// 型引数 `V` は状態のベクトルで、公開している関数が返すのは2次元の [`Point`] のもの。
+/// N次元のサンプラーは同じ結果をサンプルを1本の列に並べた [`HmcResultNd`] に詰め直して返す。
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct HmcResult<V = Point> {
+    /// 状態の次元（2次元のAPIでは常に2。N次元の [`HmcResultNd`] と同じ名前のフィールド）
+    #[serde(default = "default_dim")]
+    pub dim: usize,
+    /// ウォームアップ後のサンプル
+    ///
+    /// `Algorithm::Ensemble` では保存する遷移ごとに全ウォーカーの位置を順に並べたもの
+    /// （ウォーカーごとの列は `walker_samples`）。以下の「各点」についての記録も同じ並び。
+    /// `flat_samples` 指定時は空で、代わりに `samples_flat` に入る。
+    pub samples: Vec<V>,
+    /// `samples` を x, y の順に行優先で並べた `n × dim` の列（`flat_samples` 指定時のみ）
+    ///
+    /// 点ごとのオブジェクトを作らないので、JSON・Python・JSへの変換が軽い。`Point::unflatten` で点の列に戻せる。
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub samples_flat: Vec<f64>,
+    /// サンプリング期間の採択率（`Algorithm::Ensemble` では全ウォーカーの更新についての採択率）
     pub acceptance_rate: f64,
+    /// ウォームアップ期間の採択率（`n_warmup == 0` の場合は0）
+    pub warmup_acceptance_rate: f64,
+    /// ウォームアップ中のサンプル（`save_warmup` 指定時のみ。空ならシリアライズしない）
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub warmup_samples: Vec<V>,
+    /// キャンセル・時間切れで打ち切られずに終了したか（ESS目標の達成による早期終了は `true`）
+    pub completed: bool,
+    /// サンプリングに要した時間 [秒]
+    pub elapsed_secs: f64,
+    /// 終了時点のチェーンの位置（打ち切り時も含め、次の実行の再開位置になる）
+    pub final_position: V,
+    /// 最後に計算した座標ごとのESS（`target_ess` 指定時のみ）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub achieved_ess: Option<V>,
+    /// 全座標のESSが `target_ess` に達したか（`target_ess` 指定時のみ）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ess_target_met: Option<bool>,
+    /// `samples` の各点を生んだ遷移が採択されたか（`save_accept_flags` 指定時のみ）
+    ///
+    /// `thin == 1` なら平均は `acceptance_rate` に一致する。
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub accepted: Vec<bool>,
+    /// `samples` の各点を生んだ遷移のMetropolis採択確率（`save_accept_prob` 指定時のみ）
+    ///
+    /// 平均はdual averagingで目標とする統計量になる。
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub accept_prob: Vec<f64>,
+    /// サンプリング期間の遷移についてのMetropolis採択確率の平均（間引き・`save_accept_prob` によらない）
+    ///
+    /// ステップ幅を適応した場合、`target_accept` に近ければ適応が目標に届いたことになる。
+    #[serde(default)]
+    pub mean_accept_prob: f64,
+    /// サンプリング期間中の発散した遷移の数（ウォームアップは含まない）
+    ///
+    /// `DivergencePolicy::ShrinkStepSize` では、やり直しても発散したままだった遷移の数。
+    pub n_divergent: usize,
+    /// サンプリング期間中に発散したためステップ幅を縮めて遷移をやり直した回数
+    ///
+    /// `DivergencePolicy::ShrinkStepSize` 以外では常に0。
+    #[serde(default)]
+    pub n_step_size_shrinks: usize,
+    /// サンプリング期間中に行った積分のステップ数（ウォームアップは含まない）
+    #[serde(default)]
+    pub n_leapfrog: usize,
+    /// サンプリング期間の1遷移あたりの平均の積分のステップ数（`n_leapfrog` を遷移数で割ったもの）
+    #[serde(default)]
+    pub mean_n_leapfrog: f64,
+    /// サンプリング期間中の積分で勾配を評価した回数（リープフロッグでは `n_leapfrog` と同じ）
+    #[serde(default)]
+    pub n_gradient_evals: usize,
+    /// サンプリング期間中に陰的中点法の反復が収束せず、軌道を打ち切って棄却した回数
+    #[serde(default)]
+    pub n_solver_failures: usize,
+    /// サンプリング期間中に `max_grad_norm` を超えた勾配を縮めた回数
+    #[serde(default)]
+    pub n_gradient_clips: usize,
+    /// サンプリング期間中のNUTSの木の深さの平均（`Algorithm::Nuts` のみ）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mean_tree_depth: Option<f64>,
+    /// サンプリング期間中に木の深さが `max_depth` に達した遷移の数（`Algorithm::Nuts` のみ）
+    ///
+    /// 多ければ軌道がUターンする前に打ち切られており、`max_depth` かステップ幅を大きくする余地がある。
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub n_max_tree_depth: Option<usize>,
+    /// サンプリング期間中に軌道が台の外（ポテンシャルが有限でない点）に出て棄却された遷移の数
+    #[serde(default)]
+    pub n_out_of_support: usize,
+    /// サンプリング期間中にNaNなどの数値の破綻で軌道を打ち切った遷移の数（`n_divergent` にも含む）
+    #[serde(default)]
+    pub n_numerical_errors: usize,
+    /// 発散した遷移の開始位置（`save_divergences` 指定時のみ）
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub divergent_positions: Vec<V>,
+    /// `samples` の各点のポテンシャル U(q)（`save_energy` 指定時のみ）
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub potential_energy: Vec<f64>,
+    /// `samples` の各点を生んだ遷移後の状態のハミルトニアン H = U + K（`save_energy` 指定時のみ）
+    ///
+    /// 棄却時は遷移前の位置と新たに引いた運動量のもの。
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub energy: Vec<f64>,
+    /// `samples` の各点を生んだ遷移のエネルギー誤差 H_new - H_current（`save_energy` 指定時のみ）
+    ///
+    /// 積分器の精度の確認に使う。アンサンブルでは空。
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub energy_errors: Vec<f64>,
+    /// `samples` の各点を生んだ遷移で実際に使ったステップ幅（`save_step_sizes` 指定時のみ）
+    ///
+    /// `step_size_jitter` で揺らした後の値で、`DivergencePolicy::ShrinkStepSize` でやり直した場合は最後の試行のもの。
+    /// リープフロッグを使わないアルゴリズムでは空。
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub step_sizes: Vec<f64>,
+    /// 記録した積分の軌道（`record_trajectory_every` 指定時のみ。`Trajectory::sample_index` の昇順）
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub trajectories: Vec<Trajectory<V>>,
+    /// `samples` の各点を生んだ遷移の軌道の始点の運動量（`save_momentum` 指定時のみ）
+    ///
+    /// 質量行列 M について p ~ N(0, M) から引いたもの（`kinetic` がガウス以外ならその分布から）。
+    /// リープフロッグを使わないアルゴリズムでは空。
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub momenta: Vec<V>,
+    /// ウォームアップで適応させたステップ幅（`adapt_step_size` 指定時のみ）
+    ///
+    /// サンプリング期間はこの値に固定されている。ウォームアップ中に打ち切られた場合はその時点の値。
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub adapted_step_size: Option<f64>,
+    /// サンプリング期間に使った質量行列
+    ///
+    /// 推定しなければ `HmcConfig::metric` そのもの。`adapt_mass_matrix` か `Metric::AdaptDense` 指定時は
+    /// ウォームアップで推定した値（`Diagonal` か `Dense`）。
+    #[serde(default)]
+    pub metric: Metric,
+    /// `HmcConfig::warmup_schedule` の各区間を終えた時点のステップ幅と質量行列（指定時のみ）
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub adaptation_windows: Vec<AdaptationWindow>,
+    /// `InitStrategy::FindMode` で見つけた開始位置（それ以外では `None`）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub init_mode: Option<V>,
+    /// `init_mode` でのポテンシャル
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub init_potential: Option<f64>,
+    /// `HmcConfig::mode_centers` の各中心に最も近いサンプルの割合（`mode_centers` 指定時のみ）
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub mode_occupancy: Vec<f64>,
+    /// 連続するサンプルの間で最も近い中心が変わった回数（`mode_centers` 指定時のみ）
+    ///
+    /// `Algorithm::Ensemble` ではウォーカーごとの列について数えた合計。
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mode_switches: Option<usize>,
+    /// サンプル列の収束診断（`compute_diagnostics` 指定時のみ）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub diagnostics: Option<diagnostics::Diagnostics>,
+    /// サンプリング期間のウォーカーごとの採択率（`Algorithm::Ensemble` のみ）
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub walker_acceptance_rates: Vec<f64>,
+    /// サンプリング期間に隣り合う温度のレプリカの組ごとの状態の交換が採択された割合
+    /// （`Algorithm::ParallelTempering` のみ。先頭が温度1と2番目の温度の組）
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub swap_acceptance_rates: Vec<f64>,
+    /// このチェーンのRNGシード（`run_hmc_chains` では導出したチェーンごとのシード）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub seed: Option<u64>,
 }
 
 /// ターゲット分布の種類
+///
+/// 分布名の文字列（`"bimodal"`）か、分布名をキーとしたパラメータ付きのオブジェクトから読み込める。
+/// 例: `{"banana": {"b": 100}}`、`{"student_t": {"dof": 2}}`、
+/// `{"mixture": [{"weight": 1, "mean": {"x": 0, "y": 0}, "cov": [[1, 0], [0, 1]]}, ...]}`。
+/// パラメータの意味は各分布の構造体を参照。文字列で指定した場合や省略したパラメータは既定値になる。
+/// `"donut"` は `"ring"` の別名。
+/// シリアライズすると常に全パラメータを含む `{分布名: パラメータ}` の形（`Normal` だけは `"normal"`）になり、
+/// そのまま読み戻せる。`Display` は分布名だけを書く。
+///
+/// `Custom` はRustのコードで作ったターゲットを `DistType` を受け取る関数にそのまま渡すためのもので、
+/// 文字列からは作れず、シリアライズしようとするとエラーになる。比較は同じ `Arc` を指すかどうかで行う。
+///
+/// ```
+/// use hamiltonian_sampler_rs::{run_hmc, DistType, HmcConfig, Point, Target};
+/// use std::sync::Arc;
+///
+/// let target = Target::from_fn(|p: &Point| 0.5 * (p.x * p.x + p.y * p.y));
+/// let config = HmcConfig {
+///     target: DistType::Custom(Arc::new(target)),
+///     n_samples: 100,
+///     seed: Some(1),
+///     ..HmcConfig::default()
+/// };
+/// assert_eq!(run_hmc(&config).unwrap().samples.len(), 100);
+/// assert!(serde_json::to_string(&config).is_err());
+/// ```
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "lowercase", try_from = "DistTypeRepr")]
 pub enum DistType {
-    Bimodal, // 二峰性分布
-    Banana,  // バナナ型（Rosenbrock）分布
+    Bimodal(Bimodal),         // 二峰性分布
+    Banana(Banana),           // バナナ型（Rosenbrock）分布
+    Funnel(Funnel),           // Nealの漏斗分布
+    Ring(Ring),               // ドーナツ型分布
+    Gaussian(MvNormal2),      // 相関のある2次元正規分布
+    Mixture(GaussianMixture), // 正規混合分布
+    #[serde(rename = "student_t")]
+    StudentT(StudentT),       // 裾の重いt分布
+    Cauchy(Cauchy2),          // 裾が極端に重いコーシー分布
+    #[serde(rename = "von_mises")]
+    VonMises(VonMises2),      // トーラス上のフォン・ミーゼス分布
+    Normal,                   // 2次元標準正規分布（パラメータなし）
+    #[serde(skip)]
+    Custom(Arc<dyn TargetDistribution + Send + Sync>), // Rustで定義した任意のターゲット
+}
+
+impl Default for DistType {
+    fn default() -> Self {
+        DistType::Bimodal(Bimodal::default())
+    }
+}
+
+/// `DistType` のデシリアライズ用の中間表現（名前だけの指定も受け付ける）
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum DistTypeRepr {
+    Name(String),
+    Params(DistTypeParams),
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum DistTypeParams {
+    Bimodal(Bimodal),
+    Banana(Banana),
+    Funnel(Funnel),
+    #[serde(alias = "donut")]
+    Ring(Ring),
+    Gaussian(MvNormal2),
+    Mixture(GaussianMixture),
+    #[serde(rename = "student_t")]
+    StudentT(StudentT),
+    Cauchy(Cauchy2),
+    #[serde(rename = "von_mises")]
+    VonMises(VonMises2),
+    Normal,
+}
+
+impl TryFrom<DistTypeRepr> for DistType {
+    type Error = HmcError;
+
+    fn try_from(repr: DistTypeRepr) -> Result<Self, HmcError> {
+        match repr {
+            DistTypeRepr::Name(name) => name.parse(),
+            DistTypeRepr::Params(params) => Ok(params.into()),
+        }
+    }
+}
+
+impl From<DistTypeParams> for DistType {
+    fn from(params: DistTypeParams) -> Self {
+        match params {
+            DistTypeParams::Bimodal(bimodal) => DistType::Bimodal(bimodal),
+            DistTypeParams::Banana(banana) => DistType::Banana(banana),
+            DistTypeParams::Funnel(funnel) => DistType::Funnel(funnel),
+            DistTypeParams::Ring(ring) => DistType::Ring(ring),
+            DistTypeParams::Gaussian(gaussian) => DistType::Gaussian(gaussian),
+            DistTypeParams::Mixture(mixture) => DistType::Mixture(mixture),
+            DistTypeParams::StudentT(student_t) => DistType::StudentT(student_t),
+            DistTypeParams::Cauchy(cauchy) => DistType::Cauchy(cauchy),
+            DistTypeParams::VonMises(von_mises) => DistType::VonMises(von_mises),
+            DistTypeParams::Normal => DistType::Normal,
+        }
+    }
 }
 
 impl DistType {
-    fn from_str(s: &str) -> Self {
-        match s {
-            "banana" => DistType::Banana,
-            _ => DistType::Bimodal,
+    /// 指定できる分布名の一覧
+    pub fn variants() -> &'static [&'static str] {
+        &[
+            "bimodal",
+            "banana",
+            "funnel",
+            "ring",
+            "gaussian",
+            "mixture",
+            "student_t",
+            "cauchy",
+            "von_mises",
+            "normal",
+        ]
+    }
+
+    /// 分布名（`Custom` 以外は `FromStr` で読み戻せる）
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DistType::Bimodal(_) => "bimodal",
+            DistType::Banana(_) => "banana",
+            DistType::Funnel(_) => "funnel",
+            DistType::Ring(_) => "ring",
+            DistType::Gaussian(_) => "gaussian",
+            DistType::Mixture(_) => "mixture",
+            DistType::StudentT(_) => "student_t",
+            DistType::Cauchy(_) => "cauchy",
+            DistType::VonMises(_) => "von_mises",
+            DistType::Normal => "normal",
+            DistType::Custom(_) => "custom",
         }
     }
+
+    /// 分布名とパラメータのオブジェクトから作る（文字列で分布名を受け取るフロントエンド用）
+    ///
+    /// 分布名は `FromStr` と同じく大文字・小文字を区別しない。`params` が `None` なら既定のパラメータ、
+    /// 指定すれば省略したフィールドだけが既定値になる。
+    pub fn with_params(name: &str, params: Option<serde_json::Value>) -> Result<Self, HmcError> {
+        let dist: DistType = name.parse()?;
+        let Some(params) = params else {
+            return Ok(dist);
+        };
+        let tagged = serde_json::Value::Object(
+            [(dist.as_str().to_string(), params)].into_iter().collect(),
+        );
+        let params: DistTypeParams =
+            serde_json::from_value(tagged).map_err(|e| HmcError::Serialization(e.to_string()))?;
+        Ok(params.into())
+    }
 }
 
-/// ポテンシャルエネルギー U(q)
-fn potential(p: &Point, dist_type: &DistType) -> f64 {
-    match dist_type {
-        DistType::Bimodal => {
-            let d1 = (p.x - 2.5).powi(2) + (p.y - 2.5).powi(2);
-            let d2 = (p.x + 2.5).powi(2) + (p.y + 2.5).powi(2);
-            // 修正: + 0.0001 を削除し、遠方でポテンシャルが無限大になるようにする（閉じ込めポテンシャル）
-            -((-d1 / 1.5).exp() + (-d2 / 1.5).exp()).ln()
+/// 分布名から変換する（大文字・小文字は区別しない）。パラメータは既定値になり、未知の名前はエラーになる。
+impl std::str::FromStr for DistType {
+    type Err = HmcError;
+
+    fn from_str(s: &str) -> Result<Self, HmcError> {
+        match s.to_ascii_lowercase().as_str() {
+            "bimodal" => Ok(DistType::Bimodal(Bimodal::default())),
+            "banana" => Ok(DistType::Banana(Banana::default())),
+            "funnel" => Ok(DistType::Funnel(Funnel::default())),
+            "ring" | "donut" => Ok(DistType::Ring(Ring::default())),
+            "gaussian" => Ok(DistType::Gaussian(MvNormal2::default())),
+            "mixture" => Ok(DistType::Mixture(GaussianMixture::default())),
+            "student_t" => Ok(DistType::StudentT(StudentT::default())),
+            "cauchy" => Ok(DistType::Cauchy(Cauchy2::default())),
+            "von_mises" => Ok(DistType::VonMises(VonMises2::default())),
+            "normal" => Ok(DistType::Normal),
+            _ => Err(HmcError::UnknownDistribution(s.to_string())),
         }
-        DistType::Banana => (1.0 - p.x).powi(2) + 10.0 * (p.y - p.x.powi(2)).powi(2),
     }
 }
 
-/// ポテンシャルエネルギーの勾配 ∇U(q)
-fn gradient(p: &Point, dist_type: &DistType) -> Point {
-    // 数値微分（中心差分近似）
-    let eps = 1e-4;
-    let u_x_p = potential(&Point { x: p.x + eps, y: p.y }, dist_type);
-    let u_x_m = potential(&Point { x: p.x - eps, y: p.y }, dist_type);
-    let u_y_p = potential(&Point { x: p.x, y: p.y + eps }, dist_type);
-    let u_y_m = potential(&Point { x: p.x, y: p.y - eps }, dist_type);
-    
-    Point {
-        x: (u_x_p - u_x_m) / (2.0 * eps),
-        y: (u_y_p - u_y_m) / (2.0 * eps),
+impl std::fmt::Display for DistType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// 次元を持たない古い結果を読み込んだ場合の `HmcResult::dim`
+fn default_dim() -> usize {
+    2
+}
+
+impl HmcResult {
+    /// チェーンの最終状態（次の実行を再開する位置）
+    pub fn last_position(&self) -> Point {
+        self.final_position.clone()
+    }
+
+    /// 保存したサンプル数（`flat_samples` 指定時は `samples_flat` の点の数）
+    pub fn n_samples(&self) -> usize {
+        self.samples.len().max(self.samples_flat.len() / 2)
+    }
+
+    /// サンプルの点の列（`flat_samples` 指定時は `samples_flat` から組み立てる）
+    fn points(&self) -> Cow<'_, [Point]> {
+        if self.samples.is_empty() {
+            Cow::Owned(Point::unflatten(&self.samples_flat))
+        } else {
+            Cow::Borrowed(&self.samples)
+        }
+    }
+
+    /// 座標ごとのESS（[`diagnostics::ess`]。`flat_samples` 指定時は `samples_flat` から求める）
+    pub fn ess(&self) -> Point {
+        sample_ess(&self.points())
+    }
+
+    /// x 座標と y 座標それぞれのラグ 0..=`max_lag` の自己相関（[`diagnostics::autocorrelation`]）
+    ///
+    /// 間引き間隔を決めるのに使う。`Algorithm::Ensemble` ではウォーカーを順に並べた列のものになる。
+    pub fn acf(&self, max_lag: usize) -> (Vec<f64>, Vec<f64>) {
+        let (xs, ys) = diagnostics::coordinates(self);
+        (
+            diagnostics::autocorrelation(&xs, max_lag),
+            diagnostics::autocorrelation(&ys, max_lag),
+        )
+    }
+
+    /// x 座標と y 座標それぞれの積分自己相関時間（[`diagnostics::iact`]）
+    pub fn iact(&self) -> (diagnostics::Iact, diagnostics::Iact) {
+        let (xs, ys) = diagnostics::coordinates(self);
+        (diagnostics::iact(&xs), diagnostics::iact(&ys))
+    }
+
+    /// 座標ごとのGewekeのzスコア（[`diagnostics::geweke`]。先頭10%と末尾50%の比較）
+    pub fn geweke(&self) -> Point {
+        let (xs, ys) = diagnostics::coordinates(self);
+        Point {
+            x: diagnostics::geweke(&xs, diagnostics::GEWEKE_FIRST, diagnostics::GEWEKE_LAST),
+            y: diagnostics::geweke(&ys, diagnostics::GEWEKE_FIRST, diagnostics::GEWEKE_LAST),
+        }
+    }
+
+    /// `samples` をウォーカーごとの列に分けたもの（`Algorithm::Ensemble` 以外では `samples` だけの1列）
+    ///
+    /// `flat_samples` 指定時は `samples_flat` から分ける。
+    pub fn walker_samples(&self) -> Vec<Vec<Point>> {
+        let n_walkers = self.walker_acceptance_rates.len().max(1);
+        let points = self.points();
+        (0..n_walkers)
+            .map(|k| points.iter().skip(k).step_by(n_walkers).cloned().collect())
+            .collect()
+    }
+
+    /// `samples` の x, y を描画用に `f32` に丸めた列
+    ///
+    /// `f64` で計算し終えたサンプルを丸めるだけで、各値の相対誤差は 2^-24 程度。
+    /// 積分から単精度で行う経路は `sample_wasm_f32` にある。`flat_samples` 指定時は `samples_flat` から作る。
+    pub fn samples_f32(&self) -> (Vec<f32>, Vec<f32>) {
+        if self.samples.is_empty() {
+            return self
+                .samples_flat
+                .chunks_exact(2)
+                .map(|xy| (xy[0] as f32, xy[1] as f32))
+                .unzip();
+        }
+        self.samples
+            .iter()
+            .map(|p| (p.x as f32, p.y as f32))
+            .unzip()
+    }
+}
+
+/// [`run_hmc_into`] の結果（サンプル以外のスカラーの統計量）
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ChainStats {
+    /// 保存したサンプル数
+    pub n_samples: usize,
+    /// サンプリング期間の採択率
+    pub acceptance_rate: f64,
+    /// ウォームアップ期間の採択率
+    pub warmup_acceptance_rate: f64,
+    /// サンプリング期間の採択確率の平均
+    pub mean_accept_prob: f64,
+    /// サンプリング期間中の発散の回数
+    pub n_divergent: usize,
+    /// サンプリング期間のリープフロッグの総ステップ数
+    pub n_leapfrog: usize,
+    /// サンプリング期間の勾配の評価回数
+    pub n_gradient_evals: usize,
+    /// ウォームアップで適応したステップ幅（`adapt_step_size` 指定時のみ）
+    pub adapted_step_size: Option<f64>,
+    /// 終了時点のチェーンの位置
+    pub final_position: Point,
+    /// 打ち切られずに終了したか
+    pub completed: bool,
+    /// サンプリングに要した時間 [秒]
+    pub elapsed_secs: f64,
+}
+
+impl From<&HmcResult> for ChainStats {
+    fn from(result: &HmcResult) -> Self {
+        Self {
+            n_samples: result.n_samples(),
+            acceptance_rate: result.acceptance_rate,
+            warmup_acceptance_rate: result.warmup_acceptance_rate,
+            mean_accept_prob: result.mean_accept_prob,
+            n_divergent: result.n_divergent,
+            n_leapfrog: result.n_leapfrog,
+            n_gradient_evals: result.n_gradient_evals,
+            adapted_step_size: result.adapted_step_size,
+            final_position: result.final_position.clone(),
+            completed: result.completed,
+            elapsed_secs: result.elapsed_secs,
+        }
+    }
+}
+
+/// HMCサンプリングの設定
+///
+/// JSONなどから読み込めるよう、全フィールドにデフォルト値を持つ。未知のフィールド名はエラーになる。
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct HmcConfig {
+    /// 生成するサンプル数
+    pub n_samples: usize,
+    /// サンプリング前に捨てるウォームアップ遷移の回数
+    pub n_warmup: usize,
+    /// 間引き間隔（`thin` 回の遷移ごとに1点だけ保存する）
+    pub thin: usize,
+    /// ウォームアップ中の全遷移を `HmcResult::warmup_samples` に保存する（間引きは適用しない）
+    pub save_warmup: bool,
+    /// 保存した各サンプルについて、それを生んだ遷移の採択/棄却を `HmcResult::accepted` に記録する
+    pub save_accept_flags: bool,
+    /// 保存した各サンプルについて、それを生んだ遷移の採択確率を `HmcResult::accept_prob` に記録する
+    pub save_accept_prob: bool,
+    /// エネルギー誤差 H_new - H_current がこの値を超えた遷移を発散とみなす
+    pub divergence_threshold: f64,
+    /// 発散した遷移の後の扱い（棄却して続ける・ステップ幅を縮めてやり直す・打ち切る）
+    pub on_divergence: DivergencePolicy,
+    /// サンプリング期間中に発散した遷移の開始位置を `HmcResult::divergent_positions` に記録する
+    pub save_divergences: bool,
+    /// 保存した各サンプルのポテンシャルとハミルトニアンを `HmcResult` に記録する
+    pub save_energy: bool,
+    /// 保存した各サンプルを生んだ遷移で実際に使ったステップ幅を `HmcResult::step_sizes` に記録する
+    pub save_step_sizes: bool,
+    /// 保存するサンプルのうち、この個数ごとに1つ、それを生んだ遷移の積分の軌道を `HmcResult::trajectories` に記録する
+    ///
+    /// 固定長のHMC（レプリカ交換法では温度1のチェーン）のみ。記録は `n_samples / record_trajectory_every` 本まで。
+    pub record_trajectory_every: Option<usize>,
+    /// 保存した各サンプルを生んだ遷移の軌道の始点の運動量を `HmcResult::momenta` に記録する
+    pub save_momentum: bool,
+    /// サンプルを `HmcResult::samples` の点の列でなく、`HmcResult::samples_flat` の1本の列で返す
+    pub flat_samples: bool,
+    /// リープフロッグ積分のステップ幅 ε（`adapt_step_size` 指定時は適応の初期値）
+    pub step_size: f64,
+    /// 遷移ごとにステップ幅を [ε(1 - j), ε(1 + j)] から一様に引き直す幅 j（0以上1未満。0なら揺らさない）
+    ///
+    /// 固定のステップ幅がターゲットの周期と共鳴して探索が滞るのを防ぐ。乱数はチェーンのRNGから引くため、
+    /// シードを指定すれば再現できる。ε は適応中ならその時点の値で、固定長のHMC・NUTS・レプリカ交換法にだけ効く。
+    pub step_size_jitter: f64,
+    /// ウォームアップ中にステップ幅をdual averagingで適応させ、サンプリング期間は適応後の値に固定する
+    ///
+    /// `n_warmup` が0なら適応しない。適応後の値は `HmcResult::adapted_step_size` に載る。
+    pub adapt_step_size: bool,
+    /// 適応を始める前に、開始点で1ステップの採択確率が 1/2 をまたぐまで `step_size` を倍々に伸ばすか縮め、
+    /// その値を適応の初期値にする（`adapt_step_size` 指定時で、固定長のHMC・NUTS・レプリカ交換法のみ）
+    ///
+    /// 桁違いの `step_size` から始めてもウォームアップを無駄にしない。運動量はチェーンのRNGから引き、
+    /// 探索での勾配の評価は最初の遷移の `n_gradient_evals` に数える。単独で使うには `find_reasonable_step_size`。
+    pub find_step_size: bool,
+    /// ステップ幅の適応で目標とする平均採択確率（0より大きく1未満）
+    ///
+    /// 達成した値は `HmcResult::mean_accept_prob` で確かめられる。
+    pub target_accept: f64,
+    /// 質量行列 M（既定は単位行列）
+    ///
+    /// 運動量は N(0, M) から引き、運動エネルギーは pᵀM⁻¹p/2、位置の更新は M⁻¹p に比例する。
+    /// `transform` と併用した場合は非制約空間の座標に対するもの。実際に使った値は `HmcResult::metric` に載る。
+    pub metric: Metric,
+    /// ウォームアップ中に引いた点の座標ごとの分散を対角質量行列の逆 M⁻¹ として推定する
+    ///
+    /// `metric` が `UnitE` か `Diagonal` の場合のみ（`Diagonal` なら推定までその値を使う）。
+    /// ウォームアップの15%から75%までの点（`warmup_schedule` 指定時は各遅い区間の点）を使い、推定後は（`adapt_step_size` 指定時は）
+    /// 残りのウォームアップでステップ幅を適応し直す。
+    pub adapt_mass_matrix: bool,
+    /// 質量行列の推定で分散（共分散行列）を1（単位行列）に引き寄せる強さ（擬似サンプル数。0なら標本分散そのまま）
+    pub mass_regularization: f64,
+    /// ウォームアップをStanと同じ速い区間・遅い区間の列に分けて適応する（`None` なら従来の1区間）
+    ///
+    /// 質量行列は遅い区間が終わるたびに推定し直し、ステップ幅の適応もそこでやり直す。
+    /// 各区間の終わりのステップ幅と質量行列は `HmcResult::adaptation_windows` に載る。
+    /// JSONでは `{"warmup_schedule": {}}` で既定の長さ（`WarmupSchedule` を参照）になる。
+    pub warmup_schedule: Option<WarmupSchedule>,
+    /// 1遷移あたりのリープフロッグステップ数 L（`Algorithm::Nuts` では使わない）
+    pub num_steps: usize,
+    /// 遷移ごとに L を {1, ..., `num_steps`} から一様に引き直す（randomized HMC）
+    ///
+    /// 固定の軌道の長さがターゲットの周期とちょうど合って探索が滞るのを防ぐ。乱数はチェーンのRNGから引くため、
+    /// シードを指定すれば再現できる。固定長のHMCとレプリカ交換法にだけ効き、平均の計算量は
+    /// `HmcResult::mean_n_leapfrog` で比べられる。
+    pub randomize_steps: bool,
+    /// 運動量の持続率 α（一般化HMC, Horowitz 1991）
+    ///
+    /// 0 なら遷移ごとに運動量を引き直す（通常のHMC）。正なら前の遷移の運動量 p を残して
+    /// p ← α p + √(1 - α²) z（z ~ N(0, M)）と部分的に更新し、棄却時は運動量を反転する。
+    /// 短い軌道（小さい `num_steps`）でも同じ向きに進み続けられる。固定長のHMCとレプリカ交換法の温度1のチェーンにだけ効く。
+    pub momentum_persistence: f64,
+    /// 固定長のHMC・NUTS・レプリカ交換法で使う積分器
+    ///
+    /// `Yoshida4` と `ImplicitMidpoint` は1ステップあたりの勾配の評価が多いので、`HmcResult::n_gradient_evals` で
+    /// 計算量を比べる。
+    pub integrator: IntegratorKind,
+    /// 固定長のHMC・NUTS・レプリカ交換法の運動エネルギーの形
+    ///
+    /// ガウス分布以外は `momentum_persistence` と `IntegratorKind::ImplicitMidpoint` とは組み合わせられない。
+    pub kinetic: Kinetic,
+    /// 遷移アルゴリズム
+    ///
+    /// 固定長のHMC・NUTS・ランダムウォークMetropolis・MALA・Barker提案・スライスサンプリング・
+    /// アンサンブルサンプラー・レプリカ交換法のいずれか（各アルゴリズムが使う設定は [`Algorithm`] を参照）。
+    pub algorithm: Algorithm,
+    /// チェーンの初期位置
+    pub initial_pos: Point,
+    /// 前回の実行の終了位置から再開する場合に指定する（`initial_pos` より優先）
+    pub resume_from: Option<Point>,
+    /// 開始位置の決め方（`resume_from` 指定時は無視される）
+    pub init: InitStrategy,
+    /// ターゲット分布
+    pub target: DistType,
+    /// ターゲットを平坦化する温度 T。ポテンシャルを U/T にして p(q)^(1/T) からサンプリングする（1なら元の分布）
+    ///
+    /// `potential_energy`・`energy` も U/T のもの。`transform` のヤコビアンの項は温度で割らない。
+    pub temperature: f64,
+    /// 座標ごとの変数変換。指定すると非制約空間でサンプリングし、`samples` は元の座標で返す
+    ///
+    /// `potential_energy`・`energy` は非制約空間のもの（ヤコビアンの項 -log |dθ/dz| を含む）。
+    pub transform: Transforms,
+    /// 指定すると、リープフロッグの位置更新で矩形の境界を越えた座標を反射させる（開始位置は矩形の内側）
+    ///
+    /// `transform` と併用した場合は非制約空間の座標に対する範囲になる。
+    pub bounds: Option<BoundingBox>,
+    /// 状態空間の位相。`Periodic` ではリープフロッグの位置更新のたびに位置を [0, period) に折り畳み、
+    /// 開始位置とサンプルも基本領域で表す（`bounds` と併用した場合は反射の後に折り畳む）
+    pub topology: Topology,
+    /// N次元のサンプラー（[`run_hmc_nd`] など）の座標ごとの変数変換（2次元の `transform` に当たる）
+    ///
+    /// 長さは状態の次元と同じで、空なら変換しない。2次元のチェーンには `transform` を使う。
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub transform_nd: Vec<Transform>,
+    /// N次元のサンプラーの座標ごとの反射境界 (min, max)（2次元の `bounds` に当たる）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bounds_nd: Option<Vec<(f64, f64)>>,
+    /// N次元のサンプラーの座標ごとの周期（2次元の `topology` の `Periodic` に当たる）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub periods_nd: Option<Vec<f64>>,
+    /// 解析的な勾配を持たないターゲットに使う数値微分（`None` ならターゲットの `gradient` に任せる）
+    pub numdiff: Option<numdiff::NumDiff>,
+    /// 積分で使う勾配のノルムの上限。超えた勾配は向きを保ったままこのノルムに縮める（`None` なら縮めない）
+    ///
+    /// 密度がほぼ0の領域で数値微分の勾配が桁外れに大きくなり、軌道が無限大に飛ぶのを防ぐ。
+    /// 縮めた勾配も位置だけの関数なので、リープフロッグは可逆で体積を保ったままであり、
+    /// Metropolis判定は本来のポテンシャルで行うため、目標分布は変わらない（提案が棄却されやすくなるだけ）。
+    /// 縮めた回数は `HmcResult::n_gradient_clips`。陰的中点法とは併用できない。
+    pub max_grad_norm: Option<f64>,
+    /// 開始前に開始位置でターゲットの勾配を中心差分と比較し、食い違えばエラーにする
+    pub debug_check_gradient: bool,
+    /// 乱数シード（`None` の場合はスレッドローカルRNGを使う）
+    pub seed: Option<u64>,
+    /// 実行時間の上限。超えた時点でそれまでのサンプルを返す（`n_samples` は上限として働く）
+    pub max_duration: Option<Duration>,
+    /// x, y 両方のESSがこの値を超えた時点でサンプリングを終える（`n_samples` は上限として働く）
+    pub target_ess: Option<f64>,
+    /// `target_ess` 指定時にESSを計算するサンプル間隔
+    ///
+    /// 計算量を抑えるため、実際の間隔は保存済みサンプル数の1割を下回らない。
+    pub ess_check_every: usize,
+    /// 多峰性のターゲットの山の中心。指定すると各サンプルを最も近い中心に割り当て、
+    /// 占有率と山の間の行き来の回数を `HmcResult` に記録する（[`diagnostics::mode_occupancy`]）
+    pub mode_centers: Vec<Point>,
+    /// N次元のサンプラーの山の中心（2次元の `mode_centers` に当たる）
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub mode_centers_nd: Vec<Vec<f64>>,
+    /// ESSなどの収束診断を計算して `HmcResult::diagnostics` に載せる
+    pub compute_diagnostics: bool,
+}
+
+impl Default for HmcConfig {
+    fn default() -> Self {
+        Self {
+            n_samples: 1000,
+            n_warmup: 0,
+            thin: 1,
+            save_warmup: false,
+            save_accept_flags: false,
+            save_accept_prob: false,
+            divergence_threshold: 1000.0,
+            on_divergence: DivergencePolicy::Continue,
+            save_divergences: false,
+            save_energy: false,
+            save_step_sizes: false,
+            record_trajectory_every: None,
+            save_momentum: false,
+            flat_samples: false,
+            step_size: 0.1,
+            step_size_jitter: 0.0,
+            adapt_step_size: false,
+            find_step_size: false,
+            target_accept: 0.8,
+            metric: Metric::UnitE,
+            adapt_mass_matrix: false,
+            mass_regularization: 5.0,
+            warmup_schedule: None,
+            num_steps: 20,
+            randomize_steps: false,
+            momentum_persistence: 0.0,
+            integrator: IntegratorKind::Leapfrog,
+            kinetic: Kinetic::Gaussian,
+            algorithm: Algorithm::Hmc,
+            initial_pos: Point::default(),
+            resume_from: None,
+            init: InitStrategy::Given,
+            target: DistType::default(),
+            temperature: 1.0,
+            transform: Transforms::default(),
+            bounds: None,
+            topology: Topology::Euclidean,
+            transform_nd: Vec::new(),
+            bounds_nd: None,
+            periods_nd: None,
+            numdiff: None,
+            max_grad_norm: None,
+            debug_check_gradient: false,
+            seed: None,
+            max_duration: None,
+            target_ess: None,
+            ess_check_every: 1000,
+            mode_centers: Vec::new(),
+            mode_centers_nd: Vec::new(),
+            compute_diagnostics: false,
+        }
+    }
+}
+
+impl HmcConfig {
+    /// チェーンの開始位置（`resume_from` があればそちら）
+    pub fn start_position(&self) -> &Point {
+        self.resume_from.as_ref().unwrap_or(&self.initial_pos)
+    }
+
+    /// `result` の終了位置から続きを実行する設定を作る
+    ///
+    /// 再開時はバーンイン済みとみなし、`n_warmup` を0にする（必要なら呼び出し後に上書きする）。
+    /// `result` で使った質量行列を `metric` に、ステップ幅の適応結果があればそれを `step_size` に引き継ぐ。
+    pub fn resumed_from(&self, result: &HmcResult) -> HmcConfig {
+        HmcConfig {
+            resume_from: Some(result.last_position()),
+            n_warmup: 0,
+            step_size: result.adapted_step_size.unwrap_or(self.step_size),
+            metric: result.metric.clone(),
+            ..self.clone()
+        }
+    }
+
+    /// 設定値の整合性を `self.target` に対して検証する
+    pub fn validate(&self) -> Result<(), HmcError> {
+        self.validate_for(&self.target)
+    }
+
+    /// 設定値の整合性を検証する（`target` のパラメータと開始位置でのポテンシャルも確かめる）
+    pub fn validate_for<T>(&self, target: &T) -> Result<(), HmcError>
+    where
+        T: TargetDistribution + ?Sized,
+    {
+        self.validate_settings(2)?;
+        let nd_settings = [
+            ("transform_nd", !self.transform_nd.is_empty()),
+            ("bounds_nd", self.bounds_nd.is_some()),
+            ("periods_nd", self.periods_nd.is_some()),
+            ("mode_centers_nd", !self.mode_centers_nd.is_empty()),
+        ];
+        if let Some(&(setting, _)) = nd_settings.iter().find(|(_, used)| *used) {
+            return Err(HmcError::SettingDimension { setting, dim: 2 });
+        }
+        target.validate()?;
+        self.transform.validate()?;
+        let start = self.start_position();
+        let Point { x, y } = *start;
+        if !(x.is_finite() && y.is_finite()) {
+            return Err(HmcError::NonFiniteInitialPoint { x, y });
+        }
+        let z = self.transform.to_unconstrained(start);
+        if !(z.x.is_finite() && z.y.is_finite()) {
+            return Err(HmcError::OutsideTransformDomain { x, y });
+        }
+        self.topology.validate()?;
+        if let Some(bounds) = &self.bounds {
+            bounds.validate()?;
+            if !bounds.contains(&z) {
+                return Err(HmcError::OutsideBounds { x, y });
+            }
+        }
+        let potential = target.potential(start);
+        if !potential.is_finite() {
+            return Err(HmcError::NonFiniteInitialPotential { x, y, potential });
+        }
+        Ok(())
+    }
+
+    /// 開始位置とターゲットによらない設定値を `dim` 次元の状態について検証する
+    pub(crate) fn validate_settings(&self, dim: usize) -> Result<(), HmcError> {
+        if self.n_samples == 0 {
+            return Err(HmcError::ZeroSamples);
+        }
+        if self.thin == 0 {
+            return Err(HmcError::ZeroThin);
+        }
+        if self.target_ess.is_some() && self.ess_check_every == 0 {
+            return Err(HmcError::ZeroEssCheckInterval);
+        }
+        if self.record_trajectory_every == Some(0) {
+            return Err(HmcError::ZeroTrajectoryInterval);
+        }
+        if self.num_steps == 0 {
+            return Err(HmcError::ZeroLeapfrogSteps);
+        }
+        match &self.algorithm {
+            Algorithm::Nuts { max_depth: 0 } => return Err(HmcError::ZeroTreeDepth),
+            &Algorithm::RandomWalk { proposal_std }
+                if !(proposal_std.is_finite() && proposal_std > 0.0) =>
+            {
+                return Err(HmcError::InvalidProposalStd(proposal_std));
+            }
+            &Algorithm::Mala { step_size } | &Algorithm::Barker { step_size }
+                if !(step_size.is_finite() && step_size > 0.0) =>
+            {
+                return Err(HmcError::InvalidStepSize(step_size));
+            }
+            &Algorithm::Slice { initial_width, .. }
+                if !(initial_width.is_finite() && initial_width > 0.0) =>
+            {
+                return Err(HmcError::InvalidSliceWidth(initial_width));
+            }
+            &Algorithm::Ensemble { n_walkers, .. } if n_walkers < 2 * dim => {
+                return Err(HmcError::TooFewWalkers(n_walkers));
+            }
+            &Algorithm::Ensemble { a, .. } if !(a.is_finite() && a > 1.0) => {
+                return Err(HmcError::InvalidStretchScale(a));
+            }
+            Algorithm::Ensemble { .. }
+                if self.topology != Topology::Euclidean || self.periods_nd.is_some() =>
+            {
+                return Err(HmcError::PeriodicEnsemble);
+            }
+            Algorithm::ParallelTempering { temperatures, .. }
+                if temperatures.len() < 2
+                    || temperatures[0] != 1.0
+                    || !temperatures.iter().all(|t| t.is_finite())
+                    || !temperatures.windows(2).all(|pair| pair[0] < pair[1]) =>
+            {
+                return Err(HmcError::InvalidTemperatureLadder(temperatures.clone()));
+            }
+            Algorithm::ParallelTempering { swap_every: 0, .. } => {
+                return Err(HmcError::ZeroSwapInterval);
+            }
+            _ => {}
+        }
+        if let Some(numdiff) = &self.numdiff {
+            numdiff.validate()?;
+        }
+        if !(self.temperature.is_finite() && self.temperature > 0.0) {
+            return Err(HmcError::InvalidTemperature(self.temperature));
+        }
+        if let InitStrategy::FindMode { learning_rate, .. } = self.init {
+            if !(learning_rate.is_finite() && learning_rate > 0.0) {
+                return Err(HmcError::InvalidLearningRate(learning_rate));
+            }
+        }
+        if !(self.step_size.is_finite() && self.step_size > 0.0) {
+            return Err(HmcError::InvalidStepSize(self.step_size));
+        }
+        if !(self.step_size_jitter >= 0.0 && self.step_size_jitter < 1.0) {
+            return Err(HmcError::InvalidStepSizeJitter(self.step_size_jitter));
+        }
+        if !(self.momentum_persistence >= 0.0 && self.momentum_persistence < 1.0) {
+            return Err(HmcError::InvalidMomentumPersistence(
+                self.momentum_persistence,
+            ));
+        }
+        if !(self.target_accept > 0.0 && self.target_accept < 1.0) {
+            return Err(HmcError::InvalidTargetAccept(self.target_accept));
+        }
+        if !(self.mass_regularization.is_finite() && self.mass_regularization >= 0.0) {
+            return Err(HmcError::InvalidMassRegularization(self.mass_regularization));
+        }
+        if let Some(schedule) = &self.warmup_schedule {
+            schedule.validate()?;
+        }
+        self.metric.validate_dim(dim)?;
+        self.integrator.validate()?;
+        self.kinetic.validate()?;
+        if self.kinetic != Kinetic::Gaussian {
+            if self.momentum_persistence > 0.0 {
+                return Err(HmcError::UnsupportedKinetic("momentum_persistence"));
+            }
+            if let IntegratorKind::ImplicitMidpoint { .. } = self.integrator {
+                return Err(HmcError::UnsupportedKinetic("implicit_midpoint"));
+            }
+        }
+        if self.divergence_threshold.is_nan() || self.divergence_threshold <= 0.0 {
+            return Err(HmcError::InvalidDivergenceThreshold(self.divergence_threshold));
+        }
+        if let Some(cap) = self.max_grad_norm {
+            if !(cap.is_finite() && cap > 0.0) {
+                return Err(HmcError::InvalidMaxGradNorm(cap));
+            }
+            if let IntegratorKind::ImplicitMidpoint { .. } = self.integrator {
+                return Err(HmcError::ClippedImplicitMidpoint);
+            }
+        }
+        self.on_divergence.validate()
+    }
+}
+
+/// 進捗コールバックに渡される情報
+#[derive(Clone, Debug)]
+pub struct ProgressInfo<V = Point> {
+    /// 完了した遷移の回数（ウォームアップを含む）
+    pub iteration: usize,
+    /// 予定している遷移の総数
+    pub total_iterations: usize,
+    /// これまでの全遷移に対する採択率
+    pub acceptance_rate: f64,
+    /// 現在位置
+    pub position: V,
+}
+
+pub(crate) type ProgressCallback<'a, V = Point> = Box<dyn FnMut(ProgressInfo<V>) + 'a>;
+
+/// 保存するサンプルを `HmcResult::samples` に溜めずに1点ずつ受け取る関数
+type SampleWriter<'a, V = Point> = &'a mut dyn FnMut(&V) -> Result<(), HmcError>;
+
+/// シリアライズ対象の `HmcConfig` に載せられない実行時フック
+pub(crate) struct RunHooks<'a, V = Point> {
+    /// (報告間隔, コールバック)
+    pub(crate) on_progress: Option<(usize, ProgressCallback<'a, V>)>,
+    /// `true` がセットされたら次の遷移の前に打ち切る
+    pub(crate) cancel: Option<Arc<AtomicBool>>,
+    /// `config.integrator` の代わりに使う独自の積分器
+    pub(crate) integrator: Option<Arc<dyn Integrator<V>>>,
+}
+
+impl<V> Default for RunHooks<'_, V> {
+    fn default() -> Self {
+        Self {
+            on_progress: None,
+            cancel: None,
+            integrator: None,
+        }
     }
 }
 
-/// 運動エネルギー K(p) = p^2 / 2m (m=1とする)
-fn kinetic(momentum: &Point) -> f64 {
-    0.5 * (momentum.x.powi(2) + momentum.y.powi(2))
+impl<V> std::fmt::Debug for RunHooks<'_, V> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RunHooks")
+            .field("on_progress", &self.on_progress.as_ref().map(|(every, _)| every))
+            .field("cancel", &self.cancel)
+            .field("integrator", &self.integrator.is_some())
+            .finish()
+    }
 }
 
 /// HMCサンプリングのメインロジック
-fn run_hmc_chain(
-    n_samples: usize,
-    step_size: f64,
-    num_steps: usize,
-    initial_pos: Point,
-    dist_name: &str,
-) -> HmcResult {
-    let mut rng = rand::thread_rng();
-    let dist_type = DistType::from_str(dist_name);
-    
-    let mut current_q = initial_pos;
-    let mut samples = Vec::with_capacity(n_samples);
+///
+/// Rustライブラリとしての公開エントリポイント。Python/WASMの各バインディングもこの関数を経由する。
+pub fn run_hmc(config: &HmcConfig) -> Result<HmcResult, HmcError> {
+    run_hmc_with_hooks(config, &mut RunHooks::default())
+}
+
+pub(crate) fn run_hmc_with_hooks(
+    config: &HmcConfig,
+    hooks: &mut RunHooks,
+) -> Result<HmcResult, HmcError> {
+    match config.seed {
+        Some(seed) => {
+            let mut chain = Chain::with_rng(config.clone(), ChainRng::seed_from_u64(seed))?;
+            sample_chain(&mut chain, hooks)
+        }
+        None => {
+            let mut chain = Chain::with_rng(config.clone(), rand::thread_rng())?;
+            sample_chain(&mut chain, hooks)
+        }
+    }
+}
+
+/// サンプルを呼び出し側のバッファ `out` に書き込み、スカラーの統計量だけを返す
+///
+/// `out` は空にしてから使い、容量が足りなければ最初に一度だけ広げる。同じバッファで繰り返し呼べば、
+/// 2回目以降はサンプルの列を確保し直さない（既定の設定ではヒープ確保は0回）。`config.flat_samples` は無視する。
+/// `HmcError::Diverged` で終わった場合、それまでのサンプルは `out` ではなく `partial` に入る。
+///
+/// ```
+/// use hamiltonian_sampler_rs::{run_hmc_into, HmcConfig};
+///
+/// let config = HmcConfig {
+///     n_samples: 200,
+///     seed: Some(1),
+///     ..HmcConfig::default()
+/// };
+/// let mut samples = Vec::new();
+/// for _ in 0..3 {
+///     let stats = run_hmc_into(&config, &mut samples).unwrap();
+///     assert_eq!(stats.n_samples, samples.len());
+/// }
+/// assert_eq!(samples.len(), 200);
+/// ```
+pub fn run_hmc_into(config: &HmcConfig, out: &mut Vec<Point>) -> Result<ChainStats, HmcError> {
+    let samples = std::mem::take(out);
+    let result = match config.seed {
+        Some(seed) => {
+            let mut chain = Chain::with_rng(config.clone(), ChainRng::seed_from_u64(seed))?;
+            sample_chain_into(&mut chain, &mut RunHooks::default(), samples, false, None)?
+        }
+        None => {
+            let mut chain = Chain::with_rng(config.clone(), rand::thread_rng())?;
+            sample_chain_into(&mut chain, &mut RunHooks::default(), samples, false, None)?
+        }
+    };
+    let stats = ChainStats::from(&result);
+    *out = result.samples;
+    Ok(stats)
+}
+
+/// 保存するサンプルをメモリに溜めず、1点ずつ `sink` に渡しながらHMCサンプリングを行う
+///
+/// 返す `HmcResult` の `samples` は空で、統計量（と `save_*` で指定した記録）だけを持つ。
+/// 全サンプルを渡し終えたら `sink.finish` を呼ぶ。全サンプルを見返す `target_ess`・`mode_centers`・
+/// `compute_diagnostics` は使えない（`HmcError::UnsupportedStreaming`）。`config.flat_samples` は無視する。
+///
+/// ```
+/// use hamiltonian_sampler_rs::{run_hmc_streaming, BinarySink, HmcConfig};
+///
+/// let config = HmcConfig {
+///     n_samples: 100,
+///     seed: Some(1),
+///     ..HmcConfig::default()
+/// };
+/// let mut bytes = Vec::new();
+/// let result = run_hmc_streaming(&config, &mut BinarySink::new(&mut bytes)).unwrap();
+/// assert!(result.samples.is_empty());
+/// assert_eq!(bytes.len(), 100 * 16);
+/// ```
+pub fn run_hmc_streaming(
+    config: &HmcConfig,
+    sink: &mut dyn SampleSink,
+) -> Result<HmcResult, HmcError> {
+    if config.target_ess.is_some() {
+        return Err(HmcError::UnsupportedStreaming("target_ess"));
+    }
+    if !config.mode_centers.is_empty() {
+        return Err(HmcError::UnsupportedStreaming("mode_centers"));
+    }
+    if config.compute_diagnostics {
+        return Err(HmcError::UnsupportedStreaming("compute_diagnostics"));
+    }
+    let mut counting = CountingSink { sink, n: 0 };
+    let result = match config.seed {
+        Some(seed) => {
+            let mut chain = Chain::with_rng(config.clone(), ChainRng::seed_from_u64(seed))?;
+            let hooks = &mut RunHooks::default();
+            let write = &mut |q: &Point| counting.write_sample(q);
+            sample_chain_into(&mut chain, hooks, Vec::new(), false, Some(write))?
+        }
+        None => {
+            let mut chain = Chain::with_rng(config.clone(), rand::thread_rng())?;
+            let hooks = &mut RunHooks::default();
+            let write = &mut |q: &Point| counting.write_sample(q);
+            sample_chain_into(&mut chain, hooks, Vec::new(), false, Some(write))?
+        }
+    };
+    let stats = ChainStats {
+        n_samples: counting.n,
+        ..ChainStats::from(&result)
+    };
+    counting.sink.finish(&stats)?;
+    Ok(result)
+}
+
+/// 渡したサンプルの数を数える `SampleSink`
+struct CountingSink<'a> {
+    sink: &'a mut dyn SampleSink,
+    n: usize,
+}
+
+impl SampleSink for CountingSink<'_> {
+    fn write_sample(&mut self, sample: &Point) -> Result<(), HmcError> {
+        self.n += 1;
+        self.sink.write_sample(sample)
+    }
+}
+
+/// ユーザー定義のターゲット分布でHMCサンプリングを行う（`config.target` は無視される）
+pub fn run_hmc_with_target<T: TargetDistribution>(
+    config: &HmcConfig,
+    target: T,
+) -> Result<HmcResult, HmcError> {
+    let rng = match config.seed {
+        Some(seed) => ChainRng::seed_from_u64(seed),
+        None => ChainRng::from_entropy(),
+    };
+    let mut chain = Chain::with_target(config.clone(), target, rng)?;
+    sample_chain(&mut chain, &mut RunHooks::default())
+}
+
+/// 外部から与えたRNGでHMCチェーンを実行する
+///
+/// 運動量のサンプリングとMetropolis判定の一様乱数は全て `rng` から引かれる。
+/// `config.seed` は無視される。
+pub fn run_hmc_chain_with_rng<R: Rng + ?Sized>(
+    config: &HmcConfig,
+    rng: &mut R,
+) -> Result<HmcResult, HmcError> {
+    let mut chain = Chain::with_rng(config.clone(), rng)?;
+    let result = sample_chain(&mut chain, &mut RunHooks::default())?;
+    Ok(HmcResult { seed: None, ..result })
+}
+
+/// ウォームアップ・間引きを適用しながらチェーンを回して結果を集める
+///
+/// `DivergencePolicy::Abort` で打ち切った場合は、それまでの結果を `HmcError::Diverged` に載せて返す。
+fn sample_chain<R: Rng, T: TargetDistribution>(
+    chain: &mut Chain<R, T>,
+    hooks: &mut RunHooks,
+) -> Result<HmcResult, HmcError> {
+    let flat_samples = chain.config().flat_samples;
+    sample_chain_into(chain, hooks, Vec::new(), flat_samples, None)
+}
+
+/// `sample_chain` の本体（`samples` を空にしてサンプルの格納先に使う）
+///
+/// `sink` を渡すと、保存するサンプルは `samples` に入れずにそちらへ渡す。
+fn sample_chain_into<R: Rng, T: TargetDistribution>(
+    chain: &mut Chain<R, T>,
+    hooks: &mut RunHooks,
+    samples: Vec<Point>,
+    flat_samples: bool,
+    sink: Option<SampleWriter<'_>>,
+) -> Result<HmcResult, HmcError> {
+    let (mut result, diverged_at) = run_chain(chain, hooks, samples, sink)?;
+    let mode_centers = &chain.config().mode_centers;
+    if !mode_centers.is_empty() {
+        result.mode_occupancy = diagnostics::mode_occupancy(&result.samples, mode_centers);
+        result.mode_switches = Some(
+            result
+                .walker_samples()
+                .iter()
+                .map(|samples| diagnostics::mode_switches(samples, mode_centers))
+                .sum(),
+        );
+    }
+    if chain.config().compute_diagnostics {
+        result.diagnostics = Some(diagnostics::Diagnostics::new(&result));
+    }
+    if flat_samples {
+        result.samples_flat = Point::flatten(&std::mem::take(&mut result.samples));
+    }
+    match diverged_at {
+        Some(iteration) => Err(HmcError::Diverged {
+            iteration,
+            partial: Box::new(result),
+        }),
+        None => Ok(result),
+    }
+}
+
+/// 状態の型によらないサンプリングのループ（2次元とN次元のサンプラーが共有する）
+///
+/// `DivergencePolicy::Abort` で打ち切った場合は、発散した遷移の番号も返す。
+/// 山の中心と収束診断は呼び出し側で結果に足す。
+pub(crate) fn run_chain<R: Rng, T: Model<V>, V: Vector>(
+    chain: &mut Chain<R, T, V>,
+    hooks: &mut RunHooks<'_, V>,
+    mut samples: Vec<V>,
+    mut sink: Option<SampleWriter<'_, V>>,
+) -> Result<(HmcResult<V>, Option<usize>), HmcError> {
+    if let Some(integrator) = &hooks.integrator {
+        chain.set_integrator(integrator.clone());
+    }
+    let config = chain.config();
+    let n_samples = config.n_samples;
+    let n_warmup = config.n_warmup;
+    let thin = config.thin;
+    let save_warmup = config.save_warmup;
+    let save_accept_flags = config.save_accept_flags;
+    let save_accept_prob = config.save_accept_prob;
+    let save_energy = config.save_energy;
+    let save_step_sizes = config.save_step_sizes;
+    let save_momentum = config.save_momentum;
+    let save_divergences = config.save_divergences;
+    let abort_on_divergence = config.on_divergence == DivergencePolicy::Abort;
+    let n_transitions = n_samples * thin;
+    let total_iterations = n_warmup + n_transitions;
+    let (is_ensemble, n_walkers) = match config.algorithm {
+        Algorithm::Ensemble { n_walkers, .. } => (true, n_walkers),
+        _ => (false, 1),
+    };
+
+    samples.clear();
+    if sink.is_none() {
+        samples.reserve(n_samples * n_walkers);
+    }
+    let mut save = |samples: &mut Vec<V>, sample: V| match sink.as_mut() {
+        Some(sink) => sink(&sample),
+        None => {
+            samples.push(sample);
+            Ok(())
+        }
+    };
+    let mut warmup_samples = Vec::with_capacity(if save_warmup { n_warmup } else { 0 });
+    let mut accepted = Vec::with_capacity(if save_accept_flags { n_samples } else { 0 });
+    let mut accept_prob = Vec::with_capacity(if save_accept_prob { n_samples } else { 0 });
+    let mut n_divergent = 0;
+    let mut n_step_size_shrinks = 0;
+    let mut diverged_at = None;
+    let mut n_out_of_support = 0;
+    let mut n_numerical_errors = 0;
+    let mut n_leapfrog = 0;
+    let mut n_gradient_evals = 0;
+    let mut n_solver_failures = 0;
+    let mut n_gradient_clips = 0;
+    let mut tree_depth_sum = 0;
+    let mut n_max_tree_depth = 0;
+    let n_swap_pairs = match &config.algorithm {
+        Algorithm::ParallelTempering { temperatures, .. } => temperatures.len() - 1,
+        _ => 0,
+    };
+    let mut swap_counts = vec![0; n_swap_pairs];
+    let mut n_swap_attempts = 0;
+    let max_tree_depth = match config.algorithm {
+        Algorithm::Nuts { max_depth } => Some(max_depth),
+        _ => None,
+    };
+    let mut divergent_positions = Vec::new();
+    let mut trajectories = Vec::new();
+    let energy_capacity = if save_energy { n_samples } else { 0 };
+    let mut potential_energy = Vec::with_capacity(energy_capacity);
+    let mut energy = Vec::with_capacity(energy_capacity);
+    let mut energy_errors = Vec::with_capacity(if is_ensemble { 0 } else { energy_capacity });
+    let mut step_sizes = Vec::with_capacity(if save_step_sizes { n_samples } else { 0 });
+    let mut momenta = Vec::with_capacity(if save_momentum { n_samples } else { 0 });
     let mut accepted_count = 0;
+    let mut warmup_accepted_count = 0;
+    let mut walker_accepted_counts = vec![0; if is_ensemble { n_walkers } else { 0 }];
+    let mut accept_prob_sum = 0.0;
+    let mut completed = true;
+    let max_duration = config.max_duration;
+    let target_ess = config.target_ess;
+    let ess_check_every = config.ess_check_every;
+    let seed = config.seed;
+    let init_mode = chain.init_mode().cloned();
+    let init_potential = init_mode.as_ref().map(|p| chain.target().potential_at(p));
+    let mut next_ess_check = ess_check_every;
+    let mut achieved_ess = None;
+    let mut ess_target_met = false;
+    let stopwatch = Stopwatch::start();
+
+    for i in 0..total_iterations {
+        if let Some(cancel) = &hooks.cancel {
+            if cancel.load(Ordering::Relaxed) {
+                completed = false;
+                break;
+            }
+        }
+        if let Some(budget) = max_duration {
+            if i.is_multiple_of(TIME_CHECK_INTERVAL) && stopwatch.elapsed() >= budget {
+                completed = false;
+                break;
+            }
+        }
+
+        let start = (save_divergences && i >= n_warmup).then(|| chain.current_position().clone());
+        let transition = chain.transition();
+
+        if let Some((report_every, callback)) = hooks.on_progress.as_mut() {
+            if (i + 1).is_multiple_of(*report_every) {
+                callback(ProgressInfo {
+                    iteration: i + 1,
+                    total_iterations,
+                    acceptance_rate: chain.acceptance_rate(),
+                    position: transition.position.clone(),
+                });
+            }
+        }
+
+        for (count, walker) in walker_accepted_counts.iter_mut().zip(&transition.walkers) {
+            *count += (i >= n_warmup && walker.accepted) as usize;
+        }
+        let n_moves_accepted = if transition.walkers.is_empty() {
+            transition.accepted as usize
+        } else {
+            transition.walkers.iter().filter(|w| w.accepted).count()
+        };
 
-    for _ in 0..n_samples {
-        // 1. 運動量のサンプリング p ~ N(0, M)
-        let current_p = Point {
-            x: StandardNormal.sample(&mut rng),
-            y: StandardNormal.sample(&mut rng),
-        };
-
-        // ハミルトニアンの計算 H = U + K
-        let current_u = potential(&current_q, &dist_type);
-        let current_k = kinetic(&current_p);
-        let current_h = current_u + current_k;
-
-        // 2. リープフロッグ積分
-        // --- Velocity Verlet (Standard Leapfrog) ---
-        let mut q_lf = current_q.clone();
-        let mut p_lf = current_p.clone();
-        let mut grad_lf = gradient(&q_lf, &dist_type);
-
-        for _ in 0..num_steps {
-            // p half step
-            p_lf.x -= 0.5 * step_size * grad_lf.x;
-            p_lf.y -= 0.5 * step_size * grad_lf.y;
-            
-            // q full step
-            q_lf.x += step_size * p_lf.x;
-            q_lf.y += step_size * p_lf.y;
-            
-            // p half step
-            grad_lf = gradient(&q_lf, &dist_type); // Re-evaluate gradient at new q
-            p_lf.x -= 0.5 * step_size * grad_lf.x;
-            p_lf.y -= 0.5 * step_size * grad_lf.y;
-        }
-        // ---------------------------
-
-        // 3. Metropolis Accept/Reject
-        let new_u = potential(&q_lf, &dist_type);
-        let new_k = kinetic(&p_lf);
-        let new_h = new_u + new_k;
-
-        // 判定
-        // H_new が無限大（NaN含む）になった場合は、確率0として扱う
-        let diff = current_h - new_h;
-        let probability = if diff.is_nan() { 0.0 } else { diff.exp() };
-
-        if rng.gen::<f64>() < probability.min(1.0) {
-            current_q = q_lf;
-            accepted_count += 1;
-        }
-        
-        samples.push(current_q.clone());
-    }
-
-    HmcResult {
+        if i < n_warmup {
+            warmup_accepted_count += n_moves_accepted;
+            if save_warmup {
+                if transition.walkers.is_empty() {
+                    warmup_samples.push(transition.position);
+                } else {
+                    warmup_samples.extend(transition.walkers.iter().map(|w| w.position.clone()));
+                }
+            }
+        } else {
+            accepted_count += n_moves_accepted;
+            accept_prob_sum += if transition.walkers.is_empty() {
+                transition.accept_prob
+            } else {
+                transition.walkers.iter().map(|w| w.accept_prob).sum()
+            };
+            if transition.divergent {
+                n_divergent += 1;
+                divergent_positions.extend(start);
+            }
+            n_step_size_shrinks += transition.n_step_size_shrinks;
+            if !transition.swaps.is_empty() {
+                n_swap_attempts += 1;
+                for (count, &swapped) in swap_counts.iter_mut().zip(&transition.swaps) {
+                    *count += swapped as usize;
+                }
+            }
+            n_out_of_support += transition.out_of_support as usize;
+            n_numerical_errors += transition.numerical_error as usize;
+            n_leapfrog += transition.n_leapfrog;
+            n_gradient_evals += transition.n_gradient_evals;
+            n_solver_failures += transition.n_solver_failures;
+            n_gradient_clips += transition.n_gradient_clips;
+            if let Some(depth) = transition.tree_depth {
+                tree_depth_sum += depth;
+                n_max_tree_depth += (Some(depth) == max_tree_depth) as usize;
+            }
+            if (i - n_warmup + 1).is_multiple_of(thin) {
+                if transition.walkers.is_empty() {
+                    save(&mut samples, transition.position)?;
+                    if save_accept_flags {
+                        accepted.push(transition.accepted);
+                    }
+                    if save_accept_prob {
+                        accept_prob.push(transition.accept_prob);
+                    }
+                    if save_energy {
+                        potential_energy.push(transition.potential_energy);
+                        energy.push(transition.energy);
+                        energy_errors.push(transition.energy_error);
+                    }
+                    if save_step_sizes {
+                        step_sizes.extend(transition.step_size);
+                    }
+                    if save_momentum {
+                        momenta.extend(transition.initial_momentum);
+                    }
+                    trajectories.extend(transition.trajectory);
+                } else {
+                    // 勾配も運動量も使わないので、ハミルトニアンはポテンシャルそのもの
+                    for walker in transition.walkers {
+                        if save_accept_flags {
+                            accepted.push(walker.accepted);
+                        }
+                        if save_accept_prob {
+                            accept_prob.push(walker.accept_prob);
+                        }
+                        if save_energy {
+                            potential_energy.push(walker.potential_energy);
+                            energy.push(walker.potential_energy);
+                        }
+                        save(&mut samples, walker.position)?;
+                    }
+                }
+
+                if let Some(target) = target_ess {
+                    if samples.len() >= next_ess_check {
+                        let ess = coordinate_ess(&samples, chain.current_position());
+                        if ess.as_slice().iter().all(|&e| e >= target) {
+                            ess_target_met = true;
+                        }
+                        achieved_ess = Some(ess);
+                        if ess_target_met {
+                            break;
+                        }
+                        next_ess_check = samples.len() + ess_check_every.max(samples.len() / 10);
+                    }
+                }
+            }
+            if abort_on_divergence && transition.divergent {
+                diverged_at = Some(i);
+                completed = false;
+                break;
+            }
+        }
+    }
+
+    if target_ess.is_some() && !ess_target_met {
+        achieved_ess = Some(coordinate_ess(&samples, chain.current_position()));
+    }
+
+    // 打ち切られた場合も、実際に実行した遷移数で採択率を計算する
+    let performed = chain.iteration();
+    let performed_warmup = performed.min(n_warmup);
+    let performed_sampling = performed - performed_warmup;
+    let walker_acceptance_rates = if is_ensemble {
+        walker_accepted_counts
+            .iter()
+            .map(|&count| ratio(count, performed_sampling))
+            .collect()
+    } else {
+        Vec::new()
+    };
+
+    let result = HmcResult {
+        dim: chain.current_position().dim(),
         samples,
-        acceptance_rate: accepted_count as f64 / n_samples as f64,
+        samples_flat: Vec::new(),
+        acceptance_rate: ratio(accepted_count, performed_sampling * n_walkers),
+        warmup_acceptance_rate: ratio(warmup_accepted_count, performed_warmup * n_walkers),
+        warmup_samples,
+        completed,
+        elapsed_secs: stopwatch.elapsed().as_secs_f64(),
+        final_position: chain.current_position().clone(),
+        achieved_ess,
+        ess_target_met: target_ess.map(|_| ess_target_met),
+        accepted,
+        accept_prob,
+        mean_accept_prob: accept_prob_sum / (performed_sampling * n_walkers).max(1) as f64,
+        n_divergent,
+        n_step_size_shrinks,
+        n_leapfrog,
+        mean_n_leapfrog: n_leapfrog as f64 / performed_sampling.max(1) as f64,
+        n_gradient_evals,
+        n_solver_failures,
+        n_gradient_clips,
+        mean_tree_depth: max_tree_depth.map(|_| ratio(tree_depth_sum, performed_sampling)),
+        n_max_tree_depth: max_tree_depth.map(|_| n_max_tree_depth),
+        n_out_of_support,
+        n_numerical_errors,
+        divergent_positions,
+        potential_energy,
+        energy,
+        energy_errors,
+        step_sizes,
+        trajectories,
+        momenta,
+        init_mode,
+        init_potential,
+        adapted_step_size: chain.config().adapt_step_size.then(|| chain.step_size()),
+        metric: chain.metric(),
+        adaptation_windows: chain.adaptation_windows().to_vec(),
+        mode_occupancy: Vec::new(),
+        mode_switches: None,
+        diagnostics: None,
+        walker_acceptance_rates,
+        swap_acceptance_rates: swap_counts
+            .iter()
+            .map(|&count| ratio(count, n_swap_attempts))
+            .collect(),
+        seed,
+    };
+    Ok((result, diverged_at))
+}
+
+/// 座標ごとのESS
+fn sample_ess(samples: &[Point]) -> Point {
+    coordinate_ess(samples, &Point::default())
+}
+
+/// 座標ごとのESSを `like` と同じ形のベクトルで返す
+pub(crate) fn coordinate_ess<V: Vector>(samples: &[V], like: &V) -> V {
+    let mut ess = like.clone();
+    for (j, e) in ess.as_mut_slice().iter_mut().enumerate() {
+        let values: Vec<f64> = samples.iter().map(|q| q.as_slice()[j]).collect();
+        *e = diagnostics::ess(&values);
+    }
+    ess
+}
+
+/// 時間上限を確認する間隔（`Instant::now()` を毎反復呼ばないため）
+const TIME_CHECK_INTERVAL: usize = 64;
+
+/// 経過時間の計測
+///
+/// wasm32 では `std::time::Instant` がパニックするため、JSの `Date.now()` を使う。
+struct Stopwatch {
+    #[cfg(not(all(target_arch = "wasm32", feature = "wasm")))]
+    start: std::time::Instant,
+    #[cfg(all(target_arch = "wasm32", feature = "wasm"))]
+    start_ms: f64,
+}
+
+impl Stopwatch {
+    #[cfg(not(all(target_arch = "wasm32", feature = "wasm")))]
+    fn start() -> Self {
+        Self {
+            start: std::time::Instant::now(),
+        }
+    }
+
+    #[cfg(not(all(target_arch = "wasm32", feature = "wasm")))]
+    fn elapsed(&self) -> Duration {
+        self.start.elapsed()
+    }
+
+    #[cfg(all(target_arch = "wasm32", feature = "wasm"))]
+    fn start() -> Self {
+        Self {
+            start_ms: js_sys::Date::now(),
+        }
+    }
+
+    #[cfg(all(target_arch = "wasm32", feature = "wasm"))]
+    fn elapsed(&self) -> Duration {
+        Duration::from_secs_f64(((js_sys::Date::now() - self.start_ms) / 1000.0).max(0.0))
+    }
+}
+
+/// 分母が0なら0を返す割り算
+fn ratio(count: usize, total: usize) -> f64 {
+    if total == 0 {
+        0.0
+    } else {
+        count as f64 / total as f64
     }
 }
 
@@ -143,60 +1609,2661 @@ fn run_hmc_chain(
 // Module: Python Interface (PyO3)
 // -----------------------------------------------------------------------------
 #[cfg(feature = "python")]
+use pyo3::exceptions::PyValueError;
+#[cfg(feature = "python")]
 use pyo3::prelude::*;
+#[cfg(feature = "python")]
+use pyo3::types::PyDict;
+#[cfg(feature = "python")]
+use std::cell::RefCell;
+
+#[cfg(feature = "python")]
+impl From<HmcError> for PyErr {
+    fn from(err: HmcError) -> PyErr {
+        PyValueError::new_err(err.to_string())
+    }
+}
 
 #[cfg(feature = "python")]
 #[pyfunction]
+#[pyo3(signature = (n_samples, step_size, num_steps, start_x, start_y, dist_type, seed=None, n_warmup=0, thin=1, save_warmup=false, max_duration=None, params=None, flat=false))]
+#[allow(clippy::too_many_arguments)]
 fn sample(
+    py: Python<'_>,
     n_samples: usize,
     step_size: f64,
     num_steps: usize,
     start_x: f64,
     start_y: f64,
-    dist_type: String
-) -> PyResult<(Vec<(f64, f64)>, f64)> {
-    let result = run_hmc_chain(
-        n_samples, 
-        step_size, 
-        num_steps, 
-        Point { x: start_x, y: start_y }, 
-        &dist_type
-    );
+    dist_type: String,
+    seed: Option<u64>,
+    n_warmup: usize,
+    thin: usize,
+    save_warmup: bool,
+    max_duration: Option<f64>,
+    params: Option<&PyAny>,
+    flat: bool,
+) -> PyResult<PyObject> {
+    let config = HmcConfig {
+        n_samples,
+        n_warmup,
+        thin,
+        save_warmup,
+        flat_samples: flat,
+        step_size,
+        num_steps,
+        initial_pos: Point { x: start_x, y: start_y },
+        target: py_dist_type(py, &dist_type, params)?,
+        seed,
+        max_duration: max_duration
+            .map(|secs| {
+                Duration::try_from_secs_f64(secs).map_err(|_| HmcError::InvalidMaxDuration(secs))
+            })
+            .transpose()?,
+        ..HmcConfig::default()
+    };
+    let result = run_hmc(&config)?;
     
-    let py_samples: Vec<(f64, f64)> = result.samples.iter().map(|p| (p.x, p.y)).collect();
-    Ok((py_samples, result.acceptance_rate))
+    // flat=True なら (x, y) のタプルを作らず、x0, y0, x1, y1, ... の浮動小数点数のリストで返す
+    let py_samples = if flat {
+        result.samples_flat.into_py(py)
+    } else {
+        to_py_points(&result.samples).into_py(py)
+    };
+    // save_warmup=True の場合のみ、ウォームアップのサンプルを3要素目として返す
+    if save_warmup {
+        let py_warmup = to_py_points(&result.warmup_samples);
+        Ok((py_samples, result.acceptance_rate, py_warmup).into_py(py))
+    } else {
+        Ok((py_samples, result.acceptance_rate).into_py(py))
+    }
 }
 
+/// `HmcConfig` のフィールドをキーワード引数で受け取り、`HmcResult` を辞書で返す
+///
+/// 引数・戻り値はWASMの `run_wasm` と同じJSON表現（点は `{"x": .., "y": ..}`）。
+/// 例: `run(n_samples=500, target="banana", seed=1, save_accept_flags=True)`
 #[cfg(feature = "python")]
-#[pymodule]
-fn hamiltonian_sampler_rs(_py: Python, m: &PyModule) -> PyResult<()> {
-    m.add_function(wrap_pyfunction!(sample, m)?)?;
-    Ok(())
+#[pyfunction]
+#[pyo3(signature = (**kwargs))]
+fn run(py: Python<'_>, kwargs: Option<&PyDict>) -> PyResult<PyObject> {
+    let json = py.import("json")?;
+    let config: HmcConfig = match kwargs {
+        Some(kwargs) => {
+            let text: String = json.call_method1("dumps", (kwargs,))?.extract()?;
+            serde_json::from_str(&text).map_err(|e| HmcError::Serialization(e.to_string()))?
+        }
+        None => HmcConfig::default(),
+    };
+    let result = run_hmc(&config)?;
+
+    let text = serde_json::to_string(&result).map_err(|e| HmcError::Serialization(e.to_string()))?;
+    Ok(json.call_method1("loads", (text,))?.into())
 }
 
-// -----------------------------------------------------------------------------
-// Module: WebAssembly Interface (wasm-bindgen)
-// -----------------------------------------------------------------------------
-#[cfg(feature = "wasm")]
-use wasm_bindgen::prelude::*;
+/// 1次元の分布をサンプリングし、(サンプルの値のリスト, 採択率) を返す
+///
+/// `potential` にPythonの関数 U(x) を渡せばそれを（勾配は中心差分で）、省略すれば二重井戸 `DoubleWell1`
+/// （`barrier`・`a`）を使う。`potential` が例外を投げるか数値を返さなければ、以後は呼ばずにチェーンを
+/// 棄却だけで終わらせ、その例外を投げる。
+#[cfg(feature = "python")]
+#[pyfunction]
+#[pyo3(signature = (n_samples, step_size, num_steps, start=0.0, potential=None, barrier=2.0, a=1.0, seed=None, n_warmup=0, thin=1))]
+#[allow(clippy::too_many_arguments)]
+fn sample_1d(
+    py: Python<'_>,
+    n_samples: usize,
+    step_size: f64,
+    num_steps: usize,
+    start: f64,
+    potential: Option<&PyAny>,
+    barrier: f64,
+    a: f64,
+    seed: Option<u64>,
+    n_warmup: usize,
+    thin: usize,
+) -> PyResult<PyObject> {
+    let config = HmcConfig {
+        n_samples,
+        n_warmup,
+        thin,
+        step_size,
+        num_steps,
+        seed,
+        ..HmcConfig::default()
+    };
+    let result = match potential {
+        Some(f) => {
+            // 最初の例外を覚えておく（ループの途中では返せないので、値は NaN として使わない）
+            let error: RefCell<Option<PyErr>> = RefCell::new(None);
+            let result = run_hmc_1d(
+                &config,
+                |x| {
+                    if error.borrow().is_some() {
+                        return f64::NAN;
+                    }
+                    f.call1((x,))
+                        .and_then(|u| u.extract::<f64>())
+                        .unwrap_or_else(|e| {
+                            *error.borrow_mut() = Some(e);
+                            f64::NAN
+                        })
+                },
+                start,
+            );
+            if let Some(e) = error.into_inner() {
+                return Err(e);
+            }
+            result?
+        }
+        None => run_hmc_fixed(&config, &DoubleWell1 { barrier, a }, PointN([start]))?,
+    };
 
-#[cfg(feature = "wasm")]
-#[wasm_bindgen]
-pub fn sample_wasm(
+    Ok((result.samples, result.acceptance_rate).into_py(py))
+}
+
+/// `run` と同じキーワード引数のHMCカーネルで焼きなましを行い、`AnnealResult` を辞書で返す
+///
+/// `schedule` は `AnnealSchedule` と同じ形の辞書（省略したキーは既定値）。
+/// 例: `anneal({"cooling": "linear", "n_iterations": 5000}, target={"banana": {"b": 100}}, step_size=0.02)`
+#[cfg(feature = "python")]
+#[pyfunction]
+#[pyo3(name = "anneal", signature = (schedule=None, **kwargs))]
+fn py_anneal(
+    py: Python<'_>,
+    schedule: Option<&PyDict>,
+    kwargs: Option<&PyDict>,
+) -> PyResult<PyObject> {
+    let json = py.import("json")?;
+    let config: HmcConfig = match kwargs {
+        Some(kwargs) => {
+            let text: String = json.call_method1("dumps", (kwargs,))?.extract()?;
+            serde_json::from_str(&text).map_err(|e| HmcError::Serialization(e.to_string()))?
+        }
+        None => HmcConfig::default(),
+    };
+    let schedule: AnnealSchedule = match schedule {
+        Some(schedule) => {
+            let text: String = json.call_method1("dumps", (schedule,))?.extract()?;
+            serde_json::from_str(&text).map_err(|e| HmcError::Serialization(e.to_string()))?
+        }
+        None => AnnealSchedule::default(),
+    };
+    let result = anneal(&config, &schedule)?;
+
+    let text =
+        serde_json::to_string(&result).map_err(|e| HmcError::Serialization(e.to_string()))?;
+    Ok(json.call_method1("loads", (text,))?.into())
+}
+
+/// `initial_points` を省略すると全チェーンが原点から始まる
+///
+/// 戻り値は (チェーンごとのサンプル列のリスト, チェーンごとの採択率のリスト)。`compute_diagnostics=True` なら
+/// 3要素目に収束診断の辞書 `{"rhat": (x, y) または None, "ess": [(x, y), ...]}`（ESSはチェーンごと）を返す。
+#[cfg(feature = "python")]
+#[pyfunction]
+#[pyo3(signature = (n_chains, n_samples, step_size, num_steps, dist_type, initial_points=None, seed=None, n_warmup=0, thin=1, params=None, compute_diagnostics=false))]
+#[allow(clippy::too_many_arguments)]
+fn sample_chains(
+    py: Python<'_>,
+    n_chains: usize,
     n_samples: usize,
     step_size: f64,
     num_steps: usize,
+    dist_type: String,
+    initial_points: Option<Vec<(f64, f64)>>,
+    seed: Option<u64>,
+    n_warmup: usize,
+    thin: usize,
+    params: Option<&PyAny>,
+    compute_diagnostics: bool,
+) -> PyResult<PyObject> {
+    let config = HmcConfig {
+        n_samples,
+        n_warmup,
+        thin,
+        step_size,
+        num_steps,
+        target: py_dist_type(py, &dist_type, params)?,
+        seed,
+        compute_diagnostics,
+        ..HmcConfig::default()
+    };
+    let initial_points: Vec<Point> = initial_points
+        .unwrap_or_default()
+        .into_iter()
+        .map(|(x, y)| Point { x, y })
+        .collect();
+    let result = run_hmc_chains(&config, n_chains, &initial_points)?;
+
+    let samples: Vec<_> = result.chains.iter().map(|c| to_py_points(&c.samples)).collect();
+    if !compute_diagnostics {
+        return Ok((samples, result.acceptance_rates()).into_py(py));
+    }
+    let diagnostics = PyDict::new(py);
+    diagnostics.set_item("rhat", result.rhat.as_ref().map(|r| (r.x, r.y)))?;
+    let ess: Vec<_> = result
+        .chains
+        .iter()
+        .map(|c| c.ess())
+        .map(|e| (e.x, e.y))
+        .collect();
+    diagnostics.set_item("ess", ess)?;
+    Ok((samples, result.acceptance_rates(), diagnostics).into_py(py))
+}
+
+/// アフィン不変なアンサンブルサンプラー（`Algorithm::Ensemble`）で `n_walkers` 個のウォーカーを動かす
+///
+/// 戻り値は (ウォーカーごとのサンプル列のリスト, ウォーカーごとの採択率のリスト)。
+/// `numpy.array` に渡すとウォーカーが先頭の軸になり、形は (n_walkers, n_samples, 2)。
+#[cfg(feature = "python")]
+#[pyfunction]
+#[pyo3(signature = (n_walkers, n_samples, dist_type, a=2.0, start_x=0.0, start_y=0.0, seed=None, n_warmup=0, thin=1, params=None))]
+#[allow(clippy::too_many_arguments)]
+fn sample_ensemble(
+    py: Python<'_>,
+    n_walkers: usize,
+    n_samples: usize,
+    dist_type: String,
+    a: f64,
     start_x: f64,
     start_y: f64,
-    dist_type: String
-) -> JsValue {
-    let result = run_hmc_chain(
-        n_samples, 
-        step_size, 
-        num_steps, 
-        Point { x: start_x, y: start_y }, 
-        &dist_type
-    );
-    
-    serde_wasm_bindgen::to_value(&result).unwrap()
-}
\ No newline at end of file
+    seed: Option<u64>,
+    n_warmup: usize,
+    thin: usize,
+    params: Option<&PyAny>,
+) -> PyResult<PyObject> {
+    let config = HmcConfig {
+        n_samples,
+        n_warmup,
+        thin,
+        algorithm: Algorithm::Ensemble { n_walkers, a },
+        initial_pos: Point {
+            x: start_x,
+            y: start_y,
+        },
+        target: py_dist_type(py, &dist_type, params)?,
+        seed,
+        ..HmcConfig::default()
+    };
+    let result = run_hmc(&config)?;
+
+    let samples: Vec<_> = result
+        .walker_samples()
+        .iter()
+        .map(|samples| to_py_points(samples))
+        .collect();
+    Ok((samples, result.walker_acceptance_rates).into_py(py))
+}
+
+/// 説明変数 `x` と0/1の目的変数 `y`（リストやnumpy配列）からロジスティック回帰の事後分布をサンプリングする
+///
+/// 各点は (傾き, 切片)。戻り値は `sample` と同じ (サンプル列, 採択率)。
+#[cfg(feature = "python")]
+#[pyfunction]
+#[pyo3(signature = (x, y, n_samples, step_size, num_steps, prior_std=10.0, seed=None, n_warmup=0, thin=1))]
+#[allow(clippy::too_many_arguments)]
+fn sample_logistic_regression(
+    py: Python<'_>,
+    x: Vec<f64>,
+    y: Vec<u8>,
+    n_samples: usize,
+    step_size: f64,
+    num_steps: usize,
+    prior_std: f64,
+    seed: Option<u64>,
+    n_warmup: usize,
+    thin: usize,
+) -> PyResult<PyObject> {
+    let target = LogisticRegression2::new(&x, &y, prior_std)?;
+    let config = HmcConfig {
+        n_samples,
+        n_warmup,
+        thin,
+        step_size,
+        num_steps,
+        seed,
+        ..HmcConfig::default()
+    };
+    let result = run_hmc_with_target(&config, &target)?;
+
+    Ok((to_py_points(&result.samples), result.acceptance_rate).into_py(py))
+}
+
+/// 線形回帰の事後分布をサンプリングし、事後分布の要約を辞書で返す
+///
+/// 各点は (傾き, 切片)。戻り値のキーは `samples`、`acceptance_rate`、サンプルから求めた
+/// `mean`・`cov`、厳密な事後分布の `exact_mean`・`exact_cov`。
+#[cfg(feature = "python")]
+#[pyfunction]
+#[pyo3(signature = (x, y, n_samples, step_size, num_steps, noise_std=1.0, prior_std=10.0, seed=None, n_warmup=0, thin=1))]
+#[allow(clippy::too_many_arguments)]
+fn sample_linear_regression(
+    py: Python<'_>,
+    x: Vec<f64>,
+    y: Vec<f64>,
+    n_samples: usize,
+    step_size: f64,
+    num_steps: usize,
+    noise_std: f64,
+    prior_std: f64,
+    seed: Option<u64>,
+    n_warmup: usize,
+    thin: usize,
+) -> PyResult<PyObject> {
+    let target = LinearRegression2::new(&x, &y, noise_std, prior_std)?;
+    let config = HmcConfig {
+        n_samples,
+        n_warmup,
+        thin,
+        step_size,
+        num_steps,
+        initial_pos: target.posterior().mean().clone(),
+        seed,
+        ..HmcConfig::default()
+    };
+    let result = run_hmc_with_target(&config, &target)?;
+    let mean = validate::sample_mean(&result.samples);
+    let posterior = target.posterior();
+
+    let summary = PyDict::new(py);
+    summary.set_item("samples", to_py_points(&result.samples))?;
+    summary.set_item("acceptance_rate", result.acceptance_rate)?;
+    summary.set_item("mean", (mean.x, mean.y))?;
+    summary.set_item("cov", validate::sample_cov(&result.samples))?;
+    summary.set_item("exact_mean", (posterior.mean().x, posterior.mean().y))?;
+    summary.set_item("exact_cov", posterior.cov())?;
+    Ok(summary.into())
+}
+
+/// 格子上のポテンシャル値 `values`（形状 (ny, nx) の配列を平らにしたもの）を双線形補間してサンプリングする
+///
+/// numpy配列なら `values.ravel()` を渡す。戻り値は `sample` と同じ (サンプル列, 採択率)。
+#[cfg(feature = "python")]
+#[pyfunction]
+#[pyo3(signature = (values, nx, ny, xmin, xmax, ymin, ymax, n_samples, step_size, num_steps, start_x, start_y, outside_potential=1e10, seed=None, n_warmup=0, thin=1))]
+#[allow(clippy::too_many_arguments)]
+fn sample_grid_potential(
+    py: Python<'_>,
+    values: Vec<f64>,
+    nx: usize,
+    ny: usize,
+    xmin: f64,
+    xmax: f64,
+    ymin: f64,
+    ymax: f64,
+    n_samples: usize,
+    step_size: f64,
+    num_steps: usize,
+    start_x: f64,
+    start_y: f64,
+    outside_potential: f64,
+    seed: Option<u64>,
+    n_warmup: usize,
+    thin: usize,
+) -> PyResult<PyObject> {
+    let target = GridPotential::new(values, nx, ny, xmin, xmax, ymin, ymax)?
+        .with_outside_potential(outside_potential)?;
+    let config = HmcConfig {
+        n_samples,
+        n_warmup,
+        thin,
+        step_size,
+        num_steps,
+        initial_pos: Point {
+            x: start_x,
+            y: start_y,
+        },
+        seed,
+        ..HmcConfig::default()
+    };
+    let result = run_hmc_with_target(&config, &target)?;
+
+    Ok((to_py_points(&result.samples), result.acceptance_rate).into_py(py))
+}
+
+/// (x, y) での正規化されていない対数密度 -U(x, y)（サンプラーが使う定義そのもの）
+///
+/// `dist_type` と `params` は `sample` と同じ。散布図の下に等高線を描くときなどに使う。
+#[cfg(feature = "python")]
+#[pyfunction]
+#[pyo3(signature = (x, y, dist_type, params=None))]
+fn log_density(
+    py: Python<'_>,
+    x: f64,
+    y: f64,
+    dist_type: &str,
+    params: Option<&PyAny>,
+) -> PyResult<f64> {
+    let target = py_dist_type(py, dist_type, params)?;
+    target.validate()?;
+    Ok(target.log_density(&Point { x, y }))
+}
+
+/// (x, y) での対数密度の勾配 (d/dx, d/dy)
+#[cfg(feature = "python")]
+#[pyfunction]
+#[pyo3(signature = (x, y, dist_type, params=None))]
+fn grad_log_density(
+    py: Python<'_>,
+    x: f64,
+    y: f64,
+    dist_type: &str,
+    params: Option<&PyAny>,
+) -> PyResult<(f64, f64)> {
+    let target = py_dist_type(py, dist_type, params)?;
+    target.validate()?;
+    let g = target.grad_log_density(&Point { x, y });
+    Ok((g.x, g.y))
+}
+
+/// 格子点でのポテンシャル U を行優先の平らなリストで返す（Rustの `evaluate_potential_grid` と同じ並び）
+///
+/// numpyでは `np.array(values).reshape(ny, nx)` で等高線描画用の配列になる。
+/// 非有限の値はそのまま `inf` / `nan` として返る。
+#[cfg(feature = "python")]
+#[pyfunction]
+#[pyo3(name = "evaluate_potential_grid", signature = (dist_type, xmin, xmax, ymin, ymax, nx, ny, params=None))]
+#[allow(clippy::too_many_arguments)]
+fn py_evaluate_potential_grid(
+    py: Python<'_>,
+    dist_type: &str,
+    xmin: f64,
+    xmax: f64,
+    ymin: f64,
+    ymax: f64,
+    nx: usize,
+    ny: usize,
+    params: Option<&PyAny>,
+) -> PyResult<Vec<f64>> {
+    let target = py_dist_type(py, dist_type, params)?;
+    target.validate()?;
+    Ok(evaluate_potential_grid(&target, xmin, xmax, ymin, ymax, nx, ny))
+}
+
+/// (x, y) から始めたときのステップ幅の適応の初期値の目安（Rustの `find_reasonable_step_size`）
+#[cfg(feature = "python")]
+#[pyfunction]
+#[pyo3(name = "find_reasonable_step_size", signature = (dist_type, x, y, seed=0, params=None))]
+fn py_find_reasonable_step_size(
+    py: Python<'_>,
+    dist_type: &str,
+    x: f64,
+    y: f64,
+    seed: u64,
+    params: Option<&PyAny>,
+) -> PyResult<f64> {
+    let target = py_dist_type(py, dist_type, params)?;
+    target.validate()?;
+    Ok(find_reasonable_step_size(&target, &Point { x, y }, seed))
+}
+
+/// `sample_chains` がチェーン `chain_index` に使うシード（`seed` 引数で単独再実行できる）
+#[cfg(feature = "python")]
+#[pyfunction]
+#[pyo3(name = "derive_chain_seed")]
+fn py_derive_chain_seed(master_seed: u64, chain_index: usize) -> u64 {
+    multichain::derive_chain_seed(master_seed, chain_index)
+}
+
+/// `sample_chains` の `initial_points` にそのまま渡せる (x, y) のリストを返す
+#[cfg(feature = "python")]
+#[pyfunction]
+#[pyo3(name = "init_uniform_box", signature = (n, xmin, xmax, ymin, ymax, seed=None))]
+fn py_init_uniform_box(
+    n: usize,
+    xmin: f64,
+    xmax: f64,
+    ymin: f64,
+    ymax: f64,
+    seed: Option<u64>,
+) -> PyResult<Vec<(f64, f64)>> {
+    Ok(to_py_points(&init_uniform_box(n, xmin, xmax, ymin, ymax, seed)?))
+}
+
+#[cfg(feature = "python")]
+#[pyfunction]
+#[pyo3(name = "init_jitter", signature = (n, center_x, center_y, scale, seed=None))]
+fn py_init_jitter(
+    n: usize,
+    center_x: f64,
+    center_y: f64,
+    scale: f64,
+    seed: Option<u64>,
+) -> PyResult<Vec<(f64, f64)>> {
+    let center = Point { x: center_x, y: center_y };
+    Ok(to_py_points(&init_jitter(n, &center, scale, seed)?))
+}
+
+/// 系列のラグ 0..=`max_lag` の自己相関のリスト（Rustの `diagnostics::autocorrelation`）
+///
+/// 平均を引き、全ラグで n で割る偏りのある推定を使う。`matplotlib` の `plt.stem` などにそのまま渡せる。
+/// 例: `xs = [p["x"] for p in run(...)["samples"]]; rho = autocorrelation(xs, 50)`
+#[cfg(feature = "python")]
+#[pyfunction]
+#[pyo3(name = "autocorrelation")]
+fn py_autocorrelation(series: Vec<f64>, max_lag: usize) -> Vec<f64> {
+    diagnostics::autocorrelation(&series, max_lag)
+}
+
+/// 分布名と `params`（例: `{"b": 100}`、混合分布なら成分の辞書のリスト）から `DistType` を作る
+#[cfg(feature = "python")]
+fn py_dist_type(py: Python<'_>, name: &str, params: Option<&PyAny>) -> PyResult<DistType> {
+    let params = match params {
+        Some(params) => {
+            let text: String = py.import("json")?.call_method1("dumps", (params,))?.extract()?;
+            let value =
+                serde_json::from_str(&text).map_err(|e| HmcError::Serialization(e.to_string()))?;
+            Some(value)
+        }
+        None => None,
+    };
+    Ok(DistType::with_params(name, params)?)
+}
+
+#[cfg(feature = "python")]
+fn to_py_points(points: &[Point]) -> Vec<(f64, f64)> {
+    points.iter().map(|p| (p.x, p.y)).collect()
+}
+
+#[cfg(feature = "python")]
+#[pymodule]
+fn hamiltonian_sampler_rs(_py: Python, m: &PyModule) -> PyResult<()> {
+    m.add_function(wrap_pyfunction!(sample, m)?)?;
+    m.add_function(wrap_pyfunction!(run, m)?)?;
+    m.add_function(wrap_pyfunction!(sample_1d, m)?)?;
+    m.add_function(wrap_pyfunction!(py_anneal, m)?)?;
+    m.add_function(wrap_pyfunction!(sample_chains, m)?)?;
+    m.add_function(wrap_pyfunction!(sample_ensemble, m)?)?;
+    m.add_function(wrap_pyfunction!(sample_logistic_regression, m)?)?;
+    m.add_function(wrap_pyfunction!(sample_linear_regression, m)?)?;
+    m.add_function(wrap_pyfunction!(sample_grid_potential, m)?)?;
+    m.add_function(wrap_pyfunction!(log_density, m)?)?;
+    m.add_function(wrap_pyfunction!(grad_log_density, m)?)?;
+    m.add_function(wrap_pyfunction!(py_evaluate_potential_grid, m)?)?;
+    m.add_function(wrap_pyfunction!(py_find_reasonable_step_size, m)?)?;
+    m.add_function(wrap_pyfunction!(py_derive_chain_seed, m)?)?;
+    m.add_function(wrap_pyfunction!(py_init_uniform_box, m)?)?;
+    m.add_function(wrap_pyfunction!(py_init_jitter, m)?)?;
+    m.add_function(wrap_pyfunction!(py_autocorrelation, m)?)?;
+    Ok(())
+}
+
+// -----------------------------------------------------------------------------
+// Module: WebAssembly Interface (wasm-bindgen)
+// -----------------------------------------------------------------------------
+#[cfg(feature = "wasm")]
+use wasm_bindgen::prelude::*;
+
+/// `seed` はJS側では `BigInt`（省略時は `undefined`）で渡す
+///
+/// `params` は分布のパラメータのオブジェクト（例: `{ b: 100 }`、省略時は `undefined`）。
+#[cfg(feature = "wasm")]
+#[wasm_bindgen]
+#[allow(clippy::too_many_arguments)]
+pub fn sample_wasm(
+    n_samples: usize,
+    step_size: f64,
+    num_steps: usize,
+    start_x: f64,
+    start_y: f64,
+    dist_type: String,
+    seed: Option<u64>,
+    params: JsValue,
+) -> Result<JsValue, JsError> {
+    let config = HmcConfig {
+        n_samples,
+        step_size,
+        num_steps,
+        initial_pos: Point { x: start_x, y: start_y },
+        target: js_dist_type(&dist_type, params)?,
+        seed,
+        ..HmcConfig::default()
+    };
+    let result = run_hmc(&config)?;
+    
+    to_js(&result)
+}
+
+/// `sample_wasm` と同じ引数でサンプリングし、サンプルを `f32` の配列で返す（描画用）
+///
+/// 戻り値は `{ xs: Float32Array, ys: Float32Array, acceptance_rate, final_position }`。
+/// 位置・運動量と正規乱数は `f32` で持ってリープフロッグも `f32` で進め、採択判定のエネルギーだけを
+/// `f64` で計算する（小さな H の差を `f32` で取ると丸め誤差が採択確率に乗るため）。
+/// 統計的には `sample_wasm` と同じ分布になり、サンプルの転送量は `Float64Array` の半分になる。
+#[cfg(feature = "wasm")]
+#[wasm_bindgen]
+#[allow(clippy::too_many_arguments)]
+pub fn sample_wasm_f32(
+    n_samples: usize,
+    step_size: f64,
+    num_steps: usize,
+    start_x: f64,
+    start_y: f64,
+    dist_type: String,
+    seed: Option<u64>,
+    params: JsValue,
+) -> Result<JsValue, JsError> {
+    let config = HmcConfig {
+        n_samples,
+        step_size,
+        num_steps,
+        initial_pos: Point {
+            x: start_x,
+            y: start_y,
+        },
+        target: js_dist_type(&dist_type, params)?,
+        seed,
+        ..HmcConfig::default()
+    };
+    let result = single::run_hmc_f32(&config)?;
+
+    js_object([
+        ("xs", js_sys::Float32Array::from(&result.xs[..]).into()),
+        ("ys", js_sys::Float32Array::from(&result.ys[..]).into()),
+        ("acceptance_rate", result.acceptance_rate.into()),
+        ("final_position", to_js(&result.final_position)?),
+    ])
+}
+
+/// `sample_wasm` と同じ引数でサンプリングし、サンプルを1本の `Float64Array` で返す
+///
+/// 戻り値は `{ samples: Float64Array, dim: 2, acceptance_rate, final_position }`。`samples` は
+/// x0, y0, x1, y1, ... の順（`HmcResult::samples_flat`）で、点ごとのオブジェクトを作らずに渡せる。
+#[cfg(feature = "wasm")]
+#[wasm_bindgen]
+#[allow(clippy::too_many_arguments)]
+pub fn sample_wasm_flat(
+    n_samples: usize,
+    step_size: f64,
+    num_steps: usize,
+    start_x: f64,
+    start_y: f64,
+    dist_type: String,
+    seed: Option<u64>,
+    params: JsValue,
+) -> Result<JsValue, JsError> {
+    let config = HmcConfig {
+        n_samples,
+        step_size,
+        num_steps,
+        flat_samples: true,
+        initial_pos: Point {
+            x: start_x,
+            y: start_y,
+        },
+        target: js_dist_type(&dist_type, params)?,
+        seed,
+        ..HmcConfig::default()
+    };
+    let result = run_hmc(&config)?;
+
+    js_object([
+        (
+            "samples",
+            js_sys::Float64Array::from(&result.samples_flat[..]).into(),
+        ),
+        ("dim", result.dim.into()),
+        ("acceptance_rate", result.acceptance_rate.into()),
+        ("final_position", to_js(&result.final_position)?),
+    ])
+}
+
+/// 格子点でのポテンシャル U を行優先の `Float64Array` で返す（`values[j * nx + i]` が (x_i, y_j)）
+///
+/// 等高線や密度の描画用。非有限の値は `Infinity` / `NaN` のまま返す。
+#[cfg(feature = "wasm")]
+#[wasm_bindgen]
+#[allow(clippy::too_many_arguments)]
+pub fn evaluate_potential_grid_wasm(
+    dist_type: String,
+    xmin: f64,
+    xmax: f64,
+    ymin: f64,
+    ymax: f64,
+    nx: usize,
+    ny: usize,
+    params: JsValue,
+) -> Result<Vec<f64>, JsError> {
+    let target = js_dist_type(&dist_type, params)?;
+    target.validate()?;
+    Ok(evaluate_potential_grid(&target, xmin, xmax, ymin, ymax, nx, ny))
+}
+
+/// `HmcConfig` と同じ形のオブジェクトを受け取ってサンプリングする
+///
+/// 省略したフィールドはデフォルト値になる。例: `run_wasm({ n_samples: 500, n_warmup: 200, save_warmup: true })`
+///
+/// 軌道を描くには `record_trajectory_every: 50` などを指定し、戻り値の `trajectories` の各 `positions` を結ぶ。
+#[cfg(feature = "wasm")]
+#[wasm_bindgen]
+pub fn run_wasm(config: JsValue) -> Result<JsValue, JsError> {
+    let config: HmcConfig = serde_wasm_bindgen::from_value(config)
+        .map_err(|e| HmcError::Serialization(e.to_string()))?;
+    let result = run_hmc(&config)?;
+
+    to_js(&result)
+}
+
+/// `run_wasm` と同じ設定オブジェクトのHMCカーネルで焼きなましを行う
+///
+/// `schedule` は `AnnealSchedule` と同じ形のオブジェクト（省略したフィールドは既定値）。戻り値は `AnnealResult`。
+#[cfg(feature = "wasm")]
+#[wasm_bindgen]
+pub fn anneal_wasm(config: JsValue, schedule: JsValue) -> Result<JsValue, JsError> {
+    let config: HmcConfig = serde_wasm_bindgen::from_value(config)
+        .map_err(|e| HmcError::Serialization(e.to_string()))?;
+    let schedule: AnnealSchedule = serde_wasm_bindgen::from_value(schedule)
+        .map_err(|e| HmcError::Serialization(e.to_string()))?;
+    let result = anneal(&config, &schedule)?;
+
+    to_js(&result)
+}
+/// `run_wasm` と同じ設定オブジェクトで `n_chains` 本のチェーンを実行する
+///
+/// `initial_points` は `[{ x, y }, ...]`（省略時は `undefined`）。戻り値は `{ chains: [HmcResult, ...] }`。
+#[cfg(feature = "wasm")]
+#[wasm_bindgen]
+pub fn sample_chains_wasm(
+    config: JsValue,
+    n_chains: usize,
+    initial_points: JsValue,
+) -> Result<JsValue, JsError> {
+    let config: HmcConfig = serde_wasm_bindgen::from_value(config)
+        .map_err(|e| HmcError::Serialization(e.to_string()))?;
+    let initial_points: Vec<Point> = if initial_points.is_undefined() || initial_points.is_null() {
+        Vec::new()
+    } else {
+        serde_wasm_bindgen::from_value(initial_points)
+            .map_err(|e| HmcError::Serialization(e.to_string()))?
+    };
+    let result = run_hmc_chains(&config, n_chains, &initial_points)?;
+
+    to_js(&result)
+}
+
+/// JSから少しずつ進めるチェーン（`run_wasm` と同じ設定オブジェクトで作る）
+///
+/// `sample_chunk(n)` を `requestAnimationFrame` などから繰り返し呼べば、メインスレッドを長く塞がずに
+/// サンプリングできる。つなげた結果は同じシードの `run_wasm` の（ウォームアップを含めた）列と一致する。
+#[cfg(feature = "wasm")]
+#[wasm_bindgen]
+pub struct ChainWasm {
+    chain: Chain,
+    /// `sample_chunk` の位置を x, y の順に溜めるバッファ（呼び出しごとに空にして使い回し、JSへはここから写す）
+    buffer: Vec<f64>,
+}
+
+#[cfg(feature = "wasm")]
+#[wasm_bindgen]
+impl ChainWasm {
+    #[wasm_bindgen(constructor)]
+    pub fn new(config: JsValue) -> Result<ChainWasm, JsError> {
+        let config: HmcConfig = serde_wasm_bindgen::from_value(config)
+            .map_err(|e| HmcError::Serialization(e.to_string()))?;
+        Ok(ChainWasm {
+            chain: Chain::new(config)?,
+            buffer: Vec::new(),
+        })
+    }
+
+    /// `n` 回の遷移を進め、その間の位置を x0, y0, x1, y1, ... の `Float64Array` で返す
+    pub fn sample_chunk(&mut self, n: usize) -> js_sys::Float64Array {
+        self.buffer.clear();
+        self.chain.sample_chunk_flat_into(n, &mut self.buffer);
+        js_sys::Float64Array::from(&self.buffer[..])
+    }
+
+    /// これまでの全遷移に対する採択率
+    pub fn acceptance_rate(&self) -> f64 {
+        self.chain.acceptance_rate()
+    }
+
+    /// これまでに実行した遷移の回数
+    pub fn iteration(&self) -> usize {
+        self.chain.iteration()
+    }
+
+    /// 次の遷移に使うステップ幅
+    pub fn step_size(&self) -> f64 {
+        self.chain.step_size()
+    }
+}
+
+/// 分布名とパラメータのオブジェクト（`undefined` / `null` なら既定値）から `DistType` を作る
+#[cfg(feature = "wasm")]
+fn js_dist_type(name: &str, params: JsValue) -> Result<DistType, HmcError> {
+    let params = if params.is_undefined() || params.is_null() {
+        None
+    } else {
+        Some(
+            serde_wasm_bindgen::from_value(params)
+                .map_err(|e| HmcError::Serialization(e.to_string()))?,
+        )
+    };
+    DistType::with_params(name, params)
+}
+
+/// キーと値の組からJSのオブジェクトを作る（型付き配列をserdeを通さずにそのまま載せる）
+#[cfg(feature = "wasm")]
+fn js_object<const N: usize>(entries: [(&str, JsValue); N]) -> Result<JsValue, JsError> {
+    let object = js_sys::Object::new();
+    for (key, value) in entries {
+        js_sys::Reflect::set(&object, &JsValue::from_str(key), &value)
+            .map_err(|_| JsError::new("failed to build the result object"))?;
+    }
+    Ok(object.into())
+}
+
+/// u64のシードが `Number` の安全な整数範囲を超えても失われないよう、`BigInt` として渡す
+#[cfg(feature = "wasm")]
+fn to_js<T: Serialize>(value: &T) -> Result<JsValue, JsError> {
+    let serializer =
+        serde_wasm_bindgen::Serializer::new().serialize_large_number_types_as_bigints(true);
+    value
+        .serialize(&serializer)
+        .map_err(|e| HmcError::Serialization(e.to_string()).into())
+}
+
+// -----------------------------------------------------------------------------
+// Tests
+// -----------------------------------------------------------------------------
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn run_hmc_with_default_config() {
+        let config = HmcConfig::default();
+        let result = run_hmc(&config).unwrap();
+
+        assert_eq!(result.samples.len(), config.n_samples);
+        assert!((0.0..=1.0).contains(&result.acceptance_rate));
+    }
+
+    #[test]
+    fn run_hmc_on_banana() {
+        let config = HmcConfig {
+            n_samples: 200,
+            n_warmup: 0,
+            thin: 1,
+            save_warmup: false,
+            save_accept_flags: false,
+            save_accept_prob: false,
+            divergence_threshold: 1000.0,
+            on_divergence: DivergencePolicy::Continue,
+            save_divergences: false,
+            save_energy: false,
+            save_step_sizes: false,
+            record_trajectory_every: None,
+            save_momentum: false,
+            flat_samples: false,
+            step_size: 0.05,
+            step_size_jitter: 0.0,
+            adapt_step_size: false,
+            find_step_size: false,
+            target_accept: 0.8,
+            metric: Metric::UnitE,
+            adapt_mass_matrix: false,
+            mass_regularization: 5.0,
+            warmup_schedule: None,
+            num_steps: 10,
+            randomize_steps: false,
+            momentum_persistence: 0.0,
+            integrator: IntegratorKind::Leapfrog,
+            kinetic: Kinetic::Gaussian,
+            algorithm: Algorithm::Hmc,
+            initial_pos: Point { x: 1.0, y: 1.0 },
+            resume_from: None,
+            init: InitStrategy::Given,
+            target: DistType::Banana(Banana::default()),
+            temperature: 1.0,
+            transform: Transforms::default(),
+            bounds: None,
+            topology: Topology::Euclidean,
+            transform_nd: Vec::new(),
+            bounds_nd: None,
+            periods_nd: None,
+            numdiff: None,
+            max_grad_norm: None,
+            debug_check_gradient: false,
+            seed: None,
+            max_duration: None,
+            target_ess: None,
+            ess_check_every: 1000,
+            mode_centers: Vec::new(),
+            mode_centers_nd: Vec::new(),
+            compute_diagnostics: false,
+        };
+        let result = run_hmc(&config).unwrap();
+
+        assert_eq!(result.samples.len(), 200);
+        assert!(result.acceptance_rate > 0.0);
+        assert!(result
+            .samples
+            .iter()
+            .all(|p| p.x.is_finite() && p.y.is_finite()));
+    }
+
+    #[test]
+    fn same_seed_reproduces_chain() {
+        let config = HmcConfig {
+            n_samples: 300,
+            seed: Some(42),
+            ..HmcConfig::default()
+        };
+        let a = run_hmc(&config).unwrap();
+        let b = run_hmc(&config).unwrap();
+
+        assert_eq!(a.samples, b.samples);
+        assert_eq!(a.acceptance_rate, b.acceptance_rate);
+
+        let other = run_hmc(&HmcConfig {
+            seed: Some(43),
+            ..config
+        })
+        .unwrap();
+        assert_ne!(a.samples, other.samples);
+    }
+
+    /// RngCore の呼び出し回数を数えるラッパー
+    struct CountingRng {
+        inner: StdRng,
+        calls: usize,
+    }
+
+    impl RngCore for CountingRng {
+        fn next_u32(&mut self) -> u32 {
+            self.calls += 1;
+            self.inner.next_u32()
+        }
+        fn next_u64(&mut self) -> u64 {
+            self.calls += 1;
+            self.inner.next_u64()
+        }
+        fn fill_bytes(&mut self, dest: &mut [u8]) {
+            self.calls += 1;
+            self.inner.fill_bytes(dest)
+        }
+        fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand::Error> {
+            self.calls += 1;
+            self.inner.try_fill_bytes(dest)
+        }
+    }
+
+    #[test]
+    fn injected_rng_is_used_for_all_draws() {
+        let config = HmcConfig {
+            n_samples: 1000,
+            ..HmcConfig::default()
+        };
+        let mut rng = CountingRng {
+            inner: StdRng::seed_from_u64(1),
+            calls: 0,
+        };
+        run_hmc_chain_with_rng(&config, &mut rng).unwrap();
+
+        // 1反復あたり 正規乱数2回 + 一様乱数1回。Ziggurat法の棄却で稀に追加の呼び出しが入る
+        assert!(rng.calls >= 3 * config.n_samples);
+        assert!(rng.calls < 3 * config.n_samples + config.n_samples / 10);
+    }
+
+    #[test]
+    fn injected_rng_matches_seeded_run() {
+        let config = HmcConfig {
+            n_samples: 200,
+            seed: Some(9),
+            ..HmcConfig::default()
+        };
+        let seeded = run_hmc(&config).unwrap();
+        let injected = run_hmc_chain_with_rng(&config, &mut StdRng::seed_from_u64(9)).unwrap();
+        assert_eq!(seeded.samples, injected.samples);
+    }
+
+    #[test]
+    fn warmup_draws_are_discarded() {
+        let base = HmcConfig {
+            n_samples: 300,
+            seed: Some(5),
+            ..HmcConfig::default()
+        };
+        let full = run_hmc(&HmcConfig {
+            n_samples: 400,
+            ..base.clone()
+        })
+        .unwrap();
+        let warm = run_hmc(&HmcConfig {
+            n_warmup: 100,
+            ..base
+        })
+        .unwrap();
+
+        assert_eq!(warm.samples.len(), 300);
+        assert_eq!(warm.samples[..], full.samples[100..]);
+        assert!((0.0..=1.0).contains(&warm.warmup_acceptance_rate));
+        assert_eq!(full.warmup_acceptance_rate, 0.0);
+    }
+
+    #[test]
+    fn thinning_keeps_every_kth_draw() {
+        let base = HmcConfig {
+            n_samples: 200,
+            n_warmup: 10,
+            seed: Some(11),
+            ..HmcConfig::default()
+        };
+        let thinned = run_hmc(&HmcConfig {
+            thin: 5,
+            ..base.clone()
+        })
+        .unwrap();
+        let full = run_hmc(&HmcConfig {
+            n_samples: 1000,
+            ..base
+        })
+        .unwrap();
+
+        let sliced: Vec<Point> = full.samples.iter().skip(4).step_by(5).cloned().collect();
+        assert_eq!(thinned.samples.len(), 200);
+        assert_eq!(thinned.samples, sliced);
+        // 採択率は保存した点ではなく全遷移で計算される
+        assert_eq!(thinned.acceptance_rate, full.acceptance_rate);
+
+        let zero = HmcConfig {
+            thin: 0,
+            ..HmcConfig::default()
+        };
+        assert_eq!(run_hmc(&zero).unwrap_err(), HmcError::ZeroThin);
+    }
+
+    #[test]
+    fn warmup_draws_saved_on_request() {
+        let config = HmcConfig {
+            n_samples: 100,
+            n_warmup: 50,
+            thin: 2,
+            save_warmup: true,
+            seed: Some(2),
+            ..HmcConfig::default()
+        };
+        let saved = run_hmc(&config).unwrap();
+        let plain = run_hmc(&HmcConfig {
+            save_warmup: false,
+            ..config
+        })
+        .unwrap();
+
+        assert_eq!(saved.warmup_samples.len(), 50);
+        assert_eq!(saved.samples, plain.samples);
+        assert!(plain.warmup_samples.is_empty());
+
+        // 空の warmup_samples はJSONに出力されない
+        let json = serde_json::to_string(&plain).unwrap();
+        assert!(!json.contains("warmup_samples"));
+        assert!(serde_json::to_string(&saved).unwrap().contains("warmup_samples"));
+    }
+
+    #[test]
+    fn time_budget_truncates_chain() {
+        let config = HmcConfig {
+            n_samples: 10_000_000,
+            max_duration: Some(Duration::from_millis(20)),
+            ..HmcConfig::default()
+        };
+        let result = run_hmc(&config).unwrap();
+
+        assert!(!result.completed);
+        assert!(result.samples.len() < config.n_samples);
+        assert!(result.elapsed_secs < 1.0);
+
+        // 十分な予算があれば n_samples が上限として働く
+        let bounded = run_hmc(&HmcConfig {
+            n_samples: 100,
+            max_duration: Some(Duration::from_secs(60)),
+            ..HmcConfig::default()
+        })
+        .unwrap();
+        assert!(bounded.completed);
+        assert_eq!(bounded.samples.len(), 100);
+    }
+
+    #[test]
+    fn stops_once_target_ess_is_reached() {
+        let config = HmcConfig {
+            n_samples: 200_000,
+            n_warmup: 100,
+            target: DistType::Banana(Banana::default()),
+            target_ess: Some(400.0),
+            seed: Some(6),
+            ..HmcConfig::default()
+        };
+        let result = run_hmc(&config).unwrap();
+        let ess = result.achieved_ess.clone().unwrap();
+
+        assert_eq!(result.ess_target_met, Some(true));
+        assert!(result.completed);
+        assert!(ess.x >= 400.0 && ess.y >= 400.0);
+        assert!(result.samples.len() < config.n_samples);
+        assert!(result.samples.len() >= config.ess_check_every);
+
+        // 上限に達しても目標に届かなければ met = false
+        let capped = run_hmc(&HmcConfig {
+            n_samples: 500,
+            target_ess: Some(1.0e6),
+            ..config
+        })
+        .unwrap();
+        assert_eq!(capped.samples.len(), 500);
+        assert_eq!(capped.ess_target_met, Some(false));
+        assert!(capped.achieved_ess.is_some());
+    }
+
+    #[test]
+    fn resumed_runs_continue_the_chain() {
+        let config = HmcConfig {
+            n_samples: 1000,
+            n_warmup: 200,
+            ..HmcConfig::default()
+        };
+        let single = run_hmc_chain_with_rng(
+            &HmcConfig {
+                n_samples: 2000,
+                ..config.clone()
+            },
+            &mut StdRng::seed_from_u64(12),
+        )
+        .unwrap();
+
+        // RNGの状態も引き継いで2回に分けて実行する
+        let mut rng = StdRng::seed_from_u64(12);
+        let first = run_hmc_chain_with_rng(&config, &mut rng).unwrap();
+        let resumed = config.resumed_from(&first);
+        assert_eq!(resumed.n_warmup, 0);
+        let second = run_hmc_chain_with_rng(&resumed, &mut rng).unwrap();
+
+        assert_eq!(second.last_position(), single.last_position());
+        assert_eq!(&second.last_position(), single.samples.last().unwrap());
+        assert_eq!(first.samples[..], single.samples[..1000]);
+        assert_eq!(second.samples[..], single.samples[1000..]);
+    }
+
+    #[test]
+    fn step_size_adapts_to_target_acceptance_during_warmup() {
+        for (algorithm, initial, target_accept) in [
+            (Algorithm::Hmc, 2.0, 0.8),
+            (Algorithm::Hmc, 1e-3, 0.8),
+            (Algorithm::Hmc, 0.1, 0.65),
+            (Algorithm::Nuts { max_depth: 10 }, 1.5, 0.8),
+        ] {
+            let config = HmcConfig {
+                n_samples: 4000,
+                n_warmup: 1000,
+                step_size: initial,
+                num_steps: 10,
+                algorithm: algorithm.clone(),
+                adapt_step_size: true,
+                target_accept,
+                save_accept_prob: true,
+                target: DistType::Normal,
+                seed: Some(57),
+                ..HmcConfig::default()
+            };
+            let result = run_hmc(&config).unwrap();
+            let adapted = result.adapted_step_size.unwrap();
+            let mean_accept =
+                result.accept_prob.iter().sum::<f64>() / result.accept_prob.len() as f64;
+            assert!(
+                (mean_accept - target_accept).abs() < 0.05,
+                "{:?} from {}: accept {} with step {}",
+                algorithm,
+                initial,
+                mean_accept,
+                adapted
+            );
+
+            // 適応はウォームアップの間だけで、サンプリング中のステップ幅は変わらない
+            let mut chain = Chain::new(config.clone()).unwrap();
+            let steps: Vec<f64> = (0..1100)
+                .map(|_| {
+                    chain.step();
+                    chain.step_size()
+                })
+                .collect();
+            assert_ne!(steps[0], steps[1]);
+            assert!(steps[999..].iter().all(|&step| step == adapted));
+
+            // 適応後の値を再開時に引き継ぐ
+            let resumed = config.resumed_from(&result);
+            assert_eq!(resumed.step_size, adapted);
+            assert_eq!(resumed.n_warmup, 0);
+            assert!(run_hmc(&resumed).unwrap().adapted_step_size == Some(adapted));
+        }
+
+        // 適応しなければ報告しない
+        let fixed = run_hmc(&HmcConfig {
+            n_warmup: 100,
+            seed: Some(1),
+            ..HmcConfig::default()
+        })
+        .unwrap();
+        assert!(fixed.adapted_step_size.is_none());
+
+        for bad in [0.0, 1.0, f64::NAN] {
+            let config = HmcConfig {
+                target_accept: bad,
+                ..HmcConfig::default()
+            };
+            assert!(matches!(
+                config.validate(),
+                Err(HmcError::InvalidTargetAccept(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn mean_accept_prob_reports_achieved_target() {
+        let run = |target_accept| {
+            run_hmc(&HmcConfig {
+                n_samples: 2000,
+                n_warmup: 1000,
+                algorithm: Algorithm::Nuts { max_depth: 10 },
+                adapt_step_size: true,
+                target_accept,
+                save_accept_prob: true,
+                thin: 2,
+                seed: Some(77),
+                ..HmcConfig::default()
+            })
+            .unwrap()
+        };
+        let (low, high) = (run(0.6), run(0.95));
+        assert!(
+            (low.mean_accept_prob - 0.6).abs() < 0.05,
+            "{}",
+            low.mean_accept_prob
+        );
+        assert!(
+            (high.mean_accept_prob - 0.95).abs() < 0.03,
+            "{}",
+            high.mean_accept_prob
+        );
+        assert!(low.mean_accept_prob < 0.7 && high.mean_accept_prob > 0.9);
+        assert!(low.adapted_step_size.unwrap() > high.adapted_step_size.unwrap());
+        // 間引いて保存した分ではなく、サンプリング期間の全遷移の平均
+        assert_eq!(low.accept_prob.len(), 2000);
+        let saved = low.accept_prob.iter().sum::<f64>() / 2000.0;
+        assert!((saved - low.mean_accept_prob).abs() < 0.05);
+
+        // アンサンブルでは全ウォーカーの更新についての平均
+        let ensemble = run_hmc(&HmcConfig {
+            n_samples: 50,
+            n_warmup: 0,
+            algorithm: Algorithm::Ensemble {
+                n_walkers: 8,
+                a: 2.0,
+            },
+            save_accept_prob: true,
+            seed: Some(77),
+            ..HmcConfig::default()
+        })
+        .unwrap();
+        let mean = ensemble.accept_prob.iter().sum::<f64>() / 400.0;
+        assert!((ensemble.mean_accept_prob - mean).abs() < 1e-12);
+    }
+
+    #[test]
+    fn diagonal_mass_matrix_adapts_to_coordinate_scales() {
+        // 分散 (1, 100) の軸に沿った正規分布。単位質量ではステップ幅が x のスケールで決まり、
+        // y 方向には短い軌道でしか動けない
+        let target = MvNormal2::new(Point::default(), [[1.0, 0.0], [0.0, 100.0]]).unwrap();
+        let config = |adapt_mass_matrix, algorithm| HmcConfig {
+            n_samples: 5000,
+            n_warmup: 1000,
+            num_steps: 2,
+            algorithm,
+            adapt_step_size: true,
+            adapt_mass_matrix,
+            target: DistType::Gaussian(target.clone()),
+            seed: Some(58),
+            ..HmcConfig::default()
+        };
+        let ess_y = |result: &HmcResult| {
+            let ys: Vec<f64> = result.samples.iter().map(|p| p.y).collect();
+            diagnostics::ess(&ys)
+        };
+        let unit = run_hmc(&config(false, Algorithm::Hmc)).unwrap();
+        let adapted = run_hmc(&config(true, Algorithm::Hmc)).unwrap();
+        assert_eq!(unit.metric, Metric::UnitE);
+        let Metric::Diagonal(inv_mass) = &adapted.metric else {
+            panic!("{:?}", adapted.metric);
+        };
+        assert!((0.5..2.0).contains(&inv_mass[0]), "{:?}", inv_mass);
+        assert!((50.0..200.0).contains(&inv_mass[1]), "{:?}", inv_mass);
+        assert!(
+            ess_y(&adapted) > 10.0 * ess_y(&unit),
+            "ESS y: {} vs {}",
+            ess_y(&adapted),
+            ess_y(&unit)
+        );
+        let cov = validate::sample_cov(&adapted.samples);
+        assert!(
+            (cov[0][0] - 1.0).abs() < 0.15 && (cov[1][1] - 100.0).abs() < 15.0,
+            "{:?}",
+            cov
+        );
+
+        // NUTSでも短い木で済むようになる
+        let nuts = Algorithm::Nuts { max_depth: 10 };
+        let unit = run_hmc(&config(false, nuts.clone())).unwrap();
+        let adapted = run_hmc(&config(true, nuts)).unwrap();
+        assert!(adapted.n_leapfrog * 2 < unit.n_leapfrog);
+        let cov = validate::sample_cov(&adapted.samples);
+        assert!((cov[1][1] - 100.0).abs() < 15.0, "{:?}", cov);
+
+        // 推定した質量行列を再開時に引き継ぐ
+        let resumed = config(true, Algorithm::Hmc).resumed_from(&adapted);
+        assert_eq!(resumed.metric, adapted.metric);
+
+        let bad = HmcConfig {
+            metric: Metric::Diagonal(vec![1.0, 0.0]),
+            ..HmcConfig::default()
+        };
+        assert_eq!(
+            bad.validate(),
+            Err(HmcError::InvalidInvMass(vec![1.0, 0.0]))
+        );
+        let bad = HmcConfig {
+            mass_regularization: -1.0,
+            ..HmcConfig::default()
+        };
+        assert_eq!(
+            bad.validate(),
+            Err(HmcError::InvalidMassRegularization(-1.0))
+        );
+    }
+
+    #[test]
+    fn dense_metric_whitens_correlated_gaussian() {
+        let (cov, rho) = ([[1.0, 0.95], [0.95, 1.0]], 0.95);
+        let det = 1.0 - rho * rho;
+        let precision = vec![vec![1.0 / det, -rho / det], vec![-rho / det, 1.0 / det]];
+        let correlated = DistType::Gaussian(MvNormal2::new(Point::default(), cov).unwrap());
+        let config = |target, metric| HmcConfig {
+            n_samples: 5000,
+            n_warmup: 1000,
+            num_steps: 3,
+            adapt_step_size: true,
+            metric,
+            target,
+            seed: Some(59),
+            ..HmcConfig::default()
+        };
+        let min_ess = |result: &HmcResult| {
+            let ess = sample_ess(&result.samples);
+            ess.x.min(ess.y)
+        };
+
+        // 共分散の逆行列を質量行列にすると、等方的な正規分布を単位質量で引くのと同じになる
+        let isotropic = run_hmc(&config(DistType::Normal, Metric::UnitE)).unwrap();
+        let dense = run_hmc(&config(
+            correlated.clone(),
+            Metric::Dense(precision.clone()),
+        ))
+        .unwrap();
+        let unit = run_hmc(&config(correlated.clone(), Metric::UnitE)).unwrap();
+        assert!(
+            (dense.acceptance_rate - isotropic.acceptance_rate).abs() < 0.05,
+            "{} vs {}",
+            dense.acceptance_rate,
+            isotropic.acceptance_rate
+        );
+        assert!(min_ess(&dense) > 0.5 * min_ess(&isotropic));
+        assert!(min_ess(&dense) > 5.0 * min_ess(&unit));
+        let sample_cov = validate::sample_cov(&dense.samples);
+        assert!((sample_cov[0][1] - rho).abs() < 0.05, "{:?}", sample_cov);
+        assert_eq!(dense.metric, Metric::Dense(precision));
+
+        // ウォームアップで推定した場合も精度行列に近く、単位質量より桁違いに良い
+        let adapted = run_hmc(&config(correlated.clone(), Metric::AdaptDense)).unwrap();
+        let Metric::Dense(mass) = &adapted.metric else {
+            panic!("{:?}", adapted.metric);
+        };
+        assert!((mass[0][1] / mass[0][0] + rho).abs() < 0.05, "{:?}", mass);
+        assert!(adapted.acceptance_rate > 0.7);
+        assert!(min_ess(&adapted) > 5.0 * min_ess(&unit));
+        let resumed = config(correlated, Metric::AdaptDense).resumed_from(&adapted);
+        assert_eq!(resumed.metric, Metric::Dense(mass.clone()));
+
+        let singular = vec![vec![1.0, 1.0], vec![1.0, 1.0]];
+        let bad = HmcConfig {
+            metric: Metric::Dense(singular.clone()),
+            ..HmcConfig::default()
+        };
+        assert_eq!(bad.validate(), Err(HmcError::InvalidMassMatrix(singular)));
+        assert!(Chain::new(bad).is_err());
+
+        let parsed: HmcConfig =
+            serde_json::from_str(r#"{"metric": {"dense": [[2, 0.5], [0.5, 1]]}}"#).unwrap();
+        assert_eq!(
+            parsed.metric,
+            Metric::Dense(vec![vec![2.0, 0.5], vec![0.5, 1.0]])
+        );
+        let parsed: HmcConfig = serde_json::from_str(r#"{"metric": "adapt_dense"}"#).unwrap();
+        assert_eq!(parsed.metric, Metric::AdaptDense);
+    }
+
+    #[test]
+    fn fixed_diagonal_metric_matches_adapted_one() {
+        // 真の分散を M⁻¹ に渡せば、ウォームアップで推定した場合と同程度に y 方向へ動ける
+        let target = MvNormal2::new(Point::default(), [[1.0, 0.0], [0.0, 100.0]]).unwrap();
+        let config = |metric, adapt_mass_matrix| HmcConfig {
+            n_samples: 5000,
+            n_warmup: 1000,
+            num_steps: 2,
+            adapt_step_size: true,
+            metric,
+            adapt_mass_matrix,
+            target: DistType::Gaussian(target.clone()),
+            seed: Some(60),
+            ..HmcConfig::default()
+        };
+        let ess_y = |result: &HmcResult| {
+            let ys: Vec<f64> = result.samples.iter().map(|p| p.y).collect();
+            diagnostics::ess(&ys)
+        };
+        let true_var = Metric::Diagonal(vec![1.0, 100.0]);
+        let fixed = run_hmc(&config(true_var.clone(), false)).unwrap();
+        let adapted = run_hmc(&config(Metric::UnitE, true)).unwrap();
+        let unit = run_hmc(&config(Metric::UnitE, false)).unwrap();
+        assert!(
+            ess_y(&fixed) > 0.5 * ess_y(&adapted),
+            "ESS y: {} vs {}",
+            ess_y(&fixed),
+            ess_y(&adapted)
+        );
+        assert!(ess_y(&fixed) > 10.0 * ess_y(&unit));
+        let cov = validate::sample_cov(&fixed.samples);
+        assert!((cov[1][1] - 100.0).abs() < 15.0, "{:?}", cov);
+
+        // 使った質量行列が結果に載り、JSONを経ても変わらない
+        assert_eq!(fixed.metric, true_var);
+        let json = serde_json::to_string(&fixed).unwrap();
+        let parsed: HmcResult = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed.metric, true_var);
+        assert_eq!(
+            config(Metric::UnitE, false).resumed_from(&parsed).metric,
+            true_var
+        );
+
+        // 推定の初期値として使う場合も推定値に替わる
+        let from_fixed = run_hmc(&config(true_var, true)).unwrap();
+        assert!(matches!(from_fixed.metric, Metric::Diagonal(ref v) if v != &[1.0, 100.0]));
+
+        // 推定した値をJSONの設定で次の実行に渡しても1 ULPも変わらない（Python の `run` はJSONを経る）
+        let mut rng = ChainRng::seed_from_u64(60);
+        let estimates =
+            (0..500).map(|_| vec![rng.gen_range(0.0..200.0), rng.gen_range(0.0..200.0)]);
+        for inv_mass in estimates.chain([match adapted.metric {
+            Metric::Diagonal(ref v) => v.clone(),
+            ref other => panic!("{:?}", other),
+        }]) {
+            let json = serde_json::to_string(&Metric::Diagonal(inv_mass.clone())).unwrap();
+            let parsed: HmcConfig =
+                serde_json::from_str(&format!(r#"{{"metric": {}}}"#, json)).unwrap();
+            assert_eq!(parsed.metric, Metric::Diagonal(inv_mass));
+        }
+        let again = run_hmc(&HmcConfig {
+            n_samples: 10,
+            metric: adapted.metric.clone(),
+            ..HmcConfig::default()
+        })
+        .unwrap();
+        assert_eq!(again.metric, adapted.metric);
+
+        let parsed: HmcConfig = serde_json::from_str(r#"{"metric": [1, 100]}"#).unwrap();
+        assert_eq!(parsed.metric, Metric::Diagonal(vec![1.0, 100.0]));
+        let bad: HmcConfig = serde_json::from_str(r#"{"metric": [1, 100, 1]}"#).unwrap();
+        assert_eq!(
+            bad.validate(),
+            Err(HmcError::MetricDimension {
+                expected: 2,
+                got: 3
+            })
+        );
+        assert!(serde_json::from_str::<HmcConfig>(r#"{"metric": "diag_e"}"#).is_err());
+    }
+
+    #[test]
+    fn find_mode_init_starts_near_banana_mode() {
+        let config = HmcConfig {
+            n_samples: 10,
+            initial_pos: Point { x: -1.5, y: 2.0 },
+            init: InitStrategy::FindMode {
+                max_iters: 20_000,
+                learning_rate: 0.005,
+            },
+            target: DistType::Banana(Banana::default()),
+            seed: Some(4),
+            ..HmcConfig::default()
+        };
+        let result = run_hmc(&config).unwrap();
+        let mode = result.init_mode.clone().unwrap();
+        assert!((mode.x - 1.0).abs() < 0.01 && (mode.y - 1.0).abs() < 0.02);
+        assert!(result.init_potential.unwrap() < 1e-3);
+
+        // 再開時はモード探索をやり直さない
+        let resumed = run_hmc(&config.resumed_from(&result)).unwrap();
+        assert!(resumed.init_mode.is_none());
+
+        let bad = HmcConfig {
+            init: InitStrategy::FindMode {
+                max_iters: 10,
+                learning_rate: 0.0,
+            },
+            ..HmcConfig::default()
+        };
+        assert_eq!(bad.validate().unwrap_err(), HmcError::InvalidLearningRate(0.0));
+    }
+
+    #[test]
+    fn mode_estimate_follows_banana_params() {
+        for banana in [
+            Banana { a: 1.0, b: 100.0 },
+            Banana { a: -0.5, b: 100.0 },
+            Banana { a: 1.5, b: 5.0 },
+        ] {
+            let config = HmcConfig {
+                n_samples: 2000,
+                n_warmup: 200,
+                step_size: 0.02,
+                num_steps: 20,
+                init: InitStrategy::FindMode {
+                    max_iters: 200_000,
+                    learning_rate: 4e-4,
+                },
+                target: DistType::Banana(banana),
+                seed: Some(9),
+                save_energy: true,
+                ..HmcConfig::default()
+            };
+            let (a, a2) = (banana.a, banana.a * banana.a);
+            let result = run_hmc(&config).unwrap();
+            let mode = result.init_mode.clone().unwrap();
+            assert!(
+                (mode.x - a).abs() < 1e-3 && (mode.y - a2).abs() < 1e-3,
+                "{:?}: {:?}",
+                banana,
+                mode
+            );
+
+            // サンプル中で最もポテンシャルの低い点もモードの近くにある
+            let (best, _) = result
+                .samples
+                .iter()
+                .zip(&result.potential_energy)
+                .min_by(|x, y| x.1.total_cmp(y.1))
+                .unwrap();
+            assert!(
+                (best.x - a).abs() < 0.1 && (best.y - a2).abs() < 0.2,
+                "{:?}: {:?}",
+                banana,
+                best
+            );
+        }
+
+        let bad = HmcConfig {
+            target: DistType::Banana(Banana { a: 1.0, b: 0.0 }),
+            ..HmcConfig::default()
+        };
+        assert_eq!(
+            bad.validate().unwrap_err(),
+            HmcError::InvalidTargetParam {
+                param: "b".to_string(),
+                value: 0.0
+            }
+        );
+    }
+
+    #[test]
+    fn accept_flags_match_acceptance_rate() {
+        let config = HmcConfig {
+            n_samples: 400,
+            n_warmup: 50,
+            save_accept_flags: true,
+            step_size: 0.15,
+            num_steps: 10,
+            target: DistType::Banana(Banana::default()),
+            seed: Some(8),
+            ..HmcConfig::default()
+        };
+        let result = run_hmc(&config).unwrap();
+        assert_eq!(result.accepted.len(), result.samples.len());
+        let n_accepted = result.accepted.iter().filter(|&&a| a).count();
+        assert!(n_accepted > 0 && n_accepted < 400, "{}", n_accepted);
+        assert_eq!(ratio(n_accepted, 400), result.acceptance_rate);
+        // 棄却された遷移では位置が変わらない
+        for (i, a) in result.accepted.iter().enumerate().skip(1) {
+            if !a {
+                assert_eq!(result.samples[i], result.samples[i - 1]);
+            }
+        }
+
+        let off = run_hmc(&HmcConfig {
+            save_accept_flags: false,
+            ..config
+        })
+        .unwrap();
+        assert!(off.accepted.is_empty());
+        assert_eq!(off.accepted.capacity(), 0);
+    }
+
+    #[test]
+    fn tiny_steps_are_almost_always_accepted() {
+        let config = HmcConfig {
+            n_samples: 500,
+            save_accept_prob: true,
+            step_size: 1e-3,
+            num_steps: 10,
+            target: DistType::Banana(Banana::default()),
+            seed: Some(6),
+            ..HmcConfig::default()
+        };
+        let result = run_hmc(&config).unwrap();
+        assert_eq!(result.accept_prob.len(), 500);
+        assert!(result.accept_prob.iter().all(|p| (0.0..=1.0).contains(p)));
+        let mean = result.accept_prob.iter().sum::<f64>() / 500.0;
+        assert!(mean > 0.99, "mean accept prob {}", mean);
+    }
+
+    #[test]
+    fn huge_step_size_causes_divergences() {
+        let config = HmcConfig {
+            n_samples: 200,
+            save_divergences: true,
+            step_size: 2.0,
+            target: DistType::Banana(Banana::default()),
+            seed: Some(3),
+            ..HmcConfig::default()
+        };
+        let result = run_hmc(&config).unwrap();
+        assert!(result.n_divergent > 0);
+        assert_eq!(result.divergent_positions.len(), result.n_divergent);
+        assert!(serde_json::to_value(&result).unwrap()["n_divergent"].as_u64().unwrap() > 0);
+
+        let calm = run_hmc(&HmcConfig {
+            step_size: 0.01,
+            ..config
+        })
+        .unwrap();
+        assert_eq!(calm.n_divergent, 0);
+
+        let bad = HmcConfig {
+            divergence_threshold: f64::NAN,
+            ..HmcConfig::default()
+        };
+        assert!(matches!(
+            bad.validate(),
+            Err(HmcError::InvalidDivergenceThreshold(_))
+        ));
+    }
+
+    #[test]
+    fn divergence_policies_on_banana() {
+        let config = |on_divergence| HmcConfig {
+            n_samples: 500,
+            step_size: 1.0,
+            on_divergence,
+            target: DistType::Banana(Banana::default()),
+            seed: Some(61),
+            ..HmcConfig::default()
+        };
+
+        // 棄却して続ける（従来通り）
+        let kept = run_hmc(&config(DivergencePolicy::Continue)).unwrap();
+        assert!(kept.n_divergent > 10, "{}", kept.n_divergent);
+        assert_eq!(kept.n_step_size_shrinks, 0);
+        assert!(kept.completed);
+
+        // ステップ幅を縮めてやり直すと、発散したまま残る遷移が減る
+        let shrink = DivergencePolicy::ShrinkStepSize {
+            factor: 0.1,
+            max_retries: 3,
+        };
+        let shrunk = run_hmc(&config(shrink)).unwrap();
+        assert_eq!(shrunk.samples.len(), 500);
+        assert!(shrunk.n_step_size_shrinks >= kept.n_divergent / 2);
+        assert!(
+            shrunk.n_divergent * 5 < kept.n_divergent,
+            "{} vs {}",
+            shrunk.n_divergent,
+            kept.n_divergent
+        );
+        assert!(shrunk.acceptance_rate > kept.acceptance_rate);
+        assert!(shrunk.n_leapfrog > kept.n_leapfrog);
+        let json = serde_json::to_value(&shrunk).unwrap();
+        assert_eq!(json["n_step_size_shrinks"], shrunk.n_step_size_shrinks);
+
+        // 最初の発散で打ち切り、それまでの結果をエラーに載せる
+        let Err(HmcError::Diverged { iteration, partial }) =
+            run_hmc(&config(DivergencePolicy::Abort))
+        else {
+            panic!("expected a divergence");
+        };
+        assert!(!partial.completed);
+        assert_eq!(partial.n_divergent, 1);
+        assert_eq!(partial.samples.len(), iteration + 1);
+        assert_eq!(partial.samples[..], kept.samples[..=iteration]);
+        let err = run_hmc(&config(DivergencePolicy::Abort)).unwrap_err();
+        assert!(err.to_string().contains("diverged"), "{}", err);
+
+        // 発散しなければ打ち切らない
+        let calm = HmcConfig {
+            step_size: 0.05,
+            ..config(DivergencePolicy::Abort)
+        };
+        assert!(run_hmc(&calm).unwrap().completed);
+
+        let bad = config(DivergencePolicy::ShrinkStepSize {
+            factor: 1.0,
+            max_retries: 3,
+        });
+        assert_eq!(bad.validate(), Err(HmcError::InvalidShrinkFactor(1.0)));
+        let parsed: HmcConfig =
+            serde_json::from_str(r#"{"on_divergence": {"shrink_step_size": {"factor": 0.25}}}"#)
+                .unwrap();
+        assert_eq!(
+            parsed.on_divergence,
+            DivergencePolicy::ShrinkStepSize {
+                factor: 0.25,
+                max_retries: 3
+            }
+        );
+        let parsed: HmcConfig = serde_json::from_str(r#"{"on_divergence": "abort"}"#).unwrap();
+        assert_eq!(parsed.on_divergence, DivergencePolicy::Abort);
+    }
+
+    #[test]
+    fn saved_energies_match_recomputed_values() {
+        let config = HmcConfig {
+            n_samples: 300,
+            thin: 2,
+            save_energy: true,
+            step_size: 0.15,
+            num_steps: 10,
+            target: DistType::Banana(Banana::default()),
+            seed: Some(12),
+            ..HmcConfig::default()
+        };
+        let result = run_hmc(&config).unwrap();
+        assert_eq!(result.potential_energy.len(), 300);
+        assert_eq!(result.energy.len(), 300);
+        for ((p, &u), &h) in result
+            .samples
+            .iter()
+            .zip(&result.potential_energy)
+            .zip(&result.energy)
+        {
+            assert_eq!(u, config.target.potential(p));
+            // 運動エネルギーは非負
+            assert!(h >= u);
+        }
+
+        let json = serde_json::to_value(&result).unwrap();
+        assert_eq!(json["energy"].as_array().unwrap().len(), 300);
+        let off = run_hmc(&HmcConfig {
+            save_energy: false,
+            ..config
+        })
+        .unwrap();
+        assert!(off.potential_energy.is_empty() && off.energy.is_empty());
+    }
+
+    /// リープフロッグ・メトロポリス判定の回帰テスト
+    ///
+    /// ステップ幅が大きく採択率が低い設定でも、正しいHMCなら標準正規分布の1次・2次モーメントが
+    /// ESSから決まる誤差の範囲で再現される。
+    #[test]
+    fn standard_normal_moments_are_reproduced() {
+        use crate::validate::{sample_cov, z_score_of_mean};
+
+        let origin = Point { x: 0.0, y: 0.0 };
+        let identity = [[1.0, 0.0], [0.0, 1.0]];
+        for (step_size, num_steps, seed) in [(0.1, 20, 1), (0.6, 5, 2), (1.8, 3, 3)] {
+            let config = HmcConfig {
+                n_samples: 20_000,
+                n_warmup: 200,
+                step_size,
+                num_steps,
+                target: DistType::Normal,
+                seed: Some(seed),
+                ..HmcConfig::default()
+            };
+            let result = run_hmc(&config).unwrap();
+            let z = z_score_of_mean(&result.samples, &origin, &identity);
+            assert!(
+                z.x.abs() < 4.0 && z.y.abs() < 4.0,
+                "step {}: z {:?}",
+                step_size,
+                z
+            );
+
+            // x^2 の平均は 1、分散は 2
+            let squares: Vec<Point> = result
+                .samples
+                .iter()
+                .map(|p| Point {
+                    x: p.x * p.x,
+                    y: p.y * p.y,
+                })
+                .collect();
+            let z = z_score_of_mean(
+                &squares,
+                &Point { x: 1.0, y: 1.0 },
+                &[[2.0, 0.0], [0.0, 2.0]],
+            );
+            assert!(
+                z.x.abs() < 4.0 && z.y.abs() < 4.0,
+                "step {}: z {:?}",
+                step_size,
+                z
+            );
+
+            let cov = sample_cov(&result.samples);
+            assert!(cov[0][1].abs() < 0.1, "step {}: cov {:?}", step_size, cov);
+        }
+    }
+
+    #[test]
+    fn misspelled_distribution_is_an_error() {
+        assert!(matches!("banana".parse(), Ok(DistType::Banana(_))));
+        assert_eq!(
+            "bananna".parse::<DistType>().unwrap_err(),
+            HmcError::UnknownDistribution("bananna".to_string())
+        );
+        assert!("gausian".parse::<DistType>().is_err());
+        let message = "gausian".parse::<DistType>().unwrap_err().to_string();
+        assert!(message.contains("bimodal") && message.contains("banana"), "{}", message);
+    }
+
+    #[test]
+    fn distribution_names_are_case_insensitive() {
+        for name in ["Banana", "BANANA", "banana"] {
+            assert!(matches!(name.parse(), Ok(DistType::Banana(_))), "{}", name);
+        }
+        assert!(matches!("BiModal".parse(), Ok(DistType::Bimodal(_))));
+    }
+
+    #[test]
+    fn distribution_names_round_trip() {
+        for &name in DistType::variants() {
+            let dist: DistType = name.parse().unwrap();
+            assert_eq!(dist.to_string(), name);
+            assert_eq!(dist.as_str(), name);
+        }
+    }
+
+    #[test]
+    fn run_hmc_rejects_invalid_config() {
+        let config = HmcConfig {
+            initial_pos: Point {
+                x: f64::NAN,
+                y: 0.0,
+            },
+            ..HmcConfig::default()
+        };
+        assert!(matches!(
+            run_hmc(&config),
+            Err(HmcError::NonFiniteInitialPoint { .. })
+        ));
+
+        let check = |config: HmcConfig| config.validate().unwrap_err();
+        assert_eq!(
+            check(HmcConfig {
+                initial_pos: Point {
+                    x: 0.0,
+                    y: f64::INFINITY
+                },
+                ..HmcConfig::default()
+            }),
+            HmcError::NonFiniteInitialPoint {
+                x: 0.0,
+                y: f64::INFINITY
+            }
+        );
+        assert_eq!(
+            check(HmcConfig {
+                num_steps: 0,
+                ..HmcConfig::default()
+            }),
+            HmcError::ZeroLeapfrogSteps
+        );
+        assert_eq!(
+            check(HmcConfig {
+                step_size: -0.5,
+                ..HmcConfig::default()
+            }),
+            HmcError::InvalidStepSize(-0.5)
+        );
+        assert!(matches!(
+            check(HmcConfig { step_size: f64::NAN, ..HmcConfig::default() }),
+            HmcError::InvalidStepSize(v) if v.is_nan()
+        ));
+        // バナナ分布で遠く離れた点ではポテンシャルがオーバーフローして無限大になる
+        assert!(matches!(
+            check(HmcConfig {
+                initial_pos: Point { x: 1e200, y: 0.0 },
+                target: DistType::Banana(Banana::default()),
+                ..HmcConfig::default()
+            }),
+            HmcError::NonFiniteInitialPotential { x, y, .. } if x == 1e200 && y == 0.0
+        ));
+        // 再開位置も同じように検証する
+        assert!(matches!(
+            check(HmcConfig {
+                resume_from: Some(Point {
+                    x: f64::NAN,
+                    y: 0.0
+                }),
+                ..HmcConfig::default()
+            }),
+            HmcError::NonFiniteInitialPoint { .. }
+        ));
+    }
+
+    #[test]
+    fn config_rejects_unknown_fields() {
+        assert!(serde_json::from_str::<HmcConfig>(r#"{"n_sample": 50}"#).is_err());
+    }
+
+    #[test]
+    fn bimodal_params_load_from_json() {
+        let config: HmcConfig = serde_json::from_str(
+            r#"{"target": {"bimodal": {"centers": [{"x": 6, "y": 6}, {"x": -6, "y": -6}], "weights": [1, 2]}}}"#,
+        )
+        .unwrap();
+        let expected = Bimodal {
+            centers: [Point { x: 6.0, y: 6.0 }, Point { x: -6.0, y: -6.0 }],
+            weights: [1.0, 2.0],
+            ..Bimodal::default()
+        };
+        assert_eq!(config.target, DistType::Bimodal(expected));
+
+        // 名前だけなら既定のパラメータ、シリアライズ結果は読み戻せる
+        let config: HmcConfig = serde_json::from_str(r#"{"target": "Bimodal"}"#).unwrap();
+        assert_eq!(config.target, DistType::default());
+        let json = serde_json::to_string(&config).unwrap();
+        let restored: HmcConfig = serde_json::from_str(&json).unwrap();
+        assert_eq!(restored.target, config.target);
+
+        assert!(serde_json::from_str::<HmcConfig>(r#"{"target": "laplace"}"#).is_err());
+        assert!(
+            serde_json::from_str::<HmcConfig>(r#"{"target": {"bimodal": {"scale": [1, 1]}}}"#)
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn every_dist_type_round_trips_through_json() {
+        let targets = [
+            DistType::Bimodal(Bimodal {
+                centers: [Point { x: 1.0, y: -2.0 }, Point { x: -3.0, y: 0.5 }],
+                weights: [0.25, 0.75],
+                ..Bimodal::default()
+            }),
+            DistType::Banana(Banana { a: 0.5, b: 30.0 }),
+            DistType::Funnel(Funnel { scale: 2.0 }),
+            DistType::Ring(Ring {
+                radius: 2.5,
+                width: 0.3,
+            }),
+            DistType::Gaussian(
+                MvNormal2::new(Point { x: 1.0, y: 2.0 }, [[2.0, 0.3], [0.3, 0.5]]).unwrap(),
+            ),
+            DistType::Mixture(
+                GaussianMixture::new(vec![
+                    MixtureComponent {
+                        weight: 1.0,
+                        mean: Point { x: -1.0, y: 0.0 },
+                        cov: [[1.0, 0.0], [0.0, 1.0]],
+                    },
+                    MixtureComponent {
+                        weight: 3.0,
+                        mean: Point { x: 2.0, y: 1.0 },
+                        cov: [[0.5, -0.1], [-0.1, 0.4]],
+                    },
+                ])
+                .unwrap(),
+            ),
+            DistType::StudentT(StudentT {
+                dof: 3.5,
+                mean: Point { x: 0.5, y: -0.5 },
+                scale: 2.0,
+            }),
+            DistType::Cauchy(Cauchy2 {
+                location: Point { x: -1.0, y: 3.0 },
+                scale: 0.5,
+            }),
+            DistType::VonMises(VonMises2 {
+                mean: Point { x: 1.0, y: 5.0 },
+                kappa_x: 0.5,
+                kappa_y: 8.0,
+            }),
+            DistType::Normal,
+        ];
+        let names: Vec<&str> = targets.iter().map(DistType::as_str).collect();
+        assert_eq!(names, DistType::variants());
+        for target in targets {
+            let json = serde_json::to_value(&target).unwrap();
+            let expected_json = match &target {
+                DistType::Normal => serde_json::json!("normal"),
+                _ => serde_json::json!({ target.as_str(): json[target.as_str()] }),
+            };
+            assert_eq!(json, expected_json);
+            assert_eq!(serde_json::from_value::<DistType>(json).unwrap(), target);
+            let name = target.to_string();
+            assert_eq!(
+                std::mem::discriminant(&name.parse::<DistType>().unwrap()),
+                std::mem::discriminant(&target)
+            );
+        }
+    }
+
+    #[test]
+    fn saved_config_reproduces_the_run() {
+        let config = HmcConfig {
+            n_samples: 200,
+            n_warmup: 50,
+            thin: 2,
+            step_size: 0.15,
+            num_steps: 12,
+            initial_pos: Point { x: 0.5, y: 0.5 },
+            init: InitStrategy::FindMode {
+                max_iters: 100,
+                learning_rate: 0.01,
+            },
+            target: DistType::StudentT(StudentT {
+                dof: 4.0,
+                mean: Point { x: 1.0, y: 0.0 },
+                scale: 1.5,
+            }),
+            transform: Transforms {
+                x: Transform::Identity,
+                y: Transform::Logit {
+                    lower: -10.0,
+                    upper: 10.0,
+                },
+            },
+            bounds: Some(BoundingBox {
+                xmin: -20.0,
+                xmax: 20.0,
+                ymin: -5.0,
+                ymax: 5.0,
+            }),
+            numdiff: Some(numdiff::NumDiff::default()),
+            seed: Some(51),
+            max_duration: Some(Duration::from_secs(60)),
+            ..HmcConfig::default()
+        };
+        let json = serde_json::to_string_pretty(&config).unwrap();
+        let restored: HmcConfig = serde_json::from_str(&json).unwrap();
+        assert_eq!(restored, config);
+        assert_eq!(
+            run_hmc(&restored).unwrap().samples,
+            run_hmc(&config).unwrap().samples
+        );
+    }
+
+    #[test]
+    fn mode_occupancy_of_equal_weight_bimodal_is_balanced() {
+        let centers = [Point { x: 1.0, y: 1.0 }, Point { x: -1.0, y: -1.0 }];
+        let config = HmcConfig {
+            n_samples: 5000,
+            n_warmup: 200,
+            step_size: 0.3,
+            num_steps: 10,
+            target: DistType::Bimodal(Bimodal {
+                centers: centers.clone(),
+                ..Bimodal::default()
+            }),
+            mode_centers: centers.to_vec(),
+            seed: Some(54),
+            ..HmcConfig::default()
+        };
+        let result = run_hmc(&config).unwrap();
+        assert_eq!(result.mode_occupancy.len(), 2);
+        assert!((result.mode_occupancy.iter().sum::<f64>() - 1.0).abs() < 1e-12);
+        for fraction in &result.mode_occupancy {
+            assert!((fraction - 0.5).abs() < 0.05, "{:?}", result.mode_occupancy);
+        }
+        let switches = result.mode_switches.unwrap();
+        assert!(switches > 500, "{} switches", switches);
+
+        let json = serde_json::to_value(&result).unwrap();
+        assert_eq!(json["mode_switches"], switches);
+        let plain = run_hmc(&HmcConfig {
+            mode_centers: Vec::new(),
+            ..config
+        })
+        .unwrap();
+        assert!(plain.mode_occupancy.is_empty() && plain.mode_switches.is_none());
+        assert!(serde_json::to_value(&plain).unwrap().get("mode_switches").is_none());
+    }
+
+    #[test]
+    fn acf_per_coordinate() {
+        let config = HmcConfig {
+            n_samples: 500,
+            seed: Some(97),
+            ..HmcConfig::default()
+        };
+        let result = run_hmc(&config).unwrap();
+        let (x, y) = result.acf(30);
+        assert_eq!((x.len(), y.len()), (31, 31));
+        assert_eq!((x[0], y[0]), (1.0, 1.0));
+        let xs: Vec<f64> = result.samples.iter().map(|p| p.x).collect();
+        assert_eq!(x, diagnostics::autocorrelation(&xs, 30));
+
+        let flat = run_hmc(&HmcConfig {
+            flat_samples: true,
+            ..config
+        })
+        .unwrap();
+        assert_eq!(flat.acf(30), (x, y));
+    }
+
+    #[test]
+    fn geweke_flags_a_chain_still_leaving_its_start() {
+        let config = HmcConfig {
+            n_samples: 2000,
+            target: DistType::Normal,
+            compute_diagnostics: true,
+            seed: Some(99),
+            ..HmcConfig::default()
+        };
+        let mixed = run_hmc(&config).unwrap();
+        let z = mixed.geweke();
+        assert!(z.x.abs() < 3.0 && z.y.abs() < 3.0, "{:?}", z);
+        assert_eq!(mixed.diagnostics.unwrap().geweke, z);
+
+        // 遠くから短い軌道で始め、ウォームアップを捨てない
+        let far = run_hmc(&HmcConfig {
+            initial_pos: Point { x: 30.0, y: -30.0 },
+            step_size: 0.05,
+            num_steps: 2,
+            ..config
+        })
+        .unwrap();
+        let z = far.geweke();
+        assert!(z.x.abs() > 3.0 && z.y.abs() > 3.0, "{:?}", z);
+    }
+
+    #[test]
+    fn ebfmi_is_low_on_the_funnel() {
+        let config = HmcConfig {
+            n_samples: 5000,
+            save_energy: true,
+            compute_diagnostics: true,
+            seed: Some(100),
+            ..HmcConfig::default()
+        };
+        let normal = run_hmc(&HmcConfig {
+            target: DistType::Normal,
+            ..config.clone()
+        })
+        .unwrap();
+        let normal_ebfmi = normal.diagnostics.unwrap().ebfmi.unwrap();
+        assert!((normal_ebfmi - 1.0).abs() < 0.1, "{}", normal_ebfmi);
+        assert_eq!(normal_ebfmi, diagnostics::ebfmi(&normal.energy));
+
+        // 単位質量行列では首と口の間でエネルギーの幅が大きく変わり、運動量の引き直しでは追いつかない。
+        // y の広がりが大きいほどエネルギーの周辺分布が広がるので、scale = 10 の漏斗をNUTSで首の奥まで探索させる
+        let funnel = run_hmc(&HmcConfig {
+            n_samples: 10_000,
+            n_warmup: 1000,
+            adapt_step_size: true,
+            algorithm: Algorithm::Nuts { max_depth: 10 },
+            target: DistType::Funnel(Funnel { scale: 10.0 }),
+            ..config.clone()
+        })
+        .unwrap();
+        let funnel_ebfmi = funnel.diagnostics.unwrap().ebfmi.unwrap();
+        assert!(funnel_ebfmi < 0.3, "{}", funnel_ebfmi);
+
+        let unrecorded = run_hmc(&HmcConfig {
+            save_energy: false,
+            ..config
+        })
+        .unwrap();
+        assert_eq!(unrecorded.diagnostics.unwrap().ebfmi, None);
+    }
+
+    #[test]
+    fn compute_diagnostics_reports_per_coordinate_ess() {
+        let config = HmcConfig {
+            n_samples: 2000,
+            compute_diagnostics: true,
+            seed: Some(95),
+            ..HmcConfig::default()
+        };
+        let result = run_hmc(&config).unwrap();
+        let ess = result.diagnostics.as_ref().unwrap().ess.clone();
+        assert_eq!(ess, result.ess());
+        // 反周期的なチェーンでも n·log10(n) で頭打ちになる
+        let cap = 2000.0 * 2000f64.log10();
+        for value in [ess.x, ess.y] {
+            assert!(value > 100.0 && value <= cap, "{:?}", ess);
+        }
+        let json = serde_json::to_value(&result).unwrap();
+        assert_eq!(json["diagnostics"]["ess"]["x"], ess.x);
+        let (iact_x, _) = &result.diagnostics.as_ref().unwrap().iact;
+        assert_eq!(result.diagnostics.as_ref().unwrap().iact, result.iact());
+        assert_eq!(json["diagnostics"]["iact"][0]["tau"], iact_x.tau);
+        assert!(!iact_x.too_short, "{:?}", iact_x);
+
+        // 平らなサンプル列からも同じ値になる
+        let flat = run_hmc(&HmcConfig {
+            flat_samples: true,
+            ..config.clone()
+        })
+        .unwrap();
+        assert_eq!(flat.diagnostics, result.diagnostics);
+        assert_eq!(flat.ess(), ess);
+
+        let short = run_hmc(&HmcConfig {
+            n_samples: 3,
+            ..config.clone()
+        })
+        .unwrap();
+        let short_ess = short.diagnostics.unwrap().ess;
+        assert!(short_ess.x.is_nan() && short_ess.y.is_nan());
+
+        let plain = run_hmc(&HmcConfig {
+            compute_diagnostics: false,
+            ..config.clone()
+        })
+        .unwrap();
+        assert!(plain.diagnostics.is_none());
+        let json = serde_json::to_value(&plain).unwrap();
+        assert!(json.get("diagnostics").is_none());
+        assert_eq!(
+            run_hmc_streaming(&config, &mut Vec::new()).unwrap_err(),
+            HmcError::UnsupportedStreaming("compute_diagnostics")
+        );
+    }
+
+    #[test]
+    fn donut_is_an_alias_for_ring() {
+        assert_eq!("Donut".parse::<DistType>().unwrap(), DistType::Ring(Ring::default()));
+        let config: HmcConfig =
+            serde_json::from_str(r#"{"target": {"donut": {"radius": 2}}}"#).unwrap();
+        let ring = Ring {
+            radius: 2.0,
+            ..Ring::default()
+        };
+        assert_eq!(config.target, DistType::Ring(ring));
+        assert_eq!(config.target.to_string(), "ring");
+    }
+
+    #[test]
+    fn banana_params_load_from_json_and_name() {
+        let config: HmcConfig =
+            serde_json::from_str(r#"{"target": {"banana": {"b": 100}}}"#).unwrap();
+        assert_eq!(config.target, DistType::Banana(Banana { a: 1.0, b: 100.0 }));
+
+        let params = serde_json::json!({ "a": 2.0 });
+        assert_eq!(
+            DistType::with_params("Banana", Some(params)).unwrap(),
+            DistType::Banana(Banana { a: 2.0, b: 10.0 })
+        );
+        assert_eq!(
+            DistType::with_params("banana", None).unwrap(),
+            DistType::Banana(Banana::default())
+        );
+        assert!(matches!(
+            DistType::with_params("banana", Some(serde_json::json!({ "c": 1.0 }))),
+            Err(HmcError::Serialization(_))
+        ));
+        assert!(matches!(
+            DistType::with_params("laplace", Some(serde_json::json!({}))),
+            Err(HmcError::UnknownDistribution(_))
+        ));
+    }
+
+    #[test]
+    fn config_loads_from_partial_json() {
+        let config: HmcConfig =
+            serde_json::from_str(r#"{"n_samples": 50, "target": "banana"}"#).unwrap();
+
+        assert_eq!(config.n_samples, 50);
+        assert!(matches!(config.target, DistType::Banana(_)));
+        assert_eq!(config.num_steps, HmcConfig::default().num_steps);
+
+        let json = serde_json::to_string(&config).unwrap();
+        let restored: HmcConfig = serde_json::from_str(&json).unwrap();
+        assert_eq!(restored.n_samples, 50);
+    }
+
+    #[test]
+    fn step_size_jitter_is_reproducible_and_off_by_default() {
+        // ジッター導入前の実装で記録した値（幅0では乱数を引かないので、ビット単位で一致する）
+        let expected = [
+            (
+                Algorithm::Hmc,
+                Point {
+                    x: 1.1128803654381363,
+                    y: 1.4717742526444122,
+                },
+                0.07633533972006235,
+            ),
+            (
+                Algorithm::Nuts { max_depth: 10 },
+                Point {
+                    x: 0.8626574455684766,
+                    y: 0.5298584013189197,
+                },
+                0.1255565989966715,
+            ),
+        ];
+        for (algorithm, last, adapted) in expected {
+            let config = HmcConfig {
+                n_samples: 200,
+                n_warmup: 100,
+                adapt_step_size: true,
+                save_step_sizes: true,
+                target: DistType::Banana(Banana::default()),
+                algorithm,
+                seed: Some(68),
+                ..HmcConfig::default()
+            };
+            let plain = run_hmc(&config).unwrap();
+            assert_eq!(plain.samples[199], last);
+            assert_eq!(plain.adapted_step_size, Some(adapted));
+            assert_eq!(plain.step_sizes, vec![adapted; 200]);
+
+            let jittered_config = HmcConfig {
+                step_size_jitter: 0.5,
+                ..config
+            };
+            let jittered = run_hmc(&jittered_config).unwrap();
+            assert_ne!(jittered.samples, plain.samples);
+            assert_eq!(jittered, {
+                let mut again = run_hmc(&jittered_config).unwrap();
+                again.elapsed_secs = jittered.elapsed_secs;
+                again
+            });
+            let base = jittered.adapted_step_size.unwrap();
+            assert!(jittered
+                .step_sizes
+                .iter()
+                .all(|&eps| eps >= 0.5 * base && eps <= 1.5 * base));
+            let mean = jittered.step_sizes.iter().sum::<f64>() / 200.0;
+            assert!((mean / base - 1.0).abs() < 0.1, "{} vs {}", mean, base);
+        }
+
+        for jitter in [-0.1, 1.0, f64::NAN] {
+            assert!(matches!(
+                HmcConfig {
+                    step_size_jitter: jitter,
+                    ..HmcConfig::default()
+                }
+                .validate(),
+                Err(HmcError::InvalidStepSizeJitter(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn saved_initial_momenta_are_standard_normal_for_any_target() {
+        for (target, algorithm) in [
+            (DistType::Banana(Banana::default()), Algorithm::Hmc),
+            (
+                DistType::Funnel(Funnel::default()),
+                Algorithm::Nuts { max_depth: 10 },
+            ),
+            (DistType::Normal, Algorithm::Hmc),
+        ] {
+            let result = run_hmc(&HmcConfig {
+                n_samples: 4000,
+                thin: 2,
+                step_size: 0.05,
+                algorithm,
+                target: target.clone(),
+                save_momentum: true,
+                seed: Some(83),
+                ..HmcConfig::default()
+            })
+            .unwrap();
+            assert_eq!(result.momenta.len(), result.samples.len());
+            let z = validate::z_score_of_mean(
+                &result.momenta,
+                &Point::default(),
+                &[[1.0, 0.0], [0.0, 1.0]],
+            );
+            let cov = validate::sample_cov(&result.momenta);
+            assert!(z.x.abs() < 4.0 && z.y.abs() < 4.0, "{:?}: {:?}", target, z);
+            assert!(
+                (cov[0][0] - 1.0).abs() < 0.1
+                    && (cov[1][1] - 1.0).abs() < 0.1
+                    && cov[0][1].abs() < 0.1,
+                "{:?}: {:?}",
+                target,
+                cov
+            );
+        }
+
+        // 指定しなければ記録せず、JSONにも出さない。リープフロッグを使わないアルゴリズムでは空
+        let plain = run_hmc(&HmcConfig {
+            n_samples: 10,
+            seed: Some(83),
+            ..HmcConfig::default()
+        })
+        .unwrap();
+        assert!(plain.momenta.is_empty());
+        assert!(serde_json::to_value(&plain)
+            .unwrap()
+            .get("momenta")
+            .is_none());
+        let random_walk = run_hmc(&HmcConfig {
+            n_samples: 10,
+            algorithm: Algorithm::RandomWalk { proposal_std: 0.5 },
+            save_momentum: true,
+            seed: Some(83),
+            ..HmcConfig::default()
+        })
+        .unwrap();
+        assert!(random_walk.momenta.is_empty());
+    }
+    #[test]
+    fn randomized_steps_are_reproducible_and_report_mean_cost() {
+        let config = HmcConfig {
+            n_samples: 2000,
+            n_warmup: 0,
+            num_steps: 20,
+            randomize_steps: true,
+            target: DistType::Normal,
+            seed: Some(69),
+            ..HmcConfig::default()
+        };
+        let result = run_hmc(&config).unwrap();
+        assert_eq!(result, {
+            let mut again = run_hmc(&config).unwrap();
+            again.elapsed_secs = result.elapsed_secs;
+            again
+        });
+        // L ~ U{1, ..., 20} の平均は 10.5
+        assert!(
+            (result.mean_n_leapfrog - 10.5).abs() < 0.5,
+            "{}",
+            result.mean_n_leapfrog
+        );
+        assert_eq!(
+            result.mean_n_leapfrog,
+            result.n_leapfrog as f64 / result.samples.len() as f64
+        );
+
+        let fixed = run_hmc(&HmcConfig {
+            randomize_steps: false,
+            ..config.clone()
+        })
+        .unwrap();
+        assert_eq!(fixed.mean_n_leapfrog, 20.0);
+        assert_ne!(fixed.samples, result.samples);
+
+        // 逐次の Chain::step も同じ長さを引く
+        let mut chain = Chain::new(config).unwrap();
+        let stepped: Vec<Point> = (0..2000).map(|_| chain.step().position).collect();
+        assert_eq!(stepped, result.samples);
+    }
+
+    #[test]
+    fn f32_samples_round_the_f64_samples() {
+        let result = run_hmc(&HmcConfig {
+            n_samples: 5000,
+            target: DistType::Banana(Banana::default()),
+            seed: Some(88),
+            ..HmcConfig::default()
+        })
+        .unwrap();
+        let (xs, ys) = result.samples_f32();
+        assert_eq!((xs.len(), ys.len()), (5000, 5000));
+        for (p, (&x, &y)) in result.samples.iter().zip(xs.iter().zip(&ys)) {
+            assert!((x as f64 - p.x).abs() <= p.x.abs() * f32::EPSILON as f64);
+            assert!((y as f64 - p.y).abs() <= p.y.abs() * f32::EPSILON as f64);
+        }
+        assert_eq!(
+            HmcResult {
+                samples: Vec::new(),
+                samples_flat: Point::flatten(&result.samples),
+                ..result.clone()
+            }
+            .samples_f32(),
+            (xs, ys)
+        );
+    }
+
+    #[test]
+    fn flat_samples_round_trip_and_serialize_cheaper() {
+        let config = HmcConfig {
+            n_samples: 500,
+            target: DistType::Banana(Banana::default()),
+            seed: Some(89),
+            ..HmcConfig::default()
+        };
+        let points = run_hmc(&config).unwrap();
+        let flat = run_hmc(&HmcConfig {
+            flat_samples: true,
+            ..config.clone()
+        })
+        .unwrap();
+        assert!(flat.samples.is_empty());
+        assert_eq!(flat.dim, 2);
+        assert_eq!(flat.samples_flat, Point::flatten(&points.samples));
+        assert_eq!(Point::unflatten(&flat.samples_flat), points.samples);
+        let json = serde_json::to_string(&flat).unwrap();
+        let loaded: HmcResult = serde_json::from_str(&json).unwrap();
+        assert_eq!(loaded.samples_flat.len(), 1000);
+        let json = serde_json::to_string(&points).unwrap();
+        assert!(!json.contains("samples_flat"));
+
+        // 点の列を読むアクセサは平らな列からも同じ値を返す
+        assert_eq!(flat.n_samples(), 500);
+        assert_eq!(flat.samples_f32(), points.samples_f32());
+        assert_eq!(flat.walker_samples(), vec![points.samples.clone()]);
+        assert_eq!(flat.ess(), points.ess());
+        assert_eq!(ChainStats::from(&flat).n_samples, 500);
+        let ensemble = HmcConfig {
+            n_samples: 100,
+            algorithm: Algorithm::Ensemble {
+                n_walkers: 8,
+                a: 2.0,
+            },
+            ..config.clone()
+        };
+        let walkers = run_hmc(&ensemble).unwrap().walker_samples();
+        let flat_walkers = run_hmc(&HmcConfig {
+            flat_samples: true,
+            ..ensemble
+        })
+        .unwrap()
+        .walker_samples();
+        assert_eq!(flat_walkers.len(), 8);
+        assert!(flat_walkers.iter().all(|w| w.len() == 100));
+        assert_eq!(flat_walkers, walkers);
+
+        // 10^6 点の変換: JSONの文字列は点ごとのキーと括弧の分だけ短くなり、
+        // （PythonやJSが受け取るような）動的な値の木では点ごとのオブジェクトの確保がなくなる
+        let many: Vec<Point> = (0..1_000_000)
+            .map(|i| {
+                let t = i as f64 * 1e-3;
+                Point {
+                    x: t.sin(),
+                    y: t.cos(),
+                }
+            })
+            .collect();
+        let flat = Point::flatten(&many);
+        let as_points = serde_json::to_vec(&many).unwrap();
+        let as_flat = serde_json::to_vec(&flat).unwrap();
+        assert!(
+            (as_flat.len() as f64) < 0.85 * as_points.len() as f64,
+            "{} {}",
+            as_flat.len(),
+            as_points.len()
+        );
+        let points_allocations =
+            ndim::tests::count_allocations(|| drop(serde_json::to_value(&many).unwrap()));
+        let flat_allocations =
+            ndim::tests::count_allocations(|| drop(serde_json::to_value(&flat).unwrap()));
+        assert!(
+            points_allocations > 1_000_000 && flat_allocations < 10,
+            "{} {}",
+            points_allocations,
+            flat_allocations
+        );
+    }
+
+    #[test]
+    fn run_hmc_into_reuses_the_buffer_without_allocating() {
+        let config = HmcConfig {
+            n_samples: 100_000,
+            n_warmup: 200,
+            adapt_step_size: true,
+            seed: Some(92),
+            ..HmcConfig::default()
+        };
+        let mut samples = Vec::new();
+        let first = ndim::tests::count_allocations(|| {
+            run_hmc_into(&config, &mut samples).unwrap();
+        });
+        assert!(first > 0);
+        let buffer = samples.as_ptr();
+        let mut stats = None;
+        let again = ndim::tests::count_allocations(|| {
+            stats = Some(run_hmc_into(&config, &mut samples).unwrap());
+        });
+        assert_eq!(again, 0);
+        assert_eq!(samples.as_ptr(), buffer);
+
+        // run_hmc と同じサンプル・統計量になる
+        let result = run_hmc(&config).unwrap();
+        assert_eq!(samples, result.samples);
+        let stats = stats.unwrap();
+        assert_eq!(
+            stats,
+            ChainStats {
+                elapsed_secs: stats.elapsed_secs,
+                ..ChainStats::from(&result)
+            }
+        );
+        assert_eq!(stats.n_samples, 100_000);
+
+        // 容量が足りなければ広げ、flat_samples は無視する
+        let more = HmcConfig {
+            n_samples: 150_000,
+            flat_samples: true,
+            ..config
+        };
+        run_hmc_into(&more, &mut samples).unwrap();
+        assert_eq!(samples.len(), 150_000);
+    }
+}
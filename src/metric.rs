@@ -0,0 +1,461 @@
+//! 質量行列（運動エネルギーの計量）
+
+use rand::Rng;
+use rand_distr::{Distribution, StandardNormal};
+use serde::{Deserialize, Serialize};
+
+use crate::vector::{self, Vector};
+use crate::{HmcError, Point};
+
+/// 質量行列の形
+///
+/// JSONでは `"unit_e"`、`{"diagonal": [v_x, v_y]}`、`{"dense": [[m00, m01], [m10, m11]]}`、`"adapt_dense"`。
+/// 読み込むときはタグなしの配列も受け付け、`[v_x, v_y]` は `Diagonal`、`[[..], [..]]` は `Dense` になる。
+/// 要素数はサンプリングする状態の次元に合わせる（2次元のAPIでは2、`run_hmc_nd` ではターゲットの `dim`）。
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(rename_all = "snake_case", try_from = "MetricRepr")]
+pub enum Metric {
+    /// 単位行列
+    #[default]
+    UnitE,
+    /// 対角の質量行列。要素は逆行列 M⁻¹ の対角成分で、各座標の分散の目安を渡す
+    Diagonal(Vec<f64>),
+    /// 対称正定値の密な質量行列 M（行の列）
+    ///
+    /// コレスキー分解 M = LLᵀ を使い、運動量を p = Lz (z ~ N(0, I)) で引き、
+    /// 運動エネルギー pᵀM⁻¹p/2 と速度 M⁻¹p は三角行列の前進・後退代入で求める。
+    /// 目安はターゲットの共分散行列の逆行列。
+    Dense(Vec<Vec<f64>>),
+    /// ウォームアップ中に引いた点の標本共分散 Σ から密な質量行列 M = Σ⁻¹ を推定する
+    ///
+    /// 推定に使う区間と `mass_regularization` の扱いは `adapt_mass_matrix` と同じ。推定までは単位行列を使う。
+    AdaptDense,
+}
+
+/// `Metric` のデシリアライズ用の中間表現（タグなしの配列も受け付ける）
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum MetricRepr {
+    Name(String),
+    Diagonal(Vec<f64>),
+    Dense(Vec<Vec<f64>>),
+    Tagged(MetricTagged),
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum MetricTagged {
+    Diagonal(Vec<f64>),
+    Dense(Vec<Vec<f64>>),
+}
+
+impl TryFrom<MetricRepr> for Metric {
+    type Error = HmcError;
+
+    fn try_from(repr: MetricRepr) -> Result<Self, HmcError> {
+        match repr {
+            MetricRepr::Name(name) => match name.as_str() {
+                "unit_e" => Ok(Metric::UnitE),
+                "adapt_dense" => Ok(Metric::AdaptDense),
+                _ => Err(HmcError::UnknownMetric(name)),
+            },
+            MetricRepr::Diagonal(inv_mass)
+            | MetricRepr::Tagged(MetricTagged::Diagonal(inv_mass)) => {
+                Ok(Metric::Diagonal(inv_mass))
+            }
+            MetricRepr::Dense(mass) | MetricRepr::Tagged(MetricTagged::Dense(mass)) => {
+                Ok(Metric::Dense(mass))
+            }
+        }
+    }
+}
+
+impl Metric {
+    /// 2次元の状態について、`Diagonal` の要素数と値、`Dense` の行列が対称正定値かを確かめる
+    pub fn validate(&self) -> Result<(), HmcError> {
+        self.validate_dim(2)
+    }
+
+    /// `dim` 次元の状態について `validate` と同じことを確かめる
+    pub(crate) fn validate_dim(&self, dim: usize) -> Result<(), HmcError> {
+        match self {
+            // 確かめることがないので、既定の設定では検証のためのベクトルを確保しない
+            Metric::UnitE | Metric::AdaptDense => Ok(()),
+            _ => MassMatrix::new(self, &vec![0.0; dim]).map(|_| ()),
+        }
+    }
+}
+
+/// 遷移に使う質量行列（`Metric` を解決したもの）
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum MassMatrix<V = Point> {
+    /// 質量行列の逆 M⁻¹ の対角成分
+    Diagonal(V),
+    Dense(DenseMass),
+}
+
+/// 密な質量行列とそのコレスキー因子
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct DenseMass {
+    pub(crate) mass: Vec<Vec<f64>>,
+    /// M = LLᵀ となる下三角行列 L（i 行目は対角成分までの i + 1 個）
+    chol: Vec<Vec<f64>>,
+}
+
+impl DenseMass {
+    /// `mass` が正方・有限・対称・正定値でなければ `HmcError::InvalidMassMatrix`
+    pub(crate) fn new(mass: Vec<Vec<f64>>) -> Result<Self, HmcError> {
+        let n = mass.len();
+        let square = mass.iter().all(|row| row.len() == n);
+        let finite = mass.iter().flatten().all(|v| v.is_finite());
+        let symmetric = square && (0..n).all(|i| (0..i).all(|j| mass[i][j] == mass[j][i]));
+        if !(finite && symmetric) {
+            return Err(HmcError::InvalidMassMatrix(mass));
+        }
+        match cholesky(&mass) {
+            Some(chol) => Ok(Self { mass, chol }),
+            None => Err(HmcError::InvalidMassMatrix(mass)),
+        }
+    }
+
+    /// 共分散行列 `cov` の逆行列を質量行列とする
+    ///
+    /// Σ = LLᵀ から Σ⁻¹ = L⁻ᵀL⁻¹ を作る。(i, j) 成分は L⁻¹ の i 列と j 列の内積なので厳密に対称になる。
+    pub(crate) fn from_covariance(cov: &[Vec<f64>]) -> Result<Self, HmcError> {
+        let n = cov.len();
+        let Some(l) = cholesky(cov) else {
+            return Err(HmcError::InvalidMassMatrix(cov.to_vec()));
+        };
+        // L⁻¹ の各列（単位ベクトルを前進代入したもの）
+        let columns: Vec<Vec<f64>> = (0..n)
+            .map(|j| {
+                let mut e = vec![0.0; n];
+                e[j] = 1.0;
+                forward_substitute(&l, &mut e);
+                e
+            })
+            .collect();
+        let mass = columns
+            .iter()
+            .map(|a| {
+                columns
+                    .iter()
+                    .map(|b| a.iter().zip(b).map(|(a, b)| a * b).sum())
+                    .collect()
+            })
+            .collect();
+        Self::new(mass)
+    }
+
+    /// L⁻¹p（前進代入）
+    fn whiten<V: Vector>(&self, p: &V) -> V {
+        let mut w = p.clone();
+        forward_substitute(&self.chol, w.as_mut_slice());
+        w
+    }
+}
+
+/// 下三角行列 `l` について Lx = b を解き、`b` を x で置き換える（前進代入）
+fn forward_substitute(l: &[Vec<f64>], b: &mut [f64]) {
+    for (i, row) in l.iter().enumerate() {
+        let mut s = b[i];
+        for (l, x) in row.iter().zip(&b[..i]) {
+            s -= l * x;
+        }
+        b[i] = s / row[i];
+    }
+}
+
+/// 対称な行列 `a` のコレスキー因子（正定値でなければ `None`）
+fn cholesky(a: &[Vec<f64>]) -> Option<Vec<Vec<f64>>> {
+    let mut l: Vec<Vec<f64>> = Vec::with_capacity(a.len());
+    for (i, a_row) in a.iter().enumerate() {
+        let mut row: Vec<f64> = Vec::with_capacity(i + 1);
+        for (j, l_j) in l.iter().enumerate() {
+            let mut s = a_row[j];
+            for (x, y) in row.iter().zip(l_j) {
+                s -= x * y;
+            }
+            row.push(s / l_j[j]);
+        }
+        let mut s = a_row[i];
+        for x in &row {
+            s -= x * x;
+        }
+        if !(s.is_finite() && s > 0.0) {
+            return None;
+        }
+        row.push(s.sqrt());
+        l.push(row);
+    }
+    Some(l)
+}
+
+/// 対角の質量行列の逆 `inv_mass` の下での運動エネルギー pᵀM⁻¹p / 2（次元を問わない）
+pub(crate) fn diagonal_kinetic(inv_mass: &[f64], p: &[f64]) -> f64 {
+    0.5 * inv_mass
+        .iter()
+        .zip(p)
+        .map(|(m, p)| m * p.powi(2))
+        .sum::<f64>()
+}
+
+/// 白色化した運動量 `w` を対角の質量行列の下での運動量 M^(1/2) w に置き換える（次元を問わない）
+pub(crate) fn diagonal_color(inv_mass: &[f64], w: &mut [f64]) {
+    for (w, m) in w.iter_mut().zip(inv_mass) {
+        *w /= m.sqrt();
+    }
+}
+
+impl<V: Vector> MassMatrix<V> {
+    /// `like` と同じ次元の状態に使う `metric` の質量行列（`AdaptDense` は推定前の単位行列）
+    pub(crate) fn new(metric: &Metric, like: &V) -> Result<Self, HmcError> {
+        let dim = like.dim();
+        match metric {
+            Metric::UnitE | Metric::AdaptDense => {
+                Ok(MassMatrix::Diagonal(vector::map(like, |_| 1.0)))
+            }
+            Metric::Diagonal(inv_mass) => {
+                if inv_mass.len() != dim {
+                    return Err(HmcError::MetricDimension {
+                        expected: dim,
+                        got: inv_mass.len(),
+                    });
+                }
+                if !inv_mass.iter().all(|v| v.is_finite() && *v > 0.0) {
+                    return Err(HmcError::InvalidInvMass(inv_mass.clone()));
+                }
+                Ok(MassMatrix::Diagonal(vector::from_slice(like, inv_mass)))
+            }
+            Metric::Dense(mass) => {
+                if mass.len() != dim {
+                    return Err(HmcError::MetricDimension {
+                        expected: dim,
+                        got: mass.len(),
+                    });
+                }
+                Ok(MassMatrix::Dense(DenseMass::new(mass.clone())?))
+            }
+        }
+    }
+
+    /// `HmcResult` に載せる形（対角なら `Diagonal`、密なら `Dense`）
+    pub(crate) fn to_metric(&self) -> Metric {
+        match self {
+            MassMatrix::Diagonal(inv_mass) => Metric::Diagonal(inv_mass.as_slice().to_vec()),
+            MassMatrix::Dense(dense) => Metric::Dense(dense.mass.clone()),
+        }
+    }
+
+    /// 運動量 p ~ N(0, M) を `like` と同じ形で引く（標準正規分布から座標の順に引く）
+    pub(crate) fn sample_momentum<R: Rng + ?Sized>(&self, like: &V, rng: &mut R) -> V {
+        let mut w = like.clone();
+        for w in w.as_mut_slice() {
+            *w = StandardNormal.sample(rng);
+        }
+        self.color(&w)
+    }
+
+    /// M = LLᵀ として Lw（白色化した運動量 w から p を作る）
+    pub(crate) fn color(&self, w: &V) -> V {
+        match self {
+            MassMatrix::Diagonal(inv_mass) => {
+                let mut p = w.clone();
+                diagonal_color(inv_mass.as_slice(), p.as_mut_slice());
+                p
+            }
+            MassMatrix::Dense(dense) => {
+                let mut p = w.clone();
+                let w = w.as_slice();
+                for (p, row) in p.as_mut_slice().iter_mut().zip(&dense.chol) {
+                    let mut s = row[0] * w[0];
+                    for k in 1..row.len() {
+                        s += row[k] * w[k];
+                    }
+                    *p = s;
+                }
+                p
+            }
+        }
+    }
+
+    /// M = LLᵀ として L⁻¹p（pᵀM⁻¹p = |L⁻¹p|²）
+    pub(crate) fn whiten(&self, p: &V) -> V {
+        match self {
+            MassMatrix::Diagonal(inv_mass) => vector::zip_with(p, inv_mass, |p, m| p * m.sqrt()),
+            MassMatrix::Dense(dense) => dense.whiten(p),
+        }
+    }
+
+    /// M = LLᵀ として L⁻ᵀw（白色化した座標での K の勾配を p の勾配に戻す）
+    pub(crate) fn unwhiten(&self, w: &V) -> V {
+        match self {
+            MassMatrix::Diagonal(inv_mass) => vector::zip_with(w, inv_mass, |w, m| w * m.sqrt()),
+            MassMatrix::Dense(dense) => {
+                // 後退代入
+                let l = &dense.chol;
+                let mut x = w.clone();
+                let out = x.as_mut_slice();
+                for i in (0..out.len()).rev() {
+                    let mut s = out[i];
+                    for k in i + 1..out.len() {
+                        s -= l[k][i] * out[k];
+                    }
+                    out[i] = s / l[i][i];
+                }
+                x
+            }
+        }
+    }
+
+    /// 運動エネルギー K(p) = pᵀM⁻¹p / 2
+    pub(crate) fn kinetic(&self, p: &V) -> f64 {
+        match self {
+            MassMatrix::Diagonal(inv_mass) => diagonal_kinetic(inv_mass.as_slice(), p.as_slice()),
+            MassMatrix::Dense(dense) => {
+                let w = dense.whiten(p);
+                0.5 * w.dot(&w)
+            }
+        }
+    }
+
+    /// 速度 M⁻¹p
+    pub(crate) fn velocity(&self, p: &V) -> V {
+        match self {
+            MassMatrix::Diagonal(inv_mass) => vector::zip_with(inv_mass, p, |m, p| m * p),
+            MassMatrix::Dense(dense) => self.unwhiten(&dense.whiten(p)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+
+    fn dense(rows: [[f64; 2]; 2]) -> Vec<Vec<f64>> {
+        rows.iter().map(|row| row.to_vec()).collect()
+    }
+
+    #[test]
+    fn dense_mass_matches_explicit_inverse() {
+        let mass = [[4.0, 1.2], [1.2, 0.9]];
+        let metric: MassMatrix = MassMatrix::Dense(DenseMass::new(dense(mass)).unwrap());
+        let det = 4.0 * 0.9 - 1.2 * 1.2;
+        let inv = [[0.9 / det, -1.2 / det], [-1.2 / det, 4.0 / det]];
+        let p = Point { x: 0.7, y: -1.3 };
+
+        let v = metric.velocity(&p);
+        assert!((v.x - (inv[0][0] * p.x + inv[0][1] * p.y)).abs() < 1e-12);
+        assert!((v.y - (inv[1][0] * p.x + inv[1][1] * p.y)).abs() < 1e-12);
+        assert!((metric.kinetic(&p) - 0.5 * (p.x * v.x + p.y * v.y)).abs() < 1e-12);
+
+        // 引いた運動量の共分散は M
+        let mut rng = rand_chacha::ChaCha12Rng::seed_from_u64(59);
+        let draws: Vec<Point> = (0..100_000)
+            .map(|_| metric.sample_momentum(&p, &mut rng))
+            .collect();
+        let cov = crate::validate::sample_cov(&draws);
+        for (row, expected) in cov.iter().zip(mass) {
+            for (c, m) in row.iter().zip(expected) {
+                assert!((c - m).abs() < 0.05, "{:?}", cov);
+            }
+        }
+
+        for bad in [
+            [[1.0, 2.0], [2.0, 1.0]],
+            [[1.0, 0.5], [0.4, 1.0]],
+            [[-1.0, 0.0], [0.0, 1.0]],
+            [[1.0, 0.0], [0.0, f64::NAN]],
+        ] {
+            assert!(matches!(
+                Metric::Dense(dense(bad)).validate(),
+                Err(HmcError::InvalidMassMatrix(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn metric_loads_from_tagged_and_bare_json() {
+        let parse = |json: &str| serde_json::from_str::<Metric>(json);
+        assert_eq!(parse(r#""unit_e""#).unwrap(), Metric::UnitE);
+        assert_eq!(parse(r#""adapt_dense""#).unwrap(), Metric::AdaptDense);
+        assert_eq!(
+            parse("[1, 100]").unwrap(),
+            Metric::Diagonal(vec![1.0, 100.0])
+        );
+        assert_eq!(
+            parse(r#"{"diagonal": [1, 100]}"#).unwrap(),
+            Metric::Diagonal(vec![1.0, 100.0])
+        );
+        let dense = Metric::Dense(dense([[2.0, 0.5], [0.5, 1.0]]));
+        assert_eq!(parse("[[2, 0.5], [0.5, 1]]").unwrap(), dense);
+        assert_eq!(parse(r#"{"dense": [[2, 0.5], [0.5, 1]]}"#).unwrap(), dense);
+        assert!(parse(r#""diagonal""#).is_err());
+
+        for metric in [
+            Metric::UnitE,
+            Metric::Diagonal(vec![1.0, 100.0]),
+            dense,
+            Metric::AdaptDense,
+        ] {
+            let json = serde_json::to_string(&metric).unwrap();
+            assert_eq!(parse(&json).unwrap(), metric);
+        }
+
+        assert_eq!(
+            Metric::Diagonal(vec![1.0, 2.0, 3.0]).validate(),
+            Err(HmcError::MetricDimension {
+                expected: 2,
+                got: 3
+            })
+        );
+        assert_eq!(
+            Metric::Diagonal(vec![1.0, 0.0]).validate(),
+            Err(HmcError::InvalidInvMass(vec![1.0, 0.0]))
+        );
+    }
+
+    #[test]
+    fn dense_mass_inverts_any_dimension() {
+        // 3次元の共分散 Σ から作った M = Σ⁻¹ では、速度 M⁻¹p = Σp
+        let cov = vec![
+            vec![2.0, 0.3, -0.4],
+            vec![0.3, 1.0, 0.2],
+            vec![-0.4, 0.2, 0.5],
+        ];
+        let metric: MassMatrix<Vec<f64>> =
+            MassMatrix::Dense(DenseMass::from_covariance(&cov).unwrap());
+        let MassMatrix::Dense(dense) = &metric else {
+            unreachable!()
+        };
+        for i in 0..3 {
+            for j in 0..3 {
+                assert_eq!(dense.mass[i][j], dense.mass[j][i]);
+            }
+        }
+        let p = vec![0.7, -1.3, 0.4];
+        let v = metric.velocity(&p);
+        for (v, row) in v.iter().zip(&cov) {
+            let expected: f64 = row.iter().zip(&p).map(|(c, p)| c * p).sum();
+            assert!((v - expected).abs() < 1e-12, "{} {}", v, expected);
+        }
+        assert!((metric.kinetic(&p) - 0.5 * p.dot(&v)).abs() < 1e-12);
+        for (w, p) in metric.whiten(&metric.color(&p)).iter().zip(&p) {
+            assert!((w - p).abs() < 1e-12);
+        }
+
+        assert_eq!(
+            Metric::Dense(cov.clone()).validate_dim(2),
+            Err(HmcError::MetricDimension {
+                expected: 2,
+                got: 3
+            })
+        );
+        assert_eq!(Metric::Dense(cov).validate_dim(3), Ok(()));
+        assert!(matches!(
+            Metric::Dense(vec![vec![1.0, 0.0], vec![0.0]]).validate(),
+            Err(HmcError::InvalidMassMatrix(_))
+        ));
+    }
+}
@@ -0,0 +1,332 @@
+//! 複数の独立チェーンの実行
+
+use rand::SeedableRng;
+#[cfg(all(feature = "parallel", not(target_arch = "wasm32")))]
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    diagnostics, sample_chain, Chain, ChainRng, HmcConfig, HmcError, HmcResult, Point, RunHooks,
+    TargetDistribution,
+};
+
+/// 複数チェーンの実行結果
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct MultiChainResult {
+    /// チェーンごとの結果（チェーン番号順）
+    pub chains: Vec<HmcResult>,
+    /// 座標ごとの分割R̂（`compute_diagnostics` 指定時のみ。求められない場合も `None`）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rhat: Option<Point>,
+}
+
+impl MultiChainResult {
+    /// チェーンごとの採択率
+    pub fn acceptance_rates(&self) -> Vec<f64> {
+        self.chains.iter().map(|c| c.acceptance_rate).collect()
+    }
+
+    /// 全チェーンのサンプルから求めた座標ごとの分割R̂（[`diagnostics::rhat`]）
+    pub fn rhat(&self) -> Result<Point, HmcError> {
+        diagnostics::rhat_per_coordinate(&self.chains)
+    }
+}
+
+/// `n_chains` 本の独立したチェーンを実行する
+///
+/// `initial_points` が空なら全チェーンを `config` の開始位置から始め、
+/// そうでなければチェーン `i` を `initial_points[i]` から始める。
+/// `config.seed` 指定時はそれをマスターシードとし、チェーン `i` は
+/// [`derive_chain_seed`]`(seed, i)` をシードとする。未指定なら各チェーンのシードを乱数で決める。
+/// いずれの場合も使ったシードは `HmcResult::seed` に記録され、
+/// `HmcConfig { seed: Some(s), .. }` で `run_hmc` を呼べばそのチェーンだけを再実行できる。
+///
+/// `parallel` フィーチャ有効時はrayonで並列に実行する。各チェーンのRNGは実行順に依存しないため、
+/// 結果は直列実行と一致する。
+pub fn run_hmc_chains(
+    config: &HmcConfig,
+    n_chains: usize,
+    initial_points: &[Point],
+) -> Result<MultiChainResult, HmcError> {
+    run_hmc_chains_with_target(config, &config.target, n_chains, initial_points)
+}
+
+/// ユーザー定義のターゲット分布で `n_chains` 本の独立したチェーンを実行する
+///
+/// 全チェーンが `target` を共有するため、並列実行に備えて `Sync` を要求する。
+/// それ以外は [`run_hmc_chains`] と同じ。
+pub fn run_hmc_chains_with_target<T: TargetDistribution + Sync>(
+    config: &HmcConfig,
+    target: &T,
+    n_chains: usize,
+    initial_points: &[Point],
+) -> Result<MultiChainResult, HmcError> {
+    if n_chains == 0 {
+        return Err(HmcError::ZeroChains);
+    }
+    if !initial_points.is_empty() && initial_points.len() != n_chains {
+        return Err(HmcError::InitialPointCount {
+            expected: n_chains,
+            got: initial_points.len(),
+        });
+    }
+
+    let chains = (0..n_chains)
+        .map(|index| {
+            let seed = match config.seed {
+                Some(master) => derive_chain_seed(master, index),
+                None => rand::random(),
+            };
+            let mut chain_config = HmcConfig {
+                seed: Some(seed),
+                ..config.clone()
+            };
+            if let Some(point) = initial_points.get(index) {
+                chain_config.initial_pos = point.clone();
+                chain_config.resume_from = None;
+            }
+            Chain::with_target(chain_config, target, ChainRng::seed_from_u64(seed))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    #[cfg(all(feature = "parallel", not(target_arch = "wasm32")))]
+    let chains = run_parallel(chains)?;
+    #[cfg(not(all(feature = "parallel", not(target_arch = "wasm32"))))]
+    let chains = run_serial(chains)?;
+
+    let rhat = if config.compute_diagnostics {
+        diagnostics::rhat_per_coordinate(&chains).ok()
+    } else {
+        None
+    };
+    Ok(MultiChainResult { chains, rhat })
+}
+
+// `parallel` 有効時もテストで並列実行との一致を確かめるために残す
+#[cfg_attr(
+    all(feature = "parallel", not(target_arch = "wasm32")),
+    allow(dead_code)
+)]
+fn run_serial<T: TargetDistribution>(
+    chains: Vec<Chain<ChainRng, T>>,
+) -> Result<Vec<HmcResult>, HmcError> {
+    chains
+        .into_iter()
+        .map(|mut chain| sample_chain(&mut chain, &mut RunHooks::default()))
+        .collect()
+}
+
+#[cfg(all(feature = "parallel", not(target_arch = "wasm32")))]
+fn run_parallel<T: TargetDistribution + Send>(
+    chains: Vec<Chain<ChainRng, T>>,
+) -> Result<Vec<HmcResult>, HmcError> {
+    chains
+        .into_par_iter()
+        .map(|mut chain| sample_chain(&mut chain, &mut RunHooks::default()))
+        .collect()
+}
+
+/// マスターシードからチェーン `chain_index` のシードを導出する
+///
+/// SplitMix64 をマスターシードで初期化したときの `chain_index` 番目（0始まり）の出力。
+/// チェーン数や実行順序に依存せず、Rust/Python/WASMのどこから呼んでも同じ値になる。
+/// 再現性のため、この導出方式はバージョン間で変更しない。
+pub fn derive_chain_seed(master_seed: u64, chain_index: usize) -> u64 {
+    const GOLDEN_GAMMA: u64 = 0x9E37_79B9_7F4A_7C15;
+    let mut z = master_seed.wrapping_add(GOLDEN_GAMMA.wrapping_mul(chain_index as u64 + 1));
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Banana, Bimodal, DistType};
+
+    fn config() -> HmcConfig {
+        HmcConfig {
+            n_samples: 200,
+            target: DistType::Banana(Banana::default()),
+            seed: Some(42),
+            ..HmcConfig::default()
+        }
+    }
+
+    #[test]
+    fn chains_are_independent_and_reproducible() {
+        let first = run_hmc_chains(&config(), 3, &[]).unwrap();
+        let second = run_hmc_chains(&config(), 3, &[]).unwrap();
+        assert_eq!(first.chains.len(), 3);
+        assert_eq!(first.acceptance_rates().len(), 3);
+        for (a, b) in first.chains.iter().zip(&second.chains) {
+            assert_eq!(a.samples, b.samples);
+        }
+        assert_ne!(first.chains[0].samples, first.chains[1].samples);
+        assert_ne!(first.chains[1].samples, first.chains[2].samples);
+    }
+
+    #[test]
+    fn derived_seeds_are_stable() {
+        // SplitMix64(0) の既知の出力列
+        assert_eq!(derive_chain_seed(0, 0), 0xE220_A839_7B1D_CDAF);
+        assert_eq!(derive_chain_seed(0, 1), 0x6E78_9E6A_A1B9_65F4);
+        assert_ne!(derive_chain_seed(42, 2), derive_chain_seed(43, 2));
+    }
+
+    #[test]
+    fn chain_seed_does_not_depend_on_chain_count() {
+        let two = run_hmc_chains(&config(), 2, &[]).unwrap();
+        let four = run_hmc_chains(&config(), 4, &[]).unwrap();
+        for i in 0..2 {
+            assert_eq!(two.chains[i].seed, Some(derive_chain_seed(42, i)));
+            assert_eq!(two.chains[i].samples, four.chains[i].samples);
+        }
+    }
+
+    #[test]
+    fn single_chain_reruns_from_recorded_seed() {
+        let multi = run_hmc_chains(&config(), 3, &[]).unwrap();
+        let chain = &multi.chains[2];
+        let rerun = crate::run_hmc(&HmcConfig {
+            seed: chain.seed,
+            ..config()
+        })
+        .unwrap();
+        assert_eq!(rerun.samples, chain.samples);
+
+        let unseeded = HmcConfig {
+            seed: None,
+            ..config()
+        };
+        let multi = run_hmc_chains(&unseeded, 2, &[]).unwrap();
+        let rerun = crate::run_hmc(&HmcConfig {
+            seed: multi.chains[1].seed,
+            ..unseeded
+        })
+        .unwrap();
+        assert_eq!(rerun.samples, multi.chains[1].samples);
+    }
+
+    #[test]
+    fn chains_start_from_given_points() {
+        let points = [Point { x: -2.0, y: 3.0 }, Point { x: 2.0, y: 1.0 }];
+        let result = run_hmc_chains(
+            &HmcConfig {
+                n_samples: 1,
+                step_size: 1e-6,
+                num_steps: 1,
+                ..config()
+            },
+            2,
+            &points,
+        )
+        .unwrap();
+        for (chain, start) in result.chains.iter().zip(&points) {
+            assert!((chain.samples[0].x - start.x).abs() < 1e-3);
+            assert!((chain.samples[0].y - start.y).abs() < 1e-3);
+        }
+    }
+
+    #[cfg(all(feature = "parallel", not(target_arch = "wasm32")))]
+    #[test]
+    fn parallel_matches_serial() {
+        let build = || {
+            (0..4)
+                .map(|i| {
+                    let rng = ChainRng::seed_from_u64(derive_chain_seed(42, i));
+                    Chain::with_rng(config(), rng).unwrap()
+                })
+                .collect::<Vec<_>>()
+        };
+        let serial = run_serial(build()).unwrap();
+        let parallel = run_parallel(build()).unwrap();
+        for (s, p) in serial.iter().zip(&parallel) {
+            assert_eq!(s.samples, p.samples);
+            assert_eq!(s.acceptance_rate, p.acceptance_rate);
+        }
+    }
+
+    #[test]
+    fn rhat_is_near_one_for_mixed_chains() {
+        let config = HmcConfig {
+            n_samples: 2000,
+            target: DistType::Normal,
+            compute_diagnostics: true,
+            ..config()
+        };
+        let result = run_hmc_chains(&config, 4, &[]).unwrap();
+        let rhat = result.rhat.clone().unwrap();
+        assert_eq!(rhat, result.rhat().unwrap());
+        assert!((rhat.x - 1.0).abs() < 0.05, "{:?}", rhat);
+        assert!((rhat.y - 1.0).abs() < 0.05, "{:?}", rhat);
+        assert!(result.chains.iter().all(|c| c.diagnostics.is_some()));
+    }
+
+    #[test]
+    fn rhat_detects_chains_stuck_in_different_modes() {
+        let bimodal = Bimodal {
+            scales: [0.5, 0.5],
+            ..Bimodal::default()
+        };
+        let config = HmcConfig {
+            n_samples: 1000,
+            target: DistType::Bimodal(bimodal.clone()),
+            compute_diagnostics: true,
+            ..config()
+        };
+        let starts = [
+            bimodal.centers[0].clone(),
+            bimodal.centers[1].clone(),
+            bimodal.centers[0].clone(),
+            bimodal.centers[1].clone(),
+        ];
+        let rhat = run_hmc_chains(&config, 4, &starts).unwrap().rhat.unwrap();
+        assert!(rhat.x > 1.1 && rhat.y > 1.1, "{:?}", rhat);
+    }
+
+    #[test]
+    fn rhat_rejects_degenerate_chains() {
+        assert_eq!(diagnostics::rhat(&[]), Err(HmcError::ZeroChains));
+        let short = [0.0, 1.0, 2.0];
+        assert_eq!(
+            diagnostics::rhat(&[&short, &[0.0; 10]]),
+            Err(HmcError::ChainTooShort { min: 4, got: 3 })
+        );
+        assert_eq!(
+            diagnostics::rhat(&[&[2.0; 10], &[3.0; 10]]),
+            Err(HmcError::DegenerateChains)
+        );
+        // 1本のチェーンでも前半と後半の2系列で比べられる
+        let drifting: Vec<f64> = (0..100).map(|i| i as f64).collect();
+        assert!(diagnostics::rhat(&[&drifting]).unwrap() > 1.1);
+
+        // 2点ずつしかないチェーンでは求められず、結果にも載らない
+        let config = HmcConfig {
+            n_samples: 2,
+            compute_diagnostics: true,
+            ..config()
+        };
+        let result = run_hmc_chains(&config, 2, &[]).unwrap();
+        assert!(result.rhat.is_none());
+        assert_eq!(
+            result.rhat(),
+            Err(HmcError::ChainTooShort { min: 4, got: 2 })
+        );
+    }
+
+    #[test]
+    fn rejects_mismatched_initial_points() {
+        assert_eq!(
+            run_hmc_chains(&config(), 0, &[]).unwrap_err(),
+            HmcError::ZeroChains
+        );
+        assert_eq!(
+            run_hmc_chains(&config(), 3, &[Point::default()]).unwrap_err(),
+            HmcError::InitialPointCount {
+                expected: 3,
+                got: 1
+            }
+        );
+    }
+}
@@ -0,0 +1,1338 @@
+//! 次元を問わない状態（`&[f64]`）のサンプラー
+//!
+//! 位置・運動量・勾配を長さ `dim` のベクトルで持つ。チェーンは状態の型 [`Vector`] について総称的で、
+//! 2次元の [`Point`] のAPIはその `V = Point` の場合にあたる。N次元のターゲットは同じ [`Chain`] で回すので、
+//! アルゴリズム・質量行列・積分器・適応・記録の設定は2次元と同じように使える。
+//! 2次元のターゲットは [`Planar`] で包めばこちらでも使える（同じ設定・シードなら同じサンプル列になる）。
+//! 次元が小さく固定なら [`PointN`] を使う [`run_hmc_fixed`] で遷移ごとのヒープ確保を避けられる。
+
+use rand::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::bounds::Space;
+use crate::diagnostics::{self, DiagnosticsNd};
+use crate::gradcheck::ensure_gradient_at;
+use crate::numdiff::NumDiff;
+use crate::target::Model;
+use crate::vector;
+use crate::{
+    run_chain, AdaptationWindow, Chain, ChainRng, HmcConfig, HmcError, HmcResult, Metric, Point,
+    PointN, RosenbrockNd, RunHooks, TargetDistribution, Topology, Trajectory, Vector,
+};
+
+/// N次元のターゲット分布（ポテンシャル U(q) = -log p(q) + const）
+pub trait TargetDistributionNd {
+    /// 状態の次元
+    fn dim(&self) -> usize;
+
+    /// ポテンシャルエネルギー U(q)（`q` の長さは `dim`）
+    fn potential(&self, q: &[f64]) -> f64;
+
+    /// ポテンシャルエネルギーの勾配 ∇U(q)（既定は座標ごとの中心差分近似）
+    fn gradient(&self, q: &[f64]) -> Vec<f64> {
+        let numdiff = NumDiff::default();
+        (0..q.len())
+            .map(|i| {
+                let along = |x| {
+                    let mut shifted = q.to_vec();
+                    shifted[i] = x;
+                    self.potential(&shifted)
+                };
+                numdiff.derivative(along, q[i])
+            })
+            .collect()
+    }
+
+    /// 勾配を `out`（長さ `dim`）に書き込む（既定は `gradient` の値を写す）
+    ///
+    /// 積分器はこちらを呼ぶ。上書きすれば [`run_hmc_fixed`] の遷移でヒープを確保しない。
+    fn gradient_into(&self, q: &[f64], out: &mut [f64]) {
+        out.copy_from_slice(&self.gradient(q));
+    }
+
+    /// `gradient` が数値微分ではなく解析的に計算されているか
+    ///
+    /// `true` なら `HmcConfig::numdiff` を指定しても `gradient_into` をそのまま使う。既定は `false`。
+    fn has_analytic_gradient(&self) -> bool {
+        false
+    }
+
+    /// パラメータの検証（既定では何もしない）。サンプリング開始前に呼ばれる。
+    fn validate(&self) -> Result<(), HmcError> {
+        Ok(())
+    }
+}
+
+impl<T: TargetDistributionNd + ?Sized> TargetDistributionNd for &T {
+    fn dim(&self) -> usize {
+        (**self).dim()
+    }
+
+    fn potential(&self, q: &[f64]) -> f64 {
+        (**self).potential(q)
+    }
+
+    fn gradient(&self, q: &[f64]) -> Vec<f64> {
+        (**self).gradient(q)
+    }
+
+    fn gradient_into(&self, q: &[f64], out: &mut [f64]) {
+        (**self).gradient_into(q, out)
+    }
+
+    fn has_analytic_gradient(&self) -> bool {
+        (**self).has_analytic_gradient()
+    }
+
+    fn validate(&self) -> Result<(), HmcError> {
+        (**self).validate()
+    }
+}
+
+/// 2次元のターゲットを `dim == 2` のN次元のターゲットとして使う
+///
+/// ```
+/// use hamiltonian_sampler_rs::{Banana, Planar, Point, TargetDistribution, TargetDistributionNd};
+///
+/// let banana = Banana::default();
+/// let planar = Planar(banana);
+/// assert_eq!(planar.dim(), 2);
+/// assert_eq!(planar.potential(&[0.5, 1.0]), banana.potential(&Point { x: 0.5, y: 1.0 }));
+/// ```
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Planar<T>(pub T);
+
+impl<T: TargetDistribution> TargetDistributionNd for Planar<T> {
+    fn dim(&self) -> usize {
+        2
+    }
+
+    fn potential(&self, q: &[f64]) -> f64 {
+        self.0.potential(&Point { x: q[0], y: q[1] })
+    }
+
+    fn gradient(&self, q: &[f64]) -> Vec<f64> {
+        let g = self.0.gradient(&Point { x: q[0], y: q[1] });
+        vec![g.x, g.y]
+    }
+
+    fn gradient_into(&self, q: &[f64], out: &mut [f64]) {
+        let g = self.0.gradient(&Point { x: q[0], y: q[1] });
+        out.copy_from_slice(&[g.x, g.y]);
+    }
+
+    fn has_analytic_gradient(&self) -> bool {
+        self.0.has_analytic_gradient()
+    }
+
+    fn validate(&self) -> Result<(), HmcError> {
+        self.0.validate()
+    }
+}
+
+/// `dim` 次元の標準正規分布 U = |q|^2 / 2
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct StandardNormalNd {
+    /// 次元（1以上）
+    pub dim: usize,
+}
+
+impl TargetDistributionNd for StandardNormalNd {
+    fn dim(&self) -> usize {
+        self.dim
+    }
+
+    fn potential(&self, q: &[f64]) -> f64 {
+        0.5 * q.iter().map(|x| x * x).sum::<f64>()
+    }
+
+    fn gradient(&self, q: &[f64]) -> Vec<f64> {
+        q.to_vec()
+    }
+
+    fn gradient_into(&self, q: &[f64], out: &mut [f64]) {
+        out.copy_from_slice(q);
+    }
+
+    fn has_analytic_gradient(&self) -> bool {
+        true
+    }
+
+    fn validate(&self) -> Result<(), HmcError> {
+        if self.dim == 0 {
+            return Err(HmcError::InvalidTargetParam {
+                param: "dim".to_string(),
+                value: 0.0,
+            });
+        }
+        Ok(())
+    }
+}
+
+impl TargetDistributionNd for RosenbrockNd {
+    fn dim(&self) -> usize {
+        self.dim
+    }
+
+    fn potential(&self, q: &[f64]) -> f64 {
+        self.potential_nd(q)
+    }
+
+    fn gradient(&self, q: &[f64]) -> Vec<f64> {
+        self.gradient_nd(q)
+    }
+
+    fn gradient_into(&self, q: &[f64], out: &mut [f64]) {
+        self.gradient_nd_into(q, out)
+    }
+
+    fn has_analytic_gradient(&self) -> bool {
+        true
+    }
+
+    fn validate(&self) -> Result<(), HmcError> {
+        self.validate_nd()
+    }
+}
+
+/// 1次元のポテンシャル U(x) の関数を `dim == 1` のターゲットとして使う（勾配は中心差分近似）
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Univariate<F>(pub F);
+
+impl<F: Fn(f64) -> f64> TargetDistributionNd for Univariate<F> {
+    fn dim(&self) -> usize {
+        1
+    }
+
+    fn potential(&self, q: &[f64]) -> f64 {
+        (self.0)(q[0])
+    }
+
+    fn gradient(&self, q: &[f64]) -> Vec<f64> {
+        vec![NumDiff::default().derivative(&self.0, q[0])]
+    }
+
+    fn gradient_into(&self, q: &[f64], out: &mut [f64]) {
+        out[0] = NumDiff::default().derivative(&self.0, q[0]);
+    }
+}
+
+/// 1次元の二重井戸 U(x) = barrier ((x / a)^2 - 1)^2
+///
+/// x = ±a に同じ深さの谷を持ち、その間の x = 0 に高さ `barrier` の山がある。
+/// `barrier` が大きいほど谷の間の行き来が稀になる、多峰性の説明に向いた分布。
+///
+/// ```
+/// use hamiltonian_sampler_rs::{run_hmc_fixed, DoubleWell1, HmcConfig, PointN};
+///
+/// let config = HmcConfig { n_samples: 500, step_size: 0.2, seed: Some(1), ..HmcConfig::default() };
+/// let result = run_hmc_fixed(&config, &DoubleWell1::default(), PointN([1.0])).unwrap();
+/// assert_eq!(result.samples.len(), 500);
+/// ```
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct DoubleWell1 {
+    /// 谷の底から見た x = 0 の山の高さ（非負の有限値）
+    pub barrier: f64,
+    /// 谷の位置（正の有限値）
+    pub a: f64,
+}
+
+impl Default for DoubleWell1 {
+    fn default() -> Self {
+        Self {
+            barrier: 2.0,
+            a: 1.0,
+        }
+    }
+}
+
+impl TargetDistributionNd for DoubleWell1 {
+    fn dim(&self) -> usize {
+        1
+    }
+
+    fn potential(&self, q: &[f64]) -> f64 {
+        let r = (q[0] / self.a).powi(2) - 1.0;
+        self.barrier * r * r
+    }
+
+    fn gradient(&self, q: &[f64]) -> Vec<f64> {
+        let mut out = vec![0.0];
+        self.gradient_into(q, &mut out);
+        out
+    }
+
+    fn gradient_into(&self, q: &[f64], out: &mut [f64]) {
+        let r = (q[0] / self.a).powi(2) - 1.0;
+        out[0] = 4.0 * self.barrier * r * q[0] / (self.a * self.a);
+    }
+
+    fn has_analytic_gradient(&self) -> bool {
+        true
+    }
+
+    fn validate(&self) -> Result<(), HmcError> {
+        if !(self.barrier.is_finite() && self.barrier >= 0.0) {
+            return Err(HmcError::InvalidTargetParam {
+                param: "barrier".to_string(),
+                value: self.barrier,
+            });
+        }
+        if !(self.a.is_finite() && self.a > 0.0) {
+            return Err(HmcError::InvalidTargetParam {
+                param: "a".to_string(),
+                value: self.a,
+            });
+        }
+        Ok(())
+    }
+}
+
+/// [`run_hmc_nd`] の結果
+///
+/// 各フィールドは [`HmcResult`] の同名のフィールドと同じもので、点の列は行優先に1本の列に並べている
+/// （i 番目の点は `[i * dim..(i + 1) * dim]`）。
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct HmcResultNd {
+    /// 状態の次元
+    pub dim: usize,
+    /// ウォームアップ後のサンプル（`Algorithm::Ensemble` では保存する遷移ごとに全ウォーカーの位置を順に並べたもの）
+    pub samples: Vec<f64>,
+    /// サンプリング期間の採択率
+    pub acceptance_rate: f64,
+    /// ウォームアップ期間の採択率（`n_warmup == 0` の場合は0）
+    pub warmup_acceptance_rate: f64,
+    /// ウォームアップ中のサンプル（`save_warmup` 指定時のみ）
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub warmup_samples: Vec<f64>,
+    /// キャンセル・時間切れで打ち切られずに終了したか
+    pub completed: bool,
+    /// サンプリングに要した時間 [秒]
+    pub elapsed_secs: f64,
+    /// 終了時点のチェーンの位置
+    pub final_position: Vec<f64>,
+    /// 最後に計算した座標ごとのESS（`target_ess` 指定時のみ）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub achieved_ess: Option<Vec<f64>>,
+    /// 全座標のESSが `target_ess` に達したか（`target_ess` 指定時のみ）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ess_target_met: Option<bool>,
+    /// 各サンプルを生んだ遷移が採択されたか（`save_accept_flags` 指定時のみ）
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub accepted: Vec<bool>,
+    /// 各サンプルを生んだ遷移のMetropolis採択確率（`save_accept_prob` 指定時のみ）
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub accept_prob: Vec<f64>,
+    /// サンプリング期間の遷移についてのMetropolis採択確率の平均
+    #[serde(default)]
+    pub mean_accept_prob: f64,
+    /// サンプリング期間中の発散した遷移の数
+    pub n_divergent: usize,
+    /// サンプリング期間中に発散したためステップ幅を縮めて遷移をやり直した回数
+    #[serde(default)]
+    pub n_step_size_shrinks: usize,
+    /// サンプリング期間中に行った積分のステップ数
+    #[serde(default)]
+    pub n_leapfrog: usize,
+    /// サンプリング期間の1遷移あたりの平均の積分のステップ数
+    #[serde(default)]
+    pub mean_n_leapfrog: f64,
+    /// サンプリング期間中の積分で勾配を評価した回数（リープフロッグでは `n_leapfrog` と同じ）
+    #[serde(default)]
+    pub n_gradient_evals: usize,
+    /// サンプリング期間中に陰的中点法の反復が収束しなかった回数
+    #[serde(default)]
+    pub n_solver_failures: usize,
+    /// サンプリング期間中に `max_grad_norm` を超えた勾配を縮めた回数
+    #[serde(default)]
+    pub n_gradient_clips: usize,
+    /// サンプリング期間中のNUTSの木の深さの平均（`Algorithm::Nuts` のみ）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mean_tree_depth: Option<f64>,
+    /// サンプリング期間中に木の深さが `max_depth` に達した遷移の数（`Algorithm::Nuts` のみ）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub n_max_tree_depth: Option<usize>,
+    /// サンプリング期間中に軌道が台の外に出て棄却された遷移の数
+    #[serde(default)]
+    pub n_out_of_support: usize,
+    /// サンプリング期間中にNaNなどの数値の破綻で軌道を打ち切った遷移の数
+    #[serde(default)]
+    pub n_numerical_errors: usize,
+    /// 発散した遷移の開始位置（`save_divergences` 指定時のみ）
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub divergent_positions: Vec<f64>,
+    /// 各サンプルのポテンシャル U(q)（`save_energy` 指定時のみ）
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub potential_energy: Vec<f64>,
+    /// 各サンプルを生んだ遷移後の状態のハミルトニアン H = U + K（`save_energy` 指定時のみ）
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub energy: Vec<f64>,
+    /// 各サンプルを生んだ遷移のエネルギー誤差 H_new - H_current（`save_energy` 指定時のみ）
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub energy_errors: Vec<f64>,
+    /// 各サンプルを生んだ遷移で実際に使ったステップ幅（`save_step_sizes` 指定時のみ）
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub step_sizes: Vec<f64>,
+    /// 記録した積分の軌道（`record_trajectory_every` 指定時のみ）
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub trajectories: Vec<Trajectory<Vec<f64>>>,
+    /// 各サンプルを生んだ遷移の軌道の始点の運動量（`save_momentum` 指定時のみ）
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub momenta: Vec<f64>,
+    /// ウォームアップで適応させたステップ幅（`adapt_step_size` 指定時のみ）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub adapted_step_size: Option<f64>,
+    /// サンプリング期間に使った質量行列（推定しなければ `HmcConfig::metric` そのもの）
+    #[serde(default)]
+    pub metric: Metric,
+    /// `HmcConfig::warmup_schedule` の各区間を終えた時点のステップ幅と質量行列（指定時のみ）
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub adaptation_windows: Vec<AdaptationWindow>,
+    /// `InitStrategy::FindMode` で見つけた開始位置（それ以外では `None`）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub init_mode: Option<Vec<f64>>,
+    /// `init_mode` でのポテンシャル
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub init_potential: Option<f64>,
+    /// `HmcConfig::mode_centers_nd` の各中心に最も近いサンプルの割合（指定時のみ）
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub mode_occupancy: Vec<f64>,
+    /// 連続するサンプルの間で最も近い中心が変わった回数（`mode_centers_nd` 指定時のみ）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mode_switches: Option<usize>,
+    /// 座標ごとの収束診断（`compute_diagnostics` 指定時のみ）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub diagnostics: Option<DiagnosticsNd>,
+    /// サンプリング期間のウォーカーごとの採択率（`Algorithm::Ensemble` のみ）
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub walker_acceptance_rates: Vec<f64>,
+    /// 隣り合う温度のレプリカの組ごとの交換の採択率（`Algorithm::ParallelTempering` のみ）
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub swap_acceptance_rates: Vec<f64>,
+    /// このチェーンのRNGシード
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub seed: Option<u64>,
+}
+
+impl HmcResultNd {
+    /// サンプル数
+    pub fn n_samples(&self) -> usize {
+        self.samples.len() / self.dim.max(1)
+    }
+
+    /// `i` 番目のサンプル
+    pub fn sample(&self, i: usize) -> &[f64] {
+        &self.samples[i * self.dim..(i + 1) * self.dim]
+    }
+
+    /// 座標 `j` の値の列
+    pub fn coordinate(&self, j: usize) -> Vec<f64> {
+        self.samples.chunks_exact(self.dim).map(|q| q[j]).collect()
+    }
+}
+
+/// 点の列を行優先の1本の列にする
+fn flatten<V: Vector>(points: &[V]) -> Vec<f64> {
+    let mut out = Vec::with_capacity(points.iter().map(|q| q.dim()).sum());
+    for q in points {
+        out.extend_from_slice(q.as_slice());
+    }
+    out
+}
+
+impl<V: Vector> From<HmcResult<V>> for HmcResultNd {
+    fn from(result: HmcResult<V>) -> Self {
+        let to_vec = |q: V| q.as_slice().to_vec();
+        Self {
+            dim: result.dim,
+            samples: flatten(&result.samples),
+            acceptance_rate: result.acceptance_rate,
+            warmup_acceptance_rate: result.warmup_acceptance_rate,
+            warmup_samples: flatten(&result.warmup_samples),
+            completed: result.completed,
+            elapsed_secs: result.elapsed_secs,
+            final_position: to_vec(result.final_position),
+            achieved_ess: result.achieved_ess.map(to_vec),
+            ess_target_met: result.ess_target_met,
+            accepted: result.accepted,
+            accept_prob: result.accept_prob,
+            mean_accept_prob: result.mean_accept_prob,
+            n_divergent: result.n_divergent,
+            n_step_size_shrinks: result.n_step_size_shrinks,
+            n_leapfrog: result.n_leapfrog,
+            mean_n_leapfrog: result.mean_n_leapfrog,
+            n_gradient_evals: result.n_gradient_evals,
+            n_solver_failures: result.n_solver_failures,
+            n_gradient_clips: result.n_gradient_clips,
+            mean_tree_depth: result.mean_tree_depth,
+            n_max_tree_depth: result.n_max_tree_depth,
+            n_out_of_support: result.n_out_of_support,
+            n_numerical_errors: result.n_numerical_errors,
+            divergent_positions: flatten(&result.divergent_positions),
+            potential_energy: result.potential_energy,
+            energy: result.energy,
+            energy_errors: result.energy_errors,
+            step_sizes: result.step_sizes,
+            trajectories: result
+                .trajectories
+                .into_iter()
+                .map(|t| Trajectory {
+                    sample_index: t.sample_index,
+                    positions: t.positions.into_iter().map(to_vec).collect(),
+                    energies: t.energies,
+                    accepted: t.accepted,
+                })
+                .collect(),
+            momenta: flatten(&result.momenta),
+            adapted_step_size: result.adapted_step_size,
+            metric: result.metric,
+            adaptation_windows: result.adaptation_windows,
+            init_mode: result.init_mode.map(to_vec),
+            init_potential: result.init_potential,
+            mode_occupancy: result.mode_occupancy,
+            mode_switches: result.mode_switches,
+            diagnostics: None,
+            walker_acceptance_rates: result.walker_acceptance_rates,
+            swap_acceptance_rates: result.swap_acceptance_rates,
+            seed: result.seed,
+        }
+    }
+}
+
+/// N次元のターゲットを2次元のチェーンと同じ [`Chain`] に載せるための包み
+pub(crate) struct Nd<T>(pub(crate) T);
+
+impl<T: TargetDistributionNd, V: Vector> Model<V> for Nd<T> {
+    fn potential_at(&self, q: &V) -> f64 {
+        self.0.potential(q.as_slice())
+    }
+
+    fn gradient_at(&self, q: &V) -> V {
+        let mut g = q.clone();
+        self.0.gradient_into(q.as_slice(), g.as_mut_slice());
+        g
+    }
+
+    fn has_exact_gradient(&self) -> bool {
+        self.0.has_analytic_gradient()
+    }
+}
+
+/// N次元の状態について設定と開始位置を検証する（`HmcConfig::validate_for` のN次元版）
+///
+/// 2次元の状態だけに当てはまる `transform`・`bounds`・`topology`・`resume_from`・`mode_centers` は
+/// [`HmcError::SettingDimension`] で拒否し、代わりに `_nd` の付いたフィールドを使う。
+fn validate_nd<T>(config: &HmcConfig, target: &T, initial: &[f64]) -> Result<(), HmcError>
+where
+    T: TargetDistributionNd + ?Sized,
+{
+    let dim = target.dim();
+    let planar_settings = [
+        ("transform", !config.transform.is_identity()),
+        ("bounds", config.bounds.is_some()),
+        ("topology", config.topology != Topology::Euclidean),
+        ("resume_from", config.resume_from.is_some()),
+        ("mode_centers", !config.mode_centers.is_empty()),
+    ];
+    if let Some(&(setting, _)) = planar_settings.iter().find(|(_, used)| *used) {
+        return Err(HmcError::SettingDimension { setting, dim });
+    }
+    target.validate()?;
+    if initial.len() != dim {
+        return Err(HmcError::DimensionMismatch {
+            expected: dim,
+            got: initial.len(),
+        });
+    }
+    config.validate_settings(dim)?;
+    let lengths = [
+        (
+            "transform_nd",
+            Some(config.transform_nd.len()).filter(|&n| n > 0),
+        ),
+        ("bounds_nd", config.bounds_nd.as_ref().map(Vec::len)),
+        ("periods_nd", config.periods_nd.as_ref().map(Vec::len)),
+    ]
+    .into_iter()
+    .chain(
+        config
+            .mode_centers_nd
+            .iter()
+            .map(|center| ("mode_centers_nd", Some(center.len()))),
+    );
+    for (setting, len) in lengths {
+        match len {
+            Some(got) if got != dim => {
+                return Err(HmcError::SettingLength {
+                    setting,
+                    expected: dim,
+                    got,
+                });
+            }
+            _ => {}
+        }
+    }
+    for transform in &config.transform_nd {
+        transform.validate()?;
+    }
+    for &(min, max) in config.bounds_nd.iter().flatten() {
+        if !(min.is_finite() && max.is_finite() && min < max) {
+            return Err(HmcError::InvalidBounds { min, max });
+        }
+    }
+    for &period in config.periods_nd.iter().flatten() {
+        if !(period.is_finite() && period > 0.0) {
+            return Err(HmcError::InvalidPeriod(period));
+        }
+    }
+
+    if !initial.iter().all(|x| x.is_finite()) {
+        return Err(HmcError::NonFiniteInitialStateNd {
+            position: initial.to_vec(),
+            potential: target.potential(initial),
+        });
+    }
+    let space = Space::nd(config);
+    let z = space.to_unconstrained(&initial.to_vec());
+    if !z.iter().all(|z| z.is_finite()) {
+        return Err(HmcError::OutsideTransformDomainNd {
+            position: initial.to_vec(),
+        });
+    }
+    if !space.contains(&z) {
+        return Err(HmcError::OutsideBoundsNd {
+            position: initial.to_vec(),
+        });
+    }
+    let potential = target.potential(initial);
+    if !potential.is_finite() {
+        return Err(HmcError::NonFiniteInitialStateNd {
+            position: initial.to_vec(),
+            potential,
+        });
+    }
+    Ok(())
+}
+
+/// `target` から `initial` を開始位置としてサンプリングする
+///
+/// 2次元の [`run_hmc`](crate::run_hmc) と同じチェーンを使い、`config` のアルゴリズム・質量行列・積分器・
+/// ウォームアップ・記録などの設定はそのまま効く。2次元の状態に関する `target`・`initial_pos`・`resume_from`・
+/// `transform`・`bounds`・`topology`・`mode_centers` の代わりに、`transform_nd`・`bounds_nd`・`periods_nd`・
+/// `mode_centers_nd` を使う。`flat_samples` は無視する（`samples` は常に1本の列）。
+///
+/// ```
+/// use hamiltonian_sampler_rs::{run_hmc_nd, HmcConfig, StandardNormalNd};
+///
+/// let config = HmcConfig { n_samples: 200, seed: Some(1), ..HmcConfig::default() };
+/// let result = run_hmc_nd(&config, &StandardNormalNd { dim: 3 }, &[0.0; 3]).unwrap();
+/// assert_eq!(result.dim, 3);
+/// assert_eq!(result.n_samples(), 200);
+/// assert_eq!(result.sample(199).len(), 3);
+/// ```
+pub fn run_hmc_nd<T>(
+    config: &HmcConfig,
+    target: &T,
+    initial: &[f64],
+) -> Result<HmcResultNd, HmcError>
+where
+    T: TargetDistributionNd + ?Sized,
+{
+    sample(config, target, initial.to_vec())
+}
+
+/// [`run_hmc_nd`] の固定長の版。状態を [`PointN`] で持ち、遷移ごとにはヒープを確保しない
+///
+/// 同じ設定・シードなら [`run_hmc_nd`] と同じサンプル列になる。遷移の外では結果の `samples` などを
+/// 確保するほか、質量行列を推定する区間の終わりにも確保する。ターゲットの `gradient_into` が確保するものと、
+/// NUTSの木や軌道の記録のように設定で有効にした記録が確保するものは避けられない。
+///
+/// ```
+/// use hamiltonian_sampler_rs::{run_hmc_fixed, HmcConfig, PointN, StandardNormalNd};
+///
+/// let config = HmcConfig { n_samples: 200, seed: Some(1), ..HmcConfig::default() };
+/// let result = run_hmc_fixed(&config, &StandardNormalNd { dim: 3 }, PointN([0.0; 3])).unwrap();
+/// assert_eq!(result.dim, 3);
+/// assert_eq!(result.n_samples(), 200);
+/// ```
+pub fn run_hmc_fixed<const D: usize, T>(
+    config: &HmcConfig,
+    target: &T,
+    initial: PointN<D>,
+) -> Result<HmcResultNd, HmcError>
+where
+    T: TargetDistributionNd + ?Sized,
+{
+    sample(config, target, initial)
+}
+
+/// 1次元のポテンシャル `potential` から `initial` を開始位置としてサンプリングする
+///
+/// [`Univariate`] で包んで [`run_hmc_fixed`] に渡すのと同じ。結果の `dim` は1で、`samples` がそのまま各サンプルの値の列になる。
+/// 勾配は中心差分で求めるので、解析的な勾配を使うには [`TargetDistributionNd`] を実装して `run_hmc_fixed` に渡す。
+///
+/// ```
+/// use hamiltonian_sampler_rs::{run_hmc_1d, HmcConfig};
+///
+/// let config = HmcConfig { n_samples: 1000, step_size: 0.3, num_steps: 10, seed: Some(1), ..HmcConfig::default() };
+/// let result = run_hmc_1d(&config, |x| 0.5 * x * x, 0.0).unwrap();
+/// assert_eq!(result.dim, 1);
+/// let mean = result.samples.iter().sum::<f64>() / 1000.0;
+/// assert!(mean.abs() < 0.2);
+/// ```
+pub fn run_hmc_1d<F>(
+    config: &HmcConfig,
+    potential: F,
+    initial: f64,
+) -> Result<HmcResultNd, HmcError>
+where
+    F: Fn(f64) -> f64,
+{
+    run_hmc_fixed(config, &Univariate(potential), PointN([initial]))
+}
+
+/// 状態を `V` で持つチェーンでサンプリングする
+fn sample<T, V>(config: &HmcConfig, target: &T, initial: V) -> Result<HmcResultNd, HmcError>
+where
+    T: TargetDistributionNd + ?Sized,
+    V: Vector,
+{
+    validate_nd(config, target, initial.as_slice())?;
+    let model = Nd(target);
+    if config.debug_check_gradient {
+        ensure_gradient_at(&model, &initial)?;
+    }
+    let rng = match config.seed {
+        Some(seed) => ChainRng::seed_from_u64(seed),
+        None => ChainRng::from_entropy(),
+    };
+    let centers: Vec<V> = config
+        .mode_centers_nd
+        .iter()
+        .map(|center| vector::from_slice(&initial, center))
+        .collect();
+    let space = Space::nd(config);
+    let mut chain = Chain::build(config.clone(), model, rng, initial, space)?;
+    let (mut result, diverged_at) =
+        run_chain(&mut chain, &mut RunHooks::default(), Vec::new(), None)?;
+    if !centers.is_empty() {
+        result.mode_occupancy = diagnostics::mode_occupancy(&result.samples, &centers);
+        let n_walkers = result.walker_acceptance_rates.len().max(1);
+        result.mode_switches = Some(
+            (0..n_walkers)
+                .map(|k| {
+                    let walker: Vec<V> = result
+                        .samples
+                        .iter()
+                        .skip(k)
+                        .step_by(n_walkers)
+                        .cloned()
+                        .collect();
+                    diagnostics::mode_switches(&walker, &centers)
+                })
+                .sum(),
+        );
+    }
+    let mut result = HmcResultNd::from(result);
+    if config.compute_diagnostics {
+        result.diagnostics = Some(DiagnosticsNd::new(&result));
+    }
+    match diverged_at {
+        Some(iteration) => Err(HmcError::DivergedNd {
+            iteration,
+            partial: Box::new(result),
+        }),
+        None => Ok(result),
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::{
+        run_hmc_with_target, Algorithm, Banana, BoundingBox, DivergencePolicy, StandardNormal2,
+        Transform,
+    };
+    use std::alloc::{GlobalAlloc, Layout};
+    use std::cell::Cell;
+
+    thread_local! {
+        static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+    }
+
+    /// スレッドごとにヒープ確保の回数を数えるアロケータ（テストは並列に走るため）
+    struct CountingAllocator;
+
+    unsafe impl GlobalAlloc for CountingAllocator {
+        unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+            let _ = ALLOCATIONS.try_with(|n| n.set(n.get() + 1));
+            std::alloc::System.alloc(layout)
+        }
+
+        unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+            std::alloc::System.dealloc(ptr, layout)
+        }
+    }
+
+    #[global_allocator]
+    static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+    /// `f` の実行中にこのスレッドで行ったヒープ確保の回数
+    pub(crate) fn count_allocations(f: impl FnOnce()) -> usize {
+        let before = ALLOCATIONS.with(Cell::get);
+        f();
+        ALLOCATIONS.with(Cell::get) - before
+    }
+
+    #[test]
+    fn fixed_size_path_does_not_allocate_per_transition() {
+        let target = StandardNormalNd { dim: 3 };
+        let config = |n_samples| HmcConfig {
+            n_samples,
+            n_warmup: 200,
+            adapt_step_size: true,
+            adapt_mass_matrix: true,
+            seed: Some(86),
+            ..HmcConfig::default()
+        };
+        let fixed = |n_samples| {
+            count_allocations(|| {
+                run_hmc_fixed(&config(n_samples), &target, PointN([0.5; 3])).unwrap();
+            })
+        };
+        let dynamic = |n_samples| {
+            count_allocations(|| {
+                run_hmc_nd(&config(n_samples), &target, &[0.5; 3]).unwrap();
+            })
+        };
+        // 確保の回数はサンプル数によらない
+        assert_eq!(fixed(100), fixed(2000));
+        // Vec<f64> の経路は遷移ごとに確保する
+        assert!(dynamic(2000) > dynamic(100) + 1900);
+
+        let a = run_hmc_fixed(&config(500), &target, PointN([0.5; 3])).unwrap();
+        let b = run_hmc_nd(&config(500), &target, &[0.5; 3]).unwrap();
+        assert_eq!(a.samples, b.samples);
+        assert_eq!(a.metric, b.metric);
+    }
+
+    #[test]
+    fn ten_dimensional_standard_normal_has_unit_variances() {
+        let config = HmcConfig {
+            n_samples: 5000,
+            n_warmup: 500,
+            num_steps: 10,
+            step_size: 0.3,
+            adapt_step_size: true,
+            adapt_mass_matrix: true,
+            seed: Some(85),
+            ..HmcConfig::default()
+        };
+        let result = run_hmc_nd(&config, &StandardNormalNd { dim: 10 }, &[1.0; 10]).unwrap();
+        assert_eq!(result.dim, 10);
+        assert_eq!(result.n_samples(), 5000);
+        assert!(result.acceptance_rate > 0.6, "{}", result.acceptance_rate);
+        assert_eq!(result.n_divergent, 0);
+        for j in 0..10 {
+            let xs = result.coordinate(j);
+            let n = xs.len() as f64;
+            let mean = xs.iter().sum::<f64>() / n;
+            let var = xs.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / (n - 1.0);
+            assert!(mean.abs() < 0.1, "coordinate {}: mean {}", j, mean);
+            assert!(
+                (var - 1.0).abs() < 0.1,
+                "coordinate {}: variance {}",
+                j,
+                var
+            );
+            // 推定した M⁻¹ も分散に近い
+            let Metric::Diagonal(inv_mass) = &result.metric else {
+                panic!("{:?}", result.metric);
+            };
+            assert!((inv_mass[j] - 1.0).abs() < 0.3, "{:?}", inv_mass);
+        }
+    }
+
+    #[test]
+    fn planar_target_reproduces_two_dimensional_sampler() {
+        let config = HmcConfig {
+            n_samples: 300,
+            n_warmup: 200,
+            thin: 2,
+            adapt_step_size: true,
+            adapt_mass_matrix: true,
+            randomize_steps: true,
+            step_size_jitter: 0.2,
+            initial_pos: Point { x: 0.5, y: 1.0 },
+            seed: Some(7),
+            ..HmcConfig::default()
+        };
+        for_both(&config, Banana::default());
+        for_both(&config, StandardNormal2);
+
+        fn for_both<T: TargetDistribution + Copy>(config: &HmcConfig, target: T) {
+            let two = run_hmc_with_target(config, target).unwrap();
+            let nd = run_hmc_nd(config, &Planar(target), &[0.5, 1.0]).unwrap();
+            let flat: Vec<f64> = two.samples.iter().flat_map(|p| [p.x, p.y]).collect();
+            assert_eq!(nd.samples, flat);
+            assert_eq!(nd.acceptance_rate, two.acceptance_rate);
+            assert_eq!(nd.adapted_step_size, two.adapted_step_size);
+            assert_eq!(nd.n_leapfrog, two.n_leapfrog);
+            assert_eq!(nd.n_gradient_evals, two.n_gradient_evals);
+        }
+    }
+
+    #[test]
+    fn rosenbrock_uses_its_slice_potential_in_any_dimension() {
+        let target = RosenbrockNd {
+            dim: 5,
+            a: 1.0,
+            b: 2.0,
+        };
+        // 勾配を実装しないターゲットは中心差分になる
+        struct PotentialOnly(RosenbrockNd);
+        impl TargetDistributionNd for PotentialOnly {
+            fn dim(&self) -> usize {
+                self.0.dim
+            }
+
+            fn potential(&self, q: &[f64]) -> f64 {
+                self.0.potential_nd(q)
+            }
+        }
+        let q = [0.3, -0.2, 0.8, 1.1, 0.4];
+        let numerical = PotentialOnly(target).gradient(&q);
+        for (a, n) in target.gradient_nd(&q).iter().zip(&numerical) {
+            assert!((a - n).abs() < 1e-6, "{} {}", a, n);
+        }
+        let config = HmcConfig {
+            n_samples: 200,
+            step_size: 0.05,
+            seed: Some(3),
+            ..HmcConfig::default()
+        };
+        let result = run_hmc_nd(&config, &target, &[1.0; 5]).unwrap();
+        assert_eq!(result.final_position.len(), 5);
+        assert!(result.acceptance_rate > 0.5, "{}", result.acceptance_rate);
+    }
+
+    #[test]
+    fn double_well_matches_quadrature() {
+        let well = DoubleWell1 {
+            barrier: 1.0,
+            a: 1.0,
+        };
+        // Simpson法で正規化定数 Z = ∫ exp(-U) dx と、それで割った期待値を求める
+        let (n, lo, hi) = (4000, -4.0, 4.0);
+        let h = (hi - lo) / n as f64;
+        let simpson = |f: &dyn Fn(f64) -> f64| {
+            let sum: f64 = (0..=n)
+                .map(|i| {
+                    let w = match i {
+                        0 => 1.0,
+                        i if i == n => 1.0,
+                        i if i % 2 == 1 => 4.0,
+                        _ => 2.0,
+                    };
+                    w * f(lo + i as f64 * h)
+                })
+                .sum();
+            sum * h / 3.0
+        };
+        let density = |x: f64| (-well.potential(&[x])).exp();
+        let z = simpson(&density);
+        let second_moment = simpson(&|x| x * x * density(x)) / z;
+        let in_right_well = simpson(&|x| {
+            if (0.5..1.5).contains(&x) {
+                density(x)
+            } else {
+                0.0
+            }
+        }) / z;
+
+        let config = HmcConfig {
+            n_samples: 20_000,
+            n_warmup: 500,
+            step_size: 0.2,
+            num_steps: 10,
+            adapt_step_size: true,
+            seed: Some(87),
+            ..HmcConfig::default()
+        };
+        let analytic = run_hmc_fixed(&config, &well, PointN([1.0])).unwrap();
+        let closure = run_hmc_1d(&config, |x| well.potential(&[x]), 1.0).unwrap();
+        for result in [analytic, closure] {
+            assert_eq!(result.dim, 1);
+            let xs = &result.samples;
+            let n = xs.len() as f64;
+            let fraction =
+                |f: &dyn Fn(f64) -> bool| xs.iter().filter(|&&x| f(x)).count() as f64 / n;
+            let m2 = xs.iter().map(|x| x * x).sum::<f64>() / n;
+            assert!(
+                (m2 - second_moment).abs() < 0.05 * second_moment,
+                "{} {}",
+                m2,
+                second_moment
+            );
+            let frac = fraction(&|x| (0.5..1.5).contains(&x));
+            assert!(
+                (frac - in_right_well).abs() < 0.03,
+                "{} {}",
+                frac,
+                in_right_well
+            );
+            // 二つの谷を同じ割合で訪れる
+            assert!((fraction(&|x| x > 0.0) - 0.5).abs() < 0.05);
+        }
+
+        assert!(matches!(
+            run_hmc_fixed(
+                &config,
+                &DoubleWell1 {
+                    barrier: 1.0,
+                    a: 0.0
+                },
+                PointN([1.0])
+            ),
+            Err(HmcError::InvalidTargetParam { .. })
+        ));
+    }
+
+    /// 全ての対の相関が `rho` の `dim` 次元の正規分布（分散は1）
+    struct Equicorrelated {
+        dim: usize,
+        rho: f64,
+    }
+
+    impl Equicorrelated {
+        fn covariance(&self, i: usize, j: usize) -> f64 {
+            if i == j {
+                1.0
+            } else {
+                self.rho
+            }
+        }
+
+        /// Σ⁻¹ = (I - c 11ᵀ) / (1 - ρ)、c = ρ / (1 + (d - 1) ρ)
+        fn precision(&self, i: usize, j: usize) -> f64 {
+            let c = self.rho / (1.0 + (self.dim - 1) as f64 * self.rho);
+            let delta = if i == j { 1.0 } else { 0.0 };
+            (delta - c) / (1.0 - self.rho)
+        }
+    }
+
+    impl TargetDistributionNd for Equicorrelated {
+        fn dim(&self) -> usize {
+            self.dim
+        }
+
+        fn potential(&self, q: &[f64]) -> f64 {
+            let mut g = vec![0.0; self.dim];
+            self.gradient_into(q, &mut g);
+            0.5 * q.iter().zip(&g).map(|(q, g)| q * g).sum::<f64>()
+        }
+
+        fn gradient_into(&self, q: &[f64], out: &mut [f64]) {
+            let c = self.rho / (1.0 + (self.dim - 1) as f64 * self.rho);
+            let sum: f64 = q.iter().sum();
+            for (out, q) in out.iter_mut().zip(q) {
+                *out = (q - c * sum) / (1.0 - self.rho);
+            }
+        }
+
+        fn has_analytic_gradient(&self) -> bool {
+            true
+        }
+    }
+
+    #[test]
+    fn nuts_with_dense_metric_recovers_correlated_gaussian() {
+        let target = Equicorrelated { dim: 4, rho: 0.8 };
+        let config = HmcConfig {
+            n_samples: 4000,
+            n_warmup: 1000,
+            adapt_step_size: true,
+            metric: Metric::AdaptDense,
+            algorithm: Algorithm::Nuts { max_depth: 10 },
+            seed: Some(85),
+            ..HmcConfig::default()
+        };
+        let result = run_hmc_fixed(&config, &target, PointN([0.5; 4])).unwrap();
+        assert_eq!(result.n_samples(), 4000);
+        assert!(result.mean_tree_depth.is_some());
+        assert_eq!(result.n_divergent, 0);
+        let Metric::Dense(mass) = &result.metric else {
+            panic!("{:?}", result.metric);
+        };
+        let columns: Vec<Vec<f64>> = (0..4).map(|j| result.coordinate(j)).collect();
+        let n = columns[0].len() as f64;
+        let means: Vec<f64> = columns
+            .iter()
+            .map(|xs| xs.iter().sum::<f64>() / n)
+            .collect();
+        for i in 0..4 {
+            assert!(means[i].abs() < 0.15, "{:?}", means);
+            for j in 0..4 {
+                let sample_cov = columns[i]
+                    .iter()
+                    .zip(&columns[j])
+                    .map(|(a, b)| (a - means[i]) * (b - means[j]))
+                    .sum::<f64>()
+                    / (n - 1.0);
+                let truth = target.covariance(i, j);
+                assert!(
+                    (sample_cov - truth).abs() < 0.15,
+                    "{} {} {}",
+                    i,
+                    j,
+                    sample_cov
+                );
+                // 推定した質量行列 M も精度行列 Σ⁻¹ に近い
+                assert!(
+                    (mass[i][j] - target.precision(i, j)).abs() < 0.5,
+                    "{:?}",
+                    mass
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn transforms_bounds_and_periods_apply_per_coordinate() {
+        let config = HmcConfig {
+            n_samples: 20_000,
+            n_warmup: 500,
+            step_size: 0.2,
+            num_steps: 10,
+            adapt_step_size: true,
+            seed: Some(85),
+            ..HmcConfig::default()
+        };
+        let mean = |xs: &[f64]| xs.iter().sum::<f64>() / xs.len() as f64;
+
+        // 1番目の座標は指数分布 Exp(1)、2番目は標準正規分布
+        struct ExpNormal;
+        impl TargetDistributionNd for ExpNormal {
+            fn dim(&self) -> usize {
+                2
+            }
+
+            fn potential(&self, q: &[f64]) -> f64 {
+                if q[0] > 0.0 {
+                    q[0] + 0.5 * q[1] * q[1]
+                } else {
+                    f64::INFINITY
+                }
+            }
+        }
+        let positive = HmcConfig {
+            transform_nd: vec![Transform::Log, Transform::Identity],
+            ..config.clone()
+        };
+        let result = run_hmc_nd(&positive, &ExpNormal, &[1.0, 0.0]).unwrap();
+        let xs = result.coordinate(0);
+        assert!(xs.iter().all(|&x| x > 0.0));
+        assert!((mean(&xs) - 1.0).abs() < 0.05, "{}", mean(&xs));
+        assert!(mean(&result.coordinate(1)).abs() < 0.05);
+
+        // 一様分布を反射境界の中で（全て採択されるので、ステップ幅は適応させない）
+        let bounded = HmcConfig {
+            bounds_nd: Some(vec![(0.0, 1.0), (-1.0, 2.0)]),
+            adapt_step_size: false,
+            ..config.clone()
+        };
+        struct Flat;
+        impl TargetDistributionNd for Flat {
+            fn dim(&self) -> usize {
+                2
+            }
+
+            fn potential(&self, _: &[f64]) -> f64 {
+                0.0
+            }
+
+            fn gradient_into(&self, _: &[f64], out: &mut [f64]) {
+                out.fill(0.0);
+            }
+        }
+        let result = run_hmc_nd(&bounded, &Flat, &[0.5, 0.5]).unwrap();
+        for (j, (min, max)) in [(0.0, 1.0), (-1.0, 2.0)].into_iter().enumerate() {
+            let xs = result.coordinate(j);
+            assert!(xs.iter().all(|x| (min..=max).contains(x)));
+            assert!((mean(&xs) - 0.5 * (min + max)).abs() < 0.05 * (max - min));
+        }
+
+        // von Mises分布 U = -κ cos x（κ = 1）を周期 2π の円周上で
+        let circle = HmcConfig {
+            periods_nd: Some(vec![std::f64::consts::TAU]),
+            ..config
+        };
+        let result = run_hmc_1d(&circle, |x| -x.cos(), 0.0).unwrap();
+        assert!(result
+            .samples
+            .iter()
+            .all(|x| (0.0..std::f64::consts::TAU).contains(x)));
+        // E[cos x] = I₁(1) / I₀(1)
+        let cos = mean(&result.samples.iter().map(|x| x.cos()).collect::<Vec<_>>());
+        assert!((cos - 0.446_390_6).abs() < 0.02, "{}", cos);
+    }
+
+    #[test]
+    fn temperature_ensemble_and_mode_records_work_in_any_dimension() {
+        let target = StandardNormalNd { dim: 3 };
+        let tempered = HmcConfig {
+            n_samples: 5000,
+            step_size: 0.3,
+            num_steps: 10,
+            temperature: 2.0,
+            seed: Some(85),
+            ..HmcConfig::default()
+        };
+        let result = run_hmc_nd(&tempered, &target, &[0.0; 3]).unwrap();
+        for j in 0..3 {
+            let xs = result.coordinate(j);
+            let var = xs.iter().map(|x| x * x).sum::<f64>() / xs.len() as f64;
+            assert!((var - 2.0).abs() < 0.2, "{} {}", j, var);
+        }
+
+        let ensemble = HmcConfig {
+            n_samples: 2000,
+            n_warmup: 200,
+            algorithm: Algorithm::Ensemble {
+                n_walkers: 8,
+                a: 2.0,
+            },
+            seed: Some(85),
+            ..HmcConfig::default()
+        };
+        let result = run_hmc_nd(&ensemble, &target, &[0.0; 3]).unwrap();
+        assert_eq!(result.n_samples(), 2000 * 8);
+        assert_eq!(result.walker_acceptance_rates.len(), 8);
+        let var = result.samples.iter().map(|x| x * x).sum::<f64>() / result.samples.len() as f64;
+        assert!((var - 1.0).abs() < 0.15, "{}", var);
+        let too_few = HmcConfig {
+            algorithm: Algorithm::Ensemble {
+                n_walkers: 5,
+                a: 2.0,
+            },
+            ..ensemble
+        };
+        assert_eq!(
+            run_hmc_nd(&too_few, &target, &[0.0; 3]).unwrap_err(),
+            HmcError::TooFewWalkers(5)
+        );
+
+        let modes = HmcConfig {
+            n_samples: 5000,
+            step_size: 0.2,
+            num_steps: 10,
+            mode_centers_nd: vec![vec![-1.0], vec![1.0]],
+            compute_diagnostics: true,
+            seed: Some(85),
+            ..HmcConfig::default()
+        };
+        let result = run_hmc_fixed(&modes, &DoubleWell1::default(), PointN([1.0])).unwrap();
+        assert_eq!(result.mode_occupancy.len(), 2);
+        assert!(
+            (result.mode_occupancy[0] - 0.5).abs() < 0.1,
+            "{:?}",
+            result.mode_occupancy
+        );
+        assert!(result.mode_switches.unwrap() > 0);
+        let diagnostics = result.diagnostics.unwrap();
+        assert_eq!(diagnostics.ess.len(), 1);
+        assert!(diagnostics.ess[0] > 100.0, "{:?}", diagnostics.ess);
+    }
+
+    #[test]
+    fn rejects_two_dimensional_settings_and_wrong_lengths() {
+        let target = StandardNormalNd { dim: 3 };
+        let run = |config: &HmcConfig, initial: &[f64]| run_hmc_nd(config, &target, initial);
+        let planar_bounds = HmcConfig {
+            bounds: Some(BoundingBox {
+                xmin: -1.0,
+                xmax: 1.0,
+                ymin: -1.0,
+                ymax: 1.0,
+            }),
+            ..HmcConfig::default()
+        };
+        assert_eq!(
+            run(&planar_bounds, &[0.0; 3]).unwrap_err(),
+            HmcError::SettingDimension {
+                setting: "bounds",
+                dim: 3
+            }
+        );
+        let short = HmcConfig {
+            transform_nd: vec![Transform::Log; 2],
+            ..HmcConfig::default()
+        };
+        assert_eq!(
+            run(&short, &[1.0; 3]).unwrap_err(),
+            HmcError::SettingLength {
+                setting: "transform_nd",
+                expected: 3,
+                got: 2
+            }
+        );
+        let positive = HmcConfig {
+            transform_nd: vec![Transform::Log; 3],
+            ..HmcConfig::default()
+        };
+        assert!(matches!(
+            run(&positive, &[1.0, -1.0, 1.0]),
+            Err(HmcError::OutsideTransformDomainNd { .. })
+        ));
+        let boxed = HmcConfig {
+            bounds_nd: Some(vec![(-1.0, 1.0); 3]),
+            ..HmcConfig::default()
+        };
+        assert!(matches!(
+            run(&boxed, &[0.0, 2.0, 0.0]),
+            Err(HmcError::OutsideBoundsNd { .. })
+        ));
+        // 2次元の設定も N次元の設定も、相手の次元では使えない
+        let nd_on_planar = HmcConfig {
+            periods_nd: Some(vec![1.0; 2]),
+            ..HmcConfig::default()
+        };
+        assert_eq!(
+            crate::run_hmc(&nd_on_planar).unwrap_err(),
+            HmcError::SettingDimension {
+                setting: "periods_nd",
+                dim: 2
+            }
+        );
+
+        let config = HmcConfig::default();
+        assert_eq!(
+            run(&config, &[0.0; 2]).unwrap_err(),
+            HmcError::DimensionMismatch {
+                expected: 3,
+                got: 2
+            }
+        );
+        assert!(matches!(
+            run(&config, &[0.0, f64::NAN, 0.0]),
+            Err(HmcError::NonFiniteInitialStateNd { .. })
+        ));
+        assert!(matches!(
+            run_hmc_nd(&config, &StandardNormalNd { dim: 0 }, &[]),
+            Err(HmcError::InvalidTargetParam { .. })
+        ));
+
+        let abort = HmcConfig {
+            step_size: 10.0,
+            on_divergence: DivergencePolicy::Abort,
+            seed: Some(85),
+            ..HmcConfig::default()
+        };
+        let Err(HmcError::DivergedNd { iteration, partial }) = run(&abort, &[0.0; 3]) else {
+            panic!("expected a divergence");
+        };
+        assert_eq!(partial.n_samples(), iteration + 1);
+        assert!(!partial.completed);
+    }
+}
@@ -0,0 +1,206 @@
+//! ポテンシャルの数値微分
+
+use serde::{Deserialize, Serialize};
+
+use crate::{HmcError, Point, TargetDistribution, Vector};
+
+/// 差分の取り方
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum DiffScheme {
+    /// 中心差分 (f(x+h) - f(x-h)) / 2h（誤差 O(h^2)）
+    #[default]
+    Central,
+    /// 前進差分 (f(x+h) - f(x)) / h（x より小さい側で定義されない関数用）
+    Forward,
+    /// 後退差分 (f(x) - f(x-h)) / h（x より大きい側で定義されない関数用）
+    Backward,
+}
+
+/// 数値微分の設定
+///
+/// ```
+/// use hamiltonian_sampler_rs::numdiff::{DiffScheme, NumDiff};
+/// use hamiltonian_sampler_rs::{Point, Target};
+///
+/// // x > 0 でしか定義されないポテンシャルの境界付近では前進差分を使う
+/// let target = Target::from_fn(|p: &Point| -p.x.ln() + 0.5 * p.y * p.y);
+/// let numdiff = NumDiff { scheme: DiffScheme::Forward, step: 1e-8, ..NumDiff::default() };
+/// let g = numdiff.gradient(&target, &Point { x: 1e-5, y: 1.0 });
+/// assert!((g.x + 1e5).abs() / 1e5 < 1e-2);
+/// ```
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct NumDiff {
+    /// 差分の刻み幅 h
+    pub step: f64,
+    /// 刻み幅を座標の大きさに比例させる（h * max(1, |x|)）
+    pub relative: bool,
+    /// 差分の取り方
+    pub scheme: DiffScheme,
+}
+
+impl Default for NumDiff {
+    fn default() -> Self {
+        Self {
+            step: 1e-4,
+            relative: false,
+            scheme: DiffScheme::Central,
+        }
+    }
+}
+
+impl NumDiff {
+    /// 刻み幅が正の有限値か検証する
+    pub fn validate(&self) -> Result<(), HmcError> {
+        if !(self.step.is_finite() && self.step > 0.0) {
+            return Err(HmcError::InvalidNumDiffStep(self.step));
+        }
+        Ok(())
+    }
+
+    /// 1変数関数 `f` の `x` での微分
+    pub fn derivative(&self, f: impl Fn(f64) -> f64, x: f64) -> f64 {
+        match self.scheme {
+            DiffScheme::Central => {
+                let h = self.step_at(x);
+                (f(x + h) - f(x - h)) / (2.0 * h)
+            }
+            _ => self.one_sided(&f, x, f(x)),
+        }
+    }
+
+    /// `target` のポテンシャルの `q` での勾配
+    ///
+    /// 片側差分では U(q) を座標間で共有するため、評価回数は中心差分の4回に対して3回になる。
+    pub fn gradient<T: TargetDistribution + ?Sized>(&self, target: &T, q: &Point) -> Point {
+        self.gradient_of(|q: &Point| target.potential(q), q)
+    }
+
+    /// 次元を問わない `potential` の `q` での勾配（座標の順に、`gradient` と同じ規則で差分を取る）
+    pub(crate) fn gradient_of<V: Vector>(&self, potential: impl Fn(&V) -> f64, q: &V) -> V {
+        let u = match self.scheme {
+            DiffScheme::Central => None,
+            _ => Some(potential(q)),
+        };
+        let mut g = q.clone();
+        for (i, g) in g.as_mut_slice().iter_mut().enumerate() {
+            let along = |x| {
+                let mut shifted = q.clone();
+                shifted.as_mut_slice()[i] = x;
+                potential(&shifted)
+            };
+            let x = q.as_slice()[i];
+            *g = match u {
+                Some(u) => self.one_sided(&along, x, u),
+                None => self.derivative(along, x),
+            };
+        }
+        g
+    }
+
+    fn step_at(&self, x: f64) -> f64 {
+        if self.relative {
+            self.step * x.abs().max(1.0)
+        } else {
+            self.step
+        }
+    }
+
+    /// 前進/後退差分（`fx` は f(x)）
+    fn one_sided(&self, f: &impl Fn(f64) -> f64, x: f64, fx: f64) -> f64 {
+        let h = self.step_at(x);
+        match self.scheme {
+            DiffScheme::Backward => (fx - f(x - h)) / h,
+            _ => (f(x + h) - fx) / h,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Target;
+
+    /// U = x^4 / 4 + 3 sin(y)、∇U = (x^3, 3 cos(y))
+    fn quartic() -> impl TargetDistribution {
+        Target::from_fn(|p: &Point| p.x.powi(4) / 4.0 + 3.0 * p.y.sin())
+    }
+
+    fn relative_error(numeric: f64, exact: f64) -> f64 {
+        (numeric - exact).abs() / exact.abs().max(1.0)
+    }
+
+    #[test]
+    fn default_scheme_matches_analytic_derivative_across_scales() {
+        let numdiff = NumDiff::default();
+        for scale in [1e-3, 1.0, 10.0, 1e3] {
+            let x = 0.7 * scale;
+            let d = numdiff.derivative(|x| x.powi(4) / 4.0, x);
+            assert!(
+                relative_error(d, x.powi(3)) < 1e-6,
+                "scale {}: {}",
+                scale,
+                d
+            );
+        }
+
+        let q = Point { x: 1.3, y: -0.4 };
+        let g = numdiff.gradient(&quartic(), &q);
+        assert!(relative_error(g.x, q.x.powi(3)) < 1e-6);
+        assert!(relative_error(g.y, 3.0 * q.y.cos()) < 1e-6);
+    }
+
+    #[test]
+    fn relative_step_helps_for_large_coordinates() {
+        let f = |x: f64| x.powi(4) / 4.0;
+        let x = 3e5;
+        let absolute = NumDiff {
+            step: 1e-6,
+            ..NumDiff::default()
+        };
+        let relative = NumDiff {
+            relative: true,
+            ..absolute.clone()
+        };
+        let err_abs = relative_error(absolute.derivative(f, x), x.powi(3));
+        let err_rel = relative_error(relative.derivative(f, x), x.powi(3));
+        assert!(err_rel < 1e-8, "relative stepping error {}", err_rel);
+        assert!(err_rel < err_abs);
+    }
+
+    #[test]
+    fn one_sided_schemes_avoid_undefined_side() {
+        // x <= 0 では定義されないポテンシャル
+        let target = Target::from_fn(|p: &Point| -p.x.ln() + p.y * p.y);
+        let q = Point { x: 5e-5, y: 1.0 };
+        assert!(NumDiff::default().gradient(&target, &q).x.is_nan());
+
+        let forward = NumDiff {
+            step: 1e-9,
+            scheme: DiffScheme::Forward,
+            ..NumDiff::default()
+        };
+        let g = forward.gradient(&target, &q);
+        assert!((g.x + 1.0 / q.x).abs() * q.x < 1e-3, "{:?}", g);
+        assert!((g.y - 2.0).abs() < 1e-6);
+
+        let backward = NumDiff {
+            scheme: DiffScheme::Backward,
+            ..forward
+        };
+        assert!((backward.gradient(&target, &q).x + 1.0 / q.x).abs() * q.x < 1e-3);
+    }
+
+    #[test]
+    fn rejects_invalid_step() {
+        let bad = NumDiff {
+            step: 0.0,
+            ..NumDiff::default()
+        };
+        assert_eq!(
+            bad.validate().unwrap_err(),
+            HmcError::InvalidNumDiffStep(0.0)
+        );
+    }
+}
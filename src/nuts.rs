@@ -0,0 +1,585 @@
+//! 遷移アルゴリズムの選択と No-U-Turn Sampler（NUTS）
+
+use rand::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::chain::{is_numerical_error, Leapfrog};
+use crate::integrator::{PhasePoint, StepFailure};
+use crate::target::Model;
+use crate::vector::{self, Vector};
+use crate::Point;
+
+/// 1遷移の提案の作り方（軌道の長さの決め方）
+///
+/// JSONでは `"hmc"`、`{"nuts": {"max_depth": 10}}`（`max_depth` は省略可）、
+/// `{"random_walk": {"proposal_std": 0.5}}`、`{"mala": {"step_size": 0.5}}`、`{"barker": {"step_size": 0.5}}`、
+/// `{"slice": {"initial_width": 1.0, "max_step_out": 10}}`、`{"ensemble": {"n_walkers": 8, "a": 2.0}}`、
+/// `{"parallel_tempering": {"temperatures": [1, 2, 4, 8], "swap_every": 1}}`（各値は省略可）。
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Algorithm {
+    /// `num_steps` 回の固定長のリープフロッグで提案し、Metropolis判定する
+    #[default]
+    Hmc,
+    /// 軌道がUターンするまで前後に倍々に延ばし、軌道上の点から多項サンプリングで次の状態を選ぶ
+    ///
+    /// `num_steps` は使わない。木の深さ（倍にした回数）は `max_depth` で打ち切り、
+    /// 1遷移のリープフロッグは最大 2^max_depth - 1 ステップになる。
+    Nuts {
+        #[serde(default = "default_max_depth")]
+        max_depth: usize,
+    },
+    /// 比較用のランダムウォークMetropolis。q' = q + σz (z ~ N(0, I)) を提案し、U の差だけで判定する
+    ///
+    /// 勾配を使わず、`step_size`・`num_steps`・`metric` とそれらの適応、`on_divergence` は使わない。
+    /// `transform`・`bounds`・`topology`・`temperature` はHMCと同じく効く。
+    RandomWalk { proposal_std: f64 },
+    /// Metropolis-adjusted Langevin algorithm。q' = q - (ε²/2)∇U(q) + εz (z ~ N(0, I)) を提案し、
+    /// 非対称な提案密度の比 q(q | q') / q(q' | q) を含めて判定する
+    ///
+    /// 1遷移あたり勾配を2回（現在位置と提案）評価する。ε はこの `step_size` で、`HmcConfig::step_size`・
+    /// `num_steps`・`metric` とそれらの適応、`on_divergence` は使わない。
+    Mala { step_size: f64 },
+    /// Barker提案。各座標で z ~ N(0, ε²) を引き、確率 1/(1 + exp(z ∂U(q))) で +z、残りで -z だけ動かす
+    ///
+    /// 移動量が勾配に依らないため、MALAより ε の選び方に頑健。1遷移あたり勾配を2回評価し、
+    /// MALAと同じく `HmcConfig::step_size`・`num_steps`・`metric` とそれらの適応、`on_divergence` は使わない。
+    Barker { step_size: f64 },
+    /// 勾配を使わないスライスサンプリング（stepping-out と shrinkage）
+    ///
+    /// 遷移ごとにランダムに回転させた直交する `dim` 方向それぞれに沿って、幅 `initial_width` の区間を最大
+    /// `max_step_out` 回広げてから縮める。調整なしでも正確なサンプルが得られ、採択率は常に1になる。
+    /// `step_size`・`num_steps`・`metric` とそれらの適応、`on_divergence` は使わない。
+    Slice {
+        #[serde(default = "default_initial_width")]
+        initial_width: f64,
+        #[serde(default = "default_max_step_out")]
+        max_step_out: usize,
+    },
+    /// アフィン不変なアンサンブルサンプラー（emcee の stretch move）
+    ///
+    /// 状態は `n_walkers` 個のウォーカーの組で、1遷移で各ウォーカーを順に、ランダムに選んだ別のウォーカーとの
+    /// 直線上で伸縮させる提案（伸縮率は [1/a, a]）で1回ずつ更新する。勾配を使わず、ターゲットの線形な
+    /// スケール・相関に影響されない。ウォーカーは開始位置の周りに散らして置く（再開時も置き直す）。
+    /// `HmcResult::samples` は遷移ごとに全ウォーカーの位置を並べたもの。
+    /// `step_size`・`num_steps`・`metric` とそれらの適応、`on_divergence` は使わない。周期境界とは併用できない。
+    Ensemble {
+        #[serde(default = "default_n_walkers")]
+        n_walkers: usize,
+        #[serde(default = "default_stretch_scale")]
+        a: f64,
+    },
+    /// レプリカ交換法（パラレルテンパリング）
+    ///
+    /// `temperatures` の各温度で固定長のHMCのレプリカを1本ずつ動かし、`swap_every` 回の遷移ごとに
+    /// 隣り合う温度のレプリカの状態の交換をMetropolis判定で提案する。温度は1から始まる狭義単調増加の列で、
+    /// `HmcConfig::temperature` と掛け合わせて使う。サンプルとして返すのは温度1のレプリカ（チェーンの現在位置）だけ。
+    /// ステップ幅と質量行列は全レプリカで共通で、適応には温度1のレプリカだけを使う。
+    /// 高温のレプリカは `on_divergence` に関わらず発散した提案を棄却して続ける。
+    ParallelTempering {
+        #[serde(default = "default_temperatures")]
+        temperatures: Vec<f64>,
+        #[serde(default = "default_swap_every")]
+        swap_every: usize,
+    },
+}
+
+fn default_max_depth() -> usize {
+    10
+}
+
+fn default_initial_width() -> f64 {
+    1.0
+}
+
+fn default_max_step_out() -> usize {
+    10
+}
+
+fn default_n_walkers() -> usize {
+    8
+}
+
+fn default_stretch_scale() -> f64 {
+    2.0
+}
+
+fn default_temperatures() -> Vec<f64> {
+    vec![1.0, 2.0, 4.0, 8.0]
+}
+
+fn default_swap_every() -> usize {
+    1
+}
+
+/// 1回の遷移の結果（位置は非制約空間）
+pub(crate) struct Move<V = Point> {
+    /// 移動先（留まった場合は `None`）
+    pub(crate) to: Option<V>,
+    pub(crate) energy_error: f64,
+    pub(crate) divergent: bool,
+    /// 軌道上でNaNなどの数値の破綻が起きた（`divergent` にも数える）
+    pub(crate) numerical_error: bool,
+    pub(crate) out_of_support: bool,
+    pub(crate) accept_prob: f64,
+    pub(crate) potential_energy: f64,
+    pub(crate) energy: f64,
+    pub(crate) n_leapfrog: usize,
+    pub(crate) tree_depth: Option<usize>,
+    /// 遷移後の運動量（固定長のHMCのみ。採択なら終点の、棄却なら反転した初期の運動量）
+    pub(crate) momentum: Option<V>,
+    /// 移動先での勾配（軌道の積分で移動した場合のみ。次の遷移の開始点で使い回す）
+    pub(crate) grad: Option<V>,
+    /// 軌道の始点と各ステップの後の位置（非制約空間）と H（固定長のHMCで記録を求めた場合のみ）
+    pub(crate) trajectory: Option<Vec<(V, f64)>>,
+}
+
+/// 軌道の一部分（部分木）
+struct Tree<V> {
+    /// 時間的に最も前の端
+    minus: PhasePoint<V>,
+    /// 時間的に最も後の端
+    plus: PhasePoint<V>,
+    /// 部分木から選んだ点とその U, H
+    sample: PhasePoint<V>,
+    sample_u: f64,
+    sample_h: f64,
+    /// log Σ exp(H0 - H)（多項サンプリングの重みの合計）
+    log_weight: f64,
+    /// 運動量の合計（一般化したUターン判定に使う）
+    rho: V,
+}
+
+/// 木を延ばす間に共有する状態と統計
+struct Builder<'a, T: ?Sized, V, R> {
+    leapfrog: &'a Leapfrog<'a, T, V>,
+    rng: &'a mut R,
+    h0: f64,
+    k0: f64,
+    divergence_threshold: f64,
+    n_leapfrog: usize,
+    sum_accept_prob: f64,
+    divergent: bool,
+    numerical_error: bool,
+    out_of_support: bool,
+}
+
+impl<T: Model<V> + ?Sized, V: Vector, R: Rng> Builder<'_, T, V, R> {
+    /// `from` から `direction` の向きに 2^depth ステップ延ばした部分木を作る
+    ///
+    /// 発散・台の外への到達・部分木の中でのUターンのいずれかが起きたら `None`。
+    fn build(&mut self, from: &PhasePoint<V>, depth: usize, direction: f64) -> Option<Tree<V>> {
+        if depth == 0 {
+            return self.leaf(from, direction);
+        }
+        let first = self.build(from, depth - 1, direction)?;
+        let edge = if direction > 0.0 {
+            &first.plus
+        } else {
+            &first.minus
+        };
+        let second = self.build(&edge.clone(), depth - 1, direction)?;
+        // 部分木の中では重みに比例した一様な多項サンプリング
+        let total = log_add_exp(first.log_weight, second.log_weight);
+        let take_second = self.rng.gen::<f64>() < (second.log_weight - total).exp();
+        let (tree, persist) = join(self.leapfrog, first, second, direction, take_second);
+        persist.then_some(tree)
+    }
+
+    fn leaf(&mut self, from: &PhasePoint<V>, direction: f64) -> Option<Tree<V>> {
+        let mut z = from.clone();
+        self.n_leapfrog += 1;
+        let truncated = match self.leapfrog.step(&mut z, direction) {
+            Ok(()) => false,
+            // 陰的中点法の反復が収束しなかった場合は、発散とも台の外とも数えずに木の延長を止める
+            Err(StepFailure::NotConverged) => return None,
+            Err(StepFailure::NonFiniteGradient) => true,
+        };
+        let u = self.leapfrog.target.potential_at(&z.q);
+        let k = self.leapfrog.kinetic(&z.p);
+        if is_numerical_error(u, k, &z) {
+            self.divergent = true;
+            self.numerical_error = true;
+            return None;
+        }
+        if truncated || !u.is_finite() {
+            // 固定長のHMCと同じく、運動エネルギーの増加が閾値以内なら台の外に出たとみなす
+            let blew_up = !(vector::is_finite(&z.q) && k.is_finite())
+                || k - self.k0 > self.divergence_threshold;
+            if blew_up {
+                self.divergent = true;
+            } else {
+                self.out_of_support = true;
+            }
+            return None;
+        }
+        let h = u + k;
+        let energy_error = h - self.h0;
+        self.sum_accept_prob += (-energy_error).exp().min(1.0);
+        if energy_error > self.divergence_threshold {
+            self.divergent = true;
+            return None;
+        }
+        Some(Tree {
+            minus: z.clone(),
+            plus: z.clone(),
+            rho: z.p.clone(),
+            sample: z,
+            sample_u: u,
+            sample_h: h,
+            log_weight: -energy_error,
+        })
+    }
+}
+
+/// `first` の先に `direction` の向きで `second` をつなぎ、まだUターンしていないかを返す
+///
+/// 全体の両端に加え、Stanと同じく継ぎ目をまたぐ2つの区間（時間的に前の部分木全体と後ろの部分木の
+/// 最初の点、前の部分木の最後の点と後ろの部分木全体）でも調べ、部分木の間のUターンを見逃さない。
+fn join<T: Model<V> + ?Sized, V: Vector>(
+    leapfrog: &Leapfrog<'_, T, V>,
+    first: Tree<V>,
+    second: Tree<V>,
+    direction: f64,
+    take_second: bool,
+) -> (Tree<V>, bool) {
+    let (before, after) = if direction > 0.0 {
+        (&first, &second)
+    } else {
+        (&second, &first)
+    };
+    let v = |z: &PhasePoint<V>| leapfrog.velocity(&z.p);
+    let persist = no_u_turn(
+        &v(&before.minus),
+        &v(&after.minus),
+        &add(&before.rho, &after.minus.p),
+    ) && no_u_turn(
+        &v(&before.plus),
+        &v(&after.plus),
+        &add(&after.rho, &before.plus.p),
+    );
+    let tree = merge(first, second, direction, take_second);
+    let persist = persist && no_u_turn(&v(&tree.minus), &v(&tree.plus), &tree.rho);
+    (tree, persist)
+}
+
+/// `first` の先に `direction` の向きで `second` をつなぐ（`take_second` なら代表点を `second` のものにする）
+fn merge<V: Vector>(first: Tree<V>, second: Tree<V>, direction: f64, take_second: bool) -> Tree<V> {
+    let (minus, plus) = if direction > 0.0 {
+        (first.minus, second.plus)
+    } else {
+        (second.minus, first.plus)
+    };
+    let (sample, sample_u, sample_h) = if take_second {
+        (second.sample, second.sample_u, second.sample_h)
+    } else {
+        (first.sample, first.sample_u, first.sample_h)
+    };
+    Tree {
+        minus,
+        plus,
+        sample,
+        sample_u,
+        sample_h,
+        log_weight: log_add_exp(first.log_weight, second.log_weight),
+        rho: add(&first.rho, &second.rho),
+    }
+}
+
+/// 区間の両端の速度 M⁻¹p がどちらも区間の運動量の合計 `rho` と同じ向きを向いている（まだUターンしていない）か
+fn no_u_turn<V: Vector>(v_minus: &V, v_plus: &V, rho: &V) -> bool {
+    v_minus.dot(rho) > 0.0 && v_plus.dot(rho) > 0.0
+}
+
+fn add<V: Vector>(a: &V, b: &V) -> V {
+    vector::zip_with(a, b, |a, b| a + b)
+}
+
+fn log_add_exp(a: f64, b: f64) -> f64 {
+    let m = a.max(b);
+    if m == f64::NEG_INFINITY {
+        m
+    } else {
+        m + ((a - m).exp() + (b - m).exp()).ln()
+    }
+}
+
+/// NUTSの1遷移（multinomial sampling と倍々に延ばす反復）
+///
+/// 各段で向きを一様に選び、既存の軌道と同じ長さの部分木を継ぎ足す。継ぎ足した部分木の代表点は
+/// 重みの比 min(1, w_new / w_old) で採用する（biased progressive sampling）。
+/// 部分木が発散・Uターンした段では部分木を捨てて止める。
+/// 採択確率として、軌道上の全ての点の min(1, exp(H0 - H)) の平均を返す。
+pub(crate) fn transition<T, V, R>(
+    leapfrog: &Leapfrog<'_, T, V>,
+    start: PhasePoint<V>,
+    start_u: f64,
+    max_depth: usize,
+    divergence_threshold: f64,
+    rng: &mut R,
+) -> Move<V>
+where
+    T: Model<V> + ?Sized,
+    V: Vector,
+    R: Rng,
+{
+    let k0 = leapfrog.kinetic(&start.p);
+    let h0 = start_u + k0;
+    let mut tree = Tree {
+        minus: start.clone(),
+        plus: start.clone(),
+        rho: start.p.clone(),
+        sample: start,
+        sample_u: start_u,
+        sample_h: h0,
+        log_weight: 0.0,
+    };
+    let mut moved = false;
+    let mut depth = 0;
+    let mut builder = Builder {
+        leapfrog,
+        rng,
+        h0,
+        k0,
+        divergence_threshold,
+        n_leapfrog: 0,
+        sum_accept_prob: 0.0,
+        divergent: false,
+        numerical_error: false,
+        out_of_support: false,
+    };
+
+    while depth < max_depth {
+        let direction = if builder.rng.gen::<bool>() { 1.0 } else { -1.0 };
+        let edge = if direction > 0.0 {
+            &tree.plus
+        } else {
+            &tree.minus
+        };
+        let subtree = builder.build(&edge.clone(), depth, direction);
+        depth += 1;
+        let Some(subtree) = subtree else {
+            break;
+        };
+        let take_subtree = builder.rng.gen::<f64>() < (subtree.log_weight - tree.log_weight).exp();
+        moved |= take_subtree;
+        let persist;
+        (tree, persist) = join(leapfrog, tree, subtree, direction, take_subtree);
+        if !persist {
+            break;
+        }
+    }
+
+    let accept_prob = if builder.n_leapfrog > 0 {
+        builder.sum_accept_prob / builder.n_leapfrog as f64
+    } else {
+        0.0
+    };
+    Move {
+        to: moved.then_some(tree.sample.q),
+        grad: moved.then_some(tree.sample.grad),
+        energy_error: tree.sample_h - h0,
+        divergent: builder.divergent,
+        numerical_error: builder.numerical_error,
+        out_of_support: builder.out_of_support && !builder.divergent,
+        accept_prob,
+        potential_energy: tree.sample_u,
+        energy: tree.sample_h,
+        n_leapfrog: builder.n_leapfrog,
+        tree_depth: Some(depth),
+        momentum: None,
+        trajectory: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        diagnostics, run_hmc, run_hmc_with_target, validate, Banana, DistType, HmcConfig, HmcError,
+        HmcResult, Target,
+    };
+
+    fn nuts(max_depth: usize) -> Algorithm {
+        Algorithm::Nuts { max_depth }
+    }
+
+    #[test]
+    fn nuts_reproduces_standard_normal_moments() {
+        let config = HmcConfig {
+            n_samples: 4000,
+            step_size: 0.3,
+            algorithm: nuts(10),
+            target: DistType::Normal,
+            seed: Some(56),
+            ..HmcConfig::default()
+        };
+        let result = run_hmc(&config).unwrap();
+        let identity = [[1.0, 0.0], [0.0, 1.0]];
+        let z = validate::z_score_of_mean(&result.samples, &Point { x: 0.0, y: 0.0 }, &identity);
+        assert!(z.x.abs() < 4.0 && z.y.abs() < 4.0, "z = {:?}", z);
+        let cov = validate::sample_cov(&result.samples);
+        assert!(
+            (cov[0][0] - 1.0).abs() < 0.1 && (cov[1][1] - 1.0).abs() < 0.1,
+            "{:?}",
+            cov
+        );
+
+        let depth = result.mean_tree_depth.unwrap();
+        assert!((1.0..10.0).contains(&depth), "mean tree depth {}", depth);
+        assert_eq!(result.n_max_tree_depth, Some(0));
+        assert_eq!(result.n_divergent, 0);
+        // 軌道の長さはUターンで決まり、num_steps には依存しない
+        assert_ne!(result.n_leapfrog, config.n_samples * config.num_steps);
+        let fixed = run_hmc(&HmcConfig {
+            algorithm: Algorithm::Hmc,
+            ..config
+        })
+        .unwrap();
+        assert_eq!(fixed.n_leapfrog, 4000 * 20);
+        assert!(fixed.mean_tree_depth.is_none() && fixed.n_max_tree_depth.is_none());
+    }
+
+    /// 座標ごとのESSの小さい方を勾配の評価回数で割ったもの
+    fn ess_per_gradient(result: &HmcResult) -> f64 {
+        let xs: Vec<f64> = result.samples.iter().map(|p| p.x).collect();
+        let ys: Vec<f64> = result.samples.iter().map(|p| p.y).collect();
+        diagnostics::ess(&xs).min(diagnostics::ess(&ys)) / result.n_leapfrog as f64
+    }
+
+    #[test]
+    fn nuts_beats_mistuned_fixed_length_hmc_on_banana() {
+        let config = |algorithm, num_steps| HmcConfig {
+            n_samples: 10_000,
+            n_warmup: 200,
+            step_size: 0.05,
+            num_steps,
+            algorithm,
+            initial_pos: Point { x: 1.0, y: 1.0 },
+            target: DistType::Banana(Banana::default()),
+            seed: Some(56),
+            ..HmcConfig::default()
+        };
+        let nuts = run_hmc(&config(nuts(10), 1)).unwrap();
+        // 短すぎる軌道はランダムウォークになり、長すぎる軌道は何度もUターンして勾配を無駄にする
+        let short = run_hmc(&config(Algorithm::Hmc, 2)).unwrap();
+        let long = run_hmc(&config(Algorithm::Hmc, 300)).unwrap();
+
+        let (e_nuts, e_short, e_long) = (
+            ess_per_gradient(&nuts),
+            ess_per_gradient(&short),
+            ess_per_gradient(&long),
+        );
+        assert!(
+            e_nuts > 1.5 * e_short,
+            "NUTS {} vs short HMC {}",
+            e_nuts,
+            e_short
+        );
+        // 長すぎる軌道の効率には勾配の数を1/5以下に抑えたまま並ぶ
+        assert!(
+            e_nuts > 0.7 * e_long,
+            "NUTS {} vs long HMC {}",
+            e_nuts,
+            e_long
+        );
+        assert!(nuts.n_leapfrog * 5 < long.n_leapfrog);
+
+        let mean = validate::sample_mean(&nuts.samples);
+        assert!(
+            (mean.x - 1.0).abs() < 0.1 && (mean.y - 1.5).abs() < 0.2,
+            "{:?}",
+            mean
+        );
+    }
+
+    #[test]
+    fn nuts_reports_divergences_and_max_depth() {
+        let base = HmcConfig {
+            n_samples: 200,
+            initial_pos: Point { x: 1.0, y: 1.0 },
+            target: DistType::Banana(Banana { a: 1.0, b: 100.0 }),
+            seed: Some(3),
+            ..HmcConfig::default()
+        };
+        let diverging = run_hmc(&HmcConfig {
+            step_size: 0.5,
+            algorithm: nuts(10),
+            ..base.clone()
+        })
+        .unwrap();
+        assert!(diverging.n_divergent > 0);
+        assert!(diverging
+            .samples
+            .iter()
+            .all(|p| p.x.is_finite() && p.y.is_finite()));
+
+        // 小さなステップ幅では深さ2（3ステップ）でUターンに届かず、毎回打ち切られる
+        let truncated = run_hmc(&HmcConfig {
+            step_size: 1e-3,
+            algorithm: nuts(2),
+            ..base.clone()
+        })
+        .unwrap();
+        assert_eq!(truncated.n_max_tree_depth, Some(200));
+        assert_eq!(truncated.mean_tree_depth, Some(2.0));
+        assert_eq!(truncated.n_leapfrog, 200 * 3);
+
+        assert_eq!(
+            HmcConfig {
+                algorithm: nuts(0),
+                ..base
+            }
+            .validate(),
+            Err(HmcError::ZeroTreeDepth)
+        );
+    }
+
+    #[test]
+    fn nuts_stops_extending_at_the_edge_of_the_support() {
+        // x >= 0 に切断した標準正規分布
+        let half_plane = Target::from_fn_with_grad(
+            |p: &Point| {
+                if p.x < 0.0 {
+                    f64::INFINITY
+                } else {
+                    0.5 * (p.x * p.x + p.y * p.y)
+                }
+            },
+            |p: &Point| p.clone(),
+        );
+        let config = HmcConfig {
+            n_samples: 5000,
+            step_size: 0.2,
+            algorithm: nuts(10),
+            initial_pos: Point { x: 1.0, y: 0.0 },
+            seed: Some(8),
+            ..HmcConfig::default()
+        };
+        let result = run_hmc_with_target(&config, half_plane).unwrap();
+        assert!(result.samples.iter().all(|p| p.x >= 0.0));
+        assert!(result.n_out_of_support > 0);
+        assert_eq!(result.n_divergent, 0);
+        let mean_x = validate::sample_mean(&result.samples).x;
+        assert!(
+            (mean_x - (2.0 / std::f64::consts::PI).sqrt()).abs() < 0.05,
+            "{}",
+            mean_x
+        );
+    }
+
+    #[test]
+    fn algorithm_loads_from_json() {
+        let config: HmcConfig = serde_json::from_str(r#"{"algorithm": {"nuts": {}}}"#).unwrap();
+        assert_eq!(config.algorithm, nuts(10));
+        let config: HmcConfig =
+            serde_json::from_str(r#"{"algorithm": {"nuts": {"max_depth": 6}}}"#).unwrap();
+        assert_eq!(config.algorithm, nuts(6));
+        let config: HmcConfig = serde_json::from_str(r#"{"algorithm": "hmc"}"#).unwrap();
+        assert_eq!(config.algorithm, Algorithm::Hmc);
+        assert_eq!(
+            serde_json::to_value(nuts(8)).unwrap(),
+            serde_json::json!({"nuts": {"max_depth": 8}})
+        );
+    }
+}
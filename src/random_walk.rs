@@ -0,0 +1,154 @@
+//! ランダムウォークMetropolis（HMCとの比較用のベースライン）
+
+use rand::Rng;
+use rand_distr::{Distribution, StandardNormal};
+
+use crate::chain::Leapfrog;
+use crate::nuts::Move;
+use crate::target::Model;
+use crate::vector::{self, Vector};
+
+/// 等方的な正規分布の提案 q' = q + σz (z ~ N(0, I)) で1回Metropolis判定する
+///
+/// 勾配・運動量・リープフロッグは使わない（`leapfrog` からはターゲットと境界・位相だけを使う）。
+/// 反射境界で折り返し、周期境界で折り畳んだ提案も q と q' について対称なので、判定は
+/// min(1, exp(U(q) - U(q'))) のままでよい。
+pub(crate) fn transition<T, V, R>(
+    leapfrog: &Leapfrog<'_, T, V>,
+    q: &V,
+    current_u: f64,
+    proposal_std: f64,
+    rng: &mut R,
+) -> Move<V>
+where
+    T: Model<V> + ?Sized,
+    V: Vector,
+    R: Rng,
+{
+    let mut proposal = q.clone();
+    for x in proposal.as_mut_slice() {
+        let z: f64 = StandardNormal.sample(rng);
+        *x += proposal_std * z;
+    }
+    leapfrog
+        .space
+        .constrain(&mut proposal, &mut vector::map(q, |_| 0.0));
+    let new_u = leapfrog.target.potential_at(&proposal);
+
+    let out_of_support = !new_u.is_finite();
+    let diff = current_u - new_u;
+    let accept_prob = if diff.is_finite() {
+        diff.exp().min(1.0)
+    } else {
+        0.0
+    };
+    let accepted = rng.gen::<f64>() < accept_prob;
+    let potential_energy = if accepted { new_u } else { current_u };
+
+    Move {
+        to: accepted.then_some(proposal),
+        energy_error: -diff,
+        divergent: false,
+        numerical_error: false,
+        out_of_support,
+        accept_prob,
+        potential_energy,
+        energy: potential_energy,
+        n_leapfrog: 0,
+        tree_depth: None,
+        momentum: None,
+        grad: None,
+        trajectory: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        diagnostics, run_hmc, sample_ess, Algorithm, Banana, DistType, HmcConfig, HmcError,
+        HmcResult, Point,
+    };
+
+    fn min_ess_per_iteration(result: &HmcResult) -> f64 {
+        let ess = sample_ess(&result.samples);
+        ess.x.min(ess.y) / result.samples.len() as f64
+    }
+
+    #[test]
+    fn random_walk_reproduces_standard_normal_moments() {
+        let config = HmcConfig {
+            n_samples: 20_000,
+            n_warmup: 500,
+            algorithm: Algorithm::RandomWalk { proposal_std: 1.7 },
+            target: DistType::Normal,
+            seed: Some(62),
+            ..HmcConfig::default()
+        };
+        let result = run_hmc(&config).unwrap();
+        assert_eq!(result.n_leapfrog, 0);
+        assert_eq!(result.n_divergent, 0);
+        // 2次元の正規分布での最適なスケール σ = 2.4/√d では採択率は0.35前後
+        assert!(
+            (0.25..0.45).contains(&result.acceptance_rate),
+            "{}",
+            result.acceptance_rate
+        );
+        let xs: Vec<f64> = result.samples.iter().map(|p| p.x).collect();
+        let mean = xs.iter().sum::<f64>() / xs.len() as f64;
+        let var = xs.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / xs.len() as f64;
+        assert!(mean.abs() < 0.1, "mean {}", mean);
+        assert!((var - 1.0).abs() < 0.1, "var {}", var);
+        assert!(diagnostics::ess(&xs) > 1000.0);
+    }
+
+    #[test]
+    fn hmc_mixes_better_than_random_walk_on_banana_at_matched_acceptance() {
+        let config = |algorithm, step_size| HmcConfig {
+            n_samples: 10_000,
+            n_warmup: 1000,
+            step_size,
+            num_steps: 20,
+            algorithm,
+            target: DistType::Banana(Banana::default()),
+            initial_pos: Point { x: 0.0, y: 1.0 },
+            seed: Some(62),
+            ..HmcConfig::default()
+        };
+        // どちらも採択率が0.8前後になる設定。ランダムウォークは細い谷に沿って少しずつしか進めない
+        let hmc = run_hmc(&config(Algorithm::Hmc, 0.1)).unwrap();
+        let rw = run_hmc(&config(Algorithm::RandomWalk { proposal_std: 0.05 }, 0.1)).unwrap();
+        assert!(
+            (hmc.acceptance_rate - rw.acceptance_rate).abs() < 0.1,
+            "acceptance {} vs {}",
+            hmc.acceptance_rate,
+            rw.acceptance_rate
+        );
+        assert!(
+            min_ess_per_iteration(&hmc) > 10.0 * min_ess_per_iteration(&rw),
+            "ESS/iteration {} vs {}",
+            min_ess_per_iteration(&hmc),
+            min_ess_per_iteration(&rw)
+        );
+    }
+
+    #[test]
+    fn random_walk_rejects_invalid_proposal_std_and_loads_from_json() {
+        for proposal_std in [0.0, -1.0, f64::NAN] {
+            let config = HmcConfig {
+                algorithm: Algorithm::RandomWalk { proposal_std },
+                ..HmcConfig::default()
+            };
+            assert!(matches!(
+                config.validate(),
+                Err(HmcError::InvalidProposalStd(_))
+            ));
+        }
+        let parsed: HmcConfig =
+            serde_json::from_str(r#"{"algorithm": {"random_walk": {"proposal_std": 0.5}}}"#)
+                .unwrap();
+        assert_eq!(
+            parsed.algorithm,
+            Algorithm::RandomWalk { proposal_std: 0.5 }
+        );
+    }
+}
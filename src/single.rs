@@ -0,0 +1,230 @@
+//! 描画用に単精度で積分する固定長のHMC（`sample_wasm_f32` が使う）
+//!
+//! 位置・運動量・勾配と運動量の正規乱数は `f32` で持ち、リープフロッグも `f32` で進める。
+//! 採択判定に使うハミルトニアン U + K だけは `f32` の状態から `f64` で計算する。
+//! 軌道の始点と終点の H の差は小さな値どうしの引き算なので、`f32` で持つと丸め誤差が
+//! そのまま採択確率に乗るため。ターゲットの `potential`・`gradient` は `f64` のまま呼ぶ。
+
+use rand::prelude::*;
+use rand_distr::StandardNormal;
+
+use crate::{ChainRng, HmcConfig, HmcError, Point, TargetDistribution};
+
+/// [`run_hmc_f32`] の結果（`sample_wasm_f32` が返すもの）
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct SamplesF32 {
+    /// サンプルの x 座標の列
+    pub(crate) xs: Vec<f32>,
+    /// サンプルの y 座標の列
+    pub(crate) ys: Vec<f32>,
+    /// サンプリング期間の採択率
+    pub(crate) acceptance_rate: f64,
+    /// 終了時点のチェーンの位置
+    pub(crate) final_position: Point,
+}
+
+fn widen(q: [f32; 2]) -> Point {
+    Point {
+        x: q[0] as f64,
+        y: q[1] as f64,
+    }
+}
+
+/// ∇U(q) を `f64` で求めて `f32` に丸める
+fn gradient<T: TargetDistribution + ?Sized>(target: &T, q: [f32; 2]) -> [f32; 2] {
+    let g = target.gradient(&widen(q));
+    [g.x as f32, g.y as f32]
+}
+
+/// 単位質量行列の運動エネルギー K(p) = |p|² / 2 を `f64` で計算する
+fn kinetic(p: [f32; 2]) -> f64 {
+    let (px, py) = (p[0] as f64, p[1] as f64);
+    0.5 * (px * px + py * py)
+}
+
+/// `config` のうちサンプル数・ウォームアップ・間引き・ステップ幅・`num_steps`・開始位置・ターゲット・
+/// シードを使い、単位質量行列の固定長のHMCで単精度のサンプルを作る
+///
+/// `sample_wasm_f32` が組み立てる設定だけを想定しており、アルゴリズムや質量行列などの他の設定は使わない。
+/// 乱数の引き方は `f64` のチェーンと同じ（運動量 x, y、採択判定の順）で、`f32` の正規乱数は
+/// `f64` で引いた値を丸めたものになる。
+pub(crate) fn run_hmc_f32(config: &HmcConfig) -> Result<SamplesF32, HmcError> {
+    let target = &config.target;
+    config.validate_for(target)?;
+    let mut rng = match config.seed {
+        Some(seed) => ChainRng::seed_from_u64(seed),
+        None => ChainRng::from_entropy(),
+    };
+    let eps = config.step_size as f32;
+    let start = config.start_position();
+    let mut q = [start.x as f32, start.y as f32];
+    let mut grad = gradient(target, q);
+    let mut current_u = target.potential(&widen(q));
+
+    let total_iterations = config.n_warmup + config.n_samples * config.thin;
+    let mut xs = Vec::with_capacity(config.n_samples);
+    let mut ys = Vec::with_capacity(config.n_samples);
+    let mut accepted_count = 0;
+    for i in 0..total_iterations {
+        let mut p: [f32; 2] = [rng.sample(StandardNormal), rng.sample(StandardNormal)];
+        let current_h = current_u + kinetic(p);
+
+        let (mut new_q, mut new_grad) = (q, grad);
+        let mut finite = true;
+        for _ in 0..config.num_steps {
+            for k in 0..2 {
+                p[k] -= 0.5 * eps * new_grad[k];
+                new_q[k] += eps * p[k];
+            }
+            new_grad = gradient(target, new_q);
+            if !new_grad.iter().all(|g| g.is_finite()) {
+                finite = false;
+                break;
+            }
+            for k in 0..2 {
+                p[k] -= 0.5 * eps * new_grad[k];
+            }
+        }
+
+        let new_h = if finite {
+            target.potential(&widen(new_q)) + kinetic(p)
+        } else {
+            f64::INFINITY
+        };
+        let diff = current_h - new_h;
+        let accept_prob = if diff.is_finite() {
+            diff.exp().min(1.0)
+        } else {
+            0.0
+        };
+        let accepted = rng.gen::<f64>() < accept_prob;
+        if accepted {
+            q = new_q;
+            grad = new_grad;
+            current_u = target.potential(&widen(q));
+        }
+
+        if i >= config.n_warmup {
+            accepted_count += accepted as usize;
+            if (i - config.n_warmup + 1).is_multiple_of(config.thin) {
+                xs.push(q[0]);
+                ys.push(q[1]);
+            }
+        }
+    }
+
+    Ok(SamplesF32 {
+        xs,
+        ys,
+        acceptance_rate: accepted_count as f64 / (config.n_samples * config.thin) as f64,
+        final_position: widen(q),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{run_hmc, Banana, DistType, MvNormal2};
+
+    /// 平均と分散
+    fn moments(values: impl Iterator<Item = f64>) -> (f64, f64) {
+        let values: Vec<f64> = values.collect();
+        let n = values.len() as f64;
+        let mean = values.iter().sum::<f64>() / n;
+        let var = values.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / n;
+        (mean, var)
+    }
+
+    #[test]
+    fn single_precision_agrees_with_double_precision_statistically() {
+        for (target, seed) in [
+            (DistType::Gaussian(MvNormal2::default()), 88),
+            (DistType::Banana(Banana::default()), 89),
+        ] {
+            let config = HmcConfig {
+                n_samples: 20_000,
+                step_size: 0.05,
+                num_steps: 20,
+                initial_pos: Point { x: 0.5, y: 0.5 },
+                target,
+                seed: Some(seed),
+                ..HmcConfig::default()
+            };
+            let single = run_hmc_f32(&config).unwrap();
+            let double = run_hmc(&config).unwrap();
+            assert_eq!(single.xs.len(), 20_000);
+            assert_eq!(single.ys.len(), 20_000);
+
+            // 同じシードなら最初は同じ軌道をたどる（丸め誤差は遷移ごとに広がるので最初の数点だけ）
+            for (k, p) in double.samples.iter().take(3).enumerate() {
+                assert!((single.xs[k] as f64 - p.x).abs() < 1e-3, "{} {:?}", k, p);
+                assert!((single.ys[k] as f64 - p.y).abs() < 1e-3, "{} {:?}", k, p);
+            }
+            // f64 のサンプルを丸めたものではない
+            assert!(single
+                .xs
+                .iter()
+                .zip(&double.samples)
+                .any(|(&x, p)| x != p.x as f32));
+
+            assert!(
+                (single.acceptance_rate - double.acceptance_rate).abs() < 0.01,
+                "{} {}",
+                single.acceptance_rate,
+                double.acceptance_rate
+            );
+            let coordinates: [(Vec<f64>, Vec<f64>); 2] = [
+                (
+                    single.xs.iter().map(|&x| x as f64).collect(),
+                    double.samples.iter().map(|p| p.x).collect(),
+                ),
+                (
+                    single.ys.iter().map(|&y| y as f64).collect(),
+                    double.samples.iter().map(|p| p.y).collect(),
+                ),
+            ];
+            for (single, double) in coordinates {
+                let (mean32, var32) = moments(single.into_iter());
+                let (mean64, var64) = moments(double.into_iter());
+                let sd = var64.sqrt();
+                assert!((mean32 - mean64).abs() < 0.05 * sd, "{} {}", mean32, mean64);
+                assert!((var32 / var64 - 1.0).abs() < 0.05, "{} {}", var32, var64);
+            }
+        }
+    }
+
+    #[test]
+    fn single_precision_samples_halve_the_wasm_payload() {
+        let config = HmcConfig {
+            n_samples: 5000,
+            target: DistType::Banana(Banana::default()),
+            seed: Some(88),
+            ..HmcConfig::default()
+        };
+        let single = run_hmc_f32(&config).unwrap();
+        let double = run_hmc(&config).unwrap();
+        let xs: Vec<f64> = double.samples.iter().map(|p| p.x).collect();
+        let ys: Vec<f64> = double.samples.iter().map(|p| p.y).collect();
+        // `Float32Array` と `Float64Array` は要素をそのままのバイト列で渡す
+        assert_eq!(
+            2 * (std::mem::size_of_val(&single.xs[..]) + std::mem::size_of_val(&single.ys[..])),
+            std::mem::size_of_val(&xs[..]) + std::mem::size_of_val(&ys[..])
+        );
+        assert_eq!(
+            single.final_position,
+            Point {
+                x: *single.xs.last().unwrap() as f64,
+                y: *single.ys.last().unwrap() as f64,
+            }
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_settings_like_the_double_precision_chain() {
+        let config = HmcConfig {
+            num_steps: 0,
+            ..HmcConfig::default()
+        };
+        assert_eq!(run_hmc_f32(&config), Err(HmcError::ZeroLeapfrogSteps));
+    }
+}
@@ -0,0 +1,208 @@
+//! サンプルを1点ずつ書き出す先（[`crate::run_hmc_streaming`] 用）
+
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use crate::{ChainStats, HmcError, Point};
+
+/// 保存するサンプルの書き出し先
+///
+/// [`crate::run_hmc_streaming`] は保存するサンプルを `HmcResult::samples` に溜めず、
+/// 生成した順にここへ渡す。`Algorithm::Ensemble` では全ウォーカーの位置を順に渡す。
+pub trait SampleSink {
+    /// サンプルを1点受け取る
+    fn write_sample(&mut self, sample: &Point) -> Result<(), HmcError>;
+
+    /// 全サンプルを渡し終えた後に1回だけ呼ばれる（エラーで終わった場合は呼ばれない）
+    fn finish(&mut self, _stats: &ChainStats) -> Result<(), HmcError> {
+        Ok(())
+    }
+}
+
+/// メモリ上の列に溜める（`run_hmc` と同じ）
+impl SampleSink for Vec<Point> {
+    fn write_sample(&mut self, sample: &Point) -> Result<(), HmcError> {
+        self.push(sample.clone());
+        Ok(())
+    }
+}
+
+fn io_error(e: std::io::Error) -> HmcError {
+    HmcError::Io(e.to_string())
+}
+
+/// 見出し行 `x,y` に続けて1行1点をCSVで書く
+///
+/// 値は `f64` の `Display`（読み戻すと元の値に戻る最短の10進表記）で書く。
+pub struct CsvSink<W: Write> {
+    writer: BufWriter<W>,
+}
+
+impl CsvSink<File> {
+    /// `path` にファイルを作って書く（既にあれば上書きする）
+    pub fn create(path: impl AsRef<Path>) -> Result<Self, HmcError> {
+        Self::new(File::create(path).map_err(io_error)?)
+    }
+}
+
+impl<W: Write> CsvSink<W> {
+    pub fn new(writer: W) -> Result<Self, HmcError> {
+        let mut writer = BufWriter::new(writer);
+        writeln!(writer, "x,y").map_err(io_error)?;
+        Ok(Self { writer })
+    }
+}
+
+impl<W: Write> SampleSink for CsvSink<W> {
+    fn write_sample(&mut self, sample: &Point) -> Result<(), HmcError> {
+        writeln!(self.writer, "{},{}", sample.x, sample.y).map_err(io_error)
+    }
+
+    fn finish(&mut self, _stats: &ChainStats) -> Result<(), HmcError> {
+        self.writer.flush().map_err(io_error)
+    }
+}
+
+/// 各点の x, y をリトルエンディアンの `f64` で続けて書く（1点16バイト、見出しなし）
+///
+/// NumPyなら `np.fromfile(path, dtype="<f8").reshape(-1, 2)` で読める。
+pub struct BinarySink<W: Write> {
+    writer: BufWriter<W>,
+}
+
+impl BinarySink<File> {
+    /// `path` にファイルを作って書く（既にあれば上書きする）
+    pub fn create(path: impl AsRef<Path>) -> Result<Self, HmcError> {
+        Ok(Self::new(File::create(path).map_err(io_error)?))
+    }
+}
+
+impl<W: Write> BinarySink<W> {
+    pub fn new(writer: W) -> Self {
+        Self {
+            writer: BufWriter::new(writer),
+        }
+    }
+}
+
+impl<W: Write> SampleSink for BinarySink<W> {
+    fn write_sample(&mut self, sample: &Point) -> Result<(), HmcError> {
+        self.writer
+            .write_all(&sample.x.to_le_bytes())
+            .and_then(|()| self.writer.write_all(&sample.y.to_le_bytes()))
+            .map_err(io_error)
+    }
+
+    fn finish(&mut self, _stats: &ChainStats) -> Result<(), HmcError> {
+        self.writer.flush().map_err(io_error)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{run_hmc, run_hmc_streaming, Algorithm, HmcConfig};
+
+    /// テストごとに別の一時ファイルのパス
+    fn temp_path(name: &str) -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("hmc-sink-{}-{}", std::process::id(), name))
+    }
+
+    fn config(n_samples: usize) -> HmcConfig {
+        HmcConfig {
+            n_samples,
+            n_warmup: 100,
+            algorithm: Algorithm::RandomWalk { proposal_std: 1.0 },
+            seed: Some(93),
+            ..HmcConfig::default()
+        }
+    }
+
+    #[test]
+    fn binary_sink_streams_a_million_samples_to_disk() {
+        let config = config(1_000_000);
+        let path = temp_path("samples.f64");
+        let mut sink = BinarySink::create(&path).unwrap();
+        let streamed = run_hmc_streaming(&config, &mut sink).unwrap();
+        drop(sink);
+        assert!(streamed.samples.is_empty());
+
+        let bytes = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(bytes.len(), 1_000_000 * 16);
+        let in_memory = run_hmc(&config).unwrap();
+        let read = |i: usize| {
+            let value =
+                |offset: usize| f64::from_le_bytes(bytes[offset..offset + 8].try_into().unwrap());
+            Point {
+                x: value(16 * i),
+                y: value(16 * i + 8),
+            }
+        };
+        for i in [0, 1, 12_345, 500_000, 999_999] {
+            assert_eq!(read(i), in_memory.samples[i]);
+        }
+        assert_eq!(streamed.acceptance_rate, in_memory.acceptance_rate);
+        assert_eq!(streamed.final_position, in_memory.final_position);
+    }
+
+    #[test]
+    fn csv_sink_round_trips_values_exactly() {
+        let config = config(1000);
+        let path = temp_path("samples.csv");
+        let mut sink = CsvSink::create(&path).unwrap();
+        run_hmc_streaming(&config, &mut sink).unwrap();
+        drop(sink);
+
+        let text = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let mut lines = text.lines();
+        assert_eq!(lines.next(), Some("x,y"));
+        let read: Vec<Point> = lines
+            .map(|line| {
+                let (x, y) = line.split_once(',').unwrap();
+                Point {
+                    x: x.parse().unwrap(),
+                    y: y.parse().unwrap(),
+                }
+            })
+            .collect();
+        assert_eq!(read, run_hmc(&config).unwrap().samples);
+    }
+
+    #[test]
+    fn finish_receives_the_statistics_once() {
+        #[derive(Default)]
+        struct Recorder {
+            samples: Vec<Point>,
+            finished: Vec<ChainStats>,
+        }
+        impl SampleSink for Recorder {
+            fn write_sample(&mut self, sample: &Point) -> Result<(), HmcError> {
+                self.samples.write_sample(sample)
+            }
+
+            fn finish(&mut self, stats: &ChainStats) -> Result<(), HmcError> {
+                self.finished.push(stats.clone());
+                Ok(())
+            }
+        }
+
+        let mut recorder = Recorder::default();
+        let result = run_hmc_streaming(&config(500), &mut recorder).unwrap();
+        assert_eq!(recorder.samples, run_hmc(&config(500)).unwrap().samples);
+        assert_eq!(recorder.finished.len(), 1);
+        assert_eq!(recorder.finished[0].n_samples, 500);
+        assert_eq!(recorder.finished[0].acceptance_rate, result.acceptance_rate);
+
+        let needs_memory = HmcConfig {
+            target_ess: Some(100.0),
+            ..config(500)
+        };
+        assert_eq!(
+            run_hmc_streaming(&needs_memory, &mut Vec::new()),
+            Err(HmcError::UnsupportedStreaming("target_ess"))
+        );
+    }
+}
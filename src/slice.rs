@@ -0,0 +1,241 @@
+//! スライスサンプリング（勾配を使わない代替手段）
+
+use rand::Rng;
+use rand_distr::{Exp1, StandardNormal};
+
+use crate::chain::Leapfrog;
+use crate::nuts::Move;
+use crate::target::Model;
+use crate::vector::{self, Vector};
+
+/// 縮小を打ち切る回数（丸め誤差で現在位置がスライスから外れた場合などの保険）
+const MAX_SHRINK: usize = 200;
+
+/// 直交する `dim` 方向に沿って1次元のスライスサンプリング（stepping-out と shrinkage, Neal 2003）を1回ずつ行う
+///
+/// 方向の組は遷移ごとにランダムに回転させる（2次元では一様な角度だけ回転させ、それ以外の次元では
+/// [`random_basis`] を使う）。座標軸に沿った更新だけでは、対角に並んだ
+/// 山の間（条件付き分布がほぼ単峰になる）を移れないため。方向は位置に依らず選ぶので、
+/// 各方向の更新はその直線上の条件付き分布を保ち、合成しても π を不変に保つ。
+/// 反射境界の外の点はスライスの外として扱い、周期境界では評価前に折り畳む。
+pub(crate) fn transition<T, V, R>(
+    leapfrog: &Leapfrog<'_, T, V>,
+    q: &V,
+    current_u: f64,
+    initial_width: f64,
+    max_step_out: usize,
+    rng: &mut R,
+) -> Move<V>
+where
+    T: Model<V> + ?Sized,
+    V: Vector,
+    R: Rng,
+{
+    let potential = |p: &V| {
+        if leapfrog.space.contains(p) {
+            leapfrog.target.potential_at(&leapfrog.space.wrap(p))
+        } else {
+            f64::INFINITY
+        }
+    };
+    let directions = if q.dim() == 2 {
+        let angle = rng.gen_range(0.0..std::f64::consts::PI);
+        let (sin, cos) = angle.sin_cos();
+        vec![
+            vector::from_slice(q, &[cos, sin]),
+            vector::from_slice(q, &[-sin, cos]),
+        ]
+    } else {
+        random_basis(q, rng)
+    };
+    let mut position = q.clone();
+    let mut u = current_u;
+    let mut moved = false;
+    for direction in directions {
+        let along = |t: f64| {
+            let mut p = position.clone();
+            p.axpy(t, &direction);
+            p
+        };
+        let (t, new_u) = slice_1d(
+            |t| potential(&along(t)),
+            u,
+            initial_width,
+            max_step_out,
+            rng,
+        );
+        if t != 0.0 {
+            position = along(t);
+            u = new_u;
+            moved = true;
+        }
+    }
+
+    Move {
+        to: moved.then(|| leapfrog.space.wrap(&position)),
+        energy_error: u - current_u,
+        divergent: false,
+        numerical_error: false,
+        out_of_support: false,
+        accept_prob: 1.0,
+        potential_energy: u,
+        energy: u,
+        n_leapfrog: 0,
+        tree_depth: None,
+        momentum: None,
+        grad: None,
+        trajectory: None,
+    }
+}
+
+/// `like` と同じ次元の一様にランダムな向きの正規直交基底（標準正規分布のベクトルをグラム・シュミット法で直交化する）
+fn random_basis<V: Vector, R: Rng>(like: &V, rng: &mut R) -> Vec<V> {
+    let mut basis: Vec<V> = Vec::with_capacity(like.dim());
+    while basis.len() < like.dim() {
+        let mut v = like.clone();
+        for x in v.as_mut_slice() {
+            *x = rng.sample(StandardNormal);
+        }
+        for b in &basis {
+            let c = v.dot(b);
+            v.axpy(-c, b);
+        }
+        let norm = vector::norm(&v);
+        // ほぼ張られた部分空間に入ってしまった場合は引き直す
+        if norm > 1e-9 {
+            for x in v.as_mut_slice() {
+                *x /= norm;
+            }
+            basis.push(v);
+        }
+    }
+    basis
+}
+
+/// ポテンシャル `potential(t)` の直線上で t = 0 から1回スライスサンプリングし、(t, U(t)) を返す
+///
+/// 高さ y = exp(-U(0)) · V (V ~ U(0, 1)) のスライス {t : U(t) < U(0) + E}（E = -log V ~ Exp(1)）を
+/// 幅 `width` の区間から最大 `max_step_out` 回広げて囲み、区間内の一様な点がスライスに入るまで
+/// 区間を t = 0 に向けて縮める。
+fn slice_1d<R: Rng>(
+    potential: impl Fn(f64) -> f64,
+    current_u: f64,
+    width: f64,
+    max_step_out: usize,
+    rng: &mut R,
+) -> (f64, f64) {
+    let level = current_u + rng.sample::<f64, _>(Exp1);
+    let inside = |t: f64| potential(t) < level;
+
+    // stepping-out: 広げる回数の上限を左右にランダムに振り分ける
+    let mut left = -width * rng.gen::<f64>();
+    let mut right = left + width;
+    let mut n_left = (max_step_out as f64 * rng.gen::<f64>()) as usize;
+    let mut n_right = max_step_out.saturating_sub(1).saturating_sub(n_left);
+    while n_left > 0 && inside(left) {
+        left -= width;
+        n_left -= 1;
+    }
+    while n_right > 0 && inside(right) {
+        right += width;
+        n_right -= 1;
+    }
+
+    // shrinkage
+    for _ in 0..MAX_SHRINK {
+        let t = left + (right - left) * rng.gen::<f64>();
+        let u = potential(t);
+        if u < level {
+            return (t, u);
+        }
+        if t < 0.0 {
+            left = t;
+        } else {
+            right = t;
+        }
+    }
+    (0.0, current_u)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::validate::z_score_of_mean;
+    use crate::{run_hmc, Algorithm, Bimodal, DistType, HmcConfig, HmcError, Point};
+
+    #[test]
+    fn slice_matches_hmc_moments_on_standard_normal() {
+        let config = |algorithm| HmcConfig {
+            n_samples: 10_000,
+            n_warmup: 200,
+            step_size: 0.25,
+            num_steps: 7,
+            algorithm,
+            target: DistType::Normal,
+            seed: Some(64),
+            ..HmcConfig::default()
+        };
+        let slice = run_hmc(&config(Algorithm::Slice {
+            initial_width: 1.0,
+            max_step_out: 10,
+        }))
+        .unwrap();
+        let hmc = run_hmc(&config(Algorithm::Hmc)).unwrap();
+        assert_eq!(slice.acceptance_rate, 1.0);
+        assert_eq!(slice.n_leapfrog, 0);
+        let identity = [[1.0, 0.0], [0.0, 1.0]];
+        let z = z_score_of_mean(&slice.samples, &Point::default(), &identity);
+        assert!(z.x.abs() < 4.0 && z.y.abs() < 4.0, "{:?}", z);
+        let (slice_cov, hmc_cov) = (
+            crate::validate::sample_cov(&slice.samples),
+            crate::validate::sample_cov(&hmc.samples),
+        );
+        for i in 0..2 {
+            assert!((slice_cov[i][i] - 1.0).abs() < 0.1, "{:?}", slice_cov);
+            assert!(
+                (slice_cov[i][i] - hmc_cov[i][i]).abs() < 0.1,
+                "{:?} vs {:?}",
+                slice_cov,
+                hmc_cov
+            );
+        }
+    }
+
+    #[test]
+    fn slice_visits_both_bimodal_modes_without_tuning() {
+        // 既定の幅のまま、対角に離れた2つの山の間を行き来する
+        let target = Bimodal::default();
+        let config: HmcConfig = serde_json::from_str(r#"{"algorithm": {"slice": {}}}"#).unwrap();
+        let config = HmcConfig {
+            n_samples: 20_000,
+            mode_centers: target.centers.to_vec(),
+            target: DistType::Bimodal(target),
+            seed: Some(64),
+            ..config
+        };
+        let result = run_hmc(&config).unwrap();
+        for fraction in &result.mode_occupancy {
+            assert!(*fraction > 0.1, "{:?}", result.mode_occupancy);
+        }
+        assert!(result.mode_switches.unwrap() >= 2);
+    }
+
+    #[test]
+    fn slice_rejects_invalid_width_and_loads_from_json() {
+        let config = HmcConfig {
+            algorithm: Algorithm::Slice {
+                initial_width: -1.0,
+                max_step_out: 10,
+            },
+            ..HmcConfig::default()
+        };
+        assert_eq!(config.validate(), Err(HmcError::InvalidSliceWidth(-1.0)));
+        let parsed: HmcConfig = serde_json::from_str(r#"{"algorithm": {"slice": {}}}"#).unwrap();
+        assert_eq!(
+            parsed.algorithm,
+            Algorithm::Slice {
+                initial_width: 1.0,
+                max_step_out: 10
+            }
+        );
+    }
+}
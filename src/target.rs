@@ -0,0 +1,2781 @@
+//! ターゲット分布（ポテンシャル U(q) = -log p(q)）
+
+use serde::{Deserialize, Serialize};
+
+use crate::lanes::{MixtureLanes, MIN_COMPONENTS};
+use crate::numdiff::NumDiff;
+use crate::{DistType, HmcError, Point, Vector};
+
+/// サンプリング対象の分布
+///
+/// `potential` だけ実装すれば、勾配は中心差分で近似される。
+/// 解析的な勾配を実装した場合は `has_analytic_gradient` も `true` を返すようにする
+/// （`HmcConfig::numdiff` の指定がそちらを上書きしなくなる）。
+///
+/// 台の外（密度0）では `potential` が `f64::INFINITY` を返せばよい。終点のポテンシャルが
+/// 有限でない提案は必ず棄却される。軌道の途中では台の外でも `gradient` が呼ばれうるが、
+/// 有限でない値（`NaN` など）を返せばその場で軌道が打ち切られて棄却され、運動量やエネルギーに
+/// `NaN` は残らない。開始位置は台の内側でなければならない。
+///
+/// ```
+/// use hamiltonian_sampler_rs::{run_hmc_with_target, HmcConfig, Point, TargetDistribution};
+///
+/// struct StandardNormal;
+///
+/// impl TargetDistribution for StandardNormal {
+///     fn potential(&self, q: &Point) -> f64 {
+///         0.5 * (q.x * q.x + q.y * q.y)
+///     }
+/// }
+///
+/// let config = HmcConfig { seed: Some(1), ..HmcConfig::default() };
+/// let result = run_hmc_with_target(&config, StandardNormal).unwrap();
+/// assert_eq!(result.samples.len(), 1000);
+/// ```
+pub trait TargetDistribution {
+    /// ポテンシャルエネルギー U(q)
+    fn potential(&self, q: &Point) -> f64;
+
+    /// ポテンシャルエネルギーの勾配 ∇U(q)（既定は中心差分近似）
+    fn gradient(&self, q: &Point) -> Point {
+        numerical_gradient(self, q)
+    }
+
+    /// `gradient` が数値微分ではなく解析的に計算されているか
+    fn has_analytic_gradient(&self) -> bool {
+        false
+    }
+
+    /// パラメータの検証（既定では何もしない）。サンプリング開始前に呼ばれる。
+    fn validate(&self) -> Result<(), HmcError> {
+        Ok(())
+    }
+
+    /// 正規化されていない対数密度 log p(q) = -U(q)
+    ///
+    /// サンプラーが使う `potential` の符号を変えたもので、等高線の描画などで
+    /// 式を再実装せずにサンプラーと同じ分布を得るのに使う。
+    fn log_density(&self, q: &Point) -> f64 {
+        -self.potential(q)
+    }
+
+    /// 対数密度の勾配 ∇log p(q) = -∇U(q)（`gradient` の符号を変えたもの）
+    fn grad_log_density(&self, q: &Point) -> Point {
+        let g = self.gradient(q);
+        Point { x: -g.x, y: -g.y }
+    }
+}
+
+impl<T: TargetDistribution + ?Sized> TargetDistribution for &T {
+    fn potential(&self, q: &Point) -> f64 {
+        (**self).potential(q)
+    }
+
+    fn gradient(&self, q: &Point) -> Point {
+        (**self).gradient(q)
+    }
+
+    fn has_analytic_gradient(&self) -> bool {
+        (**self).has_analytic_gradient()
+    }
+
+    fn validate(&self) -> Result<(), HmcError> {
+        (**self).validate()
+    }
+}
+
+/// `DistType::Custom` の中身は任意の型なので、`Debug` では型を問わず固定の文字列を書く
+impl std::fmt::Debug for dyn TargetDistribution + Send + Sync {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("<custom target>")
+    }
+}
+
+/// 中身を比較できないため、同じオブジェクトを指すときだけ等しいとみなす
+impl PartialEq for dyn TargetDistribution + Send + Sync {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::addr_eq(self, other)
+    }
+}
+
+/// `potential` の中心差分（刻み幅 1e-4）による勾配の近似
+pub fn numerical_gradient<T: TargetDistribution + ?Sized>(target: &T, p: &Point) -> Point {
+    NumDiff::default().gradient(target, p)
+}
+
+/// 矩形 `[xmin, xmax] × [ymin, ymax]` 上の `nx × ny` 個の格子点でポテンシャルを評価する
+///
+/// 戻り値は行優先で、`values[j * nx + i]` が (x_i, y_j) での U
+/// （x_i = xmin + i (xmax - xmin) / (nx - 1)、y_j も同様。`nx == 1` なら x_0 = xmin）。
+/// [`GridPotential::new`] の引数と同じ並びなので、そのまま格子ポテンシャルにもできる。
+/// 非有限の値（台の外の `inf` や `NaN`）は加工せずにそのまま返す。
+///
+/// ```
+/// use hamiltonian_sampler_rs::{evaluate_potential_grid, StandardNormal2};
+///
+/// let values = evaluate_potential_grid(&StandardNormal2, -1.0, 1.0, 0.0, 2.0, 3, 2);
+/// assert_eq!(values, vec![0.5, 0.0, 0.5, 2.5, 2.0, 2.5]);
+/// ```
+pub fn evaluate_potential_grid<T: TargetDistribution + ?Sized>(
+    target: &T,
+    xmin: f64,
+    xmax: f64,
+    ymin: f64,
+    ymax: f64,
+    nx: usize,
+    ny: usize,
+) -> Vec<f64> {
+    let node = |min: f64, max: f64, n: usize, k: usize| {
+        if n > 1 {
+            min + (max - min) * k as f64 / (n - 1) as f64
+        } else {
+            min
+        }
+    };
+    let mut values = Vec::with_capacity(nx * ny);
+    for j in 0..ny {
+        let y = node(ymin, ymax, ny, j);
+        values.extend((0..nx).map(|i| {
+            target.potential(&Point {
+                x: node(xmin, xmax, nx, i),
+                y,
+            })
+        }));
+    }
+    values
+}
+
+/// サンプラーが使う勾配
+///
+/// 解析的な勾配を持たないターゲットで `numdiff` が指定されていれば、そのスキームで数値微分する。
+pub(crate) fn sampler_gradient<V: Vector, T: Model<V> + ?Sized>(
+    target: &T,
+    q: &V,
+    numdiff: Option<&NumDiff>,
+) -> V {
+    match numdiff {
+        Some(numdiff) if !target.has_exact_gradient() => {
+            numdiff.gradient_of(|q| target.potential_at(q), q)
+        }
+        _ => target.gradient_at(q),
+    }
+}
+
+/// チェーンが状態 `V` の上で使うターゲット
+///
+/// 2次元の [`TargetDistribution`] は `V = Point` で、N次元のターゲットは `ndim` の `Nd` で包んで実装する。
+/// メソッド名は `TargetDistribution` のものと重ならないようにしている。
+pub(crate) trait Model<V> {
+    /// ポテンシャルエネルギー U(q)
+    fn potential_at(&self, q: &V) -> f64;
+
+    /// ポテンシャルエネルギーの勾配 ∇U(q)
+    fn gradient_at(&self, q: &V) -> V;
+
+    /// `gradient_at` が数値微分ではなく解析的に計算されているか
+    fn has_exact_gradient(&self) -> bool;
+}
+
+impl<T: TargetDistribution + ?Sized> Model<Point> for T {
+    fn potential_at(&self, q: &Point) -> f64 {
+        self.potential(q)
+    }
+
+    fn gradient_at(&self, q: &Point) -> Point {
+        self.gradient(q)
+    }
+
+    fn has_exact_gradient(&self) -> bool {
+        self.has_analytic_gradient()
+    }
+}
+
+/// クロージャで与えるターゲット分布
+///
+/// 構造体を定義せずに試したいとき用。勾配を省略すると中心差分で近似する。
+/// クロージャが `Send + Sync` なら `Target` も `Send + Sync` になり、並列チェーンでも使える。
+///
+/// ```
+/// use hamiltonian_sampler_rs::{run_hmc_with_target, HmcConfig, Point, Target};
+///
+/// let target = Target::from_fn(|p: &Point| 0.5 * (p.x * p.x + p.y * p.y));
+/// let config = HmcConfig {
+///     n_samples: 4000,
+///     step_size: 0.2,
+///     num_steps: 10,
+///     seed: Some(3),
+///     ..HmcConfig::default()
+/// };
+/// let result = run_hmc_with_target(&config, target).unwrap();
+///
+/// let n = result.samples.len() as f64;
+/// let mean = result.samples.iter().map(|p| p.x).sum::<f64>() / n;
+/// let var = result.samples.iter().map(|p| (p.x - mean).powi(2)).sum::<f64>() / n;
+/// assert!((var - 1.0).abs() < 0.15);
+/// ```
+pub struct Target<U, G = fn(&Point) -> Point> {
+    potential: U,
+    gradient: Option<G>,
+}
+
+impl<U: Fn(&Point) -> f64> Target<U> {
+    /// ポテンシャルだけを与える（勾配は数値微分）
+    pub fn from_fn(potential: U) -> Self {
+        Self {
+            potential,
+            gradient: None,
+        }
+    }
+}
+
+impl<U: Fn(&Point) -> f64, G: Fn(&Point) -> Point> Target<U, G> {
+    /// ポテンシャルと解析的な勾配を与える
+    pub fn from_fn_with_grad(potential: U, gradient: G) -> Self {
+        Self {
+            potential,
+            gradient: Some(gradient),
+        }
+    }
+}
+
+impl<U: Fn(&Point) -> f64, G: Fn(&Point) -> Point> TargetDistribution for Target<U, G> {
+    fn potential(&self, q: &Point) -> f64 {
+        (self.potential)(q)
+    }
+
+    fn gradient(&self, q: &Point) -> Point {
+        match &self.gradient {
+            Some(gradient) => gradient(q),
+            None => numerical_gradient(self, q),
+        }
+    }
+
+    fn has_analytic_gradient(&self) -> bool {
+        self.gradient.is_some()
+    }
+}
+
+/// 逆温度 β = 1/T で平坦化したターゲット U_T(q) = β U(q)（密度は p(q)^β に比例する）
+///
+/// T > 1 にすると山の間の障壁が低くなり、単純なHMCでも山の間を行き来しやすくなる。
+/// `HmcConfig::temperature` を指定すると、チェーンはターゲットをこれで包んでサンプリングする。
+///
+/// ```
+/// use hamiltonian_sampler_rs::{Banana, Point, Tempered, TargetDistribution};
+///
+/// let q = Point { x: 0.0, y: 1.0 };
+/// let tempered = Tempered::with_temperature(Banana::default(), 4.0);
+/// assert_eq!(tempered.potential(&q), Banana::default().potential(&q) / 4.0);
+/// ```
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct Tempered<T> {
+    /// 元のターゲット
+    pub inner: T,
+    /// 逆温度 β（正の有限値。1なら元の分布）
+    pub inv_temp: f64,
+}
+
+impl<T> Tempered<T> {
+    /// 温度 T（= 1/β）で平坦化する
+    pub fn with_temperature(inner: T, temperature: f64) -> Self {
+        Self {
+            inner,
+            inv_temp: temperature.recip(),
+        }
+    }
+
+    /// 温度 T = 1/β
+    pub fn temperature(&self) -> f64 {
+        self.inv_temp.recip()
+    }
+}
+
+impl<T: TargetDistribution> TargetDistribution for Tempered<T> {
+    fn potential(&self, q: &Point) -> f64 {
+        self.inv_temp * self.inner.potential(q)
+    }
+
+    fn gradient(&self, q: &Point) -> Point {
+        let g = self.inner.gradient(q);
+        Point {
+            x: self.inv_temp * g.x,
+            y: self.inv_temp * g.y,
+        }
+    }
+
+    fn has_analytic_gradient(&self) -> bool {
+        self.inner.has_analytic_gradient()
+    }
+
+    fn validate(&self) -> Result<(), HmcError> {
+        check_positive("inv_temp", self.inv_temp)?;
+        self.inner.validate()
+    }
+}
+
+/// 2つの正規分布の混合からなる二峰性分布
+///
+/// 山 `i` の密度は `weights[i] / scales[i] * exp(-|q - centers[i]|^2 / scales[i])` に比例する
+/// （`scales` は各軸の分散の2倍、`weights` は混合比で、合計が1である必要はない）。
+/// ポテンシャルはlog-sum-expで計算するため、両方の山から遠く離れた点でも有限になる。
+///
+/// ```
+/// use hamiltonian_sampler_rs::{Bimodal, Point, TargetDistribution};
+///
+/// let target = Bimodal {
+///     centers: [Point { x: 6.0, y: 6.0 }, Point { x: -6.0, y: -6.0 }],
+///     ..Bimodal::default()
+/// };
+/// assert!(target.potential(&Point { x: 0.0, y: 0.0 }).is_finite());
+/// assert!(target.potential(&Point { x: 100.0, y: 100.0 }).is_finite());
+/// ```
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Bimodal {
+    /// 各山の中心
+    pub centers: [Point; 2],
+    /// 各山の広がり（正の有限値）
+    pub scales: [f64; 2],
+    /// 各山の重み（非負の有限値、少なくとも一方は正）
+    pub weights: [f64; 2],
+}
+
+impl Default for Bimodal {
+    /// (2.5, 2.5) と (-2.5, -2.5) に同じ重み・広がりの山を持つ
+    fn default() -> Self {
+        Self {
+            centers: [Point { x: 2.5, y: 2.5 }, Point { x: -2.5, y: -2.5 }],
+            scales: [1.5, 1.5],
+            weights: [1.0, 1.0],
+        }
+    }
+}
+
+impl Bimodal {
+    /// 各山の対数密度（正規化定数の共通部分を除く）
+    fn log_components(&self, p: &Point) -> [f64; 2] {
+        std::array::from_fn(|i| {
+            let c = &self.centers[i];
+            let d = (p.x - c.x).powi(2) + (p.y - c.y).powi(2);
+            self.weights[i].ln() - self.scales[i].ln() - d / self.scales[i]
+        })
+    }
+}
+
+impl TargetDistribution for Bimodal {
+    fn potential(&self, p: &Point) -> f64 {
+        let [a1, a2] = self.log_components(p);
+        let m = a1.max(a2);
+        -(m + ((a1 - m).exp() + (a2 - m).exp()).ln())
+    }
+
+    /// ∇U = Σ r_i · 2(q - c_i) / s_i（r は各山の対数密度のソフトマックス）
+    fn gradient(&self, p: &Point) -> Point {
+        let [a1, a2] = self.log_components(p);
+        // 両方の密度がアンダーフローする遠方でも有限になるよう、差から重みを求める
+        let r1 = 1.0 / (1.0 + (a2 - a1).exp());
+        let mut g = Point { x: 0.0, y: 0.0 };
+        for ((r, c), s) in [r1, 1.0 - r1].iter().zip(&self.centers).zip(&self.scales) {
+            g.x += r * 2.0 * (p.x - c.x) / s;
+            g.y += r * 2.0 * (p.y - c.y) / s;
+        }
+        g
+    }
+
+    fn has_analytic_gradient(&self) -> bool {
+        true
+    }
+
+    fn validate(&self) -> Result<(), HmcError> {
+        for (i, c) in self.centers.iter().enumerate() {
+            for value in [c.x, c.y] {
+                if !value.is_finite() {
+                    return Err(HmcError::InvalidTargetParam {
+                        param: format!("centers[{}]", i),
+                        value,
+                    });
+                }
+            }
+        }
+        for (i, &value) in self.scales.iter().enumerate() {
+            if !(value.is_finite() && value > 0.0) {
+                return Err(HmcError::InvalidTargetParam {
+                    param: format!("scales[{}]", i),
+                    value,
+                });
+            }
+        }
+        for (i, &value) in self.weights.iter().enumerate() {
+            if !(value.is_finite() && value >= 0.0) {
+                return Err(HmcError::InvalidTargetParam {
+                    param: format!("weights[{}]", i),
+                    value,
+                });
+            }
+        }
+        if self.weights.iter().all(|&w| w == 0.0) {
+            return Err(HmcError::InvalidTargetParam {
+                param: "weights".to_string(),
+                value: 0.0,
+            });
+        }
+        Ok(())
+    }
+}
+
+/// バナナ型（Rosenbrock）分布 U = (a - x)^2 + b (y - x^2)^2
+///
+/// モードは (a, a^2)。`b` を大きくするほど谷が細く曲がり、積分器にとって難しくなる
+/// （古典的なRosenbrock関数は a = 1, b = 100）。
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Banana {
+    /// モードの x 座標（有限値）
+    pub a: f64,
+    /// 谷の曲率（正の有限値）
+    pub b: f64,
+}
+
+impl Default for Banana {
+    fn default() -> Self {
+        Self { a: 1.0, b: 10.0 }
+    }
+}
+
+impl TargetDistribution for Banana {
+    fn potential(&self, p: &Point) -> f64 {
+        (self.a - p.x).powi(2) + self.b * (p.y - p.x.powi(2)).powi(2)
+    }
+
+    fn gradient(&self, p: &Point) -> Point {
+        let r = p.y - p.x.powi(2);
+        Point {
+            x: -2.0 * (self.a - p.x) - 4.0 * self.b * p.x * r,
+            y: 2.0 * self.b * r,
+        }
+    }
+
+    fn has_analytic_gradient(&self) -> bool {
+        true
+    }
+
+    fn validate(&self) -> Result<(), HmcError> {
+        if !self.a.is_finite() {
+            return Err(HmcError::InvalidTargetParam {
+                param: "a".to_string(),
+                value: self.a,
+            });
+        }
+        if !(self.b.is_finite() && self.b > 0.0) {
+            return Err(HmcError::InvalidTargetParam {
+                param: "b".to_string(),
+                value: self.b,
+            });
+        }
+        Ok(())
+    }
+}
+
+/// 連鎖型のN次元Rosenbrock分布 U = Σ_i [(a - x_i)^2 + b (x_{i+1} - x_i^2)^2]（i = 0..dim-2）
+///
+/// モードは全成分が a の点（a = 1 なら全て1のベクトル）。次元が増えるほど曲がった谷が
+/// 連なって難しくなるベンチマーク。2次元の [`TargetDistribution`] としては `dim == 2`（[`Banana`] と同じ形）でだけ使え、
+/// 任意の次元では [`crate::TargetDistributionNd`] として [`crate::run_hmc_nd`] でサンプリングする。
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct RosenbrockNd {
+    /// 次元（2以上）
+    pub dim: usize,
+    /// モードの各成分（有限値）
+    pub a: f64,
+    /// 谷の曲率（正の有限値）
+    pub b: f64,
+}
+
+impl Default for RosenbrockNd {
+    fn default() -> Self {
+        Self {
+            dim: 2,
+            a: 1.0,
+            b: 100.0,
+        }
+    }
+}
+
+impl RosenbrockNd {
+    /// `x`（長さ `dim`）でのポテンシャル
+    pub fn potential_nd(&self, x: &[f64]) -> f64 {
+        debug_assert_eq!(x.len(), self.dim);
+        x.windows(2)
+            .map(|w| (self.a - w[0]).powi(2) + self.b * (w[1] - w[0].powi(2)).powi(2))
+            .sum()
+    }
+
+    /// `x`（長さ `dim`）でのポテンシャルの勾配
+    pub fn gradient_nd(&self, x: &[f64]) -> Vec<f64> {
+        let mut grad = vec![0.0; x.len()];
+        self.gradient_nd_into(x, &mut grad);
+        grad
+    }
+
+    /// `gradient_nd` を `grad`（長さ `dim`）に書き込む
+    pub(crate) fn gradient_nd_into(&self, x: &[f64], grad: &mut [f64]) {
+        debug_assert_eq!(x.len(), self.dim);
+        grad.fill(0.0);
+        for (i, w) in x.windows(2).enumerate() {
+            let r = w[1] - w[0].powi(2);
+            grad[i] += -2.0 * (self.a - w[0]) - 4.0 * self.b * w[0] * r;
+            grad[i + 1] += 2.0 * self.b * r;
+        }
+    }
+
+    /// パラメータが有効か検証する（次元は問わない）
+    pub fn validate_nd(&self) -> Result<(), HmcError> {
+        if self.dim < 2 {
+            return Err(HmcError::InvalidTargetParam {
+                param: "dim".to_string(),
+                value: self.dim as f64,
+            });
+        }
+        Banana {
+            a: self.a,
+            b: self.b,
+        }
+        .validate()
+    }
+}
+
+impl TargetDistribution for RosenbrockNd {
+    fn potential(&self, p: &Point) -> f64 {
+        self.potential_nd(&[p.x, p.y])
+    }
+
+    fn gradient(&self, p: &Point) -> Point {
+        let g = self.gradient_nd(&[p.x, p.y]);
+        Point { x: g[0], y: g[1] }
+    }
+
+    fn has_analytic_gradient(&self) -> bool {
+        true
+    }
+
+    fn validate(&self) -> Result<(), HmcError> {
+        self.validate_nd()?;
+        if self.dim != 2 {
+            return Err(HmcError::InvalidTargetParam {
+                param: "dim".to_string(),
+                value: self.dim as f64,
+            });
+        }
+        Ok(())
+    }
+}
+
+/// Nealの漏斗（funnel）分布: y ~ N(0, scale^2)、x | y ~ N(0, exp(y))
+///
+/// y が小さい「首」の部分では x の幅が指数的に狭くなるため、固定ステップ幅のHMCでは
+/// 首で発散しやすい。ステップ幅の問題を調べる標準的なテスト用分布。
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Funnel {
+    /// y の標準偏差（正の有限値）
+    pub scale: f64,
+}
+
+impl Default for Funnel {
+    fn default() -> Self {
+        Self { scale: 3.0 }
+    }
+}
+
+impl TargetDistribution for Funnel {
+    /// U = y^2 / 2 scale^2 + x^2 / 2 exp(y) + y / 2（最後の項は x の正規化定数）
+    fn potential(&self, p: &Point) -> f64 {
+        0.5 * (p.y / self.scale).powi(2) + 0.5 * p.x.powi(2) * (-p.y).exp() + 0.5 * p.y
+    }
+
+    fn gradient(&self, p: &Point) -> Point {
+        let inv_var = (-p.y).exp();
+        Point {
+            x: p.x * inv_var,
+            y: p.y / self.scale.powi(2) - 0.5 * p.x.powi(2) * inv_var + 0.5,
+        }
+    }
+
+    fn has_analytic_gradient(&self) -> bool {
+        true
+    }
+
+    fn validate(&self) -> Result<(), HmcError> {
+        if !(self.scale.is_finite() && self.scale > 0.0) {
+            return Err(HmcError::InvalidTargetParam {
+                param: "scale".to_string(),
+                value: self.scale,
+            });
+        }
+        Ok(())
+    }
+}
+
+/// 半径 `radius` の円周の周りに幅 `width` で集中したドーナツ型分布
+///
+/// U = (|q| - radius)^2 / 2 width^2。原点では勾配が定義されないため0を返す。
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Ring {
+    /// 円の半径（非負の有限値）
+    pub radius: f64,
+    /// 円周方向に垂直な広がりの標準偏差（正の有限値）
+    pub width: f64,
+}
+
+impl Default for Ring {
+    fn default() -> Self {
+        Self {
+            radius: 3.0,
+            width: 0.3,
+        }
+    }
+}
+
+impl TargetDistribution for Ring {
+    fn potential(&self, p: &Point) -> f64 {
+        0.5 * ((p.x.hypot(p.y) - self.radius) / self.width).powi(2)
+    }
+
+    fn gradient(&self, p: &Point) -> Point {
+        let norm = p.x.hypot(p.y);
+        if norm == 0.0 {
+            return Point { x: 0.0, y: 0.0 };
+        }
+        let k = (norm - self.radius) / (self.width.powi(2) * norm);
+        Point {
+            x: k * p.x,
+            y: k * p.y,
+        }
+    }
+
+    fn has_analytic_gradient(&self) -> bool {
+        true
+    }
+
+    fn validate(&self) -> Result<(), HmcError> {
+        if !(self.radius.is_finite() && self.radius >= 0.0) {
+            return Err(HmcError::InvalidTargetParam {
+                param: "radius".to_string(),
+                value: self.radius,
+            });
+        }
+        if !(self.width.is_finite() && self.width > 0.0) {
+            return Err(HmcError::InvalidTargetParam {
+                param: "width".to_string(),
+                value: self.width,
+            });
+        }
+        Ok(())
+    }
+}
+
+/// 平均 `mean`、共分散行列 `cov` の2次元正規分布
+///
+/// 生成時に共分散行列が対称かつ正定値であることを確かめ、精度行列と対数行列式を前計算しておく。
+/// シリアライズ形式は `{"mean": {"x": .., "y": ..}, "cov": [[..], [..]]}`。
+///
+/// ```
+/// use hamiltonian_sampler_rs::{MvNormal2, Point, TargetDistribution};
+///
+/// let target = MvNormal2::new(Point { x: 1.0, y: 0.0 }, [[1.0, 0.95], [0.95, 1.0]]).unwrap();
+/// assert_eq!(target.gradient(&Point { x: 1.0, y: 0.0 }), Point { x: 0.0, y: 0.0 });
+/// assert!(MvNormal2::new(Point::default(), [[1.0, 2.0], [2.0, 1.0]]).is_err());
+/// ```
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(try_from = "MvNormal2Params", into = "MvNormal2Params")]
+pub struct MvNormal2 {
+    mean: Point,
+    cov: [[f64; 2]; 2],
+    precision: [[f64; 2]; 2],
+    log_det: f64,
+}
+
+/// `MvNormal2` のシリアライズ用の表現（前計算した値を含まない）
+#[derive(Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct MvNormal2Params {
+    mean: Point,
+    cov: [[f64; 2]; 2],
+}
+
+impl Default for MvNormal2Params {
+    fn default() -> Self {
+        Self {
+            mean: Point { x: 0.0, y: 0.0 },
+            cov: [[1.0, 0.0], [0.0, 1.0]],
+        }
+    }
+}
+
+impl TryFrom<MvNormal2Params> for MvNormal2 {
+    type Error = HmcError;
+
+    fn try_from(params: MvNormal2Params) -> Result<Self, HmcError> {
+        MvNormal2::new(params.mean, params.cov)
+    }
+}
+
+impl From<MvNormal2> for MvNormal2Params {
+    fn from(target: MvNormal2) -> Self {
+        Self {
+            mean: target.mean,
+            cov: target.cov,
+        }
+    }
+}
+
+impl Default for MvNormal2 {
+    /// 標準正規分布
+    fn default() -> Self {
+        let params = MvNormal2Params::default();
+        Self::new(params.mean, params.cov).expect("identity covariance is positive definite")
+    }
+}
+
+impl MvNormal2 {
+    /// 共分散行列が対称正定値でなければ `HmcError::InvalidCovariance`、平均が有限でなければ
+    /// `HmcError::InvalidTargetParam` を返す
+    pub fn new(mean: Point, cov: [[f64; 2]; 2]) -> Result<Self, HmcError> {
+        if !(mean.x.is_finite() && mean.y.is_finite()) {
+            let value = if mean.x.is_finite() { mean.y } else { mean.x };
+            return Err(HmcError::InvalidTargetParam {
+                param: "mean".to_string(),
+                value,
+            });
+        }
+        let [[a, b], [c, d]] = cov;
+        let det = a * d - b * c;
+        let finite = cov.iter().flatten().all(|v| v.is_finite());
+        if !(finite && b == c && a > 0.0 && det > 0.0) {
+            return Err(HmcError::InvalidCovariance(cov));
+        }
+        Ok(Self {
+            mean,
+            cov,
+            precision: [[d / det, -b / det], [-c / det, a / det]],
+            log_det: det.ln(),
+        })
+    }
+
+    /// 平均
+    pub fn mean(&self) -> &Point {
+        &self.mean
+    }
+
+    /// 共分散行列
+    pub fn cov(&self) -> [[f64; 2]; 2] {
+        self.cov
+    }
+}
+
+impl TargetDistribution for MvNormal2 {
+    /// U = (q - μ)^T Σ^-1 (q - μ) / 2 + log det Σ / 2 + log 2π
+    fn potential(&self, p: &Point) -> f64 {
+        let g = self.gradient(p);
+        let (dx, dy) = (p.x - self.mean.x, p.y - self.mean.y);
+        0.5 * (dx * g.x + dy * g.y) + 0.5 * self.log_det + (2.0 * std::f64::consts::PI).ln()
+    }
+
+    /// ∇U = Σ^-1 (q - μ)
+    fn gradient(&self, p: &Point) -> Point {
+        let (dx, dy) = (p.x - self.mean.x, p.y - self.mean.y);
+        let [[p00, p01], [p10, p11]] = self.precision;
+        Point {
+            x: p00 * dx + p01 * dy,
+            y: p10 * dx + p11 * dy,
+        }
+    }
+
+    fn has_analytic_gradient(&self) -> bool {
+        true
+    }
+}
+
+/// `GaussianMixture` の1成分
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct MixtureComponent {
+    /// 混合比（正の有限値。`GaussianMixture::new` で合計1に正規化される）
+    pub weight: f64,
+    /// 平均
+    pub mean: Point,
+    /// 共分散行列（対称正定値）
+    pub cov: [[f64; 2]; 2],
+}
+
+/// 任意個の2次元正規分布の混合
+///
+/// ポテンシャルはlog-sum-exp、勾配は各成分の負担率で重み付けした成分の勾配の和で計算する。
+/// 成分が4個以上あれば全成分を並べてSIMD命令に載る形で評価する（64成分で2〜3倍速い）。
+/// exp も多項式で近似するが、結果は1成分ずつ評価した場合と相対誤差 1e-12 以内で一致する。
+/// シリアライズ形式は成分の配列 `[{"weight": .., "mean": {..}, "cov": [[..], [..]]}, ...]`。
+///
+/// ```
+/// use hamiltonian_sampler_rs::{GaussianMixture, MixtureComponent, Point};
+///
+/// let component = |weight, x| MixtureComponent {
+///     weight,
+///     mean: Point { x, y: 0.0 },
+///     cov: [[1.0, 0.0], [0.0, 1.0]],
+/// };
+/// let mixture = GaussianMixture::new(vec![component(1.0, -1.0), component(3.0, 1.0)]).unwrap();
+/// assert_eq!(mixture.components()[1].weight, 0.75);
+/// ```
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(try_from = "Vec<MixtureComponent>", into = "Vec<MixtureComponent>")]
+pub struct GaussianMixture {
+    components: Vec<MixtureComponent>,
+    log_weights: Vec<f64>,
+    normals: Vec<MvNormal2>,
+    lanes: MixtureLanes,
+}
+
+impl TryFrom<Vec<MixtureComponent>> for GaussianMixture {
+    type Error = HmcError;
+
+    fn try_from(components: Vec<MixtureComponent>) -> Result<Self, HmcError> {
+        GaussianMixture::new(components)
+    }
+}
+
+impl From<GaussianMixture> for Vec<MixtureComponent> {
+    fn from(mixture: GaussianMixture) -> Self {
+        mixture.components
+    }
+}
+
+impl Default for GaussianMixture {
+    /// 標準正規分布1成分
+    fn default() -> Self {
+        Self::new(vec![MixtureComponent {
+            weight: 1.0,
+            mean: Point { x: 0.0, y: 0.0 },
+            cov: [[1.0, 0.0], [0.0, 1.0]],
+        }])
+        .expect("standard normal component is valid")
+    }
+}
+
+impl GaussianMixture {
+    /// 成分を検証し、混合比を合計1に正規化する
+    pub fn new(mut components: Vec<MixtureComponent>) -> Result<Self, HmcError> {
+        if components.is_empty() {
+            return Err(HmcError::InvalidTargetParam {
+                param: "components".to_string(),
+                value: 0.0,
+            });
+        }
+        for (i, c) in components.iter().enumerate() {
+            if !(c.weight.is_finite() && c.weight > 0.0) {
+                return Err(HmcError::InvalidTargetParam {
+                    param: format!("components[{}].weight", i),
+                    value: c.weight,
+                });
+            }
+        }
+        let total: f64 = components.iter().map(|c| c.weight).sum();
+        for c in &mut components {
+            c.weight /= total;
+        }
+        let normals = components
+            .iter()
+            .map(|c| MvNormal2::new(c.mean.clone(), c.cov))
+            .collect::<Result<Vec<_>, _>>()?;
+        let log_weights: Vec<f64> = components.iter().map(|c| c.weight.ln()).collect();
+        let lanes = MixtureLanes::new(log_weights.iter().zip(&normals).map(|(log_w, normal)| {
+            let offset = log_w - 0.5 * normal.log_det - (2.0 * std::f64::consts::PI).ln();
+            (&normal.mean, normal.precision, offset)
+        }));
+        Ok(Self {
+            log_weights,
+            components,
+            normals,
+            lanes,
+        })
+    }
+
+    /// 正規化済みの成分
+    pub fn components(&self) -> &[MixtureComponent] {
+        &self.components
+    }
+
+    /// `q` での各成分の負担率（事後的にその成分から生成された確率）
+    pub fn responsibilities(&self, q: &Point) -> Vec<f64> {
+        let log_p: Vec<f64> = self.log_joint(q).collect();
+        let max = log_p.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+        let unnormalized: Vec<f64> = log_p.iter().map(|l| (l - max).exp()).collect();
+        let total: f64 = unnormalized.iter().sum();
+        unnormalized.into_iter().map(|u| u / total).collect()
+    }
+
+    /// 各成分の log(w_k p_k(q))
+    fn log_joint<'a>(&'a self, q: &'a Point) -> impl Iterator<Item = f64> + 'a {
+        self.log_weights
+            .iter()
+            .zip(&self.normals)
+            .map(move |(log_w, normal)| log_w - normal.potential(q))
+    }
+
+    /// 成分を1つずつ評価するポテンシャル
+    fn potential_scalar(&self, q: &Point) -> f64 {
+        let max = self.log_joint(q).fold(f64::NEG_INFINITY, f64::max);
+        -(max + self.log_joint(q).map(|l| (l - max).exp()).sum::<f64>().ln())
+    }
+
+    /// 成分を1つずつ評価する勾配
+    fn gradient_scalar(&self, q: &Point) -> Point {
+        let mut g = Point { x: 0.0, y: 0.0 };
+        for (r, normal) in self.responsibilities(q).into_iter().zip(&self.normals) {
+            let gk = normal.gradient(q);
+            g.x += r * gk.x;
+            g.y += r * gk.y;
+        }
+        g
+    }
+}
+
+impl TargetDistribution for GaussianMixture {
+    fn potential(&self, q: &Point) -> f64 {
+        if self.components.len() >= MIN_COMPONENTS {
+            self.lanes.potential(q)
+        } else {
+            self.potential_scalar(q)
+        }
+    }
+
+    /// ∇U = Σ r_k ∇U_k（r は負担率）
+    fn gradient(&self, q: &Point) -> Point {
+        if self.components.len() >= MIN_COMPONENTS {
+            self.lanes.gradient(q)
+        } else {
+            self.gradient_scalar(q)
+        }
+    }
+
+    fn has_analytic_gradient(&self) -> bool {
+        true
+    }
+}
+
+/// 自由度 `dof` の2次元スチューデントのt分布（等方的、位置 `mean`、尺度 `scale`）
+///
+/// 密度は (1 + |q - mean|^2 / (dof scale^2))^(-(dof + 2) / 2) に比例する（ポテンシャルは定数項を除く）。
+/// 裾が重く、`dof` が小さいほど遠方まで確率が残るが、ポテンシャルは対数的にしか増えないためどこでも有限。
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct StudentT {
+    /// 自由度（正の有限値）
+    pub dof: f64,
+    /// 位置（分布の中心）
+    pub mean: Point,
+    /// 尺度（正の有限値）
+    pub scale: f64,
+}
+
+impl Default for StudentT {
+    fn default() -> Self {
+        Self {
+            dof: 3.0,
+            mean: Point { x: 0.0, y: 0.0 },
+            scale: 1.0,
+        }
+    }
+}
+
+impl StudentT {
+    /// 標準化した距離の2乗 |q - mean|^2 / scale^2
+    fn squared_distance(&self, p: &Point) -> f64 {
+        ((p.x - self.mean.x).powi(2) + (p.y - self.mean.y).powi(2)) / self.scale.powi(2)
+    }
+}
+
+impl TargetDistribution for StudentT {
+    /// U = (dof + 2) / 2 · log(1 + r^2 / dof)
+    fn potential(&self, p: &Point) -> f64 {
+        0.5 * (self.dof + 2.0) * (self.squared_distance(p) / self.dof).ln_1p()
+    }
+
+    fn gradient(&self, p: &Point) -> Point {
+        let k = (self.dof + 2.0) / ((self.dof + self.squared_distance(p)) * self.scale.powi(2));
+        Point {
+            x: k * (p.x - self.mean.x),
+            y: k * (p.y - self.mean.y),
+        }
+    }
+
+    fn has_analytic_gradient(&self) -> bool {
+        true
+    }
+
+    fn validate(&self) -> Result<(), HmcError> {
+        for (param, value) in [("dof", self.dof), ("scale", self.scale)] {
+            if !(value.is_finite() && value > 0.0) {
+                return Err(HmcError::InvalidTargetParam {
+                    param: param.to_string(),
+                    value,
+                });
+            }
+        }
+        for value in [self.mean.x, self.mean.y] {
+            if !value.is_finite() {
+                return Err(HmcError::InvalidTargetParam {
+                    param: "mean".to_string(),
+                    value,
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
+/// 2次元コーシー分布（等方的、位置 `location`、尺度 `scale`）U = 3/2 · log(1 + |q - location|^2 / scale^2)
+///
+/// 自由度1のt分布と同じ形で、平均も分散も存在しない。遠方では勾配が 1/|q| でしか減らず
+/// 運動量がほとんど戻されないため、HMCは長い遠出を繰り返し、ESSは同じ本数のサンプルでも小さくなる。
+/// 裾の重さによる混合の悪さを観察する教材用。
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Cauchy2 {
+    /// 位置（分布の中心・中央値、有限値）
+    pub location: Point,
+    /// 尺度 γ（正の有限値。中心から γ 以内に確率の約3割が入る）
+    pub scale: f64,
+}
+
+impl Default for Cauchy2 {
+    fn default() -> Self {
+        Self {
+            location: Point { x: 0.0, y: 0.0 },
+            scale: 1.0,
+        }
+    }
+}
+
+impl TargetDistribution for Cauchy2 {
+    fn potential(&self, p: &Point) -> f64 {
+        let dx = (p.x - self.location.x) / self.scale;
+        let dy = (p.y - self.location.y) / self.scale;
+        1.5 * (dx * dx + dy * dy).ln_1p()
+    }
+
+    /// ∇U = 3 (q - location) / (scale^2 + |q - location|^2)
+    fn gradient(&self, p: &Point) -> Point {
+        let dx = p.x - self.location.x;
+        let dy = p.y - self.location.y;
+        let k = 3.0 / (self.scale.powi(2) + dx * dx + dy * dy);
+        Point {
+            x: k * dx,
+            y: k * dy,
+        }
+    }
+
+    fn has_analytic_gradient(&self) -> bool {
+        true
+    }
+
+    fn validate(&self) -> Result<(), HmcError> {
+        check_positive("scale", self.scale)?;
+        check_all_finite("location", &[self.location.x, self.location.y])
+    }
+}
+
+/// トーラス上の独立な2つのフォン・ミーゼス分布 U = -κx cos(x - μx) - κy cos(y - μy)
+///
+/// 周期 2π の角度の組の分布で、モード（円周平均）は `mean`。平面上でも定義できるが、
+/// `HmcConfig::topology` に周期 2π の `Topology::Periodic` を指定して使うことを想定している。
+///
+/// ```
+/// use hamiltonian_sampler_rs::{run_hmc, DistType, HmcConfig, Topology, VonMises2};
+/// use std::f64::consts::TAU;
+///
+/// let config = HmcConfig {
+///     n_samples: 500,
+///     target: DistType::VonMises(VonMises2::default()),
+///     topology: Topology::Periodic { period_x: TAU, period_y: TAU },
+///     seed: Some(1),
+///     ..HmcConfig::default()
+/// };
+/// let result = run_hmc(&config).unwrap();
+/// assert!(result.samples.iter().all(|p| (0.0..TAU).contains(&p.x) && (0.0..TAU).contains(&p.y)));
+/// ```
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct VonMises2 {
+    /// 各角度のモード（有限値）
+    pub mean: Point,
+    /// x 方向の集中度（正の有限値。大きいほどモードの周りに集まる）
+    pub kappa_x: f64,
+    /// y 方向の集中度（正の有限値）
+    pub kappa_y: f64,
+}
+
+impl Default for VonMises2 {
+    fn default() -> Self {
+        Self {
+            mean: Point { x: 0.0, y: 0.0 },
+            kappa_x: 2.0,
+            kappa_y: 2.0,
+        }
+    }
+}
+
+impl TargetDistribution for VonMises2 {
+    fn potential(&self, p: &Point) -> f64 {
+        -self.kappa_x * (p.x - self.mean.x).cos() - self.kappa_y * (p.y - self.mean.y).cos()
+    }
+
+    fn gradient(&self, p: &Point) -> Point {
+        Point {
+            x: self.kappa_x * (p.x - self.mean.x).sin(),
+            y: self.kappa_y * (p.y - self.mean.y).sin(),
+        }
+    }
+
+    fn has_analytic_gradient(&self) -> bool {
+        true
+    }
+
+    fn validate(&self) -> Result<(), HmcError> {
+        for (param, value) in [("kappa_x", self.kappa_x), ("kappa_y", self.kappa_y)] {
+            check_positive(param, value)?;
+        }
+        check_all_finite("mean", &[self.mean.x, self.mean.y])
+    }
+}
+
+/// 2次元標準正規分布 U = |q|^2 / 2
+///
+/// 平均・共分散が厳密に分かっているため、サンプラー自体の回帰テストの基準に使う
+/// （[`crate::validate`] の関数と組み合わせる）。
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct StandardNormal2;
+
+impl TargetDistribution for StandardNormal2 {
+    fn potential(&self, p: &Point) -> f64 {
+        0.5 * (p.x * p.x + p.y * p.y)
+    }
+
+    fn gradient(&self, p: &Point) -> Point {
+        p.clone()
+    }
+
+    fn has_analytic_gradient(&self) -> bool {
+        true
+    }
+}
+
+/// 2パラメータのロジスティック回帰の事後分布
+///
+/// 点 q の `x` を傾き、`y` を切片とみなし、P(y_i = 1) = σ(slope · x_i + intercept) のベルヌーイ尤度と
+/// 各係数に独立な正規事前分布 N(0, prior_std^2) を置いたときの負の対数事後密度（定数項を除く）をポテンシャルとする。
+///
+/// ```
+/// use hamiltonian_sampler_rs::{LogisticRegression2, Point, TargetDistribution};
+///
+/// let target = LogisticRegression2::new(&[-1.0, 0.0, 2.0], &[0, 1, 1], 5.0).unwrap();
+/// assert!(target.potential(&Point { x: 1e3, y: 0.0 }).is_finite());
+/// assert!(LogisticRegression2::new(&[0.0], &[2], 5.0).is_err());
+/// ```
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct LogisticRegression2 {
+    /// 説明変数
+    pub x: Vec<f64>,
+    /// 目的変数（0 または 1、`x` と同じ長さ）
+    pub y: Vec<u8>,
+    /// 係数の事前分布の標準偏差（正の有限値）
+    pub prior_std: f64,
+}
+
+impl LogisticRegression2 {
+    /// データを検証して作る
+    pub fn new(x: &[f64], y: &[u8], prior_std: f64) -> Result<Self, HmcError> {
+        let target = Self {
+            x: x.to_vec(),
+            y: y.to_vec(),
+            prior_std,
+        };
+        target.validate()?;
+        Ok(target)
+    }
+
+    /// 各データ点の線形予測子 slope · x_i + intercept
+    fn linear_predictors<'a>(&'a self, q: &'a Point) -> impl Iterator<Item = (f64, f64, u8)> + 'a {
+        self.x
+            .iter()
+            .zip(&self.y)
+            .map(move |(&x, &y)| (x, q.x * x + q.y, y))
+    }
+}
+
+/// log(1 + exp(t)) をオーバーフローさせずに計算する
+pub(crate) fn softplus(t: f64) -> f64 {
+    t.max(0.0) + (-t.abs()).exp().ln_1p()
+}
+
+/// シグモイド関数 1 / (1 + exp(-t))
+pub(crate) fn sigmoid(t: f64) -> f64 {
+    if t >= 0.0 {
+        1.0 / (1.0 + (-t).exp())
+    } else {
+        let e = t.exp();
+        e / (1.0 + e)
+    }
+}
+
+impl TargetDistribution for LogisticRegression2 {
+    /// U = Σ [log(1 + exp(η_i)) - y_i η_i] + (slope^2 + intercept^2) / 2 prior_std^2
+    fn potential(&self, q: &Point) -> f64 {
+        let nll: f64 = self
+            .linear_predictors(q)
+            .map(|(_, eta, y)| softplus(eta) - f64::from(y) * eta)
+            .sum();
+        nll + 0.5 * (q.x * q.x + q.y * q.y) / self.prior_std.powi(2)
+    }
+
+    /// ∇U = Σ (σ(η_i) - y_i) (x_i, 1) + q / prior_std^2
+    fn gradient(&self, q: &Point) -> Point {
+        let inv_var = self.prior_std.powi(-2);
+        let mut g = Point {
+            x: q.x * inv_var,
+            y: q.y * inv_var,
+        };
+        for (x, eta, y) in self.linear_predictors(q) {
+            let residual = sigmoid(eta) - f64::from(y);
+            g.x += residual * x;
+            g.y += residual;
+        }
+        g
+    }
+
+    fn has_analytic_gradient(&self) -> bool {
+        true
+    }
+
+    fn validate(&self) -> Result<(), HmcError> {
+        if self.x.len() != self.y.len() {
+            return Err(HmcError::DataLengthMismatch {
+                x: self.x.len(),
+                y: self.y.len(),
+            });
+        }
+        check_all_finite("x", &self.x)?;
+        if let Some((i, &value)) = self.y.iter().enumerate().find(|(_, &v)| v > 1) {
+            return Err(HmcError::InvalidTargetParam {
+                param: format!("y[{}]", i),
+                value: f64::from(value),
+            });
+        }
+        check_positive("prior_std", self.prior_std)
+    }
+}
+
+/// 回帰用のデータ列がすべて有限か
+fn check_all_finite(name: &str, values: &[f64]) -> Result<(), HmcError> {
+    match values.iter().position(|v| !v.is_finite()) {
+        Some(i) => Err(HmcError::InvalidTargetParam {
+            param: format!("{}[{}]", name, i),
+            value: values[i],
+        }),
+        None => Ok(()),
+    }
+}
+
+/// 標準偏差などのパラメータが正の有限値か
+fn check_positive(name: &str, value: f64) -> Result<(), HmcError> {
+    if value.is_finite() && value > 0.0 {
+        Ok(())
+    } else {
+        Err(HmcError::InvalidTargetParam {
+            param: name.to_string(),
+            value,
+        })
+    }
+}
+
+/// ノイズが正規分布に従う線形回帰の事後分布
+///
+/// [`LogisticRegression2`] と同じく点 q の `x` を傾き、`y` を切片とみなす。
+/// y_i ~ N(slope · x_i + intercept, noise_std^2)、各係数 ~ N(0, prior_std^2) のもとで
+/// 負の対数事後密度は係数の2次式になるため、事後分布は [`LinearRegression2::posterior`] で厳密に求まる。
+/// データに依存するターゲットを自作するときの雛形にもなる。
+///
+/// ```
+/// use hamiltonian_sampler_rs::LinearRegression2;
+///
+/// let target = LinearRegression2::new(&[0.0, 1.0, 2.0], &[1.0, 3.0, 5.0], 0.1, 100.0).unwrap();
+/// let posterior = target.posterior();
+/// assert!((posterior.mean().x - 2.0).abs() < 1e-3);
+/// assert!((posterior.mean().y - 1.0).abs() < 1e-3);
+/// ```
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct LinearRegression2 {
+    /// 説明変数
+    pub x: Vec<f64>,
+    /// 目的変数（`x` と同じ長さ）
+    pub y: Vec<f64>,
+    /// 観測ノイズの標準偏差（正の有限値）
+    pub noise_std: f64,
+    /// 係数の事前分布の標準偏差（正の有限値）
+    pub prior_std: f64,
+}
+
+impl LinearRegression2 {
+    /// データを検証して作る
+    pub fn new(x: &[f64], y: &[f64], noise_std: f64, prior_std: f64) -> Result<Self, HmcError> {
+        let target = Self {
+            x: x.to_vec(),
+            y: y.to_vec(),
+            noise_std,
+            prior_std,
+        };
+        target.validate()?;
+        Ok(target)
+    }
+
+    /// 厳密な事後分布（(傾き, 切片) の2次元正規分布）
+    ///
+    /// 精度行列 A = X^T X / noise_std^2 + I / prior_std^2、平均 A^-1 X^T y / noise_std^2
+    /// （X は各行が (x_i, 1) の計画行列）。
+    pub fn posterior(&self) -> MvNormal2 {
+        let noise_prec = self.noise_std.powi(-2);
+        let prior_prec = self.prior_std.powi(-2);
+        let n = self.x.len() as f64;
+        let sx: f64 = self.x.iter().sum();
+        let sxx: f64 = self.x.iter().map(|x| x * x).sum();
+        let sy: f64 = self.y.iter().sum();
+        let sxy: f64 = self.x.iter().zip(&self.y).map(|(x, y)| x * y).sum();
+
+        let (a, b, d) = (
+            sxx * noise_prec + prior_prec,
+            sx * noise_prec,
+            n * noise_prec + prior_prec,
+        );
+        let det = a * d - b * b;
+        let cov = [[d / det, -b / det], [-b / det, a / det]];
+        let (rx, ry) = (sxy * noise_prec, sy * noise_prec);
+        let mean = Point {
+            x: cov[0][0] * rx + cov[0][1] * ry,
+            y: cov[1][0] * rx + cov[1][1] * ry,
+        };
+        MvNormal2::new(mean, cov).expect("posterior precision is positive definite")
+    }
+}
+
+impl TargetDistribution for LinearRegression2 {
+    /// U = Σ (y_i - slope · x_i - intercept)^2 / 2 noise_std^2 + (slope^2 + intercept^2) / 2 prior_std^2
+    fn potential(&self, q: &Point) -> f64 {
+        let sse: f64 = self
+            .x
+            .iter()
+            .zip(&self.y)
+            .map(|(x, y)| (y - q.x * x - q.y).powi(2))
+            .sum();
+        0.5 * sse / self.noise_std.powi(2) + 0.5 * (q.x * q.x + q.y * q.y) / self.prior_std.powi(2)
+    }
+
+    /// ∇U = -Σ r_i (x_i, 1) / noise_std^2 + q / prior_std^2（r_i は残差）
+    fn gradient(&self, q: &Point) -> Point {
+        let noise_prec = self.noise_std.powi(-2);
+        let prior_prec = self.prior_std.powi(-2);
+        let mut g = Point {
+            x: q.x * prior_prec,
+            y: q.y * prior_prec,
+        };
+        for (x, y) in self.x.iter().zip(&self.y) {
+            let residual = y - q.x * x - q.y;
+            g.x -= residual * x * noise_prec;
+            g.y -= residual * noise_prec;
+        }
+        g
+    }
+
+    fn has_analytic_gradient(&self) -> bool {
+        true
+    }
+
+    fn validate(&self) -> Result<(), HmcError> {
+        if self.x.len() != self.y.len() {
+            return Err(HmcError::DataLengthMismatch {
+                x: self.x.len(),
+                y: self.y.len(),
+            });
+        }
+        check_all_finite("x", &self.x)?;
+        check_all_finite("y", &self.y)?;
+        check_positive("noise_std", self.noise_std)?;
+        check_positive("prior_std", self.prior_std)
+    }
+}
+
+/// 格子上の値を双線形補間したポテンシャル
+///
+/// `values[j * nx + i]` が点 (x_i, y_j) での U（x_i = xmin + i (xmax - xmin) / (nx - 1)、y_j も同様）。
+/// numpyの形状 (ny, nx) の配列を `ravel()` したものがそのまま渡せる。
+/// 勾配は補間関数の（セルごとの）解析的な勾配。格子の外では一定値 `outside_potential`
+/// （既定 1e10）を返すため、外に出る提案はNaNにならず棄却される。
+/// `HmcConfig::bounds` に格子の範囲を指定すれば、棄却する代わりに境界で反射させられる。
+/// シリアライズ形式は `{"values": [..], "nx": .., "ny": .., "xmin": .., "xmax": .., "ymin": .., "ymax": ..,
+/// "outside_potential": ..}`（`outside_potential` は省略可）。
+///
+/// ```
+/// use hamiltonian_sampler_rs::{GridPotential, Point, TargetDistribution};
+///
+/// // U = x + 2y を 2x2 の格子で表す
+/// let grid = GridPotential::new(vec![0.0, 1.0, 2.0, 3.0], 2, 2, 0.0, 1.0, 0.0, 1.0).unwrap();
+/// assert!((grid.potential(&Point { x: 0.5, y: 0.25 }) - 1.0).abs() < 1e-12);
+/// assert_eq!(grid.gradient(&Point { x: 0.5, y: 0.25 }), Point { x: 1.0, y: 2.0 });
+/// assert_eq!(grid.potential(&Point { x: 2.0, y: 0.0 }), 1e10);
+/// ```
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(try_from = "GridPotentialParams", into = "GridPotentialParams")]
+pub struct GridPotential {
+    values: Vec<f64>,
+    nx: usize,
+    ny: usize,
+    xmin: f64,
+    xmax: f64,
+    ymin: f64,
+    ymax: f64,
+    outside_potential: f64,
+}
+
+/// `GridPotential` のシリアライズ用の表現（読み込み時に `GridPotential::new` で検証する）
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct GridPotentialParams {
+    values: Vec<f64>,
+    nx: usize,
+    ny: usize,
+    xmin: f64,
+    xmax: f64,
+    ymin: f64,
+    ymax: f64,
+    #[serde(default = "default_outside_potential")]
+    outside_potential: f64,
+}
+
+fn default_outside_potential() -> f64 {
+    1e10
+}
+
+impl TryFrom<GridPotentialParams> for GridPotential {
+    type Error = HmcError;
+
+    fn try_from(params: GridPotentialParams) -> Result<Self, HmcError> {
+        GridPotential::new(
+            params.values,
+            params.nx,
+            params.ny,
+            params.xmin,
+            params.xmax,
+            params.ymin,
+            params.ymax,
+        )?
+        .with_outside_potential(params.outside_potential)
+    }
+}
+
+impl From<GridPotential> for GridPotentialParams {
+    fn from(grid: GridPotential) -> Self {
+        Self {
+            values: grid.values,
+            nx: grid.nx,
+            ny: grid.ny,
+            xmin: grid.xmin,
+            xmax: grid.xmax,
+            ymin: grid.ymin,
+            ymax: grid.ymax,
+            outside_potential: grid.outside_potential,
+        }
+    }
+}
+
+impl GridPotential {
+    /// 格子の形と範囲、値を検証して作る
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        values: Vec<f64>,
+        nx: usize,
+        ny: usize,
+        xmin: f64,
+        xmax: f64,
+        ymin: f64,
+        ymax: f64,
+    ) -> Result<Self, HmcError> {
+        for (name, n) in [("nx", nx), ("ny", ny)] {
+            if n < 2 {
+                return Err(HmcError::InvalidTargetParam {
+                    param: name.to_string(),
+                    value: n as f64,
+                });
+            }
+        }
+        if values.len() != nx * ny {
+            return Err(HmcError::DataLengthMismatch {
+                x: values.len(),
+                y: nx * ny,
+            });
+        }
+        for (min, max) in [(xmin, xmax), (ymin, ymax)] {
+            if !(min.is_finite() && max.is_finite() && min < max) {
+                return Err(HmcError::InvalidInitRange { min, max });
+            }
+        }
+        check_all_finite("values", &values)?;
+        Ok(Self {
+            values,
+            nx,
+            ny,
+            xmin,
+            xmax,
+            ymin,
+            ymax,
+            outside_potential: default_outside_potential(),
+        })
+    }
+
+    /// 格子の外でのポテンシャルを変える（有限値）
+    pub fn with_outside_potential(mut self, outside_potential: f64) -> Result<Self, HmcError> {
+        check_all_finite("outside_potential", &[outside_potential])?;
+        self.outside_potential = outside_potential;
+        Ok(self)
+    }
+
+    fn dx(&self) -> f64 {
+        (self.xmax - self.xmin) / (self.nx - 1) as f64
+    }
+
+    fn dy(&self) -> f64 {
+        (self.ymax - self.ymin) / (self.ny - 1) as f64
+    }
+
+    /// `p` を含むセルの左下の添字とセル内の相対位置（格子の外なら `None`）
+    fn locate(&self, p: &Point) -> Option<(usize, usize, f64, f64)> {
+        if !((self.xmin..=self.xmax).contains(&p.x) && (self.ymin..=self.ymax).contains(&p.y)) {
+            return None;
+        }
+        let tx = (p.x - self.xmin) / self.dx();
+        let ty = (p.y - self.ymin) / self.dy();
+        // 上端・右端ちょうどの点は最後のセルに含める
+        let i = (tx.floor() as usize).min(self.nx - 2);
+        let j = (ty.floor() as usize).min(self.ny - 2);
+        Some((i, j, tx - i as f64, ty - j as f64))
+    }
+
+    /// セル (i, j) の四隅の値 [U00, U10, U01, U11]
+    fn corners(&self, i: usize, j: usize) -> [f64; 4] {
+        let at = |i: usize, j: usize| self.values[j * self.nx + i];
+        [at(i, j), at(i + 1, j), at(i, j + 1), at(i + 1, j + 1)]
+    }
+}
+
+impl TargetDistribution for GridPotential {
+    fn potential(&self, p: &Point) -> f64 {
+        match self.locate(p) {
+            Some((i, j, fx, fy)) => {
+                let [u00, u10, u01, u11] = self.corners(i, j);
+                (1.0 - fy) * ((1.0 - fx) * u00 + fx * u10) + fy * ((1.0 - fx) * u01 + fx * u11)
+            }
+            None => self.outside_potential,
+        }
+    }
+
+    fn gradient(&self, p: &Point) -> Point {
+        match self.locate(p) {
+            Some((i, j, fx, fy)) => {
+                let [u00, u10, u01, u11] = self.corners(i, j);
+                Point {
+                    x: ((1.0 - fy) * (u10 - u00) + fy * (u11 - u01)) / self.dx(),
+                    y: ((1.0 - fx) * (u01 - u00) + fx * (u11 - u10)) / self.dy(),
+                }
+            }
+            None => Point { x: 0.0, y: 0.0 },
+        }
+    }
+
+    fn has_analytic_gradient(&self) -> bool {
+        true
+    }
+}
+
+impl TargetDistribution for DistType {
+    fn potential(&self, q: &Point) -> f64 {
+        match self {
+            DistType::Bimodal(bimodal) => bimodal.potential(q),
+            DistType::Banana(banana) => banana.potential(q),
+            DistType::Funnel(funnel) => funnel.potential(q),
+            DistType::Ring(ring) => ring.potential(q),
+            DistType::Gaussian(gaussian) => gaussian.potential(q),
+            DistType::Mixture(mixture) => mixture.potential(q),
+            DistType::StudentT(student_t) => student_t.potential(q),
+            DistType::Cauchy(cauchy) => cauchy.potential(q),
+            DistType::VonMises(von_mises) => von_mises.potential(q),
+            DistType::Normal => StandardNormal2.potential(q),
+            DistType::Custom(target) => target.potential(q),
+        }
+    }
+
+    fn gradient(&self, q: &Point) -> Point {
+        match self {
+            DistType::Bimodal(bimodal) => bimodal.gradient(q),
+            DistType::Banana(banana) => banana.gradient(q),
+            DistType::Funnel(funnel) => funnel.gradient(q),
+            DistType::Ring(ring) => ring.gradient(q),
+            DistType::Gaussian(gaussian) => gaussian.gradient(q),
+            DistType::Mixture(mixture) => mixture.gradient(q),
+            DistType::StudentT(student_t) => student_t.gradient(q),
+            DistType::Cauchy(cauchy) => cauchy.gradient(q),
+            DistType::VonMises(von_mises) => von_mises.gradient(q),
+            DistType::Normal => StandardNormal2.gradient(q),
+            DistType::Custom(target) => target.gradient(q),
+        }
+    }
+
+    fn has_analytic_gradient(&self) -> bool {
+        match self {
+            DistType::Custom(target) => target.has_analytic_gradient(),
+            _ => true,
+        }
+    }
+
+    fn validate(&self) -> Result<(), HmcError> {
+        match self {
+            DistType::Bimodal(bimodal) => bimodal.validate(),
+            DistType::Banana(banana) => banana.validate(),
+            DistType::Funnel(funnel) => funnel.validate(),
+            DistType::Ring(ring) => ring.validate(),
+            DistType::StudentT(student_t) => student_t.validate(),
+            DistType::Cauchy(cauchy) => cauchy.validate(),
+            DistType::VonMises(von_mises) => von_mises.validate(),
+            DistType::Custom(target) => target.validate(),
+            // 生成時に検証済み
+            DistType::Gaussian(_) | DistType::Mixture(_) | DistType::Normal => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{run_hmc, run_hmc_with_target, HmcConfig, InitStrategy};
+
+    /// 軸ごとに平均・標準偏差の異なる正規分布
+    struct Gaussian {
+        mean: Point,
+        sd: Point,
+    }
+
+    impl TargetDistribution for Gaussian {
+        fn potential(&self, q: &Point) -> f64 {
+            let zx = (q.x - self.mean.x) / self.sd.x;
+            let zy = (q.y - self.mean.y) / self.sd.y;
+            0.5 * (zx * zx + zy * zy)
+        }
+    }
+
+    fn mean_and_variance(values: impl Iterator<Item = f64> + Clone) -> (f64, f64) {
+        let n = values.clone().count() as f64;
+        let mean = values.clone().sum::<f64>() / n;
+        let var = values.map(|v| (v - mean).powi(2)).sum::<f64>() / n;
+        (mean, var)
+    }
+
+    #[test]
+    fn samples_user_defined_gaussian() {
+        let target = Gaussian {
+            mean: Point { x: 1.0, y: -2.0 },
+            sd: Point { x: 0.5, y: 1.5 },
+        };
+        let config = HmcConfig {
+            n_samples: 4000,
+            n_warmup: 200,
+            step_size: 0.2,
+            num_steps: 10,
+            seed: Some(21),
+            ..HmcConfig::default()
+        };
+        let result = run_hmc_with_target(&config, &target).unwrap();
+
+        let (mean_x, var_x) = mean_and_variance(result.samples.iter().map(|p| p.x));
+        let (mean_y, var_y) = mean_and_variance(result.samples.iter().map(|p| p.y));
+        assert!((mean_x - 1.0).abs() < 0.1, "mean_x {}", mean_x);
+        assert!((mean_y + 2.0).abs() < 0.2, "mean_y {}", mean_y);
+        assert!((var_x - 0.25).abs() < 0.05, "var_x {}", var_x);
+        assert!((var_y - 2.25).abs() < 0.45, "var_y {}", var_y);
+    }
+
+    #[test]
+    fn closure_targets_work_with_parallel_chains() {
+        fn assert_send_sync<T: Send + Sync>(_: &T) {}
+
+        let scale = 2.0;
+        let target = Target::from_fn_with_grad(
+            move |p: &Point| 0.5 * (p.x * p.x + p.y * p.y) / (scale * scale),
+            move |p: &Point| Point {
+                x: p.x / (scale * scale),
+                y: p.y / (scale * scale),
+            },
+        );
+        assert_send_sync(&target);
+        let q = Point { x: 0.7, y: -1.2 };
+        let numeric = numerical_gradient(&target, &q);
+        let analytic = target.gradient(&q);
+        assert!((numeric.x - analytic.x).abs() < 1e-6);
+        assert!((numeric.y - analytic.y).abs() < 1e-6);
+
+        let config = HmcConfig {
+            n_samples: 50,
+            seed: Some(2),
+            ..HmcConfig::default()
+        };
+        let result = crate::run_hmc_chains_with_target(&config, &target, 3, &[]).unwrap();
+        assert_eq!(result.chains.len(), 3);
+    }
+
+    #[test]
+    fn dist_type_dispatches_to_builtin_targets() {
+        let q = Point { x: 0.3, y: -0.7 };
+        let bimodal = Bimodal {
+            weights: [1.0, 3.0],
+            ..Bimodal::default()
+        };
+        let dist = DistType::Bimodal(bimodal.clone());
+        assert_eq!(dist.potential(&q), bimodal.potential(&q));
+        assert_eq!(dist.gradient(&q), bimodal.gradient(&q));
+        assert_eq!(
+            DistType::Banana(Banana::default()).potential(&q),
+            Banana::default().potential(&q)
+        );
+        assert_eq!(
+            DistType::Banana(Banana::default()).gradient(&q),
+            Banana::default().gradient(&q)
+        );
+    }
+
+    #[test]
+    fn custom_closure_target_runs_through_dist_type() {
+        // 平均 (1, -2)、分散 (1, 4) の独立な正規分布
+        let gaussian = Target::from_fn_with_grad(
+            |p: &Point| 0.5 * ((p.x - 1.0).powi(2) + (p.y + 2.0).powi(2) / 4.0),
+            |p: &Point| Point {
+                x: p.x - 1.0,
+                y: (p.y + 2.0) / 4.0,
+            },
+        );
+        let target = DistType::Custom(std::sync::Arc::new(gaussian));
+        assert!(target.has_analytic_gradient());
+        assert_eq!(target.to_string(), "custom");
+        assert!("custom".parse::<DistType>().is_err());
+        assert_eq!(format!("{:?}", target), "Custom(<custom target>)");
+        assert_eq!(target, target.clone());
+        assert_ne!(target, DistType::Normal);
+
+        let config = HmcConfig {
+            n_samples: 4000,
+            step_size: 0.4,
+            num_steps: 10,
+            target,
+            seed: Some(55),
+            ..HmcConfig::default()
+        };
+        let true_mean = Point { x: 1.0, y: -2.0 };
+        let true_cov = [[1.0, 0.0], [0.0, 4.0]];
+        let samples = run_hmc(&config).unwrap().samples;
+        let z = crate::validate::z_score_of_mean(&samples, &true_mean, &true_cov);
+        assert!(z.x.abs() < 4.0 && z.y.abs() < 4.0, "z = {:?}", z);
+        let cov = crate::validate::sample_cov(&samples);
+        assert!((cov[0][0] - 1.0).abs() < 0.15, "cov {:?}", cov);
+        assert!((cov[1][1] - 4.0).abs() < 0.6, "cov {:?}", cov);
+
+        // 複数チェーンなど `HmcConfig` を受け取る他の経路もそのまま使える
+        let result = crate::run_hmc_chains(&config, 2, &[]).unwrap();
+        assert_eq!(result.chains.len(), 2);
+        assert!(serde_json::to_string(&config).is_err());
+    }
+
+    /// [-5, 5]^2 の格子上で解析勾配と中心差分が相対誤差1e-6以内で一致するか
+    fn assert_gradient_matches_numerical<T: TargetDistribution>(target: &T) {
+        for i in 0..=40 {
+            for j in 0..=40 {
+                let q = Point {
+                    x: -5.0 + 0.25 * i as f64,
+                    y: -5.0 + 0.25 * j as f64,
+                };
+                let analytic = target.gradient(&q);
+                let numeric = numerical_gradient(target, &q);
+                for (a, n) in [(analytic.x, numeric.x), (analytic.y, numeric.y)] {
+                    assert!(
+                        (a - n).abs() <= 1e-6 * a.abs().max(1.0),
+                        "{:?}: analytic {} vs numeric {}",
+                        q,
+                        a,
+                        n
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn analytic_gradients_match_numerical() {
+        assert_gradient_matches_numerical(&Bimodal::default());
+        assert_gradient_matches_numerical(&Bimodal {
+            centers: [Point { x: 1.0, y: -2.0 }, Point { x: -3.0, y: 0.5 }],
+            scales: [0.8, 2.5],
+            weights: [0.3, 1.7],
+        });
+        assert_gradient_matches_numerical(&Banana::default());
+        assert_gradient_matches_numerical(&Banana { a: -0.5, b: 3.0 });
+        assert_gradient_matches_numerical(&Funnel::default());
+        assert_gradient_matches_numerical(&Ring::default());
+        assert_gradient_matches_numerical(
+            &MvNormal2::new(Point { x: 1.0, y: -1.0 }, [[2.0, -0.9], [-0.9, 0.5]]).unwrap(),
+        );
+        assert_gradient_matches_numerical(&three_component_mixture());
+        assert_gradient_matches_numerical(&StudentT {
+            dof: 2.0,
+            mean: Point { x: 0.5, y: -1.0 },
+            scale: 1.5,
+        });
+        assert_gradient_matches_numerical(&Cauchy2 {
+            location: Point { x: -1.0, y: 0.5 },
+            scale: 0.7,
+        });
+        assert_gradient_matches_numerical(&VonMises2 {
+            mean: Point { x: 0.5, y: -2.0 },
+            kappa_x: 0.7,
+            kappa_y: 3.0,
+        });
+    }
+
+    #[test]
+    fn bimodal_stays_finite_far_from_both_modes() {
+        let q = Point { x: 100.0, y: 100.0 };
+        let bimodal = Bimodal::default();
+        // 遠方では近い方の山だけが効く: U ≈ d1 / 1.5 + ln(1.5 / 1)
+        let d1 = 2.0 * 97.5_f64.powi(2);
+        assert!((bimodal.potential(&q) - (d1 / 1.5 + 1.5_f64.ln())).abs() < 1e-9);
+        let g = bimodal.gradient(&q);
+        assert!((g.x - 2.0 * 97.5 / 1.5).abs() < 1e-9 && g.y.is_finite());
+
+        // 大きく離れた山の間でもポテンシャルが平坦にならない
+        let separated = Bimodal {
+            centers: [Point { x: 30.0, y: 0.0 }, Point { x: -30.0, y: 0.0 }],
+            ..Bimodal::default()
+        };
+        let u = |x: f64| separated.potential(&Point { x, y: 0.0 });
+        assert!(u(0.0).is_finite());
+        assert!(u(0.0) > u(10.0) && u(10.0) > u(20.0));
+    }
+
+    #[test]
+    fn bimodal_weights_set_mode_probabilities() {
+        let bimodal = Bimodal {
+            scales: [1.5, 0.5],
+            weights: [1.0, 3.0],
+            ..Bimodal::default()
+        };
+        // 各山の中心での密度比は (w1 / s1) / (w2 / s2)
+        let u1 = bimodal.potential(&Point { x: 2.5, y: 2.5 });
+        let u2 = bimodal.potential(&Point { x: -2.5, y: -2.5 });
+        assert!(((u2 - u1) - (1.0_f64 / 1.5 * 0.5 / 3.0).ln()).abs() < 1e-6);
+    }
+
+    #[test]
+    fn separated_modes_are_all_visited_across_chains() {
+        let target = DistType::Bimodal(Bimodal {
+            centers: [Point { x: 6.0, y: 6.0 }, Point { x: -6.0, y: -6.0 }],
+            ..Bimodal::default()
+        });
+        let config = HmcConfig {
+            n_samples: 300,
+            n_warmup: 50,
+            target,
+            seed: Some(8),
+            ..HmcConfig::default()
+        };
+        let starts = crate::init_uniform_box(8, -8.0, 8.0, -8.0, 8.0, Some(8)).unwrap();
+        let result = crate::run_hmc_chains(&config, starts.len(), &starts).unwrap();
+
+        let near = |p: &Point, c: f64| (p.x - c).hypot(p.y - c) < 2.0;
+        for mode in [6.0, -6.0] {
+            let n_chains = result
+                .chains
+                .iter()
+                .filter(|chain| {
+                    chain.samples.iter().filter(|p| near(p, mode)).count() * 2 > chain.samples.len()
+                })
+                .count();
+            assert!(n_chains >= 1, "no chain settled in mode {}", mode);
+        }
+    }
+
+    #[test]
+    fn tempered_target_scales_potential_and_gradient() {
+        let inner = Banana::default();
+        let tempered = Tempered::with_temperature(inner, 5.0);
+        assert_eq!(tempered.temperature(), 5.0);
+        for q in [Point { x: 0.3, y: -1.2 }, Point { x: -2.0, y: 4.5 }] {
+            assert!((tempered.potential(&q) - inner.potential(&q) / 5.0).abs() < 1e-12);
+            let (g, g_inner) = (tempered.gradient(&q), inner.gradient(&q));
+            assert!((g.x - g_inner.x / 5.0).abs() < 1e-12);
+            assert!((g.y - g_inner.y / 5.0).abs() < 1e-12);
+        }
+        assert!(tempered.has_analytic_gradient());
+
+        assert_eq!(
+            Tempered::with_temperature(inner, -1.0).validate(),
+            Err(HmcError::InvalidTargetParam {
+                param: "inv_temp".to_string(),
+                value: -1.0,
+            })
+        );
+        let config = |temperature| HmcConfig {
+            temperature,
+            ..HmcConfig::default()
+        };
+        assert_eq!(
+            config(0.0).validate(),
+            Err(HmcError::InvalidTemperature(0.0))
+        );
+        assert!(config(f64::NAN).validate().is_err());
+    }
+
+    #[test]
+    fn tempered_bimodal_mixes_between_modes_in_one_chain() {
+        // T = 1 では山の間の障壁（約8）を越えられず、一方の山に留まる
+        let config = HmcConfig {
+            n_samples: 4000,
+            n_warmup: 200,
+            step_size: 0.3,
+            num_steps: 10,
+            initial_pos: Point { x: 2.5, y: 2.5 },
+            seed: Some(52),
+            ..HmcConfig::default()
+        };
+        let upper_fraction = |samples: &[Point]| {
+            samples.iter().filter(|p| p.x + p.y > 0.0).count() as f64 / samples.len() as f64
+        };
+        let cold = run_hmc(&config).unwrap();
+        assert!(upper_fraction(&cold.samples) > 0.99);
+
+        let hot = run_hmc(&HmcConfig {
+            temperature: 5.0,
+            ..config
+        })
+        .unwrap();
+        let fraction = upper_fraction(&hot.samples);
+        assert!((0.3..0.7).contains(&fraction), "upper fraction {}", fraction);
+    }
+
+    #[test]
+    fn rejects_invalid_bimodal_params() {
+        let invalid = |bimodal: Bimodal| {
+            HmcConfig {
+                target: DistType::Bimodal(bimodal),
+                ..HmcConfig::default()
+            }
+            .validate()
+            .unwrap_err()
+        };
+        assert_eq!(
+            invalid(Bimodal {
+                scales: [1.0, 0.0],
+                ..Bimodal::default()
+            }),
+            HmcError::InvalidTargetParam {
+                param: "scales[1]".to_string(),
+                value: 0.0
+            }
+        );
+        assert!(matches!(
+            invalid(Bimodal { weights: [-1.0, 1.0], ..Bimodal::default() }),
+            HmcError::InvalidTargetParam { param, .. } if param == "weights[0]"
+        ));
+        assert!(matches!(
+            invalid(Bimodal { weights: [0.0, 0.0], ..Bimodal::default() }),
+            HmcError::InvalidTargetParam { param, .. } if param == "weights"
+        ));
+        assert!(matches!(
+            invalid(Bimodal {
+                centers: [Point { x: f64::NAN, y: 0.0 }, Point::default()],
+                ..Bimodal::default()
+            }),
+            HmcError::InvalidTargetParam { param, .. } if param == "centers[0]"
+        ));
+    }
+
+    #[test]
+    fn funnel_y_marginal_has_configured_scale() {
+        let config = HmcConfig {
+            n_samples: 5000,
+            n_warmup: 200,
+            step_size: 0.05,
+            num_steps: 40,
+            initial_pos: Point { x: 0.0, y: 0.0 },
+            target: DistType::Funnel(Funnel::default()),
+            seed: Some(12),
+            ..HmcConfig::default()
+        };
+        let result = crate::run_hmc_chains(&config, 4, &[]).unwrap();
+        let ys: Vec<f64> = result
+            .chains
+            .iter()
+            .flat_map(|c| c.samples.iter().map(|p| p.y))
+            .collect();
+        let (mean_y, var_y) = mean_and_variance(ys.iter().copied());
+        assert!(mean_y.abs() < 0.5, "mean_y {}", mean_y);
+        assert!((var_y.sqrt() - 3.0).abs() < 0.4, "sd_y {}", var_y.sqrt());
+    }
+
+    #[test]
+    fn funnel_neck_diverges_with_large_steps() {
+        let run = |step_size: f64| {
+            let config = HmcConfig {
+                n_samples: 2000,
+                step_size,
+                num_steps: 10,
+                target: DistType::Funnel(Funnel::default()),
+                save_divergences: true,
+                seed: Some(13),
+                ..HmcConfig::default()
+            };
+            run_hmc(&config).unwrap()
+        };
+        assert_eq!(run(0.05).n_divergent, 0);
+
+        let result = run(0.3);
+        assert!(result.n_divergent > 0);
+        // 発散は x の幅が狭い首（y < -1）から始まる遷移に集中する
+        let in_neck = result
+            .divergent_positions
+            .iter()
+            .filter(|p| p.y < -1.0)
+            .count();
+        assert!(
+            in_neck * 3 > result.divergent_positions.len() * 2,
+            "{:?}",
+            result.divergent_positions
+        );
+    }
+
+    #[test]
+    fn ring_samples_concentrate_on_the_circle() {
+        let ring = Ring {
+            radius: 4.0,
+            width: 0.3,
+        };
+        assert_eq!(ring.gradient(&Point::default()), Point::default());
+
+        let config = HmcConfig {
+            n_samples: 4000,
+            n_warmup: 200,
+            step_size: 0.1,
+            num_steps: 20,
+            initial_pos: Point { x: 4.0, y: 0.0 },
+            target: DistType::Ring(ring),
+            seed: Some(14),
+            ..HmcConfig::default()
+        };
+        let result = run_hmc(&config).unwrap();
+        let (mean_r, _) = mean_and_variance(result.samples.iter().map(|p| p.x.hypot(p.y)));
+        assert!((mean_r - 4.0).abs() < 0.04 * 4.0, "mean radius {}", mean_r);
+        // 円周全体を回っている
+        assert!(result.samples.iter().any(|p| p.x < -3.0));
+    }
+
+    #[test]
+    fn gaussian_sample_covariance_matches_target() {
+        for (cov, seed) in [
+            ([[2.0, 0.3], [0.3, 0.5]], 15),
+            ([[1.0, 0.95], [0.95, 1.0]], 16),
+        ] {
+            let mean = Point { x: 1.0, y: -2.0 };
+            let config = HmcConfig {
+                n_samples: 20_000,
+                n_warmup: 500,
+                step_size: 0.1,
+                num_steps: 15,
+                initial_pos: mean.clone(),
+                target: DistType::Gaussian(MvNormal2::new(mean, cov).unwrap()),
+                seed: Some(seed),
+                ..HmcConfig::default()
+            };
+            let samples = run_hmc(&config).unwrap().samples;
+            let (mean_x, var_x) = mean_and_variance(samples.iter().map(|p| p.x));
+            let (mean_y, var_y) = mean_and_variance(samples.iter().map(|p| p.y));
+            let cov_xy = samples
+                .iter()
+                .map(|p| (p.x - mean_x) * (p.y - mean_y))
+                .sum::<f64>()
+                / samples.len() as f64;
+            assert!((mean_x - 1.0).abs() < 0.05 && (mean_y + 2.0).abs() < 0.05);
+            for (estimate, exact) in [(var_x, cov[0][0]), (var_y, cov[1][1]), (cov_xy, cov[0][1])] {
+                assert!(
+                    (estimate - exact).abs() < 0.05 * cov[0][0].max(cov[1][1]),
+                    "{:?}: estimate {} vs exact {}",
+                    cov,
+                    estimate,
+                    exact
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn gaussian_rejects_invalid_covariance() {
+        for cov in [
+            [[1.0, 2.0], [2.0, 1.0]],
+            [[1.0, 0.5], [0.4, 1.0]],
+            [[-1.0, 0.0], [0.0, -1.0]],
+            [[f64::NAN, 0.0], [0.0, 1.0]],
+        ] {
+            assert!(matches!(
+                MvNormal2::new(Point::default(), cov),
+                Err(HmcError::InvalidCovariance(_))
+            ));
+        }
+
+        let json = r#"{"mean": {"x": 1, "y": 2}, "cov": [[1, 0.5], [0.5, 1]]}"#;
+        let target: MvNormal2 = serde_json::from_str(json).unwrap();
+        assert_eq!(target.cov(), [[1.0, 0.5], [0.5, 1.0]]);
+        let restored: MvNormal2 =
+            serde_json::from_str(&serde_json::to_string(&target).unwrap()).unwrap();
+        assert_eq!(restored, target);
+        assert!(serde_json::from_str::<MvNormal2>(r#"{"cov": [[1, 2], [2, 1]]}"#).is_err());
+    }
+
+    /// 互いに行き来できる程度に近い3成分の混合
+    fn three_component_mixture() -> GaussianMixture {
+        let component = |weight, x, y| MixtureComponent {
+            weight,
+            mean: Point { x, y },
+            cov: [[0.6, 0.1], [0.1, 0.6]],
+        };
+        GaussianMixture::new(vec![
+            component(2.0, -1.5, 0.0),
+            component(3.0, 1.5, 0.0),
+            component(5.0, 0.0, 2.0),
+        ])
+        .unwrap()
+    }
+
+    #[test]
+    fn mixture_samples_split_according_to_weights() {
+        let mixture = three_component_mixture();
+        let weights: Vec<f64> = mixture.components().iter().map(|c| c.weight).collect();
+        assert_eq!(weights, vec![0.2, 0.3, 0.5]);
+
+        let config = HmcConfig {
+            n_samples: 5000,
+            n_warmup: 200,
+            step_size: 0.2,
+            num_steps: 15,
+            target: DistType::Mixture(mixture.clone()),
+            seed: Some(17),
+            ..HmcConfig::default()
+        };
+        let result = crate::run_hmc_chains(&config, 4, &[]).unwrap();
+        // 各サンプルの負担率を平均すると混合比の推定になる
+        let mut fractions = [0.0; 3];
+        let mut n = 0.0;
+        for p in result.chains.iter().flat_map(|c| &c.samples) {
+            for (f, r) in fractions.iter_mut().zip(mixture.responsibilities(p)) {
+                *f += r;
+            }
+            n += 1.0;
+        }
+        for (f, w) in fractions.iter().zip(&weights) {
+            assert!(
+                (f / n - w).abs() < 0.05,
+                "{:?} vs {:?}",
+                fractions.map(|f| f / n),
+                weights
+            );
+        }
+    }
+
+    #[test]
+    fn mixture_rejects_invalid_components() {
+        assert!(GaussianMixture::new(Vec::new()).is_err());
+        let mut components = three_component_mixture().components().to_vec();
+        components[1].weight = 0.0;
+        assert_eq!(
+            GaussianMixture::new(components.clone()).unwrap_err(),
+            HmcError::InvalidTargetParam {
+                param: "components[1].weight".to_string(),
+                value: 0.0
+            }
+        );
+        components[1].weight = 1.0;
+        components[2].cov = [[1.0, 0.0], [0.0, -1.0]];
+        assert!(matches!(
+            GaussianMixture::new(components),
+            Err(HmcError::InvalidCovariance(_))
+        ));
+
+        let json = r#"[{"weight": 1, "mean": {"x": 0, "y": 0}, "cov": [[1, 0], [0, 1]]},
+                       {"weight": 3, "mean": {"x": 4, "y": 0}, "cov": [[1, 0], [0, 1]]}]"#;
+        let mixture: GaussianMixture = serde_json::from_str(json).unwrap();
+        assert_eq!(mixture.components()[0].weight, 0.25);
+        let restored: GaussianMixture =
+            serde_json::from_str(&serde_json::to_string(&mixture).unwrap()).unwrap();
+        assert_eq!(restored, mixture);
+        // 遠く離れた点でもポテンシャルは有限
+        assert!(mixture.potential(&Point { x: 1e3, y: 1e3 }).is_finite());
+    }
+
+    /// 平均・共分散・混合比が成分ごとに異なる `n` 成分の混合
+    fn many_component_mixture(n: usize) -> GaussianMixture {
+        let components = (0..n)
+            .map(|k| {
+                let t = k as f64;
+                let (a, d) = (
+                    0.3 + 0.2 * (1.3 * t).sin().abs(),
+                    0.4 + 0.3 * (0.7 * t).cos().abs(),
+                );
+                MixtureComponent {
+                    weight: 1.0 + (0.9 * t).sin().abs(),
+                    mean: Point {
+                        x: 4.0 * (0.5 * t).cos(),
+                        y: 4.0 * (0.3 * t).sin(),
+                    },
+                    cov: [[a, 0.1 * t.sin()], [0.1 * t.sin(), d]],
+                }
+            })
+            .collect();
+        GaussianMixture::new(components).unwrap()
+    }
+
+    #[test]
+    fn mixture_lanes_match_scalar_evaluation() {
+        let close = |a: f64, b: f64| (a - b).abs() <= 1e-12 * b.abs().max(1.0);
+        // 4の倍数でない成分数では最後のブロックに埋め草が入る
+        for n in [4, 5, 7, 64] {
+            let mixture = many_component_mixture(n);
+            let mut points: Vec<Point> = (-12..=12)
+                .flat_map(|i| {
+                    (-12..=12).map(move |j| Point {
+                        x: 0.5 * i as f64,
+                        y: 0.5 * j as f64,
+                    })
+                })
+                .collect();
+            points.push(Point { x: 1e3, y: -1e3 });
+            for q in &points {
+                let (u, u_scalar) = (mixture.potential(q), mixture.potential_scalar(q));
+                assert!(close(u, u_scalar), "n={} {:?}: {} vs {}", n, q, u, u_scalar);
+                let (g, g_scalar) = (mixture.gradient(q), mixture.gradient_scalar(q));
+                assert!(
+                    close(g.x, g_scalar.x) && close(g.y, g_scalar.y),
+                    "n={} {:?}: {:?} vs {:?}",
+                    n,
+                    q,
+                    g,
+                    g_scalar
+                );
+            }
+        }
+    }
+
+    /// `cargo test --release mixture_lanes_speedup -- --ignored --nocapture` で速度を比べる
+    #[test]
+    #[ignore = "benchmark"]
+    fn mixture_lanes_speedup() {
+        let mixture = many_component_mixture(64);
+        let points: Vec<Point> = (0..20_000)
+            .map(|i| Point {
+                x: 6.0 * (0.37 * i as f64).sin(),
+                y: 6.0 * (0.11 * i as f64).cos(),
+            })
+            .collect();
+        let time = |f: &dyn Fn(&Point) -> f64| {
+            let start = std::time::Instant::now();
+            let total: f64 = points.iter().map(f).sum();
+            (start.elapsed().as_secs_f64(), total)
+        };
+        let (lanes, a) = time(&|q| mixture.potential(q) + mixture.gradient(q).x);
+        let (scalar, b) = time(&|q| mixture.potential_scalar(q) + mixture.gradient_scalar(q).x);
+        assert!((a - b).abs() <= 1e-9 * b.abs());
+        println!(
+            "64 components: lanes {:.1} ms, scalar {:.1} ms ({:.2}x)",
+            1e3 * lanes,
+            1e3 * scalar,
+            scalar / lanes
+        );
+    }
+
+    #[test]
+    fn student_t_has_heavy_tails() {
+        let student_t = StudentT {
+            dof: 2.0,
+            mean: Point { x: 1.0, y: -1.0 },
+            scale: 1.0,
+        };
+        assert!(student_t
+            .potential(&Point { x: 1e150, y: 1e150 })
+            .is_finite());
+
+        let config = HmcConfig {
+            n_samples: 100_000,
+            step_size: 0.3,
+            num_steps: 10,
+            initial_pos: student_t.mean.clone(),
+            target: DistType::StudentT(student_t),
+            seed: Some(18),
+            ..HmcConfig::default()
+        };
+        let samples = run_hmc(&config).unwrap().samples;
+        let median = |mut values: Vec<f64>| {
+            values.sort_by(f64::total_cmp);
+            values[values.len() / 2]
+        };
+        let median_x = median(samples.iter().map(|p| p.x).collect());
+        let median_y = median(samples.iter().map(|p| p.y).collect());
+        assert!((median_x - 1.0).abs() < 0.1, "median_x {}", median_x);
+        assert!((median_y + 1.0).abs() < 0.1, "median_y {}", median_y);
+        // 自由度2では正規分布ならまず出ない遠方まで到達する
+        let max_dx = samples
+            .iter()
+            .map(|p| (p.x - 1.0).abs())
+            .fold(0.0, f64::max);
+        assert!(max_dx > 20.0, "max |x - mean| {}", max_dx);
+    }
+
+    #[test]
+    fn cauchy_runs_long_chains_without_blowup_but_mixes_poorly() {
+        let cauchy = Cauchy2 {
+            location: Point { x: 1.0, y: -1.0 },
+            scale: 1.0,
+        };
+        assert!(cauchy.potential(&Point { x: 1e150, y: 1e150 }).is_finite());
+        let g = cauchy.gradient(&Point { x: 1e150, y: 0.0 });
+        assert!(g.x.is_finite() && g.x > 0.0);
+
+        let config = |target| HmcConfig {
+            n_samples: 100_000,
+            step_size: 0.3,
+            num_steps: 10,
+            initial_pos: Point { x: 1.0, y: -1.0 },
+            target,
+            seed: Some(53),
+            ..HmcConfig::default()
+        };
+        let result = run_hmc(&config(DistType::Cauchy(cauchy))).unwrap();
+        assert!(result
+            .samples
+            .iter()
+            .all(|p| p.x.is_finite() && p.y.is_finite()));
+        assert_eq!(result.n_divergent, 0);
+        // 裾に長く遠出するため、同じ設定の正規分布よりESSがはるかに小さい
+        let xs: Vec<f64> = result.samples.iter().map(|p| p.x).collect();
+        let max_dx = xs.iter().map(|x| (x - 1.0).abs()).fold(0.0, f64::max);
+        assert!(max_dx > 50.0, "max |x - location| {}", max_dx);
+        let normal = run_hmc(&config(DistType::Normal)).unwrap();
+        let normal_xs: Vec<f64> = normal.samples.iter().map(|p| p.x).collect();
+        let (ess, normal_ess) = (crate::diagnostics::ess(&xs), crate::diagnostics::ess(&normal_xs));
+        assert!(ess * 10.0 < normal_ess, "cauchy ESS {} vs normal ESS {}", ess, normal_ess);
+    }
+
+    #[test]
+    fn logistic_regression_recovers_coefficients() {
+        use rand::prelude::*;
+
+        let (slope, intercept) = (1.5, -0.5);
+        let mut rng = StdRng::seed_from_u64(19);
+        let x: Vec<f64> = (0..500).map(|_| rng.gen_range(-3.0..3.0)).collect();
+        let y: Vec<u8> = x
+            .iter()
+            .map(|&x| u8::from(rng.gen::<f64>() < sigmoid(slope * x + intercept)))
+            .collect();
+        let target = LogisticRegression2::new(&x, &y, 10.0).unwrap();
+        assert_gradient_matches_numerical(&target);
+
+        let config = HmcConfig {
+            n_samples: 4000,
+            n_warmup: 300,
+            step_size: 0.05,
+            num_steps: 10,
+            seed: Some(19),
+            ..HmcConfig::default()
+        };
+        let samples = run_hmc_with_target(&config, &target).unwrap().samples;
+        let (mean_slope, var_slope) = mean_and_variance(samples.iter().map(|p| p.x));
+        let (mean_intercept, var_intercept) = mean_and_variance(samples.iter().map(|p| p.y));
+        // 真の係数が事後分布の3σ以内に入る
+        assert!(
+            (mean_slope - slope).abs() < 3.0 * var_slope.sqrt(),
+            "slope {} ± {}",
+            mean_slope,
+            var_slope.sqrt()
+        );
+        assert!(
+            (mean_intercept - intercept).abs() < 3.0 * var_intercept.sqrt(),
+            "intercept {} ± {}",
+            mean_intercept,
+            var_intercept.sqrt()
+        );
+        assert!(var_slope.sqrt() < 0.3 && var_intercept.sqrt() < 0.3);
+    }
+
+    #[test]
+    fn logistic_regression_rejects_invalid_data() {
+        assert_eq!(
+            LogisticRegression2::new(&[0.0, 1.0], &[1], 1.0).unwrap_err(),
+            HmcError::DataLengthMismatch { x: 2, y: 1 }
+        );
+        assert!(LogisticRegression2::new(&[f64::NAN], &[1], 1.0).is_err());
+        assert!(LogisticRegression2::new(&[0.0], &[1], 0.0).is_err());
+        // 極端な線形予測子でもポテンシャル・勾配が有限
+        let target = LogisticRegression2::new(&[1.0, -1.0], &[1, 0], 1.0).unwrap();
+        let far = Point { x: -800.0, y: 0.0 };
+        assert!(target.potential(&far).is_finite());
+        let g = target.gradient(&far);
+        assert!(g.x.is_finite() && g.y.is_finite());
+    }
+
+    #[test]
+    fn linear_regression_matches_exact_posterior() {
+        use crate::validate::{sample_cov, z_score_of_mean};
+        use rand::prelude::*;
+        use rand_distr::StandardNormal;
+
+        let (slope, intercept, noise_std) = (0.8, 2.0, 0.5);
+        let mut rng = StdRng::seed_from_u64(20);
+        let x: Vec<f64> = (0..50).map(|_| rng.gen_range(0.0..4.0)).collect();
+        let y: Vec<f64> = x
+            .iter()
+            .map(|x| slope * x + intercept + noise_std * rng.sample::<f64, _>(StandardNormal))
+            .collect();
+        let target = LinearRegression2::new(&x, &y, noise_std, 10.0).unwrap();
+        assert_gradient_matches_numerical(&target);
+        let posterior = target.posterior();
+        // 厳密な事後分布とポテンシャルの差は定数
+        let offset = |q: &Point| target.potential(q) - posterior.potential(q);
+        assert!(
+            (offset(&Point { x: 0.0, y: 0.0 }) - offset(&Point { x: 1.0, y: 3.0 })).abs() < 1e-9
+        );
+
+        let config = HmcConfig {
+            n_samples: 20_000,
+            n_warmup: 300,
+            step_size: 0.01,
+            num_steps: 30,
+            initial_pos: posterior.mean().clone(),
+            seed: Some(20),
+            ..HmcConfig::default()
+        };
+        let samples = run_hmc_with_target(&config, &target).unwrap().samples;
+        let z = z_score_of_mean(&samples, posterior.mean(), &posterior.cov());
+        assert!(z.x.abs() < 4.0 && z.y.abs() < 4.0, "z {:?}", z);
+        let cov = sample_cov(&samples);
+        let exact = posterior.cov();
+        for (i, j) in [(0, 0), (1, 1), (0, 1)] {
+            assert!(
+                (cov[i][j] - exact[i][j]).abs() < 0.1 * exact[i][i],
+                "cov {:?} vs exact {:?}",
+                cov,
+                exact
+            );
+        }
+    }
+
+    #[test]
+    fn grid_potential_reproduces_tabulated_gaussian() {
+        use crate::validate::{sample_cov, z_score_of_mean};
+
+        let exact = MvNormal2::new(Point { x: 0.5, y: -0.5 }, [[1.0, 0.3], [0.3, 0.5]]).unwrap();
+        let (nx, ny) = (201, 161);
+        let (xmin, xmax, ymin, ymax) = (-5.0, 6.0, -5.0, 4.0);
+        let mut values = Vec::with_capacity(nx * ny);
+        for j in 0..ny {
+            for i in 0..nx {
+                let q = Point {
+                    x: xmin + (xmax - xmin) * i as f64 / (nx - 1) as f64,
+                    y: ymin + (ymax - ymin) * j as f64 / (ny - 1) as f64,
+                };
+                values.push(exact.potential(&q));
+            }
+        }
+        let grid = GridPotential::new(values, nx, ny, xmin, xmax, ymin, ymax).unwrap();
+
+        for q in [
+            Point { x: 0.0, y: 0.0 },
+            Point { x: 1.23, y: -0.77 },
+            Point { x: -2.1, y: 1.9 },
+        ] {
+            assert!((grid.potential(&q) - exact.potential(&q)).abs() < 1e-2);
+            let (g, e) = (grid.gradient(&q), exact.gradient(&q));
+            assert!(
+                (g.x - e.x).abs() < 0.1 && (g.y - e.y).abs() < 0.1,
+                "{:?} vs {:?}",
+                g,
+                e
+            );
+        }
+        assert_eq!(grid.potential(&Point { x: 7.0, y: 0.0 }), 1e10);
+        assert_eq!(grid.gradient(&Point { x: 0.0, y: -6.0 }), Point::default());
+
+        let config = HmcConfig {
+            n_samples: 10_000,
+            n_warmup: 200,
+            step_size: 0.2,
+            num_steps: 10,
+            seed: Some(21),
+            ..HmcConfig::default()
+        };
+        let samples = run_hmc_with_target(&config, &grid).unwrap().samples;
+        let z = z_score_of_mean(&samples, exact.mean(), &exact.cov());
+        assert!(z.x.abs() < 4.0 && z.y.abs() < 4.0, "z {:?}", z);
+        let cov = sample_cov(&samples);
+        assert!(
+            (cov[0][0] - 1.0).abs() < 0.1 && (cov[1][1] - 0.5).abs() < 0.05,
+            "{:?}",
+            cov
+        );
+        assert!((cov[0][1] - 0.3).abs() < 0.05, "{:?}", cov);
+    }
+
+    #[test]
+    fn grid_potential_rejects_bad_shapes() {
+        assert_eq!(
+            GridPotential::new(vec![0.0; 5], 2, 3, 0.0, 1.0, 0.0, 1.0).unwrap_err(),
+            HmcError::DataLengthMismatch { x: 5, y: 6 }
+        );
+        assert!(GridPotential::new(vec![0.0; 3], 1, 3, 0.0, 1.0, 0.0, 1.0).is_err());
+        assert!(GridPotential::new(vec![0.0; 4], 2, 2, 1.0, 0.0, 0.0, 1.0).is_err());
+        assert!(
+            GridPotential::new(vec![0.0, f64::NAN, 0.0, 0.0], 2, 2, 0.0, 1.0, 0.0, 1.0).is_err()
+        );
+        let grid = GridPotential::new(vec![0.0; 4], 2, 2, 0.0, 1.0, 0.0, 1.0).unwrap();
+        assert!(grid.clone().with_outside_potential(f64::INFINITY).is_err());
+        let grid = grid.with_outside_potential(50.0).unwrap();
+        assert_eq!(grid.potential(&Point { x: -1.0, y: 0.5 }), 50.0);
+    }
+
+    #[test]
+    fn standalone_targets_round_trip_through_json() {
+        fn round_trip<T>(target: &T)
+        where
+            T: Serialize + serde::de::DeserializeOwned + PartialEq + std::fmt::Debug,
+        {
+            let json = serde_json::to_string(target).unwrap();
+            assert_eq!(
+                &serde_json::from_str::<T>(&json).unwrap(),
+                target,
+                "{}",
+                json
+            );
+        }
+        let grid = GridPotential::new(vec![0.0, 1.0, 2.0, 3.5], 2, 2, -1.0, 1.0, 0.0, 2.0)
+            .unwrap()
+            .with_outside_potential(7.0)
+            .unwrap();
+        round_trip(&grid);
+        round_trip(&RosenbrockNd {
+            dim: 4,
+            a: 0.5,
+            b: 20.0,
+        });
+        round_trip(&LogisticRegression2::new(&[-1.0, 0.5, 2.0], &[0, 1, 1], 3.0).unwrap());
+        round_trip(&LinearRegression2::new(&[0.0, 1.0], &[1.0, 2.5], 0.2, 10.0).unwrap());
+
+        // 読み込み時も `new` と同じ検証を通る。`outside_potential` は省略できる
+        let grid: GridPotential = serde_json::from_str(
+            r#"{"values": [0, 1, 2, 3], "nx": 2, "ny": 2, "xmin": 0, "xmax": 1, "ymin": 0, "ymax": 1}"#,
+        )
+        .unwrap();
+        assert_eq!(grid.potential(&Point { x: 5.0, y: 0.0 }), 1e10);
+        assert!(serde_json::from_str::<GridPotential>(
+            r#"{"values": [0, 1, 2], "nx": 2, "ny": 2, "xmin": 0, "xmax": 1, "ymin": 0, "ymax": 1}"#
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn rosenbrock_nd_gradient_matches_finite_differences_in_5d() {
+        let target = RosenbrockNd {
+            dim: 5,
+            a: 1.0,
+            b: 100.0,
+        };
+        let numdiff = NumDiff {
+            step: 1e-6,
+            ..NumDiff::default()
+        };
+        for x in [
+            [0.0, 0.0, 0.0, 0.0, 0.0],
+            [-1.2, 1.0, 0.5, -0.3, 2.0],
+            [1.5, 2.2, -0.7, 0.9, 1.1],
+        ] {
+            let grad = target.gradient_nd(&x);
+            for (i, &g) in grad.iter().enumerate() {
+                let along = |v: f64| {
+                    let mut y = x;
+                    y[i] = v;
+                    target.potential_nd(&y)
+                };
+                let num = numdiff.derivative(along, x[i]);
+                assert!(
+                    (g - num).abs() / num.abs().max(1.0) < 1e-6,
+                    "{:?}[{}]: {} vs {}",
+                    x,
+                    i,
+                    g,
+                    num
+                );
+            }
+        }
+        assert_eq!(target.potential_nd(&[1.0; 5]), 0.0);
+        assert_eq!(target.gradient_nd(&[1.0; 5]), vec![0.0; 5]);
+        assert!(target.validate_nd().is_ok());
+        // 2次元のサンプラーには5次元のターゲットは渡せない
+        assert_eq!(
+            target.validate().unwrap_err(),
+            HmcError::InvalidTargetParam {
+                param: "dim".to_string(),
+                value: 5.0
+            }
+        );
+    }
+
+    #[test]
+    fn rosenbrock_mode_estimate_approaches_all_ones() {
+        let target = RosenbrockNd::default();
+        assert_eq!(
+            target.potential(&Point { x: 0.3, y: -0.4 }),
+            Banana { a: 1.0, b: 100.0 }.potential(&Point { x: 0.3, y: -0.4 })
+        );
+        assert_gradient_matches_numerical(&RosenbrockNd { b: 3.0, ..target });
+
+        let config = HmcConfig {
+            n_samples: 500,
+            step_size: 0.02,
+            num_steps: 20,
+            initial_pos: Point { x: -1.0, y: 1.0 },
+            init: InitStrategy::FindMode {
+                max_iters: 200_000,
+                learning_rate: 4e-4,
+            },
+            seed: Some(4),
+            ..HmcConfig::default()
+        };
+        let mode = run_hmc_with_target(&config, target)
+            .unwrap()
+            .init_mode
+            .unwrap();
+        assert!(
+            (mode.x - 1.0).abs() < 1e-3 && (mode.y - 1.0).abs() < 1e-3,
+            "{:?}",
+            mode
+        );
+
+        assert!(RosenbrockNd { dim: 1, ..target }.validate_nd().is_err());
+        assert!(RosenbrockNd { b: 0.0, ..target }.validate().is_err());
+    }
+
+    #[test]
+    fn log_density_is_negated_potential() {
+        let q = Point { x: 0.7, y: -1.3 };
+        for name in DistType::variants() {
+            let target: DistType = name.parse().unwrap();
+            assert_eq!(target.log_density(&q), -target.potential(&q), "{}", name);
+            let (g, u) = (target.grad_log_density(&q), target.gradient(&q));
+            assert_eq!((g.x, g.y), (-u.x, -u.y), "{}", name);
+        }
+        assert_eq!(
+            StandardNormal2.log_density(&q),
+            -0.5 * (q.x * q.x + q.y * q.y)
+        );
+    }
+
+    #[test]
+    fn potential_grid_feeds_grid_potential() {
+        let banana = Banana::default();
+        let values = evaluate_potential_grid(&banana, -2.0, 2.0, -1.0, 4.0, 200, 200);
+        assert_eq!(values.len(), 200 * 200);
+        assert_eq!(values[0], banana.potential(&Point { x: -2.0, y: -1.0 }));
+        assert_eq!(values[199], banana.potential(&Point { x: 2.0, y: -1.0 }));
+        assert_eq!(
+            values[200 * 200 - 1],
+            banana.potential(&Point { x: 2.0, y: 4.0 })
+        );
+
+        // 格子点ではそのまま元のポテンシャルを再現する
+        let grid = GridPotential::new(values, 200, 200, -2.0, 2.0, -1.0, 4.0).unwrap();
+        let node = Point {
+            x: -2.0 + 4.0 * 57.0 / 199.0,
+            y: -1.0 + 5.0 * 120.0 / 199.0,
+        };
+        assert!((grid.potential(&node) - banana.potential(&node)).abs() < 1e-9);
+
+        // 非有限値はそのまま返す
+        let half_plane = Target::from_fn(|p: &Point| if p.x < 0.0 { f64::INFINITY } else { p.x });
+        let values = evaluate_potential_grid(&half_plane, -1.0, 1.0, 0.0, 0.0, 3, 1);
+        assert_eq!(values, vec![f64::INFINITY, 0.0, 1.0]);
+    }
+
+    #[test]
+    fn von_mises_circular_mean_matches_mode_on_torus() {
+        use crate::Topology;
+        use std::f64::consts::TAU;
+
+        // y の平均は基本領域の端の近くに置き、周期境界をまたいで混ざることを確かめる
+        let von_mises = VonMises2 {
+            mean: Point { x: 0.1, y: 6.0 },
+            kappa_x: 2.0,
+            kappa_y: 4.0,
+        };
+        let config = HmcConfig {
+            n_samples: 20_000,
+            step_size: 0.3,
+            num_steps: 10,
+            initial_pos: Point { x: 3.0, y: 3.0 },
+            target: DistType::VonMises(von_mises.clone()),
+            topology: Topology::Periodic {
+                period_x: TAU,
+                period_y: TAU,
+            },
+            seed: Some(50),
+            ..HmcConfig::default()
+        };
+        let samples = run_hmc(&config).unwrap().samples;
+        assert!(samples
+            .iter()
+            .all(|p| (0.0..TAU).contains(&p.x) && (0.0..TAU).contains(&p.y)));
+        let circular_mean = |angle: fn(&Point) -> f64| {
+            let (s, c) = samples.iter().fold((0.0, 0.0), |(s, c), p| {
+                (s + angle(p).sin(), c + angle(p).cos())
+            });
+            f64::atan2(s, c).rem_euclid(TAU)
+        };
+        let (mean_x, mean_y) = (circular_mean(|p| p.x), circular_mean(|p| p.y));
+        assert!((mean_x - 0.1).abs() < 0.05, "circular mean x {}", mean_x);
+        assert!((mean_y - 6.0).abs() < 0.05, "circular mean y {}", mean_y);
+        // 境界の両側にサンプルがある
+        assert!(samples.iter().any(|p| p.x > 5.0) && samples.iter().any(|p| p.x < 1.0));
+
+        assert_eq!(
+            VonMises2 {
+                kappa_x: 0.0,
+                ..von_mises
+            }
+            .validate()
+            .unwrap_err(),
+            HmcError::InvalidTargetParam {
+                param: "kappa_x".to_string(),
+                value: 0.0
+            }
+        );
+    }
+}
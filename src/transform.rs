@@ -0,0 +1,305 @@
+//! 制約のある座標を非制約空間に移す変換（変数変換とヤコビアン）
+
+use serde::{Deserialize, Serialize};
+
+use crate::bounds::Space;
+use crate::target::{sigmoid, softplus, Model};
+use crate::{HmcError, Point, Vector};
+
+/// 1座標の変換 θ = f(z)（z が非制約空間の座標、θ が元の座標）
+///
+/// JSONでは `"identity"`、`"log"`、`{"logit": {"lower": 0, "upper": 1}}` のように書く。
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Transform {
+    /// 変換しない（θ = z）
+    #[default]
+    Identity,
+    /// 正の値 θ > 0 を θ = exp(z) で表す
+    Log,
+    /// 区間 lower < θ < upper を θ = lower + (upper - lower) σ(z) で表す
+    Logit { lower: f64, upper: f64 },
+}
+
+impl Transform {
+    /// 区間の端が有限で lower < upper か検証する
+    pub fn validate(&self) -> Result<(), HmcError> {
+        match *self {
+            Transform::Logit { lower, upper }
+                if !(lower.is_finite() && upper.is_finite() && lower < upper) =>
+            {
+                Err(HmcError::InvalidTransform { lower, upper })
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// 非制約空間の z から元の座標 θ へ
+    pub fn to_constrained(&self, z: f64) -> f64 {
+        match *self {
+            Transform::Identity => z,
+            Transform::Log => z.exp(),
+            Transform::Logit { lower, upper } => lower + (upper - lower) * sigmoid(z),
+        }
+    }
+
+    /// 元の座標 θ から非制約空間の z へ（定義域の外では `NaN` か無限大）
+    pub fn to_unconstrained(&self, theta: f64) -> f64 {
+        match *self {
+            Transform::Identity => theta,
+            Transform::Log => theta.ln(),
+            Transform::Logit { lower, upper } => ((theta - lower) / (upper - theta)).ln(),
+        }
+    }
+
+    /// log |dθ/dz|
+    pub fn log_abs_det_jacobian(&self, z: f64) -> f64 {
+        match *self {
+            Transform::Identity => 0.0,
+            Transform::Log => z,
+            Transform::Logit { lower, upper } => (upper - lower).ln() - softplus(z) - softplus(-z),
+        }
+    }
+
+    /// (dθ/dz, d(log |dθ/dz|)/dz)
+    fn derivatives(&self, z: f64) -> (f64, f64) {
+        match *self {
+            Transform::Identity => (1.0, 0.0),
+            Transform::Log => (z.exp(), 1.0),
+            Transform::Logit { lower, upper } => {
+                let s = sigmoid(z);
+                ((upper - lower) * s * (1.0 - s), 1.0 - 2.0 * s)
+            }
+        }
+    }
+}
+
+/// 座標ごとの変換
+///
+/// ```
+/// use hamiltonian_sampler_rs::{Point, Transform, Transforms};
+///
+/// let transforms = Transforms {
+///     x: Transform::Log,
+///     y: Transform::Logit { lower: 0.0, upper: 1.0 },
+/// };
+/// let z = transforms.to_unconstrained(&Point { x: 1.0, y: 0.5 });
+/// assert_eq!(z, Point { x: 0.0, y: 0.0 });
+/// assert_eq!(transforms.to_constrained(&z), Point { x: 1.0, y: 0.5 });
+/// ```
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Transforms {
+    pub x: Transform,
+    pub y: Transform,
+}
+
+impl Transforms {
+    /// 両座標とも `Identity` か
+    pub fn is_identity(&self) -> bool {
+        self.x == Transform::Identity && self.y == Transform::Identity
+    }
+
+    pub fn validate(&self) -> Result<(), HmcError> {
+        self.x.validate()?;
+        self.y.validate()
+    }
+
+    pub fn to_constrained(&self, z: &Point) -> Point {
+        Point {
+            x: self.x.to_constrained(z.x),
+            y: self.y.to_constrained(z.y),
+        }
+    }
+
+    pub fn to_unconstrained(&self, theta: &Point) -> Point {
+        Point {
+            x: self.x.to_unconstrained(theta.x),
+            y: self.y.to_unconstrained(theta.y),
+        }
+    }
+
+    /// 両座標の log |dθ/dz| の和
+    pub fn log_abs_det_jacobian(&self, z: &Point) -> f64 {
+        self.x.log_abs_det_jacobian(z.x) + self.y.log_abs_det_jacobian(z.y)
+    }
+}
+
+/// 非制約空間で見た、逆温度 β で平坦化したターゲット Ũ(z) = β U(θ(z)) - log |dθ/dz|
+///
+/// 勾配は元のターゲットの勾配を連鎖律で z に移したもの。温度で割るのはターゲットのポテンシャルだけで、
+/// ヤコビアンの項は割らない。変換がなければ θ = z で、ヤコビアンの項は足さない。
+pub(crate) struct Unconstrained<'a, T: ?Sized> {
+    pub(crate) target: &'a T,
+    pub(crate) space: &'a Space,
+    pub(crate) inv_temp: f64,
+}
+
+impl<V: Vector, T: Model<V> + ?Sized> Model<V> for Unconstrained<'_, T> {
+    fn potential_at(&self, z: &V) -> f64 {
+        if self.space.transforms.is_empty() {
+            return self.inv_temp * self.target.potential_at(z);
+        }
+        let theta = self.space.to_constrained(z);
+        self.inv_temp * self.target.potential_at(&theta) - self.space.log_abs_det_jacobian(z)
+    }
+
+    fn gradient_at(&self, z: &V) -> V {
+        if self.space.transforms.is_empty() {
+            let mut g = self.target.gradient_at(z);
+            for g in g.as_mut_slice() {
+                *g *= self.inv_temp;
+            }
+            return g;
+        }
+        let mut g = self.target.gradient_at(&self.space.to_constrained(z));
+        for ((g, t), z) in g
+            .as_mut_slice()
+            .iter_mut()
+            .zip(&self.space.transforms)
+            .zip(z.as_slice())
+        {
+            let (d, j) = t.derivatives(*z);
+            *g = (self.inv_temp * *g) * d - j;
+        }
+        g
+    }
+
+    fn has_exact_gradient(&self) -> bool {
+        self.target.has_exact_gradient()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::numdiff::NumDiff;
+    use crate::{run_hmc_with_target, validate, HmcConfig, Target, TargetDistribution};
+
+    const TRANSFORMS: [Transform; 4] = [
+        Transform::Identity,
+        Transform::Log,
+        Transform::Logit {
+            lower: 0.0,
+            upper: 1.0,
+        },
+        Transform::Logit {
+            lower: -2.0,
+            upper: 3.0,
+        },
+    ];
+
+    #[test]
+    fn transforms_round_trip_and_have_consistent_jacobians() {
+        let numdiff = NumDiff::default();
+        for t in TRANSFORMS {
+            for z in [-3.0, -0.4, 0.0, 1.7, 5.0] {
+                let theta = t.to_constrained(z);
+                assert!(
+                    (t.to_unconstrained(theta) - z).abs() < 1e-9,
+                    "{:?} {}",
+                    t,
+                    z
+                );
+                let (d, dj) = t.derivatives(z);
+                let d_num = numdiff.derivative(|z| t.to_constrained(z), z);
+                let dj_num = numdiff.derivative(|z| t.log_abs_det_jacobian(z), z);
+                assert!((d - d_num).abs() < 1e-6 * d.abs().max(1.0), "{:?} {}", t, z);
+                assert!((dj - dj_num).abs() < 1e-6, "{:?} {}", t, z);
+                assert!(
+                    (t.log_abs_det_jacobian(z) - d.ln()).abs() < 1e-9,
+                    "{:?} {}",
+                    t,
+                    z
+                );
+            }
+        }
+        assert!(Transform::Log.to_unconstrained(-1.0).is_nan());
+        assert_eq!(
+            Transform::Logit {
+                lower: 1.0,
+                upper: 1.0
+            }
+            .validate()
+            .unwrap_err(),
+            HmcError::InvalidTransform {
+                lower: 1.0,
+                upper: 1.0
+            }
+        );
+    }
+
+    /// x ~ Gamma(3, 1)（x > 0）、y ~ Beta(2, 2)（0 < y < 1）
+    fn gamma_beta() -> impl TargetDistribution {
+        Target::from_fn_with_grad(
+            |p: &Point| {
+                if p.x <= 0.0 || p.y <= 0.0 || p.y >= 1.0 {
+                    f64::INFINITY
+                } else {
+                    -2.0 * p.x.ln() + p.x - p.y.ln() - (1.0 - p.y).ln()
+                }
+            },
+            |p: &Point| Point {
+                x: -2.0 / p.x + 1.0,
+                y: -1.0 / p.y + 1.0 / (1.0 - p.y),
+            },
+        )
+    }
+
+    #[test]
+    fn transformed_sampler_matches_gamma_and_beta_moments() {
+        let config = HmcConfig {
+            n_samples: 10_000,
+            n_warmup: 500,
+            step_size: 0.3,
+            num_steps: 8,
+            initial_pos: Point { x: 1.0, y: 0.5 },
+            seed: Some(12),
+            ..HmcConfig::default()
+        };
+        let plain = run_hmc_with_target(&config, gamma_beta()).unwrap();
+        assert!(plain.n_out_of_support > 100, "{}", plain.n_out_of_support);
+
+        let transformed = HmcConfig {
+            transform: Transforms {
+                x: Transform::Log,
+                y: Transform::Logit {
+                    lower: 0.0,
+                    upper: 1.0,
+                },
+            },
+            save_energy: true,
+            ..config.clone()
+        };
+        let result = run_hmc_with_target(&transformed, gamma_beta()).unwrap();
+        assert_eq!(result.n_out_of_support, 0);
+        assert!(result
+            .samples
+            .iter()
+            .all(|p| p.x > 0.0 && 0.0 < p.y && p.y < 1.0));
+        // Gamma(3, 1): 平均3・分散3、Beta(2, 2): 平均1/2・分散1/20
+        let exact_cov = [[3.0, 0.0], [0.0, 0.05]];
+        let z = validate::z_score_of_mean(&result.samples, &Point { x: 3.0, y: 0.5 }, &exact_cov);
+        assert!(z.x.abs() < 4.0 && z.y.abs() < 4.0, "{:?}", z);
+        let cov = validate::sample_cov(&result.samples);
+        assert!((cov[0][0] - 3.0).abs() < 0.3, "{:?}", cov);
+        assert!((cov[1][1] - 0.05).abs() < 0.005, "{:?}", cov);
+
+        // 記録するポテンシャルは非制約空間のもの（ヤコビアン込み）
+        let (p, u) = (&result.samples[0], result.potential_energy[0]);
+        let expected = gamma_beta().potential(p)
+            - transformed
+                .transform
+                .log_abs_det_jacobian(&transformed.transform.to_unconstrained(p));
+        assert!((u - expected).abs() < 1e-9, "{} vs {}", u, expected);
+
+        let outside = HmcConfig {
+            initial_pos: Point { x: -1.0, y: 0.5 },
+            ..transformed
+        };
+        assert_eq!(
+            run_hmc_with_target(&outside, gamma_beta()).unwrap_err(),
+            HmcError::OutsideTransformDomain { x: -1.0, y: 0.5 }
+        );
+    }
+}
@@ -0,0 +1,109 @@
+//! 厳密なモーメントが分かっているターゲットに対するサンプルの統計的検証
+
+use crate::diagnostics::ess;
+use crate::Point;
+
+/// サンプルの平均
+///
+/// 空のサンプルでは `NaN` を返す。
+pub fn sample_mean(samples: &[Point]) -> Point {
+    let n = samples.len() as f64;
+    Point {
+        x: samples.iter().map(|p| p.x).sum::<f64>() / n,
+        y: samples.iter().map(|p| p.y).sum::<f64>() / n,
+    }
+}
+
+/// サンプルの不偏共分散行列（分母は n - 1）
+///
+/// 2点未満のサンプルでは `NaN` を返す。
+pub fn sample_cov(samples: &[Point]) -> [[f64; 2]; 2] {
+    if samples.len() < 2 {
+        return [[f64::NAN; 2]; 2];
+    }
+    let mean = sample_mean(samples);
+    let mut cov = [[0.0; 2]; 2];
+    for p in samples {
+        let d = [p.x - mean.x, p.y - mean.y];
+        for (i, row) in cov.iter_mut().enumerate() {
+            for (j, c) in row.iter_mut().enumerate() {
+                *c += d[i] * d[j];
+            }
+        }
+    }
+    let denom = samples.len() as f64 - 1.0;
+    cov.map(|row| row.map(|c| c / denom))
+}
+
+/// 座標ごとのサンプル平均のzスコア (mean - true_mean) / sqrt(true_var / ESS)
+///
+/// MCMCのサンプルは自己相関を持つため、標準誤差はサンプル数ではなく座標ごとの
+/// [`ess`] で割って求める。サンプラーが正しければ各成分はおおよそ標準正規分布に従うので、
+/// テストでは `|z| < 4` 程度を許容範囲にできる。ESSが計算できない（4点未満など）場合は `NaN`。
+///
+/// ```
+/// use hamiltonian_sampler_rs::validate::z_score_of_mean;
+/// use hamiltonian_sampler_rs::{run_hmc, DistType, HmcConfig, Point};
+///
+/// let config = HmcConfig {
+///     n_samples: 2000,
+///     step_size: 0.3,
+///     num_steps: 10,
+///     target: DistType::Normal,
+///     seed: Some(1),
+///     ..HmcConfig::default()
+/// };
+/// let samples = run_hmc(&config).unwrap().samples;
+/// let z = z_score_of_mean(&samples, &Point { x: 0.0, y: 0.0 }, &[[1.0, 0.0], [0.0, 1.0]]);
+/// assert!(z.x.abs() < 4.0 && z.y.abs() < 4.0);
+/// ```
+pub fn z_score_of_mean(samples: &[Point], true_mean: &Point, true_cov: &[[f64; 2]; 2]) -> Point {
+    let mean = sample_mean(samples);
+    let xs: Vec<f64> = samples.iter().map(|p| p.x).collect();
+    let ys: Vec<f64> = samples.iter().map(|p| p.y).collect();
+    Point {
+        x: (mean.x - true_mean.x) / (true_cov[0][0] / ess(&xs)).sqrt(),
+        y: (mean.y - true_mean.y) / (true_cov[1][1] / ess(&ys)).sqrt(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::prelude::*;
+    use rand_distr::StandardNormal;
+
+    fn iid_normal(n: usize, seed: u64) -> Vec<Point> {
+        let mut rng = StdRng::seed_from_u64(seed);
+        (0..n)
+            .map(|_| Point {
+                x: rng.sample(StandardNormal),
+                y: rng.sample(StandardNormal),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn moments_of_small_sample() {
+        let samples = [
+            Point { x: 0.0, y: 1.0 },
+            Point { x: 2.0, y: 3.0 },
+            Point { x: 4.0, y: 2.0 },
+        ];
+        assert_eq!(sample_mean(&samples), Point { x: 2.0, y: 2.0 });
+        assert_eq!(sample_cov(&samples), [[4.0, 1.0], [1.0, 1.0]]);
+        assert!(sample_cov(&samples[..1])[0][0].is_nan());
+    }
+
+    #[test]
+    fn z_score_detects_biased_mean() {
+        let samples = iid_normal(10_000, 3);
+        let identity = [[1.0, 0.0], [0.0, 1.0]];
+        let z = z_score_of_mean(&samples, &Point { x: 0.0, y: 0.0 }, &identity);
+        assert!(z.x.abs() < 4.0 && z.y.abs() < 4.0, "{:?}", z);
+
+        // 真の平均が 0.1 ずれていれば ESS ≈ 10^4 で z ≈ 10
+        let z = z_score_of_mean(&samples, &Point { x: 0.1, y: 0.0 }, &identity);
+        assert!(z.x < -6.0, "{:?}", z);
+    }
+}
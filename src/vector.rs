@@ -0,0 +1,160 @@
+//! サンプラーが状態に使うベクトル
+
+use crate::Point;
+
+/// 位置・運動量・勾配を表すベクトル（チェーンと積分器はこれについて総称的）
+///
+/// 2次元の [`Point`]、長さを実行時に決める `Vec<f64>`、ヒープを使わない固定長の [`PointN`] が実装する。
+pub trait Vector: Clone {
+    /// 成分の列
+    fn as_slice(&self) -> &[f64];
+
+    /// 書き換えられる成分の列
+    fn as_mut_slice(&mut self) -> &mut [f64];
+
+    /// 次元
+    fn dim(&self) -> usize {
+        self.as_slice().len()
+    }
+
+    /// 内積
+    fn dot(&self, other: &Self) -> f64 {
+        self.as_slice()
+            .iter()
+            .zip(other.as_slice())
+            .map(|(a, b)| a * b)
+            .sum()
+    }
+
+    /// self += a x
+    fn axpy(&mut self, a: f64, x: &Self) {
+        for (s, x) in self.as_mut_slice().iter_mut().zip(x.as_slice()) {
+            *s += a * x;
+        }
+    }
+}
+
+// SAFETY: `Point` は `#[repr(C)]` で2つの `f64` だけを持つので、`[f64; 2]` と同じ配置になる
+impl Vector for Point {
+    fn as_slice(&self) -> &[f64] {
+        unsafe { std::slice::from_raw_parts(self as *const Point as *const f64, 2) }
+    }
+
+    fn as_mut_slice(&mut self) -> &mut [f64] {
+        unsafe { std::slice::from_raw_parts_mut(self as *mut Point as *mut f64, 2) }
+    }
+
+    fn dim(&self) -> usize {
+        2
+    }
+}
+
+impl Vector for Vec<f64> {
+    fn as_slice(&self) -> &[f64] {
+        self
+    }
+
+    fn as_mut_slice(&mut self) -> &mut [f64] {
+        self
+    }
+}
+
+/// `D` 次元の固定長の点（成分はスタック上に持つ）
+///
+/// 2〜4次元程度の小さい状態で、遷移ごとのヒープ確保を避けるのに使う。
+///
+/// ```
+/// use hamiltonian_sampler_rs::{Point, PointN};
+///
+/// let a = PointN([1.0, 2.0, 3.0]);
+/// let b = PointN([0.5, 0.0, -1.0]);
+/// assert_eq!(a.add(&b), PointN([1.5, 2.0, 2.0]));
+/// assert_eq!(a.scale(2.0), PointN([2.0, 4.0, 6.0]));
+/// assert_eq!(a.dot(&b), -2.5);
+///
+/// let p = Point { x: 1.0, y: -2.0 };
+/// assert_eq!(Point::from(PointN::from(p.clone())), p);
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PointN<const D: usize>(pub [f64; D]);
+
+impl<const D: usize> Default for PointN<D> {
+    fn default() -> Self {
+        Self([0.0; D])
+    }
+}
+
+impl<const D: usize> PointN<D> {
+    /// 成分ごとの和
+    pub fn add(&self, other: &Self) -> Self {
+        Self(std::array::from_fn(|i| self.0[i] + other.0[i]))
+    }
+
+    /// スカラー倍
+    pub fn scale(&self, a: f64) -> Self {
+        Self(self.0.map(|x| a * x))
+    }
+
+    /// 内積
+    pub fn dot(&self, other: &Self) -> f64 {
+        Vector::dot(self, other)
+    }
+}
+
+impl<const D: usize> Vector for PointN<D> {
+    fn as_slice(&self) -> &[f64] {
+        &self.0
+    }
+
+    fn as_mut_slice(&mut self) -> &mut [f64] {
+        &mut self.0
+    }
+}
+
+impl From<Point> for PointN<2> {
+    fn from(p: Point) -> Self {
+        Self([p.x, p.y])
+    }
+}
+
+impl From<PointN<2>> for Point {
+    fn from(p: PointN<2>) -> Self {
+        let [x, y] = p.0;
+        Point { x, y }
+    }
+}
+
+/// 成分ごとに `f` を適用したベクトル
+pub(crate) fn map<V: Vector>(a: &V, f: impl Fn(f64) -> f64) -> V {
+    let mut out = a.clone();
+    for x in out.as_mut_slice() {
+        *x = f(*x);
+    }
+    out
+}
+
+/// 成分ごとに `f(a_i, b_i)` を並べたベクトル
+pub(crate) fn zip_with<V: Vector>(a: &V, b: &V, f: impl Fn(f64, f64) -> f64) -> V {
+    let mut out = a.clone();
+    for (x, b) in out.as_mut_slice().iter_mut().zip(b.as_slice()) {
+        *x = f(*x, *b);
+    }
+    out
+}
+
+/// すべての成分が有限か
+pub(crate) fn is_finite<V: Vector>(a: &V) -> bool {
+    a.as_slice().iter().all(|x| x.is_finite())
+}
+
+/// ユークリッドノルム（2次元では `x.hypot(y)` と同じ値）
+pub(crate) fn norm<V: Vector>(a: &V) -> f64 {
+    a.as_slice().iter().fold(0.0, |n, &x| n.hypot(x))
+}
+
+/// `values` を成分に持つ、`like` と同じ形のベクトル
+pub(crate) fn from_slice<V: Vector>(like: &V, values: &[f64]) -> V {
+    let mut out = like.clone();
+    out.as_mut_slice().copy_from_slice(values);
+    out
+}
@@ -51,14 +51,35 @@ class TestHamiltonianSampler(unittest.TestCase):
         _, acc_bimodal = hmc.sample(50, 0.1, 5, 0.0, 0.0, "bimodal")
         self.assertIsInstance(acc_bimodal, float)
 
-    def test_03_unknown_distribution_fallback(self):
-        """堅牢性テスト: 未知の分布名でもクラッシュせずデフォルト(Bimodal)で動作するか"""
-        # Rust実装では `_ => DistType::Bimodal` となっている仕様を確認
-        try:
-            samples, _ = hmc.sample(10, 0.1, 5, 0.0, 0.0, "unknown_dist_name")
-            self.assertEqual(len(samples), 10)
-        except Exception as e:
-            self.fail(f"未知の分布名を渡してクラッシュしました: {e}")
+    def test_03_unknown_distribution_error(self):
+        """堅牢性テスト: 未知の分布名はデフォルトにフォールバックせず ValueError になるか"""
+        with self.assertRaises(ValueError) as ctx:
+            hmc.sample(10, 0.1, 5, 0.0, 0.0, "bananna")
+        # エラーメッセージに指定可能な分布名が含まれる
+        self.assertIn("bimodal", str(ctx.exception))
+        self.assertIn("banana", str(ctx.exception))
+
+        # 大文字・小文字は区別しない
+        samples, _ = hmc.sample(10, 0.1, 5, 0.0, 0.0, "Banana")
+        self.assertEqual(len(samples), 10)
+
+        # 不正なパラメータも ValueError として報告される
+        with self.assertRaises(ValueError):
+            hmc.sample(0, 0.1, 5, 0.0, 0.0, "bimodal")
+        with self.assertRaises(ValueError):
+            hmc.sample(10, 0.1, 0, 0.0, 0.0, "bimodal")
+        with self.assertRaises(ValueError):
+            hmc.sample(10, 0.1, 5, float("nan"), 0.0, "bimodal")
+        with self.assertRaises(ValueError):
+            hmc.sample(10, float("inf"), 5, 0.0, 0.0, "bimodal")
+        with self.assertRaises(ValueError):
+            hmc.sample(10, 0.1, 5, 1e200, 0.0, "banana")
+        with self.assertRaises(ValueError):
+            hmc.sample(10, -0.1, 5, 0.0, 0.0, "bimodal")
+        # 実行時間の上限は非負の有限値（パニックせず ValueError になる）
+        for max_duration in (-1.0, float("nan"), float("inf")):
+            with self.assertRaises(ValueError):
+                hmc.sample(10, 0.1, 5, 0.0, 0.0, "bimodal", max_duration=max_duration)
 
     def test_04_step_size_sensitivity(self):
         """
@@ -127,6 +148,925 @@ class TestHamiltonianSampler(unittest.TestCase):
             duration, 2.0, "処理時間が遅すぎます（Rustの利点が出ていません）"
         )
 
+    def test_07_seed_reproducibility(self):
+        """再現性テスト: 同じシードなら同一のサンプル列が得られるか"""
+        a, rate_a = hmc.sample(200, 0.1, 10, 0.0, 0.0, "banana", seed=42)
+        b, rate_b = hmc.sample(200, 0.1, 10, 0.0, 0.0, "banana", seed=42)
+        c, _ = hmc.sample(200, 0.1, 10, 0.0, 0.0, "banana", seed=7)
+
+        self.assertEqual(a, b)
+        self.assertEqual(rate_a, rate_b)
+        self.assertNotEqual(a, c)
+
+    def test_08_warmup_and_thinning(self):
+        """ウォームアップ/間引きテスト: 保存されるサンプル数と戻り値の形"""
+        samples, _ = hmc.sample(100, 0.1, 10, 0.0, 0.0, "bimodal", n_warmup=50, thin=3)
+        self.assertEqual(len(samples), 100)
+
+        result = hmc.sample(
+            100, 0.1, 10, 0.0, 0.0, "bimodal", seed=1, n_warmup=50, save_warmup=True
+        )
+        self.assertEqual(len(result), 3, "save_warmup=True ならウォームアップも返すべき")
+        samples, _, warmup = result
+        self.assertEqual(len(samples), 100)
+        self.assertEqual(len(warmup), 50)
+
+        with self.assertRaises(ValueError):
+            hmc.sample(10, 0.1, 5, 0.0, 0.0, "bimodal", thin=0)
+
+    def test_09_multiple_chains(self):
+        """マルチチェーンテスト: チェーンごとのサンプル列と採択率"""
+        chains, rates = hmc.sample_chains(3, 100, 0.1, 10, "banana", seed=42)
+        self.assertEqual(len(chains), 3)
+        self.assertEqual(len(rates), 3)
+        self.assertTrue(all(len(c) == 100 for c in chains))
+        self.assertNotEqual(chains[0], chains[1])
+
+        with self.assertRaises(ValueError):
+            hmc.sample_chains(3, 100, 0.1, 10, "banana", initial_points=[(0.0, 0.0)])
+
+    def test_10_chain_seed_derivation(self):
+        """シード導出テスト: 導出したシードでチェーンを単独再実行できるか"""
+        chains, _ = hmc.sample_chains(4, 100, 0.1, 10, "banana", seed=42)
+        seed = hmc.derive_chain_seed(42, 2)
+        single, _ = hmc.sample(100, 0.1, 10, 0.0, 0.0, "banana", seed=seed)
+        self.assertEqual(single, chains[2])
+
+    def test_11_dispersed_initial_points(self):
+        """初期位置生成テスト: 矩形内の一様点とジッターをマルチチェーンに渡せるか"""
+        starts = hmc.init_uniform_box(4, -5.0, 5.0, -5.0, 5.0, seed=3)
+        self.assertEqual(len(starts), 4)
+        self.assertTrue(all(-5.0 <= x <= 5.0 and -5.0 <= y <= 5.0 for x, y in starts))
+        chains, _ = hmc.sample_chains(4, 50, 0.1, 10, "bimodal", initial_points=starts)
+        self.assertEqual(len(chains), 4)
+
+        jittered = hmc.init_jitter(3, 1.0, 1.0, 0.0)
+        self.assertEqual(jittered, [(1.0, 1.0)] * 3)
+        with self.assertRaises(ValueError):
+            hmc.init_uniform_box(4, 1.0, -1.0, 0.0, 1.0)
+
+    def test_12_run_with_accept_flags(self):
+        """辞書APIテスト: run() が採択フラグを含む結果を辞書で返すか"""
+        result = hmc.run(
+            n_samples=200, step_size=0.15, num_steps=10, target="banana",
+            seed=8, save_accept_flags=True,
+        )
+        self.assertEqual(len(result["samples"]), 200)
+        self.assertEqual(len(result["accepted"]), 200)
+        self.assertTrue(all(isinstance(a, bool) for a in result["accepted"]))
+        self.assertAlmostEqual(
+            sum(result["accepted"]) / 200, result["acceptance_rate"]
+        )
+
+        self.assertNotIn("accepted", hmc.run(n_samples=10, seed=1))
+        with self.assertRaises(ValueError):
+            hmc.run(n_sample=10)
+
+    def test_13_divergence_count(self):
+        """発散検出テスト: 大きすぎるステップ幅で発散数が報告されるか"""
+        result = hmc.run(n_samples=200, step_size=2.0, target="banana", seed=3)
+        self.assertGreater(result["n_divergent"], 0)
+        result = hmc.run(n_samples=200, step_size=0.01, target="banana", seed=3)
+        self.assertEqual(result["n_divergent"], 0)
+
+    def test_14_bimodal_params(self):
+        """パラメータ指定テスト: 辞書で山の位置を変えた二峰分布から両方の山が得られるか"""
+        target = {"bimodal": {"centers": [{"x": 6, "y": 6}, {"x": -6, "y": -6}]}}
+        starts = hmc.init_uniform_box(8, -8.0, 8.0, -8.0, 8.0, seed=8)
+        means = []
+        for i, (x, y) in enumerate(starts):
+            result = hmc.run(
+                n_samples=200, target=target, initial_pos={"x": x, "y": y}, seed=i
+            )
+            means.append(sum(p["x"] for p in result["samples"]) / 200)
+        self.assertTrue(any(m > 4.0 for m in means), means)
+        self.assertTrue(any(m < -4.0 for m in means), means)
+
+        with self.assertRaises(ValueError):
+            hmc.run(target={"bimodal": {"scales": [1.0, -1.0]}})
+        with self.assertRaises(ValueError):
+            hmc.run(target={"bimodal": {"scale": [1.0, 1.0]}})
+
+    def test_15_banana_params(self):
+        """パラメータ指定テスト: 分布名と params の辞書でバナナ分布の形を変えられるか"""
+        result = hmc.run(target={"banana": {"a": 2.0}}, n_samples=2000, step_size=0.05,
+                         initial_pos={"x": 2.0, "y": 4.0}, seed=3)
+        mean_x = sum(p["x"] for p in result["samples"]) / 2000
+        self.assertGreater(mean_x, 1.0, "a=2 ならモードは (2, 4) 付近になるはず")
+
+        samples, _ = hmc.sample(100, 0.01, 20, 1.0, 1.0, "Banana", seed=1, params={"b": 100})
+        self.assertEqual(len(samples), 100)
+        samples, _ = hmc.sample_chains(2, 50, 0.01, 20, "banana", seed=1, params={"b": 100})
+        self.assertEqual(len(samples), 2)
+
+        with self.assertRaises(ValueError):
+            hmc.sample(10, 0.1, 5, 0.0, 0.0, "banana", params={"b": -1.0})
+        with self.assertRaises(ValueError):
+            hmc.sample(10, 0.1, 5, 0.0, 0.0, "banana", params={"c": 1.0})
+
+    def test_16_funnel(self):
+        """漏斗分布テスト: 文字列 "funnel" で指定でき、scale を params で変えられるか"""
+        samples, _ = hmc.sample(200, 0.05, 20, 0.0, 0.0, "funnel", seed=2)
+        self.assertEqual(len(samples), 200)
+        samples, _ = hmc.sample(200, 0.05, 20, 0.0, 0.0, "funnel", seed=2, params={"scale": 1.0})
+        self.assertLess(max(abs(p[1]) for p in samples), 5.0, "scale=1 なら y は ±5 に収まるはず")
+        with self.assertRaises(ValueError):
+            hmc.sample(10, 0.1, 5, 0.0, 0.0, "funnel", params={"scale": 0.0})
+
+    def test_17_ring(self):
+        """ドーナツ分布テスト: サンプルの平均半径が指定した半径に近いか"""
+        samples, _ = hmc.sample(2000, 0.1, 20, 2.0, 0.0, "donut", seed=4,
+                                params={"radius": 2.0, "width": 0.2})
+        mean_r = sum(math.hypot(x, y) for x, y in samples) / len(samples)
+        self.assertAlmostEqual(mean_r, 2.0, delta=0.1)
+
+        result = hmc.run(target={"ring": {"radius": 5.0}}, n_samples=500, step_size=0.1,
+                         initial_pos={"x": 0.0, "y": 5.0}, seed=4)
+        self.assertEqual(len(result["samples"]), 500)
+
+    def test_18_correlated_gaussian(self):
+        """正規分布テスト: 相関0.95の共分散行列をサンプル共分散で再現できるか"""
+        samples, _ = hmc.sample(10000, 0.1, 15, 0.0, 0.0, "gaussian", seed=6,
+                                params={"cov": [[1.0, 0.95], [0.95, 1.0]]})
+        n = len(samples)
+        mx = sum(x for x, _ in samples) / n
+        my = sum(y for _, y in samples) / n
+        cxy = sum((x - mx) * (y - my) for x, y in samples) / n
+        self.assertAlmostEqual(cxy, 0.95, delta=0.08)
+
+        with self.assertRaises(ValueError):
+            hmc.sample(10, 0.1, 5, 0.0, 0.0, "gaussian", params={"cov": [[1.0, 2.0], [2.0, 1.0]]})
+
+    def test_19_gaussian_mixture(self):
+        """混合分布テスト: 成分の辞書のリストで指定した混合分布からサンプリングできるか"""
+        eye = [[0.5, 0.0], [0.0, 0.5]]
+        components = [
+            {"weight": 1.0, "mean": {"x": -1.5, "y": 0.0}, "cov": eye},
+            {"weight": 3.0, "mean": {"x": 1.5, "y": 0.0}, "cov": eye},
+        ]
+        samples, _ = hmc.sample_chains(4, 3000, 0.2, 15, "mixture", seed=7, params=components)
+        xs = [x for chain in samples for x, _ in chain]
+        right = sum(1 for x in xs if x > 0) / len(xs)
+        self.assertAlmostEqual(right, 0.75, delta=0.1)
+
+        with self.assertRaises(ValueError):
+            hmc.sample(10, 0.1, 5, 0.0, 0.0, "mixture", params=[{**components[0], "weight": -1.0}])
+
+    def test_20_student_t(self):
+        """t分布テスト: 自由度を params で指定でき、中央値が位置パラメータに一致するか"""
+        params = {"dof": 2.0, "mean": {"x": 2.0, "y": 0.0}}
+        samples, _ = hmc.sample(20000, 0.3, 10, 2.0, 0.0, "student_t", seed=9, params=params)
+        xs = sorted(x for x, _ in samples)
+        self.assertAlmostEqual(xs[len(xs) // 2], 2.0, delta=0.15)
+
+        with self.assertRaises(ValueError):
+            hmc.sample(10, 0.1, 5, 0.0, 0.0, "student_t", params={"dof": 0.0})
+
+    def test_21_logistic_regression(self):
+        """ロジスティック回帰テスト: 既知の係数で生成したデータから係数を推定できるか"""
+        import random
+        rng = random.Random(0)
+        xs = [rng.uniform(-3.0, 3.0) for _ in range(400)]
+        ys = [int(rng.random() < 1.0 / (1.0 + math.exp(-(2.0 * x + 0.5)))) for x in xs]
+        samples, rate = hmc.sample_logistic_regression(xs, ys, 3000, 0.05, 10, seed=1, n_warmup=300)
+        slope = sum(s for s, _ in samples) / len(samples)
+        intercept = sum(c for _, c in samples) / len(samples)
+        self.assertAlmostEqual(slope, 2.0, delta=0.6)
+        self.assertAlmostEqual(intercept, 0.5, delta=0.5)
+        self.assertGreater(rate, 0.5)
+
+        with self.assertRaises(ValueError):
+            hmc.sample_logistic_regression([0.0, 1.0], [1], 10, 0.1, 5)
+
+    def test_22_linear_regression(self):
+        """線形回帰テスト: サンプルの事後平均・共分散が厳密な事後分布と一致するか"""
+        xs = [0.1 * i for i in range(40)]
+        ys = [0.8 * x + 2.0 + 0.3 * math.sin(7.0 * x) for x in xs]
+        s = hmc.sample_linear_regression(xs, ys, 10000, 0.01, 30, noise_std=0.3, seed=2, n_warmup=200)
+        self.assertEqual(len(s["samples"]), 10000)
+        for i in range(2):
+            sd = math.sqrt(s["exact_cov"][i][i])
+            self.assertAlmostEqual(s["mean"][i], s["exact_mean"][i], delta=0.2 * sd)
+            self.assertAlmostEqual(s["cov"][i][i], s["exact_cov"][i][i], delta=0.15 * sd * sd)
+
+        with self.assertRaises(ValueError):
+            hmc.sample_linear_regression([0.0], [1.0], 10, 0.1, 5, noise_std=0.0)
+
+    def test_23_grid_potential(self):
+        """格子ポテンシャルテスト: 格子上に並べた正規分布のポテンシャルから正しい分散が出るか"""
+        nx, ny = 121, 121
+        grid = [-6.0 + 12.0 * k / 120 for k in range(121)]
+        values = [0.5 * (x * x + y * y / 4.0) for y in grid for x in grid]
+        samples, rate = hmc.sample_grid_potential(
+            values, nx, ny, -6.0, 6.0, -6.0, 6.0, 5000, 0.3, 10, 0.0, 0.0, seed=3, n_warmup=200
+        )
+        self.assertEqual(len(samples), 5000)
+        self.assertGreater(rate, 0.5)
+        var_x = sum(x * x for x, _ in samples) / len(samples)
+        var_y = sum(y * y for _, y in samples) / len(samples)
+        self.assertAlmostEqual(var_x, 1.0, delta=0.15)
+        self.assertAlmostEqual(var_y, 4.0, delta=0.6)
+        self.assertTrue(all(abs(x) <= 6.0 and abs(y) <= 6.0 for x, y in samples))
+
+        with self.assertRaises(ValueError):
+            hmc.sample_grid_potential(values[:-1], nx, ny, -6.0, 6.0, -6.0, 6.0, 10, 0.1, 5, 0.0, 0.0)
+
+    def test_24_log_density(self):
+        """対数密度テスト: サンプラーと同じ式の対数密度と勾配が得られるか"""
+        self.assertAlmostEqual(hmc.log_density(0.5, -1.0, "normal"), -0.625)
+        self.assertEqual(hmc.grad_log_density(0.5, -1.0, "normal"), (-0.5, 1.0))
+        # U = (a - x)^2 + b (y - x^2)^2
+        self.assertAlmostEqual(hmc.log_density(0.0, 1.0, "banana", {"a": 2.0, "b": 3.0}), -7.0)
+        self.assertGreater(hmc.log_density(2.5, 2.5, "bimodal"), hmc.log_density(0.0, 0.0, "bimodal"))
+
+        with self.assertRaises(ValueError):
+            hmc.log_density(0.0, 0.0, "banana", {"b": -1.0})
+        with self.assertRaises(ValueError):
+            hmc.log_density(0.0, 0.0, "laplace")
+
+    def test_25_potential_grid(self):
+        """格子評価テスト: 行優先の並びで点ごとの対数密度と一致するか"""
+        nx, ny = 4, 3
+        values = hmc.evaluate_potential_grid("banana", -2.0, 2.0, -1.0, 3.0, nx, ny, {"b": 5.0})
+        self.assertEqual(len(values), nx * ny)
+        for j in range(ny):
+            for i in range(nx):
+                x, y = -2.0 + 4.0 * i / (nx - 1), -1.0 + 4.0 * j / (ny - 1)
+                expected = -hmc.log_density(x, y, "banana", {"b": 5.0})
+                self.assertAlmostEqual(values[j * nx + i], expected)
+
+    def test_26_transform(self):
+        """座標変換テスト: 変換した座標のサンプルが定義域に収まり元の座標で返るか"""
+        out = hmc.run(
+            n_samples=4000,
+            step_size=0.2,
+            num_steps=10,
+            target="normal",
+            initial_pos={"x": 1.0, "y": 0.5},
+            transform={"x": "log", "y": {"logit": {"lower": 0.0, "upper": 1.0}}},
+            seed=4,
+        )
+        xs = [p["x"] for p in out["samples"]]
+        ys = [p["y"] for p in out["samples"]]
+        self.assertTrue(all(x > 0.0 for x in xs))
+        self.assertTrue(all(0.0 < y < 1.0 for y in ys))
+        # x > 0 に制限した標準正規分布は平均 sqrt(2/π) の半正規分布
+        self.assertAlmostEqual(sum(xs) / len(xs), math.sqrt(2.0 / math.pi), delta=0.1)
+
+        with self.assertRaises(ValueError):
+            hmc.run(target="normal", transform={"x": "log"})
+
+    def test_27_reflective_bounds(self):
+        """反射境界テスト: 矩形を指定するとサンプルが矩形の内側に収まるか"""
+        bounds = {"xmin": -1.0, "xmax": 1.0, "ymin": 0.0, "ymax": 0.5}
+        out = hmc.run(n_samples=2000, step_size=0.3, num_steps=10, target="normal", bounds=bounds, seed=6)
+        self.assertTrue(all(-1.0 < p["x"] < 1.0 and 0.0 < p["y"] < 0.5 for p in out["samples"]))
+        self.assertGreater(out["acceptance_rate"], 0.9)
+
+        with self.assertRaises(ValueError):
+            hmc.run(target="normal", bounds={"xmin": 1.0, "xmax": 2.0, "ymin": 0.0, "ymax": 1.0})
+
+    def test_28_periodic_topology(self):
+        """周期境界テスト: トーラス上のフォン・ミーゼス分布のサンプルが基本領域に収まり円周平均がモードに一致するか"""
+        tau = 2.0 * math.pi
+        out = hmc.run(
+            n_samples=4000,
+            step_size=0.3,
+            num_steps=10,
+            target={"von_mises": {"mean": {"x": 0.2, "y": 6.0}, "kappa_x": 3.0, "kappa_y": 3.0}},
+            topology={"periodic": {"period_x": tau, "period_y": tau}},
+            seed=8,
+        )
+        samples = out["samples"]
+        self.assertTrue(all(0.0 <= p["x"] < tau and 0.0 <= p["y"] < tau for p in samples))
+        mean_y = math.atan2(sum(math.sin(p["y"]) for p in samples), sum(math.cos(p["y"]) for p in samples))
+        self.assertAlmostEqual(mean_y % tau, 6.0, delta=0.1)
+
+        with self.assertRaises(ValueError):
+            hmc.run(target="von_mises", topology={"periodic": {"period_x": 0.0, "period_y": tau}})
+
+    def test_29_tempered_target(self):
+        """温度テスト: temperature を上げると二峰性分布の1本のチェーンが両方の山を行き来するか"""
+        out = hmc.run(
+            n_samples=4000,
+            n_warmup=200,
+            step_size=0.3,
+            num_steps=10,
+            initial_pos={"x": 2.5, "y": 2.5},
+            target="bimodal",
+            temperature=5.0,
+            seed=52,
+        )
+        upper = sum(1 for p in out["samples"] if p["x"] + p["y"] > 0.0) / len(out["samples"])
+        self.assertGreater(upper, 0.3)
+        self.assertLess(upper, 0.7)
+
+        with self.assertRaises(ValueError):
+            hmc.run(target="bimodal", temperature=0.0)
+
+    def test_30_cauchy(self):
+        """コーシー分布テスト: 長いチェーンでも数値が壊れず、中央値が位置パラメータに一致するか"""
+        params = {"location": {"x": -1.0, "y": 2.0}, "scale": 1.0}
+        samples, _ = hmc.sample(100000, 0.3, 10, -1.0, 2.0, "cauchy", seed=53, params=params)
+        self.assertTrue(all(math.isfinite(x) and math.isfinite(y) for x, y in samples))
+        ys = sorted(y for _, y in samples)
+        self.assertAlmostEqual(ys[len(ys) // 2], 2.0, delta=0.3)
+
+        with self.assertRaises(ValueError):
+            hmc.sample(10, 0.1, 5, 0.0, 0.0, "cauchy", params={"scale": -1.0})
+
+    def test_31_mode_occupancy(self):
+        """山の占有率テスト: mode_centers を渡すと各山の占有率と山の間の行き来の回数が結果に載るか"""
+        centers = [{"x": 1.0, "y": 1.0}, {"x": -1.0, "y": -1.0}]
+        out = hmc.run(
+            n_samples=5000,
+            n_warmup=200,
+            step_size=0.3,
+            num_steps=10,
+            target={"bimodal": {"centers": centers}},
+            mode_centers=centers,
+            seed=54,
+        )
+        self.assertEqual(len(out["mode_occupancy"]), 2)
+        for fraction in out["mode_occupancy"]:
+            self.assertAlmostEqual(fraction, 0.5, delta=0.05)
+        self.assertGreater(out["mode_switches"], 0)
+
+        out = hmc.run(n_samples=10, target="bimodal")
+        self.assertNotIn("mode_occupancy", out)
+        self.assertNotIn("mode_switches", out)
+
+    def test_32_nuts(self):
+        """NUTSテスト: algorithm で NUTS を選ぶと木の深さの統計が結果に載り、max_depth=0 は拒否されるか"""
+        out = hmc.run(
+            n_samples=2000,
+            step_size=0.05,
+            algorithm={"nuts": {"max_depth": 8}},
+            target="banana",
+            initial_pos={"x": 1.0, "y": 1.0},
+            seed=56,
+        )
+        self.assertEqual(len(out["samples"]), 2000)
+        self.assertGreaterEqual(out["mean_tree_depth"], 1.0)
+        self.assertLessEqual(out["mean_tree_depth"], 8.0)
+        self.assertIn("n_max_tree_depth", out)
+        self.assertGreater(out["n_leapfrog"], 0)
+
+        out = hmc.run(n_samples=10, num_steps=5)
+        self.assertEqual(out["n_leapfrog"], 50)
+        self.assertNotIn("mean_tree_depth", out)
+
+        with self.assertRaises(ValueError):
+            hmc.run(n_samples=10, algorithm={"nuts": {"max_depth": 0}})
+
+    def test_33_step_size_adaptation(self):
+        """ステップ幅の適応テスト: ウォームアップで適応させると採択率が目標に近づき、適応後の値が結果に載るか"""
+        for step_size in [2.0, 0.001]:
+            out = hmc.run(
+                n_samples=4000,
+                n_warmup=1000,
+                step_size=step_size,
+                num_steps=10,
+                adapt_step_size=True,
+                target_accept=0.8,
+                target="normal",
+                seed=57,
+            )
+            self.assertAlmostEqual(out["acceptance_rate"], 0.8, delta=0.05)
+            self.assertGreater(out["adapted_step_size"], 0.0)
+
+            again = hmc.run(
+                n_samples=1000,
+                step_size=out["adapted_step_size"],
+                num_steps=10,
+                target="normal",
+                seed=1,
+            )
+            self.assertAlmostEqual(again["acceptance_rate"], 0.8, delta=0.08)
+            self.assertNotIn("adapted_step_size", again)
+
+        with self.assertRaises(ValueError):
+            hmc.run(n_samples=10, adapt_step_size=True, target_accept=1.5)
+
+    def test_34_mass_matrix_adaptation(self):
+        """質量行列の適応テスト: 座標ごとにスケールの違う正規分布で推定した質量行列が分散に近く、結果に載るか"""
+        target = {"gaussian": {"cov": [[1.0, 0.0], [0.0, 100.0]]}}
+        out = hmc.run(
+            n_samples=2000,
+            n_warmup=1000,
+            num_steps=2,
+            adapt_step_size=True,
+            adapt_mass_matrix=True,
+            target=target,
+            seed=58,
+        )
+        inv_mass = out["metric"]["diagonal"]
+        self.assertAlmostEqual(inv_mass[0], 1.0, delta=0.5)
+        self.assertAlmostEqual(inv_mass[1], 100.0, delta=50.0)
+
+        # 推定値をそのまま次の実行に渡せる
+        again = hmc.run(n_samples=100, metric=out["metric"], step_size=out["adapted_step_size"], target=target)
+        self.assertEqual(len(again["samples"]), 100)
+        self.assertEqual(again["metric"], out["metric"])
+
+        with self.assertRaises(ValueError):
+            hmc.run(n_samples=10, metric=[1.0, -1.0])
+
+    def test_35_dense_metric(self):
+        """密な質量行列テスト: 相関の強い正規分布で密な質量行列を指定・推定でき、正定値でない行列は拒否されるか"""
+        rho = 0.95
+        det = 1.0 - rho * rho
+        precision = [[1.0 / det, -rho / det], [-rho / det, 1.0 / det]]
+        target = {"gaussian": {"cov": [[1.0, rho], [rho, 1.0]]}}
+        common = dict(n_samples=3000, n_warmup=1000, num_steps=3, adapt_step_size=True, target=target, seed=59)
+
+        dense = hmc.run(metric={"dense": precision}, **common)
+        self.assertAlmostEqual(dense["acceptance_rate"], 0.8, delta=0.1)
+
+        adapted = hmc.run(metric="adapt_dense", **common)
+        mass = adapted["metric"]["dense"]
+        self.assertAlmostEqual(mass[0][1] / mass[0][0], -rho, delta=0.05)
+
+        with self.assertRaises(ValueError):
+            hmc.run(n_samples=10, metric={"dense": [[1.0, 2.0], [2.0, 1.0]]})
+
+    def test_36_fixed_metric(self):
+        """固定した質量行列テスト: リストで対角、入れ子のリストで密な質量行列を渡せ、結果にそのまま載るか"""
+        target = {"gaussian": {"cov": [[1.0, 0.0], [0.0, 100.0]]}}
+        common = dict(n_samples=3000, n_warmup=1000, num_steps=2, adapt_step_size=True, target=target, seed=60)
+
+        fixed = hmc.run(metric=[1.0, 100.0], **common)
+        self.assertEqual(fixed["metric"], {"diagonal": [1.0, 100.0]})
+        ys = [p["y"] for p in fixed["samples"]]
+        mean_y = sum(ys) / len(ys)
+        var_y = sum((y - mean_y) ** 2 for y in ys) / len(ys)
+        self.assertAlmostEqual(var_y, 100.0, delta=20.0)
+        self.assertEqual(hmc.run(**common)["metric"], "unit_e")
+
+        # 入れ子のリストは密な質量行列
+        dense = hmc.run(n_samples=100, metric=[[1.0, 0.0], [0.0, 0.01]], target=target, seed=60)
+        self.assertEqual(dense["metric"], {"dense": [[1.0, 0.0], [0.0, 0.01]]})
+
+        with self.assertRaises(ValueError):
+            hmc.run(n_samples=10, metric=[1.0, 2.0, 3.0])
+        with self.assertRaises(ValueError):
+            hmc.run(n_samples=10, metric="diag_e")
+
+    def test_37_divergence_policy(self):
+        """発散時の扱いテスト: ステップ幅を縮めるとやり直した回数が結果に載り、abort は例外になるか"""
+        common = dict(n_samples=500, step_size=1.0, target="banana", seed=61)
+        kept = hmc.run(**common)
+        self.assertGreater(kept["n_divergent"], 0)
+        self.assertEqual(kept["n_step_size_shrinks"], 0)
+
+        shrunk = hmc.run(on_divergence={"shrink_step_size": {"factor": 0.1, "max_retries": 3}}, **common)
+        self.assertGreater(shrunk["n_step_size_shrinks"], 0)
+        self.assertLess(shrunk["n_divergent"], kept["n_divergent"])
+
+        with self.assertRaises(ValueError):
+            hmc.run(on_divergence="abort", **common)
+        with self.assertRaises(ValueError):
+            hmc.run(n_samples=10, on_divergence={"shrink_step_size": {"factor": 2.0}})
+
+    def test_38_random_walk(self):
+        """ランダムウォークMetropolisテスト: algorithm を切り替えるだけで同じ形の結果が返り、HMCより混ざりが遅いか"""
+        common = dict(n_samples=5000, n_warmup=500, step_size=0.1, num_steps=20, target="banana", seed=62)
+        hmc_out = hmc.run(**common)
+        rw_out = hmc.run(algorithm={"random_walk": {"proposal_std": 0.05}}, **common)
+        self.assertEqual(set(hmc_out), set(rw_out))
+        self.assertEqual(len(rw_out["samples"]), 5000)
+        self.assertEqual(rw_out["n_leapfrog"], 0)
+        self.assertGreater(rw_out["acceptance_rate"], 0.5)
+
+        # 1反復あたりの移動距離（自己相関の粗い目安）はHMCの方がずっと大きい
+        def mean_jump(out):
+            xs = [p["x"] for p in out["samples"]]
+            return sum(abs(b - a) for a, b in zip(xs, xs[1:])) / (len(xs) - 1)
+
+        self.assertGreater(mean_jump(hmc_out), 5.0 * mean_jump(rw_out))
+
+        with self.assertRaises(ValueError):
+            hmc.run(n_samples=10, algorithm={"random_walk": {"proposal_std": 0.0}})
+
+    def test_39_mala(self):
+        """MALAテスト: 標準正規分布の平均と分散を再現し、不正なステップ幅は拒否されるか"""
+        out = hmc.run(n_samples=20000, n_warmup=500, algorithm={"mala": {"step_size": 1.2}}, target="normal", seed=63)
+        xs = [p["x"] for p in out["samples"]]
+        mean = sum(xs) / len(xs)
+        var = sum((x - mean) ** 2 for x in xs) / len(xs)
+        self.assertAlmostEqual(mean, 0.0, delta=0.1)
+        self.assertAlmostEqual(var, 1.0, delta=0.1)
+        self.assertEqual(out["n_leapfrog"], 20000)
+        self.assertGreater(out["acceptance_rate"], 0.5)
+
+        with self.assertRaises(ValueError):
+            hmc.run(n_samples=10, algorithm={"mala": {"step_size": -0.1}})
+
+    def test_40_slice(self):
+        """スライスサンプリングテスト: 標準正規分布のモーメントがHMCと一致し、調整なしで二峰性分布の両方の山を訪れるか"""
+        def moments(out):
+            xs = [p["x"] for p in out["samples"]]
+            mean = sum(xs) / len(xs)
+            return mean, sum((x - mean) ** 2 for x in xs) / len(xs)
+
+        common = dict(n_samples=10000, n_warmup=200, step_size=0.25, num_steps=7, target="normal", seed=64)
+        slice_mean, slice_var = moments(hmc.run(algorithm={"slice": {}}, **common))
+        hmc_mean, hmc_var = moments(hmc.run(**common))
+        self.assertAlmostEqual(slice_mean, hmc_mean, delta=0.1)
+        self.assertAlmostEqual(slice_var, hmc_var, delta=0.1)
+        self.assertAlmostEqual(slice_var, 1.0, delta=0.1)
+
+        centers = [{"x": 2.5, "y": 2.5}, {"x": -2.5, "y": -2.5}]
+        out = hmc.run(n_samples=20000, algorithm={"slice": {}}, target="bimodal", mode_centers=centers, seed=64)
+        self.assertEqual(out["acceptance_rate"], 1.0)
+        for fraction in out["mode_occupancy"]:
+            self.assertGreater(fraction, 0.1)
+
+        with self.assertRaises(ValueError):
+            hmc.run(n_samples=10, algorithm={"slice": {"initial_width": 0.0}})
+
+    def test_41_ensemble(self):
+        """アンサンブルサンプラーテスト: ウォーカーが先頭の軸になり、強い異方性のある正規分布の共分散を再現するか"""
+        cov = [[100.0, 0.99], [0.99, 0.01]]
+        walkers, rates = hmc.sample_ensemble(
+            16, 2000, "gaussian", n_warmup=500, seed=65, params={"cov": cov}
+        )
+        self.assertEqual(len(walkers), 16)
+        self.assertEqual(len(rates), 16)
+        self.assertTrue(all(len(w) == 2000 for w in walkers))
+        self.assertEqual(len(walkers[0][0]), 2)
+
+        points = [p for w in walkers for p in w]
+        n = len(points)
+        mx = sum(p[0] for p in points) / n
+        my = sum(p[1] for p in points) / n
+        estimate = [
+            [sum((p[i] - m_i) * (p[j] - m_j) for p in points) / n for j, m_j in enumerate((mx, my))]
+            for i, m_i in enumerate((mx, my))
+        ]
+        for i in range(2):
+            for j in range(2):
+                scale = math.sqrt(cov[i][i] * cov[j][j])
+                self.assertAlmostEqual(estimate[i][j], cov[i][j], delta=0.1 * scale)
+
+        # run() ではウォーカーを遷移ごとに並べた平らなサンプル列とウォーカーごとの採択率を返す
+        out = hmc.run(n_samples=10, algorithm={"ensemble": {"n_walkers": 6}}, target="normal", seed=65)
+        self.assertEqual(len(out["samples"]), 60)
+        self.assertEqual(len(out["walker_acceptance_rates"]), 6)
+
+        with self.assertRaises(ValueError):
+            hmc.sample_ensemble(3, 10, "normal")
+
+    def test_42_parallel_tempering(self):
+        """レプリカ交換法テスト: 温度1のチェーンが二峰性分布の山の間を何度も行き来し、交換の採択率が組ごとに返るか"""
+        centers = [{"x": 2.5, "y": 2.5}, {"x": -2.5, "y": -2.5}]
+        common = dict(n_samples=5000, n_warmup=500, step_size=0.2, num_steps=10, target="bimodal",
+                      initial_pos=centers[0], mode_centers=centers, seed=66)
+        plain = hmc.run(**common)
+        tempered = hmc.run(algorithm={"parallel_tempering": {"temperatures": [1, 2, 4, 8, 16]}}, **common)
+        self.assertLess(plain["mode_switches"], 10)
+        self.assertGreaterEqual(tempered["mode_switches"], 500)
+        self.assertEqual(len(tempered["samples"]), 5000)
+        self.assertEqual(len(tempered["swap_acceptance_rates"]), 4)
+
+        with self.assertRaises(ValueError):
+            hmc.run(n_samples=10, algorithm={"parallel_tempering": {"temperatures": [2, 4]}})
+
+    def test_43_anneal(self):
+        """焼きなましテスト: b=100 のバナナ分布でポテンシャルが1e-3未満の点を見つけ、温度の履歴と軌跡を返すか"""
+        out = hmc.anneal(
+            {"n_iterations": 2000},
+            target={"banana": {"b": 100}},
+            step_size=0.02,
+            num_steps=30,
+            initial_pos={"x": -1.0, "y": 1.0},
+            seed=67,
+        )
+        self.assertLess(out["best_potential"], 1e-3)
+        self.assertAlmostEqual(out["best_position"]["x"], 1.0, delta=0.1)
+        self.assertEqual(len(out["temperatures"]), 2000)
+        self.assertEqual(len(out["trajectory"]), 2000)
+        self.assertGreater(out["temperatures"][0], out["temperatures"][-1])
+
+        with self.assertRaises(ValueError):
+            hmc.anneal({"n_iterations": 0})
+
+    def test_44_step_size_jitter(self):
+        """ステップ幅のジッターテスト: 幅0では結果が変わらず、正の幅では実際のステップ幅が範囲内で揺れて再現できるか"""
+        common = dict(n_samples=200, target="banana", step_size=0.1, seed=68, save_step_sizes=True)
+        plain = hmc.run(**common)
+        self.assertEqual(hmc.run(step_size_jitter=0.0, **common)["samples"], plain["samples"])
+        self.assertEqual(plain["step_sizes"], [0.1] * 200)
+
+        jittered = hmc.run(step_size_jitter=0.5, **common)
+        self.assertNotEqual(jittered["samples"], plain["samples"])
+        self.assertEqual(hmc.run(step_size_jitter=0.5, **common)["samples"], jittered["samples"])
+        self.assertTrue(all(0.05 <= eps <= 0.15 for eps in jittered["step_sizes"]))
+
+        with self.assertRaises(ValueError):
+            hmc.run(n_samples=10, step_size_jitter=1.5)
+
+    def test_45_randomized_steps(self):
+        """リープフロッグ数のランダム化テスト: シード固定で再現でき、平均のステップ数が報告されるか"""
+        common = dict(n_samples=1000, target="normal", num_steps=20, seed=69)
+        fixed = hmc.run(**common)
+        self.assertEqual(fixed["mean_n_leapfrog"], 20.0)
+
+        randomized = hmc.run(randomize_steps=True, **common)
+        self.assertEqual(hmc.run(randomize_steps=True, **common)["samples"], randomized["samples"])
+        self.assertNotEqual(randomized["samples"], fixed["samples"])
+        self.assertLess(abs(randomized["mean_n_leapfrog"] - 10.5), 1.0)
+
+    def test_46_momentum_persistence(self):
+        """運動量の部分更新テスト: 持続率0では結果が変わらず、正の持続率でも標準正規分布の分散を保つか"""
+        common = dict(n_samples=5000, target="normal", step_size=0.3, num_steps=3, seed=70)
+        plain = hmc.run(**common)
+        self.assertEqual(hmc.run(momentum_persistence=0.0, **common)["samples"], plain["samples"])
+
+        persistent = hmc.run(momentum_persistence=0.9, **common)
+        self.assertNotEqual(persistent["samples"], plain["samples"])
+        xs = [p["x"] for p in persistent["samples"]]
+        mean = sum(xs) / len(xs)
+        self.assertLess(abs(sum((x - mean) ** 2 for x in xs) / len(xs) - 1.0), 0.15)
+
+        with self.assertRaises(ValueError):
+            hmc.run(n_samples=10, momentum_persistence=1.0)
+
+    def test_47_yoshida_integrator(self):
+        """4次の積分器テスト: ステップ幅を半分にしたときエネルギー誤差が約16倍小さくなり、勾配の評価回数が3倍になるか"""
+        def run(integrator, step_size):
+            out = hmc.run(n_samples=2000, target="normal", step_size=step_size,
+                          num_steps=round(1.0 / step_size), integrator=integrator,
+                          save_energy=True, seed=71)
+            errors = out["energy_errors"]
+            return sum(abs(e) for e in errors) / len(errors), out["n_gradient_evals"]
+
+        leapfrog, leapfrog_evals = run("leapfrog", 0.2)
+        yoshida, yoshida_evals = run("yoshida4", 0.2)
+        self.assertEqual(yoshida_evals, 3 * leapfrog_evals)
+        ratio = yoshida / run("yoshida4", 0.1)[0]
+        self.assertGreater(ratio, 10.0)
+        self.assertLess(ratio, 25.0)
+
+    def test_48_implicit_midpoint(self):
+        """陰的中点法テスト: リープフロッグが発散するステップ幅でも、曲率の大きいバナナ分布でエネルギーを保つか"""
+        common = dict(n_samples=50, target={"banana": {"a": 1.0, "b": 100.0}}, initial_pos={"x": 1.0, "y": 1.0},
+                      step_size=0.08, num_steps=200, save_energy=True, seed=72)
+        leapfrog = hmc.run(**common)
+        self.assertGreater(leapfrog["n_divergent"], 40)
+
+        implicit = hmc.run(integrator={"implicit_midpoint": {"tol": 1e-10, "max_iters": 100}}, **common)
+        self.assertEqual(implicit["n_divergent"], 0)
+        self.assertEqual(implicit["n_solver_failures"], 0)
+        self.assertLess(max(abs(e) for e in implicit["energy_errors"]), 5.0)
+
+        with self.assertRaises(ValueError):
+            hmc.run(n_samples=10, integrator={"implicit_midpoint": {"max_iters": 0}})
+
+    def test_49_kinetic_energies(self):
+        """運動エネルギーテスト: 相対論的・ラプラス分布の運動量が、中心の尖ったt分布で発散を防ぐか"""
+        common = dict(n_samples=1000, target={"student_t": {"scale": 0.01}}, step_size=1.0, num_steps=10, seed=74)
+        gaussian = hmc.run(**common)
+        self.assertGreater(gaussian["n_divergent"], 20)
+        for kinetic in [{"relativistic": {"c": 0.01}}, {"laplace": {"scale": 100.0}}]:
+            out = hmc.run(kinetic=kinetic, **common)
+            self.assertEqual(out["n_divergent"], 0)
+            self.assertGreater(out["acceptance_rate"], 0.5)
+
+        with self.assertRaises(ValueError):
+            hmc.run(n_samples=10, kinetic={"laplace": {}}, momentum_persistence=0.5)
+
+    def test_50_staged_warmup(self):
+        """段階的ウォームアップテスト: 区間がn_warmupを隙間なく覆い、各区間の適応結果が記録されるか"""
+        out = hmc.run(n_samples=500, n_warmup=1000, algorithm={"nuts": {"max_depth": 10}},
+                      adapt_step_size=True, adapt_mass_matrix=True, mass_regularization=0.0,
+                      warmup_schedule={}, target={"gaussian": {"cov": [[0.01, 0.0], [0.0, 100.0]]}}, seed=75)
+        windows = out["adaptation_windows"]
+        self.assertEqual([(w["start"], w["end"]) for w in windows],
+                         [(0, 75), (75, 100), (100, 150), (150, 250), (250, 450), (450, 950), (950, 1000)])
+        self.assertEqual([w["kind"] for w in windows], ["fast"] + ["slow"] * 5 + ["fast"])
+        inv_mass = out["metric"]["diagonal"]
+        self.assertLess(abs(inv_mass[0] / 0.01 - 1.0), 0.4)
+        self.assertLess(abs(inv_mass[1] / 100.0 - 1.0), 0.4)
+
+        with self.assertRaises(ValueError):
+            hmc.run(n_samples=10, warmup_schedule={"base_window": 0})
+
+    def test_51_find_reasonable_step_size(self):
+        """初期ステップ幅探索テスト: 桁違いの初期値からでも標準正規分布でε≈1付近に落ち着くか"""
+        step_size = hmc.find_reasonable_step_size("normal", 0.0, 0.0, seed=1)
+        self.assertTrue(0.1 < step_size < 10.0)
+        for initial in [1e-8, 10.0]:
+            out = hmc.run(n_samples=100, n_warmup=100, step_size=initial, adapt_step_size=True,
+                          find_step_size=True, target="normal", seed=76)
+            self.assertTrue(0.5 < out["adapted_step_size"] < 2.0)
+
+    def test_52_target_accept(self):
+        """目標採択確率テスト: 0.6と0.95を目標にした適応で、達成した平均採択確率がそれぞれ目標に近いか"""
+        common = dict(n_samples=2000, n_warmup=1000, algorithm={"nuts": {"max_depth": 10}},
+                      adapt_step_size=True, seed=77)
+        low = hmc.run(target_accept=0.6, **common)
+        high = hmc.run(target_accept=0.95, **common)
+        self.assertLess(abs(low["mean_accept_prob"] - 0.6), 0.05)
+        self.assertLess(abs(high["mean_accept_prob"] - 0.95), 0.03)
+        self.assertLess(low["mean_accept_prob"], 0.7)
+        self.assertGreater(high["mean_accept_prob"], 0.9)
+
+        with self.assertRaises(ValueError):
+            hmc.run(n_samples=10, target_accept=1.0)
+
+    def test_53_max_grad_norm(self):
+        """勾配の切り詰めテスト: 強く切り詰めても標準正規分布のモーメントが保たれ、切り詰めた回数が報告されるか"""
+        out = hmc.run(n_samples=4000, n_warmup=500, step_size=0.5, num_steps=8,
+                      target="normal", max_grad_norm=0.3, seed=78)
+        self.assertGreater(out["n_gradient_clips"], 0)
+        xs = [p["x"] for p in out["samples"]]
+        mean = sum(xs) / len(xs)
+        self.assertAlmostEqual(mean, 0.0, delta=0.15)
+        self.assertAlmostEqual(sum((x - mean) ** 2 for x in xs) / len(xs), 1.0, delta=0.15)
+        self.assertEqual(hmc.run(n_samples=10, target="normal", seed=78)["n_gradient_clips"], 0)
+
+        with self.assertRaises(ValueError):
+            hmc.run(n_samples=10, max_grad_norm=0.0)
+
+    def test_54_record_trajectory(self):
+        """軌道記録テスト: 指定した間隔で軌道が記録され、採択された軌道の終点がサンプルになるか"""
+        out = hmc.run(n_samples=200, step_size=0.15, num_steps=10, record_trajectory_every=50,
+                      save_accept_flags=True, seed=82)
+        trajectories = out["trajectories"]
+        self.assertEqual([t["sample_index"] for t in trajectories], [0, 50, 100, 150])
+        for t in trajectories:
+            self.assertEqual(len(t["positions"]), 11)
+            self.assertEqual(len(t["energies"]), 11)
+            end = t["positions"][-1] if t["accepted"] else t["positions"][0]
+            self.assertEqual(end, out["samples"][t["sample_index"]])
+
+        with self.assertRaises(ValueError):
+            hmc.run(n_samples=10, record_trajectory_every=0)
+
+    def test_55_save_momentum(self):
+        """運動量保存テスト: 保存した軌道の始点の運動量がターゲットによらず標準正規分布に従うか"""
+        for target in ["banana", "funnel"]:
+            out = hmc.run(n_samples=4000, step_size=0.05, target=target, save_momentum=True, seed=83)
+            ps = out["momenta"]
+            self.assertEqual(len(ps), 4000)
+            for key in ["x", "y"]:
+                values = [p[key] for p in ps]
+                mean = sum(values) / len(values)
+                self.assertAlmostEqual(mean, 0.0, delta=0.1)
+                self.assertAlmostEqual(sum((v - mean) ** 2 for v in values) / len(values), 1.0, delta=0.1)
+        self.assertNotIn("momenta", hmc.run(n_samples=10, seed=83))
+
+    def test_56_barker(self):
+        """Barker提案テスト: 標準正規分布の平均と分散を再現し、大きすぎるステップ幅でもMALAより採択されるか"""
+        out = hmc.run(n_samples=20000, n_warmup=500, algorithm={"barker": {"step_size": 1.5}}, target="normal", seed=84)
+        xs = [p["x"] for p in out["samples"]]
+        mean = sum(xs) / len(xs)
+        self.assertAlmostEqual(mean, 0.0, delta=0.1)
+        self.assertAlmostEqual(sum((x - mean) ** 2 for x in xs) / len(xs), 1.0, delta=0.1)
+
+        common = dict(n_samples=5000, target="normal", seed=84)
+        barker = hmc.run(algorithm={"barker": {"step_size": 10.0}}, **common)
+        mala = hmc.run(algorithm={"mala": {"step_size": 10.0}}, **common)
+        self.assertGreater(barker["acceptance_rate"], 10 * mala["acceptance_rate"])
+
+        with self.assertRaises(ValueError):
+            hmc.run(n_samples=10, algorithm={"barker": {"step_size": 0.0}})
+
+    def test_57_result_dim(self):
+        """結果の次元テスト: 2次元のAPIの結果は dim == 2 で、サンプルは2成分か"""
+        out = hmc.run(n_samples=10, seed=85)
+        self.assertEqual(out["dim"], 2)
+        self.assertEqual(set(out["samples"][0]), {"x", "y"})
+
+    def test_58_sample_1d(self):
+        """1次元テスト: 二重井戸と関数で渡したポテンシャルから浮動小数点数のリストが返るか"""
+        xs, acc = hmc.sample_1d(20000, 0.2, 10, start=1.0, barrier=1.0, seed=87)
+        self.assertEqual(len(xs), 20000)
+        self.assertIsInstance(xs[0], float)
+        self.assertGreater(acc, 0.5)
+        # 二つの谷を同じ割合で訪れる
+        self.assertAlmostEqual(sum(x > 0 for x in xs) / len(xs), 0.5, delta=0.05)
+
+        xs, _ = hmc.sample_1d(5000, 0.5, 10, potential=lambda x: 0.5 * x * x, seed=87)
+        mean = sum(xs) / len(xs)
+        self.assertAlmostEqual(mean, 0.0, delta=0.1)
+        self.assertAlmostEqual(sum((x - mean) ** 2 for x in xs) / len(xs), 1.0, delta=0.1)
+
+        with self.assertRaises(ValueError):
+            hmc.sample_1d(10, 0.2, 10, a=0.0)
+
+        # ポテンシャルの例外は NaN に置き換えずにそのまま投げる
+        def broken(x):
+            raise ZeroDivisionError("bug in potential")
+
+        with self.assertRaises(ZeroDivisionError):
+            hmc.sample_1d(100, 0.2, 10, potential=broken)
+        with self.assertRaises(ZeroDivisionError):
+            hmc.sample_1d(100, 0.2, 10, potential=lambda x: 0.5 * x * x if x < 1.5 else 1 / 0, seed=87)
+        with self.assertRaises(TypeError):
+            hmc.sample_1d(100, 0.2, 10, potential=lambda x: "not a number")
+
+    def test_59_flat_samples(self):
+        """平坦なサンプル列テスト: タプルの列と同じ値を x0, y0, x1, y1, ... の順に並べたものが返るか"""
+        points, acc = hmc.sample(500, 0.1, 20, 0.0, 0.0, "banana", seed=89)
+        flat, flat_acc = hmc.sample(500, 0.1, 20, 0.0, 0.0, "banana", seed=89, flat=True)
+        self.assertEqual(len(flat), 1000)
+        self.assertIsInstance(flat[0], float)
+        self.assertEqual(flat, [v for p in points for v in p])
+        self.assertEqual(acc, flat_acc)
+
+        out = hmc.run(n_samples=500, target="banana", seed=89, flat_samples=True)
+        self.assertEqual(out["samples"], [])
+        self.assertEqual(out["dim"], 2)
+        self.assertEqual(len(out["samples_flat"]), 1000)
+
+    def test_60_compute_diagnostics(self):
+        """収束診断テスト: compute_diagnostics=True で座標ごとのESSが結果に載るか"""
+        out = hmc.run(n_samples=2000, seed=95, compute_diagnostics=True)
+        ess = out["diagnostics"]["ess"]
+        self.assertEqual(set(ess), {"x", "y"})
+        for value in ess.values():
+            self.assertGreater(value, 100.0)
+            self.assertLessEqual(value, 2000 * math.log10(2000) + 1e-6)
+        self.assertNotIn("diagnostics", hmc.run(n_samples=100, seed=95))
+
+    def test_61_rhat(self):
+        """R̂テスト: 同じ分布を探索するチェーンでは1に近く、別々の山に留まったチェーンでは1.1を超えるか"""
+        samples, rates, diag = hmc.sample_chains(4, 2000, 0.1, 20, "normal", seed=96, compute_diagnostics=True)
+        self.assertEqual(len(samples), 4)
+        self.assertEqual(len(diag["ess"]), 4)
+        for value in diag["rhat"]:
+            self.assertAlmostEqual(value, 1.0, delta=0.05)
+
+        params = {"scales": [0.5, 0.5]}
+        starts = [(2.5, 2.5), (-2.5, -2.5)] * 2
+        _, _, diag = hmc.sample_chains(4, 1000, 0.1, 20, "bimodal", initial_points=starts, seed=96,
+                                       params=params, compute_diagnostics=True)
+        for value in diag["rhat"]:
+            self.assertGreater(value, 1.1)
+
+        self.assertEqual(len(hmc.sample_chains(2, 10, 0.1, 20, "bimodal", seed=96)), 2)
+        _, _, diag = hmc.sample_chains(2, 2, 0.1, 20, "bimodal", seed=96, compute_diagnostics=True)
+        self.assertIsNone(diag["rhat"])
+
+    def test_62_autocorrelation(self):
+        """自己相関テスト: ラグ0が1の浮動小数点数のリストが返り、AR(1)過程では ρ^k に近いか"""
+        import random
+        rng = random.Random(97)
+        rho, v, series = 0.7, 0.0, []
+        for _ in range(50000):
+            v = rho * v + math.sqrt(1 - rho * rho) * rng.gauss(0.0, 1.0)
+            series.append(v)
+        acf = hmc.autocorrelation(series, 10)
+        self.assertEqual(len(acf), 11)
+        self.assertIsInstance(acf[0], float)
+        self.assertEqual(acf[0], 1.0)
+        for k, r in enumerate(acf):
+            self.assertAlmostEqual(r, rho ** k, delta=0.03)
+
+        self.assertEqual(len(hmc.autocorrelation([1.0, 2.0, 3.0], 100)), 3)
+        self.assertTrue(all(math.isnan(r) for r in hmc.autocorrelation([1.0] * 5, 2)))
+
+    def test_63_iact(self):
+        """積分自己相関時間テスト: 座標ごとの τ と打ち切りのラグが載り、短すぎるチェーンには印が付くか"""
+        common = dict(target="normal", algorithm={"random_walk": {"proposal_std": 0.5}}, seed=98,
+                      compute_diagnostics=True)
+        out = hmc.run(n_samples=20000, **common)
+        self.assertEqual(len(out["diagnostics"]["iact"]), 2)
+        for est in out["diagnostics"]["iact"]:
+            self.assertEqual(set(est), {"tau", "window", "too_short"})
+            self.assertGreater(est["tau"], 1.0)
+            self.assertGreaterEqual(est["window"], 5 * est["tau"])
+            self.assertFalse(est["too_short"])
+
+        short = hmc.run(n_samples=100, **common)
+        self.assertTrue(any(est["too_short"] for est in short["diagnostics"]["iact"]))
+
+    def test_64_geweke(self):
+        """Gewekeテスト: 混ざったチェーンでは |z| < 3、遠くから始めてウォームアップを捨てないチェーンでは |z| > 3 か"""
+        common = dict(n_samples=2000, target="normal", seed=99, compute_diagnostics=True)
+        z = hmc.run(**common)["diagnostics"]["geweke"]
+        self.assertLess(abs(z["x"]), 3.0)
+        self.assertLess(abs(z["y"]), 3.0)
+
+        far = hmc.run(initial_pos={"x": 30.0, "y": -30.0}, step_size=0.05, num_steps=2, **common)
+        z = far["diagnostics"]["geweke"]
+        self.assertGreater(abs(z["x"]), 3.0)
+        self.assertGreater(abs(z["y"]), 3.0)
+
+    def test_65_ebfmi(self):
+        """E-BFMIテスト: 標準正規分布では1に近く、単位質量行列の漏斗では低く、エネルギーを記録しなければ載らないか"""
+        common = dict(n_samples=5000, save_energy=True, compute_diagnostics=True, seed=100)
+        normal = hmc.run(target="normal", **common)["diagnostics"]["ebfmi"]
+        self.assertAlmostEqual(normal, 1.0, delta=0.1)
+        funnel = hmc.run(target={"funnel": {"scale": 10.0}}, algorithm={"nuts": {}}, n_warmup=1000,
+                         adapt_step_size=True, **dict(common, n_samples=10000))["diagnostics"]["ebfmi"]
+        self.assertLess(funnel, 0.3)
+
+        out = hmc.run(n_samples=100, target="normal", seed=100, compute_diagnostics=True)
+        self.assertNotIn("ebfmi", out["diagnostics"])
+
 
 if __name__ == "__main__":
     unittest.main()
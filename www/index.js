@@ -1,4 +1,4 @@
-import init, { sample_wasm } from './pkg/hamiltonian_sampler_rs.js';
+import init, { sample_wasm_f32 } from './pkg/hamiltonian_sampler_rs.js';
 
 let totalPoints = 0;
 
@@ -57,25 +57,32 @@ async function run() {
         const t0 = performance.now();
 
         // --- Call Rust WASM ---
-        const result = sample_wasm(n, eps, l, currentPos.x, currentPos.y, type);
+        let result;
+        try {
+            result = sample_wasm_f32(n, eps, l, currentPos.x, currentPos.y, type);
+        } catch (e) {
+            console.error(e);
+            alert(`Sampling failed: ${e.message}`);
+            return;
+        }
         // ----------------------
 
         const t1 = performance.now();
         document.getElementById('compTime').innerText = `${(t1 - t0).toFixed(2)} ms`;
 
         // Update Stats
-        totalPoints += result.samples.length;
+        totalPoints += result.xs.length;
         document.getElementById('totalSamples').innerText = totalPoints;
         document.getElementById('accRate').innerText = `${(result.acceptance_rate * 100).toFixed(1)}%`;
 
         // Update Position for next chain
-        const last = result.samples[result.samples.length - 1];
-        currentPos = last;
+        currentPos = result.final_position;
 
         // Draw Samples
         ctx.fillStyle = 'rgba(129, 140, 248, 0.5)';
-        for (let p of result.samples) {
-            const { cx, cy } = toCanvas(p.x, p.y);
+        // 描画にしか使わないので、サンプルは f32 の配列で受け取る
+        for (let i = 0; i < result.xs.length; i++) {
+            const { cx, cy } = toCanvas(result.xs[i], result.ys[i]);
             ctx.beginPath();
             ctx.arc(cx, cy, 2, 0, Math.PI * 2);
             ctx.fill();